@@ -0,0 +1,115 @@
+//! Compares this crate with the pure-Rust JPEG implementations.
+//!
+//! Run with `cargo bench --bench compare`. Besides the usual Criterion output, a summary table is
+//! written to `target/compare-report.md`.
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+use criterion::{Criterion, criterion_group, criterion_main};
+use image::ImageEncoder as _;
+
+const QUALITY: u8 = 90;
+
+struct Input {
+    name: &'static str,
+    jpeg_data: Vec<u8>,
+    image: turbojpeg::Image<Vec<u8>>,
+}
+
+fn inputs() -> Vec<Input> {
+    let parrots_data = std::fs::read("examples/parrots.jpg").unwrap();
+    let parrots = turbojpeg::decompress(&parrots_data, turbojpeg::PixelFormat::RGB).unwrap();
+    let mandelbrot = turbojpeg::Image::mandelbrot(1920, 1080, turbojpeg::PixelFormat::RGB);
+    let mandelbrot_data = turbojpeg::compress(
+        mandelbrot.as_deref(), QUALITY as i32, turbojpeg::Subsamp::Sub2x2).unwrap().to_vec();
+    let mandelbrot = turbojpeg::decompress(&mandelbrot_data, turbojpeg::PixelFormat::RGB).unwrap();
+    vec![
+        Input { name: "parrots", jpeg_data: parrots_data, image: parrots },
+        Input { name: "mandelbrot", jpeg_data: mandelbrot_data, image: mandelbrot },
+    ]
+}
+
+type Codec = (&'static str, fn(&Input) -> usize);
+
+fn decoders() -> Vec<Codec> {
+    vec![
+        ("turbojpeg", |input| {
+            turbojpeg::decompress(&input.jpeg_data, turbojpeg::PixelFormat::RGB).unwrap().pixels.len()
+        }),
+        ("image", |input| {
+            image::load_from_memory_with_format(&input.jpeg_data, image::ImageFormat::Jpeg)
+                .unwrap().into_rgb8().len()
+        }),
+        ("jpeg-decoder", |input| {
+            jpeg_decoder::Decoder::new(&input.jpeg_data[..]).decode().unwrap().len()
+        }),
+    ]
+}
+
+fn encoders() -> Vec<Codec> {
+    vec![
+        ("turbojpeg", |input| {
+            turbojpeg::compress(input.image.as_deref(), QUALITY as i32, turbojpeg::Subsamp::Sub2x2)
+                .unwrap().len()
+        }),
+        ("image", |input| {
+            let mut jpeg_data = Vec::new();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg_data, QUALITY)
+                .write_image(&input.image.pixels, input.image.width as u32, input.image.height as u32,
+                    image::ColorType::Rgb8)
+                .unwrap();
+            jpeg_data.len()
+        }),
+    ]
+}
+
+fn bench_compare(c: &mut Criterion) {
+    let inputs = inputs();
+    for (op, codecs) in [("decode", decoders()), ("encode", encoders())] {
+        for input in inputs.iter() {
+            let mut group = c.benchmark_group(format!("{}/{}", op, input.name));
+            for &(codec_name, codec) in codecs.iter() {
+                group.bench_function(codec_name, |b| b.iter(|| codec(input)));
+            }
+            group.finish();
+        }
+    }
+
+    let report = report(&inputs);
+    print!("{}", report);
+    std::fs::create_dir_all("target").unwrap();
+    std::fs::write("target/compare-report.md", report).unwrap();
+}
+
+/// Produces a Markdown table with the median time of every codec and its speedup relative to
+/// this crate.
+fn report(inputs: &[Input]) -> String {
+    let mut report = String::new();
+    writeln!(report, "| operation | image | codec | median time | relative to turbojpeg |").unwrap();
+    writeln!(report, "|---|---|---|---|---|").unwrap();
+    for (op, codecs) in [("decode", decoders()), ("encode", encoders())] {
+        for input in inputs.iter() {
+            let times = codecs.iter()
+                .map(|&(name, codec)| (name, median_time(|| { codec(input); })))
+                .collect::<Vec<_>>();
+            let base_time = times[0].1;
+            for (name, time) in times {
+                writeln!(report, "| {} | {} | {} | {:.2?} | {:.2}x |",
+                    op, input.name, name, time, time.as_secs_f64() / base_time.as_secs_f64()).unwrap();
+            }
+        }
+    }
+    report
+}
+
+fn median_time<F: FnMut()>(mut f: F) -> Duration {
+    let mut times = (0..11).map(|_| {
+        let start = Instant::now();
+        f();
+        start.elapsed()
+    }).collect::<Vec<_>>();
+    times.sort();
+    times[times.len() / 2]
+}
+
+criterion_group!(benches, bench_compare);
+criterion_main!(benches);
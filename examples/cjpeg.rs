@@ -27,8 +27,8 @@ fn main() -> Result<()> {
     let mut compressor = Compressor::new()?;
 
     if let Some(quality) = args.value_of("QUALITY") {
-        let quality = quality.parse().context("could not parse value of --quality")?;
-        compressor.set_quality(quality);
+        let quality: i32 = quality.parse().context("could not parse value of --quality")?;
+        compressor.set_quality(quality)?;
     }
 
     let image_jpeg = compressor.compress_to_owned(Image {
@@ -2,7 +2,7 @@ use std::fs;
 use anyhow::{Result, Context as _, bail};
 use clap::clap_app;
 
-use turbojpeg::{Transform, TransformOp, Transformer};
+use turbojpeg::{EdgeHandling, MarkerCopy, Transform, TransformOp, Transformer};
 
 fn main() -> Result<()> {
     let args = clap_app!(jpegtran =>
@@ -56,11 +56,16 @@ fn main() -> Result<()> {
         transform.op = TransformOp::Transverse;
     }
 
-    transform.perfect = args.is_present("PERFECT");
-    transform.trim = args.is_present("TRIM");
+    if args.is_present("PERFECT") {
+        transform.edge = EdgeHandling::Perfect;
+    } else if args.is_present("TRIM") {
+        transform.edge = EdgeHandling::Trim;
+    }
     transform.progressive = args.is_present("PROGRESSIVE");
     transform.gray = args.is_present("GRAYSCALE");
-    transform.copy_none = args.is_present("COPY_NONE");
+    if args.is_present("COPY_NONE") {
+        transform.markers = MarkerCopy::None;
+    }
 
     // TODO: crop
 
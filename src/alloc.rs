@@ -0,0 +1,273 @@
+use std::alloc::Layout;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::sync::Arc;
+use crate::common::{Error, Result};
+
+/// Allocator of image buffers.
+///
+/// You can install an allocator into [`Compressor`][crate::Compressor] and
+/// [`Decompressor`][crate::Decompressor] using their `set_allocator()` methods. The allocator is
+/// then used for the decompressed images returned by
+/// [`Decompressor::decompress_to_buf()`][crate::Decompressor::decompress_to_buf] and for the
+/// temporary pixel buffers used by methods such as
+/// [`Compressor::compress_with()`][crate::Compressor::compress_with]. This is useful for
+/// applications that use arena or pool allocators, or that need pinned memory.
+///
+/// Note that the buffers for compressed JPEG data are allocated by TurboJPEG (see
+/// [`OutputBuf`][crate::OutputBuf]).
+///
+/// # Safety
+///
+/// [`alloc_image_buf()`][Self::alloc_image_buf] must return memory that is valid for reads and
+/// writes of `len` bytes and that is not used by anything else until it is passed to
+/// [`free()`][Self::free].
+///
+/// # Example
+///
+/// ```
+/// use std::ptr::NonNull;
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// // allocator that counts the allocated bytes
+/// #[derive(Debug, Default)]
+/// struct CountingAllocator(AtomicUsize);
+///
+/// unsafe impl turbojpeg::ImageAllocator for CountingAllocator {
+///     fn alloc_image_buf(&self, len: usize) -> Option<NonNull<u8>> {
+///         self.0.fetch_add(len, Ordering::Relaxed);
+///         turbojpeg::SystemAllocator.alloc_image_buf(len)
+///     }
+///
+///     unsafe fn free(&self, ptr: NonNull<u8>, len: usize) {
+///         turbojpeg::SystemAllocator.free(ptr, len)
+///     }
+/// }
+///
+/// let allocator = Arc::new(CountingAllocator::default());
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// decompressor.set_allocator(Some(allocator.clone()));
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// assert_eq!(allocator.0.load(Ordering::Relaxed), 384 * 256 * 3);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub unsafe trait ImageAllocator: Send + Sync + fmt::Debug {
+    /// Allocate a buffer of `len` bytes. The buffer does not need to be initialized.
+    ///
+    /// Returns `None` if the allocation failed. This is never called with `len == 0`.
+    fn alloc_image_buf(&self, len: usize) -> Option<NonNull<u8>>;
+
+    /// Free a buffer that was allocated by [`alloc_image_buf()`][Self::alloc_image_buf] with the
+    /// same `len`.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have been allocated by this allocator and must not be used after this call.
+    unsafe fn free(&self, ptr: NonNull<u8>, len: usize);
+}
+
+/// The default [`ImageAllocator`], which uses the Rust global allocator.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemAllocator;
+
+unsafe impl ImageAllocator for SystemAllocator {
+    fn alloc_image_buf(&self, len: usize) -> Option<NonNull<u8>> {
+        let layout = Layout::array::<u8>(len).ok()?;
+        NonNull::new(unsafe { std::alloc::alloc(layout) })
+    }
+
+    unsafe fn free(&self, ptr: NonNull<u8>, len: usize) {
+        std::alloc::dealloc(ptr.as_ptr(), Layout::array::<u8>(len).unwrap())
+    }
+}
+
+/// [`ImageAllocator`] that returns buffers aligned to a large boundary.
+///
+/// The [`SystemAllocator`] does not guarantee any alignment of the buffers, but SIMD code works
+/// best when the rows start on a cache line boundary, and very large frames (such as 8K video)
+/// benefit from huge pages, which reduce the number of TLB misses. Install this allocator with
+/// `set_allocator()` on [`Compressor`][crate::Compressor] or
+/// [`Decompressor`][crate::Decompressor] to use it for their internal buffers, or use it with
+/// [`ImageBuf::new()`] to allocate your own buffers.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use turbojpeg::AlignedAllocator;
+///
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// decompressor.set_allocator(Some(Arc::new(AlignedAllocator::new(AlignedAllocator::CACHE_LINE))));
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGBA)?;
+/// assert_eq!(image.pixels.as_ptr() as usize % 64, 0);
+///
+/// // a buffer for a 4K frame backed by huge pages
+/// let buf = turbojpeg::ImageBuf::new(3840 * 2160 * 4, Arc::new(AlignedAllocator::huge_pages()))?;
+/// assert_eq!(buf.as_ptr() as usize % AlignedAllocator::HUGE_PAGE, 0);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AlignedAllocator {
+    align: usize,
+    huge_pages: bool,
+}
+
+impl AlignedAllocator {
+    /// Size of a cache line on common CPUs (64 bytes), which is also the alignment that is
+    /// sufficient for all SIMD instructions used by libjpeg-turbo.
+    pub const CACHE_LINE: usize = 64;
+
+    /// Size of a huge page on x86-64 and most ARM64 systems (2 MiB).
+    pub const HUGE_PAGE: usize = 2 << 20;
+
+    /// Create an allocator that aligns the buffers to `align` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two.
+    pub fn new(align: usize) -> AlignedAllocator {
+        assert!(align.is_power_of_two(), "alignment {} is not a power of two", align);
+        AlignedAllocator { align, huge_pages: false }
+    }
+
+    /// Create an allocator that aligns the buffers to [`HUGE_PAGE`][Self::HUGE_PAGE] and asks
+    /// the operating system to back them with huge pages.
+    ///
+    /// On Linux, buffers of at least one huge page are marked with `madvise(MADV_HUGEPAGE)`, which
+    /// takes effect if transparent huge pages are enabled in the `madvise` or `always` mode. On
+    /// other systems, the buffers are only aligned.
+    pub fn huge_pages() -> AlignedAllocator {
+        AlignedAllocator { align: Self::HUGE_PAGE, huge_pages: true }
+    }
+
+    /// Returns the alignment of the buffers in bytes.
+    pub fn align(&self) -> usize {
+        self.align
+    }
+}
+
+unsafe impl ImageAllocator for AlignedAllocator {
+    fn alloc_image_buf(&self, len: usize) -> Option<NonNull<u8>> {
+        let layout = Layout::from_size_align(len, self.align).ok()?;
+        let ptr = NonNull::new(unsafe { std::alloc::alloc(layout) })?;
+        #[cfg(target_os = "linux")]
+        if self.huge_pages && len >= Self::HUGE_PAGE {
+            // this is only a hint, so the result is ignored
+            let advised_len = len - len % Self::HUGE_PAGE;
+            unsafe { libc::madvise(ptr.as_ptr() as *mut libc::c_void, advised_len, libc::MADV_HUGEPAGE) };
+        }
+        Some(ptr)
+    }
+
+    unsafe fn free(&self, ptr: NonNull<u8>, len: usize) {
+        std::alloc::dealloc(ptr.as_ptr(), Layout::from_size_align(len, self.align).unwrap())
+    }
+}
+
+/// Image buffer allocated by an [`ImageAllocator`].
+///
+/// The buffer is zero-initialized and it is freed by its allocator when dropped.
+pub struct ImageBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    allocator: Arc<dyn ImageAllocator>,
+}
+
+unsafe impl Send for ImageBuf {}
+unsafe impl Sync for ImageBuf {}
+
+impl ImageBuf {
+    /// Allocate a zero-initialized buffer of `len` bytes using the `allocator`.
+    ///
+    /// Returns [`Error::Null`] if the allocation failed.
+    pub fn new(len: usize, allocator: Arc<dyn ImageAllocator>) -> Result<ImageBuf> {
+        let ptr = if len == 0 {
+            NonNull::dangling()
+        } else {
+            let ptr = allocator.alloc_image_buf(len).ok_or(Error::Null())?;
+            unsafe { ptr.as_ptr().write_bytes(0, len) };
+            ptr
+        };
+        Ok(ImageBuf { ptr, len, allocator })
+    }
+
+    /// Returns the allocator of this buffer.
+    pub fn allocator(&self) -> &Arc<dyn ImageAllocator> {
+        &self.allocator
+    }
+}
+
+impl Deref for ImageBuf {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] { unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) } }
+}
+impl DerefMut for ImageBuf {
+    fn deref_mut(&mut self) -> &mut [u8] { unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) } }
+}
+impl AsRef<[u8]> for ImageBuf {
+    fn as_ref(&self) -> &[u8] { self.deref() }
+}
+impl AsMut<[u8]> for ImageBuf {
+    fn as_mut(&mut self) -> &mut [u8] { self.deref_mut() }
+}
+
+impl fmt::Debug for ImageBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImageBuf")
+            .field("len", &self.len)
+            .field("allocator", &self.allocator)
+            .finish()
+    }
+}
+
+impl Drop for ImageBuf {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe { self.allocator.free(self.ptr, self.len) }
+        }
+    }
+}
+
+/// Allocates a buffer using the `allocator`, or using the [`SystemAllocator`] if it is `None`.
+pub(crate) fn alloc_buf(allocator: &Option<Arc<dyn ImageAllocator>>, len: usize) -> Result<ImageBuf> {
+    let allocator = allocator.clone().unwrap_or_else(|| Arc::new(SystemAllocator));
+    ImageBuf::new(len, allocator)
+}
+
+/// Temporary buffer that is reused between the calls of a compressor or decompressor.
+#[derive(Debug, Default)]
+pub(crate) struct ScratchBuf(Option<ImageBuf>);
+
+impl ScratchBuf {
+    /// Takes a buffer of at least `len` bytes out of the scratch buffer, allocating a new one only
+    /// if the current buffer is too small. The contents of the buffer are unspecified. Return the
+    /// buffer with [`put()`][Self::put] to reuse it in the next call.
+    pub(crate) fn take(&mut self, allocator: &Option<Arc<dyn ImageAllocator>>, len: usize) -> Result<ImageBuf> {
+        match self.0.take() {
+            Some(buf) if buf.len >= len => Ok(buf),
+            old => {
+                // free the old buffer before allocating the larger one
+                drop(old);
+                alloc_buf(allocator, len)
+            },
+        }
+    }
+
+    /// Returns the buffer taken by [`take()`][Self::take].
+    pub(crate) fn put(&mut self, buf: ImageBuf) {
+        self.0 = Some(buf);
+    }
+
+    /// Frees the buffer.
+    pub(crate) fn release(&mut self) {
+        self.0 = None;
+    }
+}
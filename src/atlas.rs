@@ -0,0 +1,236 @@
+use crate::{Decompressor, Image};
+use crate::common::Result;
+
+/// Rectangular region of a texture atlas, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AtlasRegion {
+    /// Horizontal position of the left edge of the region.
+    pub x: usize,
+    /// Vertical position of the top edge of the region.
+    pub y: usize,
+    /// Width of the region.
+    pub width: usize,
+    /// Height of the region.
+    pub height: usize,
+}
+
+impl AtlasRegion {
+    /// Returns the region as texture coordinates from 0 to 1 (left, top, right, bottom) in an
+    /// atlas of `atlas_width` x `atlas_height` pixels.
+    pub fn uv_rect(&self, atlas_width: usize, atlas_height: usize) -> [f32; 4] {
+        let (w, h) = (atlas_width as f32, atlas_height as f32);
+        [
+            self.x as f32 / w,
+            self.y as f32 / h,
+            (self.x + self.width) as f32 / w,
+            (self.y + self.height) as f32 / h,
+        ]
+    }
+}
+
+/// Packer of rectangles into a texture atlas.
+///
+/// The packer places the rectangles on horizontal shelves: a shelf is as high as its first
+/// rectangle, and later rectangles go to the lowest shelf that has enough room, or to a new
+/// shelf below the others. This is simple and fast, and packs well if the rectangles are added
+/// in the order of decreasing height (which [`Decompressor::decompress_atlas()`] does).
+///
+/// Use [`set_padding()`][Self::set_padding] to leave empty pixels between the regions, so that
+/// texture filtering does not bleed the neighbors into the edges of a region.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{AtlasPacker, AtlasRegion};
+///
+/// let mut packer = AtlasPacker::new(100, 100);
+/// assert_eq!(packer.pack(60, 40), Some(AtlasRegion { x: 0, y: 0, width: 60, height: 40 }));
+/// assert_eq!(packer.pack(40, 30), Some(AtlasRegion { x: 60, y: 0, width: 40, height: 30 }));
+/// assert_eq!(packer.pack(50, 50), Some(AtlasRegion { x: 0, y: 40, width: 50, height: 50 }));
+/// // the atlas is full
+/// assert_eq!(packer.pack(60, 20), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AtlasPacker {
+    width: usize,
+    height: usize,
+    padding: usize,
+    shelves: Vec<Shelf>,
+}
+
+/// A row of regions in [`AtlasPacker`].
+#[derive(Debug, Copy, Clone)]
+struct Shelf {
+    y: usize,
+    height: usize,
+    /// The left edge of the free space on the shelf.
+    x: usize,
+}
+
+impl AtlasPacker {
+    /// Creates a packer for an empty atlas of `width` x `height` pixels.
+    pub fn new(width: usize, height: usize) -> AtlasPacker {
+        AtlasPacker { width, height, padding: 0, shelves: Vec::new() }
+    }
+
+    /// Set the number of empty pixels between the regions (0 by default).
+    pub fn set_padding(&mut self, padding: usize) {
+        self.padding = padding;
+    }
+
+    /// Returns the width of the atlas.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the atlas.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of rows of the atlas that are used by the regions. The rows below are
+    /// free.
+    pub fn used_height(&self) -> usize {
+        self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height)
+    }
+
+    /// Reserves a region of `width` x `height` pixels, or returns `None` if there is no room for
+    /// it.
+    pub fn pack(&mut self, width: usize, height: usize) -> Option<AtlasRegion> {
+        if width > self.width || height > self.height {
+            return None
+        }
+        let padding = self.padding;
+        let atlas_width = self.width;
+        let fits = |shelf: &Shelf| height <= shelf.height && shelf.x + width <= atlas_width;
+        // the shelf that wastes the least height
+        let best = self.shelves.iter_mut()
+            .filter(|shelf| fits(shelf))
+            .min_by_key(|shelf| shelf.height);
+        let shelf = match best {
+            Some(shelf) => shelf,
+            None => {
+                let y = match self.shelves.last() {
+                    Some(last) => last.y + last.height + padding,
+                    None => 0,
+                };
+                if y + height > self.height {
+                    return None
+                }
+                self.shelves.push(Shelf { y, height, x: 0 });
+                self.shelves.last_mut().unwrap()
+            },
+        };
+        let region = AtlasRegion { x: shelf.x, y: shelf.y, width, height };
+        shelf.x += width + padding;
+        Some(region)
+    }
+
+    /// Frees all regions.
+    pub fn reset(&mut self) {
+        self.shelves.clear();
+    }
+}
+
+impl Decompressor {
+    /// Decompress a JPEG image into a free region of a texture atlas.
+    ///
+    /// The region is reserved in `packer`, and the image is decompressed directly into it, using
+    /// the pitch of the `atlas` image; the pixels outside of the region are not modified. Returns
+    /// the region, or `None` if the atlas has no room for the image (for example, to continue in
+    /// a new atlas).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is outside of the `atlas` image, which happens if the `packer` is
+    /// larger than the atlas.
+    pub fn decompress_into_atlas(&mut self, jpeg_data: &[u8], atlas: Image<&mut [u8]>, packer: &mut AtlasPacker) -> Result<Option<AtlasRegion>> {
+        let header = self.read_header(jpeg_data)?;
+        let region = match packer.pack(header.width, header.height) {
+            Some(region) => region,
+            None => return Ok(None),
+        };
+        self.decompress_region(jpeg_data, atlas, region)?;
+        Ok(Some(region))
+    }
+
+    /// Decompress many JPEG images into a texture atlas.
+    ///
+    /// The images are packed in the order of decreasing height (which packs the shelves of the
+    /// [`AtlasPacker`] tightly) and decompressed directly into their regions of the `atlas`
+    /// image, without allocating an image for each of them. Returns the regions in the order of
+    /// `jpegs`, with `None` for the images for which the atlas has no room.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a region is outside of the `atlas` image, which happens if the `packer` is larger
+    /// than the atlas.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{AtlasPacker, Image, PixelFormat, Subsamp};
+    ///
+    /// // sprites of different sizes
+    /// let sprites = [(64, 48), (32, 32), (100, 20), (48, 64)]
+    ///     .map(|(w, h)| turbojpeg::compress(Image::mandelbrot(w, h, PixelFormat::RGB).as_deref(), 90, Subsamp::None));
+    /// let sprites = sprites.into_iter().collect::<Result<Vec<_>, _>>()?;
+    ///
+    /// // decompress them into an atlas of 128x128 pixels with padded rows
+    /// let mut atlas = Image { pixels: vec![0; 600 * 128], width: 128, pitch: 600, height: 128, format: PixelFormat::RGBA };
+    /// let mut packer = AtlasPacker::new(128, 128);
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let regions = decompressor.decompress_atlas(&sprites, atlas.as_deref_mut(), &mut packer)?;
+    ///
+    /// // every sprite is in its region
+    /// for (sprite, region) in sprites.iter().zip(&regions) {
+    ///     let region = region.unwrap();
+    ///     let expected = turbojpeg::decompress(sprite, PixelFormat::RGBA)?;
+    ///     assert_eq!((region.width, region.height), (expected.width, expected.height));
+    ///     for y in 0..region.height {
+    ///         let row = &atlas.pixels[(region.y + y) * atlas.pitch + region.x * 4..][..region.width * 4];
+    ///         assert_eq!(row, &expected.pixels[y * expected.pitch..][..region.width * 4]);
+    ///     }
+    /// }
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_atlas<J: AsRef<[u8]>>(&mut self, jpegs: &[J], mut atlas: Image<&mut [u8]>, packer: &mut AtlasPacker) -> Result<Vec<Option<AtlasRegion>>> {
+        let mut sizes = Vec::with_capacity(jpegs.len());
+        for jpeg_data in jpegs {
+            let header = self.read_header(jpeg_data.as_ref())?;
+            sizes.push((header.width, header.height));
+        }
+        let mut order = (0..jpegs.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| std::cmp::Reverse(sizes[index].1));
+
+        let mut regions = vec![None; jpegs.len()];
+        for index in order {
+            let (width, height) = sizes[index];
+            let region = match packer.pack(width, height) {
+                Some(region) => region,
+                None => continue,
+            };
+            self.decompress_region(jpegs[index].as_ref(), atlas.as_deref_mut(), region)?;
+            regions[index] = Some(region);
+        }
+        Ok(regions)
+    }
+
+    /// Decompress a JPEG image of the size of `region` into the region of `atlas`.
+    fn decompress_region(&mut self, jpeg_data: &[u8], atlas: Image<&mut [u8]>, region: AtlasRegion) -> Result<()> {
+        assert!(region.x + region.width <= atlas.width && region.y + region.height <= atlas.height,
+            "atlas region {:?} is outside of the atlas of {}x{} pixels", region, atlas.width, atlas.height);
+        atlas.assert_valid(atlas.pixels.len());
+        let Image { pixels, pitch, format, .. } = atlas;
+        let offset = region.y * pitch + region.x * format.size();
+        let output = Image {
+            pixels: &mut pixels[offset..],
+            width: region.width,
+            pitch,
+            height: region.height,
+            format,
+        };
+        self.decompress(jpeg_data, output)
+    }
+}
@@ -0,0 +1,95 @@
+use crate::Image;
+use crate::common::{PixelFormat, Subsamp};
+
+/// Maximal number of 2x2 blocks that are examined in each direction.
+const MAX_BLOCKS: usize = 256;
+/// Blocks whose chrominance deviates more than this from the block average lose visible detail
+/// with 4:2:0 subsampling.
+const DETAIL_THRESHOLD: i32 = 32;
+
+/// Chooses between 4:4:4 and 4:2:0 chrominance subsampling for an image.
+///
+/// The image is divided into 2x2 blocks (the chrominance of each block is averaged by 4:2:0
+/// subsampling) and the function measures how much the chrominance of the pixels in a block
+/// deviates from the average. Images with sharp color edges, such as screenshots with colored text
+/// or charts, have many such blocks and would look blurry with 4:2:0 subsampling, so this function
+/// returns [`Subsamp::None`] for them. Photos usually have smooth chrominance and get
+/// [`Subsamp::Sub2x2`], which makes them considerably smaller.
+///
+/// Large images are analyzed on a sparse grid of at most 256x256 blocks, so this is much faster
+/// than the compression itself. [`PixelFormat::GRAY`] images always get [`Subsamp::Gray`] and
+/// [`PixelFormat::CMYK`] images always get [`Subsamp::None`].
+///
+/// This is used by [`Compressor::set_auto_subsamp()`][crate::Compressor::set_auto_subsamp].
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Image, PixelFormat, Subsamp};
+///
+/// // a smooth gradient is fine with 4:2:0 subsampling
+/// let mut pixels = Vec::new();
+/// for y in 0..64 {
+///     for x in 0..64 {
+///         pixels.extend_from_slice(&[4 * x as u8, 4 * y as u8, 128]);
+///     }
+/// }
+/// let gradient = Image { pixels: &pixels[..], width: 64, pitch: 64 * 3, height: 64, format: PixelFormat::RGB };
+/// assert_eq!(turbojpeg::choose_subsamp(gradient), Subsamp::Sub2x2);
+///
+/// // thin red lines on white background would be blurred by subsampling
+/// let mut pixels = vec![255; 64 * 64 * 3];
+/// for y in (0..64).step_by(3) {
+///     for x in 0..64 {
+///         pixels[(y * 64 + x) * 3..][..3].copy_from_slice(&[255, 0, 0]);
+///     }
+/// }
+/// let lines = Image { pixels: &pixels[..], width: 64, pitch: 64 * 3, height: 64, format: PixelFormat::RGB };
+/// assert_eq!(turbojpeg::choose_subsamp(lines), Subsamp::None);
+/// ```
+pub fn choose_subsamp(image: Image<&[u8]>) -> Subsamp {
+    image.assert_valid(image.pixels.len());
+    let (r, g, b) = match image.format.rgb_offsets() {
+        Some(offsets) => offsets,
+        None if image.format == PixelFormat::GRAY => return Subsamp::Gray,
+        None => return Subsamp::None,
+    };
+
+    let pixel_size = image.format.size();
+    let chroma = |x: usize, y: usize| -> (i32, i32) {
+        let pixel = &image.pixels[y*image.pitch + x*pixel_size..];
+        let (r, g, b) = (pixel[r] as i32, pixel[g] as i32, pixel[b] as i32);
+        // fixed-point version of the RGB to YCbCr conversion used by JPEG (without the offset)
+        ((-43*r - 85*g + 128*b) >> 8, (128*r - 107*g - 21*b) >> 8)
+    };
+
+    let (blocks_x, blocks_y) = (image.width / 2, image.height / 2);
+    let step_x = blocks_x.div_ceil(MAX_BLOCKS).max(1);
+    let step_y = blocks_y.div_ceil(MAX_BLOCKS).max(1);
+    let mut sampled = 0;
+    let mut detailed = 0;
+    for by in (0..blocks_y).step_by(step_y) {
+        for bx in (0..blocks_x).step_by(step_x) {
+            let block = [
+                chroma(2*bx, 2*by), chroma(2*bx + 1, 2*by),
+                chroma(2*bx, 2*by + 1), chroma(2*bx + 1, 2*by + 1),
+            ];
+            let mean_cb = block.iter().map(|c| c.0).sum::<i32>() / 4;
+            let mean_cr = block.iter().map(|c| c.1).sum::<i32>() / 4;
+            let deviation = block.iter()
+                .map(|&(cb, cr)| (cb - mean_cb).abs().max((cr - mean_cr).abs()))
+                .max().unwrap();
+            sampled += 1;
+            if deviation > DETAIL_THRESHOLD {
+                detailed += 1;
+            }
+        }
+    }
+
+    // even a small fraction of detailed blocks (such as a line of colored text) is noticeable
+    if detailed * 64 > sampled {
+        Subsamp::None
+    } else {
+        Subsamp::Sub2x2
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::common::{Error, Result};
+use crate::marker;
+
+/// Size of the headers before the first frame (up to the end of the `movi` list header).
+const HEADER_LEN: u64 = 224;
+
+/// Size of an entry of the `idx1` index.
+const INDEX_ENTRY_LEN: u64 = 16;
+
+/// Maximal size of AVI 1.0 files, which many players read with signed 32-bit offsets.
+const MAX_FILE_LEN: u64 = i32::MAX as u64;
+
+/// The frame is a key frame (`AVIIF_KEYFRAME`).
+const KEYFRAME: u32 = 0x10;
+
+/// The file has an `idx1` index (`AVIF_HASINDEX`).
+const HAS_INDEX: u32 = 0x10;
+
+/// Writer of Motion JPEG video in AVI files.
+///
+/// The writer stores JPEG images as the frames of a video stream in an AVI file, without
+/// recompressing them, so that image sequences (such as the frames of a simulation or the images
+/// of a time-lapse) can be viewed in standard video players. All frames must have the same size;
+/// the size of the video is taken from the first frame.
+///
+/// The headers of the file are written with placeholders first and completed by
+/// [`finish()`][Self::finish], which also writes the index of the frames, so the file is only
+/// playable after `finish()` returned. The writer produces AVI 1.0 files, which are limited to
+/// 2 GiB; [`write_frame()`][Self::write_frame] returns [`Error::Unsupported`] for frames that do
+/// not fit.
+///
+/// # Example
+///
+/// ```
+/// use std::io::Cursor;
+/// use turbojpeg::{AviWriter, Image, PixelFormat, Subsamp};
+///
+/// let mut writer = AviWriter::new(Cursor::new(Vec::new()), 25.)?;
+/// for i in 0..10 {
+///     let image = Image::mandelbrot(160 + 10 * i, 120, PixelFormat::RGB);
+///     let cropped = Image { width: 160, ..image.as_deref() };
+///     writer.write_frame(&turbojpeg::compress(cropped, 75, Subsamp::Sub2x2)?)?;
+/// }
+/// assert_eq!(writer.frame_count(), 10);
+///
+/// let avi = writer.finish()?.into_inner();
+/// assert_eq!(&avi[0..4], b"RIFF");
+/// assert_eq!(&avi[8..12], b"AVI ");
+/// assert_eq!(u32::from_le_bytes(avi[4..8].try_into()?) as usize, avi.len() - 8);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct AviWriter<W: Write + Seek> {
+    writer: W,
+    start: u64,
+    fps: f64,
+    size: Option<(usize, usize)>,
+    /// The offset (from the `movi` fourcc) and size of every frame.
+    index: Vec<(u32, u32)>,
+    /// The size of the frame chunks in the `movi` list.
+    movi_len: u64,
+    max_frame_len: u32,
+}
+
+impl AviWriter<BufWriter<File>> {
+    /// Creates an AVI file at `path` for a video with `fps` frames per second.
+    pub fn create<P: AsRef<Path>>(path: P, fps: f64) -> Result<AviWriter<BufWriter<File>>> {
+        AviWriter::new(BufWriter::new(File::create(path)?), fps)
+    }
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    /// Creates a writer that writes an AVI file into `writer` (starting at its current position)
+    /// for a video with `fps` frames per second.
+    ///
+    /// Returns [`Error::InvalidArgument`] if `fps` is not positive and finite.
+    pub fn new(mut writer: W, fps: f64) -> Result<AviWriter<W>> {
+        if !(fps > 0. && fps.is_finite()) {
+            return Err(Error::InvalidArgument(format!("frame rate {} is not positive", fps)))
+        }
+        let start = writer.stream_position()?;
+        let mut avi = AviWriter {
+            writer,
+            start,
+            fps,
+            size: None,
+            index: Vec::new(),
+            movi_len: 0,
+            max_frame_len: 0,
+        };
+        let header = avi.header();
+        avi.writer.write_all(&header)?;
+        Ok(avi)
+    }
+
+    /// Appends the JPEG image in `jpeg_data` as the next frame.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the size of the image differs from the size of the
+    /// first frame.
+    pub fn write_frame(&mut self, jpeg_data: &[u8]) -> Result<()> {
+        let info = match marker::frame_info(jpeg_data) {
+            Some(info) => info,
+            None => return Err(Error::MalformedJpeg("missing SOF marker")),
+        };
+        match self.size {
+            Some((width, height)) if (width, height) != (info.width, info.height) => {
+                return Err(Error::InvalidArgument(format!(
+                    "frame size {}x{} differs from the video size {}x{}",
+                    info.width, info.height, width, height,
+                )))
+            },
+            _ => self.size = Some((info.width, info.height)),
+        }
+
+        let padded_len = jpeg_data.len() as u64 + jpeg_data.len() as u64 % 2;
+        let index_len = (self.index.len() as u64 + 1) * INDEX_ENTRY_LEN;
+        let file_len = HEADER_LEN + self.movi_len + 8 + padded_len + 8 + index_len;
+        if file_len > MAX_FILE_LEN {
+            return Err(Error::Unsupported("AVI files larger than 2 GiB are not supported"))
+        }
+
+        let offset = (self.movi_len + 4) as u32;
+        self.writer.write_all(b"00dc")?;
+        self.writer.write_all(&(jpeg_data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(jpeg_data)?;
+        if !jpeg_data.len().is_multiple_of(2) {
+            // chunks are aligned to 16 bits
+            self.writer.write_all(&[0])?;
+        }
+        self.index.push((offset, jpeg_data.len() as u32));
+        self.movi_len += 8 + padded_len;
+        self.max_frame_len = self.max_frame_len.max(jpeg_data.len() as u32);
+        Ok(())
+    }
+
+    /// Returns the number of frames that were written.
+    pub fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Writes the index of the frames and completes the headers. Returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        let index_len = self.index.len() as u32 * INDEX_ENTRY_LEN as u32;
+        let mut index = Vec::with_capacity(8 + index_len as usize);
+        index.extend_from_slice(b"idx1");
+        index.extend_from_slice(&index_len.to_le_bytes());
+        for &(offset, len) in &self.index {
+            index.extend_from_slice(b"00dc");
+            index.extend_from_slice(&KEYFRAME.to_le_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&len.to_le_bytes());
+        }
+        self.writer.write_all(&index)?;
+
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        let header = self.header();
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Returns the headers of the file, up to the header of the `movi` list, for the frames that
+    /// were written so far.
+    fn header(&self) -> Vec<u8> {
+        let (width, height) = self.size.unwrap_or((0, 0));
+        let (width, height) = (width as u32, height as u32);
+        let frames = self.index.len() as u32;
+        let micros_per_frame = (1e6 / self.fps).round() as u32;
+        // the frame rate is `rate / scale`
+        let (scale, rate) = (1000u32, (self.fps * 1000.).round() as u32);
+        let max_bytes_per_sec = (self.max_frame_len as f64 * self.fps).ceil() as u32;
+        let buffer_len = self.max_frame_len + 8;
+        let movi_len = 4 + self.movi_len as u32;
+        let riff_len = (HEADER_LEN - 8) as u32 + self.movi_len as u32 + 8 + frames * INDEX_ENTRY_LEN as u32;
+
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        let mut put = |values: &[u32]| {
+            for value in values {
+                header.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        let fourcc = |code: &[u8; 4]| u32::from_le_bytes(*code);
+
+        put(&[fourcc(b"RIFF"), riff_len, fourcc(b"AVI ")]);
+        put(&[fourcc(b"LIST"), 192, fourcc(b"hdrl")]);
+        // main AVI header
+        put(&[fourcc(b"avih"), 56]);
+        put(&[micros_per_frame, max_bytes_per_sec, 0, HAS_INDEX, frames, 0, 1, buffer_len, width, height, 0, 0, 0, 0]);
+        put(&[fourcc(b"LIST"), 116, fourcc(b"strl")]);
+        // stream header
+        put(&[fourcc(b"strh"), 56]);
+        put(&[fourcc(b"vids"), fourcc(b"MJPG"), 0, 0, 0, scale, rate, 0, frames, buffer_len, u32::MAX, 0]);
+        put(&[0, width | height << 16]);
+        // stream format (BITMAPINFOHEADER)
+        put(&[fourcc(b"strf"), 40]);
+        put(&[40, width, height, 1 | 24 << 16, fourcc(b"MJPG"), width * height * 3, 0, 0, 0, 0]);
+        put(&[fourcc(b"LIST"), movi_len, fourcc(b"movi")]);
+        debug_assert_eq!(header.len() as u64, HEADER_LEN);
+        header
+    }
+}
@@ -0,0 +1,180 @@
+use crate::Image;
+use crate::common::{PixelFormat, Subsamp, Result, Error};
+use crate::quality::Quality;
+use crate::compress::Compressor;
+use crate::decompress::Decompressor;
+use crate::geometry::MAX_DIMENSION;
+use crate::transform::{Transform, Transformer};
+
+/// JPEG engine that can be swapped or compared at runtime.
+///
+/// This trait describes the basic operations of a JPEG library, so that code that needs to support
+/// several engines (for example, TurboJPEG on native targets and a pure-Rust engine elsewhere, or
+/// a server that compares the output of several encoders) can use `Box<dyn JpegBackend>` instead
+/// of depending on a particular engine. This crate provides [`TurboJpegBackend`] and, with the
+/// `fallback` feature, `fallback::FallbackBackend`; other engines (such as mozjpeg) can be added
+/// by implementing this trait.
+///
+/// Engines report what they support with [`capabilities()`][Self::capabilities]. Engines that
+/// cannot transform JPEG images losslessly keep the default implementation of
+/// [`transform()`][Self::transform], which returns [`Error::Unsupported`].
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{JpegBackend, TurboJpegBackend, PixelFormat, Quality, Subsamp};
+///
+/// let mut backends: Vec<Box<dyn JpegBackend>> = vec![Box::new(TurboJpegBackend::new())];
+/// # #[cfg(feature = "fallback")]
+/// # backends.push(Box::new(turbojpeg::fallback::FallbackBackend::new()));
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// for backend in &mut backends {
+///     let image = backend.decompress(&jpeg_data, PixelFormat::RGB)?;
+///     let recompressed = backend.compress(image.as_deref(), Quality::new(75)?, Subsamp::Sub2x2)?;
+///     println!("{}: {} bytes", backend.name(), recompressed.len());
+/// }
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait JpegBackend: Send {
+    /// Returns a short name of the engine (such as `"turbojpeg"`).
+    fn name(&self) -> &'static str;
+
+    /// Returns the operations and options that the engine supports.
+    fn capabilities(&self) -> BackendCapabilities;
+
+    /// Decompresses a JPEG image into pixel `format`.
+    ///
+    /// The returned image has no padding between the rows.
+    fn decompress(&mut self, jpeg_data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>>>;
+
+    /// Compresses an image into JPEG with the given `quality` and chrominance subsampling.
+    fn compress(&mut self, image: Image<&[u8]>, quality: Quality, subsamp: Subsamp) -> Result<Vec<u8>>;
+
+    /// Losslessly transforms a JPEG image.
+    ///
+    /// The default implementation returns [`Error::Unsupported`].
+    fn transform(&mut self, transform: &Transform, jpeg_data: &[u8]) -> Result<Vec<u8>> {
+        let _ = (transform, jpeg_data);
+        Err(Error::Unsupported("lossless transforms"))
+    }
+}
+
+/// Operations and options supported by a [`JpegBackend`], returned by
+/// [`JpegBackend::capabilities()`].
+///
+/// Generic code can use this to detect the features of an engine instead of hard-coding
+/// assumptions about it, for example to pick a subsampling that the engine can produce or to skip
+/// lossless transforms.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{JpegBackend, TurboJpegBackend, PixelFormat, Subsamp};
+///
+/// let backend = TurboJpegBackend::new();
+/// let capabilities = backend.capabilities();
+/// assert!(capabilities.compress_formats.contains(&PixelFormat::CMYK));
+/// assert!(capabilities.subsamps.contains(&Subsamp::Sub2x2));
+/// assert_eq!(capabilities.precisions, &[8]);
+/// assert!(capabilities.transform);
+///
+/// // pick the best subsampling that the backend supports
+/// let subsamp = [Subsamp::Sub2x2, Subsamp::None].into_iter()
+///     .find(|subsamp| capabilities.subsamps.contains(subsamp));
+/// assert_eq!(subsamp, Some(Subsamp::Sub2x2));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BackendCapabilities {
+    /// Pixel formats of the images that can be compressed.
+    pub compress_formats: &'static [PixelFormat],
+    /// Pixel formats into which JPEG images can be decompressed.
+    pub decompress_formats: &'static [PixelFormat],
+    /// Chrominance subsamplings that the JPEG images can be compressed with.
+    pub subsamps: &'static [Subsamp],
+    /// Sample precisions (in bits) of the JPEG images that can be compressed and decompressed.
+    pub precisions: &'static [u8],
+    /// Whether lossless JPEG images (SOF3) can be compressed and decompressed.
+    pub lossless: bool,
+    /// Whether JPEG images with arithmetic entropy coding can be decompressed.
+    pub arithmetic: bool,
+    /// Whether [`JpegBackend::transform()`] is supported.
+    pub transform: bool,
+    /// Maximal width and height of the images in pixels.
+    pub max_dimension: usize,
+}
+
+/// [`JpegBackend`] implemented with TurboJPEG.
+///
+/// The [`Compressor`], [`Decompressor`] and [`Transformer`] are created when they are first
+/// needed and then reused for all later calls.
+#[derive(Debug, Default)]
+pub struct TurboJpegBackend {
+    compressor: Option<Compressor>,
+    decompressor: Option<Decompressor>,
+    transformer: Option<Transformer>,
+}
+
+impl TurboJpegBackend {
+    /// Creates the backend (this does not allocate any TurboJPEG instance yet).
+    pub fn new() -> TurboJpegBackend {
+        TurboJpegBackend::default()
+    }
+}
+
+impl JpegBackend for TurboJpegBackend {
+    fn name(&self) -> &'static str {
+        "turbojpeg"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        // libjpeg-turbo 2.x supports only 8-bit lossy JPEG, and it is built with arithmetic
+        // decoding by default
+        BackendCapabilities {
+            compress_formats: &PixelFormat::ALL,
+            decompress_formats: &PixelFormat::ALL,
+            subsamps: &Subsamp::ALL,
+            precisions: &[8],
+            lossless: false,
+            arithmetic: true,
+            transform: true,
+            max_dimension: MAX_DIMENSION,
+        }
+    }
+
+    fn decompress(&mut self, jpeg_data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>>> {
+        let decompressor = match self.decompressor {
+            Some(ref mut decompressor) => decompressor,
+            None => self.decompressor.insert(Decompressor::new()?),
+        };
+        let header = decompressor.read_header(jpeg_data)?;
+        let mut image = Image {
+            pixels: vec![0; header.height * header.width * format.size()],
+            width: header.width,
+            pitch: header.width * format.size(),
+            height: header.height,
+            format,
+        };
+        decompressor.decompress(jpeg_data, image.as_deref_mut())?;
+        Ok(image)
+    }
+
+    fn compress(&mut self, image: Image<&[u8]>, quality: Quality, subsamp: Subsamp) -> Result<Vec<u8>> {
+        let compressor = match self.compressor {
+            Some(ref mut compressor) => compressor,
+            None => self.compressor.insert(Compressor::new()?),
+        };
+        compressor.set_quality(quality)?;
+        compressor.set_subsamp(subsamp);
+        compressor.compress_to_vec(image)
+    }
+
+    fn transform(&mut self, transform: &Transform, jpeg_data: &[u8]) -> Result<Vec<u8>> {
+        let transformer = match self.transformer {
+            Some(ref mut transformer) => transformer,
+            None => self.transformer.insert(Transformer::new()?),
+        };
+        transformer.transform_to_vec(transform, jpeg_data)
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::{mem, panic, thread};
+use crate::cancel::{CancelToken, check_cancel};
+use crate::common::Result;
+use crate::metrics;
+
+/// Order in which [`BatchIter`] yields the results.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BatchOrder {
+    /// Yield the results in the order of the inputs (the default).
+    ///
+    /// Results that complete early are kept until all the previous results are yielded.
+    #[default]
+    Ordered,
+    /// Yield the results as soon as they complete.
+    Unordered,
+}
+
+/// Iterator over the results of a batch operation, which are produced in background threads.
+///
+/// The iterator yields pairs of the index of the input and its result, so a failure of one input
+/// does not stop the others. The results are passed through a bounded queue, so the background
+/// threads wait when the consumer is slow (with [`BatchOrder::Ordered`], the results that
+/// complete early are also kept until they can be yielded). Dropping the iterator stops the
+/// threads (after they finish the inputs that they are processing).
+///
+/// This is returned by [`Transformer::transform_batch_iter()`][crate::Transformer::transform_batch_iter]
+/// and [`Pipeline::spawn()`][crate::Pipeline::spawn].
+pub struct BatchIter<R> {
+    results: mpsc::Receiver<(usize, Result<R>)>,
+    order: BatchOrder,
+    next_index: usize,
+    pending: BTreeMap<usize, Result<R>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl<R: Send + 'static> BatchIter<R> {
+    /// Processes the `inputs` in `threads` background threads (zero means
+    /// [`thread::available_parallelism()`]), with at most `queue_len` results waiting for the
+    /// consumer (zero means twice the number of threads). Every thread lazily creates its own
+    /// worker with `make_worker`, like in [`run_ordered()`], and stops when `cancel` is cancelled.
+    /// The results are reported to the [`Metrics`][crate::Metrics] of `subsystem`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn spawn<I, W, M, F>(
+        subsystem: &'static str,
+        inputs: I,
+        threads: usize,
+        queue_len: usize,
+        order: BatchOrder,
+        cancel: Option<CancelToken>,
+        make_worker: M,
+        work: F,
+    ) -> BatchIter<R>
+        where I: IntoIterator,
+              I::IntoIter: Send + 'static,
+              M: Fn() -> Result<W> + Send + Sync + 'static,
+              F: Fn(&mut W, I::Item) -> Result<R> + Send + Sync + 'static,
+    {
+        let threads = match threads {
+            0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            threads => threads,
+        };
+        let queue_len = match queue_len {
+            0 => 2 * threads,
+            queue_len => queue_len,
+        };
+
+        let inputs = Arc::new(Mutex::new(inputs.into_iter().enumerate()));
+        let shared = Arc::new((make_worker, work));
+        let (result_tx, result_rx) = mpsc::sync_channel(queue_len);
+        let threads = (0..threads).map(|_| {
+            let (inputs, shared, result_tx) = (inputs.clone(), shared.clone(), result_tx.clone());
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                let (make_worker, work) = &*shared;
+                let mut worker = None;
+                loop {
+                    if check_cancel(&cancel).is_err() {
+                        break
+                    }
+                    // the lock is released before the input is processed
+                    let next = inputs.lock().unwrap_or_else(|err| err.into_inner()).next();
+                    let (index, input) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    let res = metrics::measure(subsystem, || match worker {
+                        Some(ref mut worker) => work(worker, input),
+                        None => make_worker().and_then(|w| work(worker.insert(w), input)),
+                    });
+                    if result_tx.send((index, res)).is_err() {
+                        break
+                    }
+                }
+            })
+        }).collect();
+
+        BatchIter { results: result_rx, order, next_index: 0, pending: BTreeMap::new(), threads }
+    }
+
+    fn recv(&mut self) -> Option<(usize, Result<R>)> {
+        match self.results.recv() {
+            Ok(result) => Some(result),
+            Err(_) => {
+                // all threads have finished; propagate their panics
+                for thread in self.threads.drain(..) {
+                    if let Err(panic) = thread.join() {
+                        panic::resume_unwind(panic);
+                    }
+                }
+                None
+            },
+        }
+    }
+}
+
+impl<R: Send + 'static> Iterator for BatchIter<R> {
+    type Item = (usize, Result<R>);
+
+    fn next(&mut self) -> Option<(usize, Result<R>)> {
+        match self.order {
+            BatchOrder::Unordered => self.recv(),
+            BatchOrder::Ordered => loop {
+                if let Some(res) = self.pending.remove(&self.next_index) {
+                    self.next_index += 1;
+                    return Some((self.next_index - 1, res))
+                }
+                let (index, res) = self.recv()?;
+                self.pending.insert(index, res);
+            },
+        }
+    }
+}
+
+impl<R> std::fmt::Debug for BatchIter<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BatchIter")
+            .field("order", &self.order)
+            .field("next_index", &self.next_index)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl<R> Drop for BatchIter<R> {
+    fn drop(&mut self) {
+        // closing the channel stops the threads
+        drop(mem::replace(&mut self.results, mpsc::sync_channel(0).1));
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Processes all `inputs` in parallel and returns the results in the order of the inputs.
+///
+/// Every thread lazily creates its own worker (such as a TurboJPEG handle) with `make_worker`; if
+/// that fails, the error is returned for the input that the thread was processing. Zero `threads`
+/// means [`thread::available_parallelism()`]. The results are reported to the
+/// [`Metrics`][crate::Metrics] of `subsystem`.
+pub(crate) fn run_ordered<T, W, R, M, F>(
+    subsystem: &'static str,
+    inputs: &[T],
+    threads: usize,
+    make_worker: M,
+    work: F,
+) -> Vec<Result<R>>
+    where T: Sync,
+          R: Send,
+          M: Fn() -> Result<W> + Sync,
+          F: Fn(&mut W, &T) -> Result<R> + Sync,
+{
+    let threads = match threads {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        threads => threads,
+    }.min(inputs.len()).max(1);
+
+    let next_input = AtomicUsize::new(0);
+    let (result_tx, result_rx) = mpsc::channel::<(usize, Result<R>)>();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let result_tx = result_tx.clone();
+            let (next_input, make_worker, work) = (&next_input, &make_worker, &work);
+            scope.spawn(move || {
+                let mut worker = None;
+                loop {
+                    let index = next_input.fetch_add(1, Ordering::Relaxed);
+                    if index >= inputs.len() {
+                        break
+                    }
+                    let res = metrics::measure(subsystem, || match worker {
+                        Some(ref mut worker) => work(worker, &inputs[index]),
+                        None => make_worker().and_then(|w| work(worker.insert(w), &inputs[index])),
+                    });
+                    if result_tx.send((index, res)).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+    });
+    drop(result_tx);
+
+    let mut results = inputs.iter().map(|_| None).collect::<Vec<_>>();
+    for (index, res) in result_rx {
+        results[index] = Some(res);
+    }
+    results.into_iter().map(|res| res.expect("every input produces a result")).collect()
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+use crate::{Image, Compressor, Decompressor, OutputBuf};
+use crate::common::{Subsamp, DctMethod, Result};
+
+/// Settings that are measured by [`run_bench()`].
+///
+/// The benchmark measures every combination of quality, subsampling and DCT method.
+#[cfg_attr(docsrs, doc(cfg(feature = "bench")))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BenchConfig {
+    /// Qualities of the compressed images (default is 75, 90 and 95).
+    pub qualities: Vec<i32>,
+    /// Chrominance subsampling options (default is [`Subsamp::None`] and [`Subsamp::Sub2x2`]).
+    pub subsamps: Vec<Subsamp>,
+    /// DCT methods (default is both [`DctMethod::Accurate`] and [`DctMethod::Fast`]).
+    pub dct_methods: Vec<DctMethod>,
+    /// Number of measured compressions and decompressions for every combination (default is 10).
+    pub iterations: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            qualities: vec![75, 90, 95],
+            subsamps: vec![Subsamp::None, Subsamp::Sub2x2],
+            dct_methods: vec![DctMethod::Accurate, DctMethod::Fast],
+            iterations: 10,
+        }
+    }
+}
+
+/// Result of [`run_bench()`] for one combination of settings.
+#[cfg_attr(docsrs, doc(cfg(feature = "bench")))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BenchResult {
+    /// Quality of the compressed image.
+    pub quality: i32,
+    /// Chrominance subsampling of the compressed image.
+    pub subsamp: Subsamp,
+    /// DCT method used for compression and decompression.
+    pub dct_method: DctMethod,
+    /// Size of the compressed image in bytes.
+    pub compressed_len: usize,
+    /// Average time of one compression.
+    pub encode_time: Duration,
+    /// Average time of one decompression.
+    pub decode_time: Duration,
+    /// Number of pixels in the image.
+    pub pixels: usize,
+}
+
+impl BenchResult {
+    /// Returns the compression throughput in megapixels per second.
+    pub fn encode_mpx_per_sec(&self) -> f64 {
+        mpx_per_sec(self.pixels, self.encode_time)
+    }
+
+    /// Returns the decompression throughput in megapixels per second.
+    pub fn decode_mpx_per_sec(&self) -> f64 {
+        mpx_per_sec(self.pixels, self.decode_time)
+    }
+
+    /// Returns the number of bits per pixel of the compressed image.
+    pub fn bits_per_pixel(&self) -> f64 {
+        if self.pixels == 0 { 0. } else { 8. * self.compressed_len as f64 / self.pixels as f64 }
+    }
+}
+
+/// Measures compression and decompression throughput of the `image` with various settings.
+///
+/// Every combination of settings in `config` is measured: the image is compressed and
+/// decompressed once to warm up, and then `config.iterations` times to measure the average time.
+/// The image is decompressed into its original pixel format. This is useful for selecting
+/// settings for your hardware and for tracking performance regressions.
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(300, 200, turbojpeg::PixelFormat::RGB);
+/// let config = turbojpeg::BenchConfig {
+///     qualities: vec![80, 95],
+///     iterations: 2,
+///     .. turbojpeg::BenchConfig::default()
+/// };
+///
+/// let results = turbojpeg::run_bench(image.as_deref(), &config)?;
+/// assert_eq!(results.len(), 2 * 2 * 2);
+/// for result in results.iter() {
+///     println!("q={} {:?} {:?}: {:.2} bpp, encode {:.1} Mpx/s, decode {:.1} Mpx/s",
+///         result.quality, result.subsamp, result.dct_method, result.bits_per_pixel(),
+///         result.encode_mpx_per_sec(), result.decode_mpx_per_sec());
+/// }
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "bench")))]
+pub fn run_bench(image: Image<&[u8]>, config: &BenchConfig) -> Result<Vec<BenchResult>> {
+    let mut compressor = Compressor::new()?;
+    let mut decompressor = Decompressor::new()?;
+    let mut jpeg_buf = OutputBuf::new_owned();
+    let mut output = Image {
+        pixels: vec![0; image.width * image.height * image.format.size()],
+        width: image.width,
+        pitch: image.width * image.format.size(),
+        height: image.height,
+        format: image.format,
+    };
+    let iterations = config.iterations.max(1) as u32;
+
+    let mut results = Vec::new();
+    for &dct_method in config.dct_methods.iter() {
+        compressor.set_dct_method(dct_method);
+        decompressor.set_dct_method(dct_method);
+        for &subsamp in config.subsamps.iter() {
+            compressor.set_subsamp(subsamp);
+            for &quality in config.qualities.iter() {
+                compressor.set_quality(quality)?;
+
+                compressor.compress(image, &mut jpeg_buf)?;
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    compressor.compress(image, &mut jpeg_buf)?;
+                }
+                let encode_time = start.elapsed() / iterations;
+
+                decompressor.decompress(&jpeg_buf, output.as_deref_mut())?;
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    decompressor.decompress(&jpeg_buf, output.as_deref_mut())?;
+                }
+                let decode_time = start.elapsed() / iterations;
+
+                results.push(BenchResult {
+                    quality,
+                    subsamp,
+                    dct_method,
+                    compressed_len: jpeg_buf.len(),
+                    encode_time,
+                    decode_time,
+                    pixels: image.width * image.height,
+                });
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn mpx_per_sec(pixels: usize, time: Duration) -> f64 {
+    let secs = time.as_secs_f64();
+    if secs == 0. { 0. } else { pixels as f64 / secs / 1e6 }
+}
@@ -21,6 +21,10 @@ impl Deref for OwnedBuf {
 impl DerefMut for OwnedBuf {
     fn deref_mut(&mut self) -> &mut [u8] { unsafe { deref_mut(self.ptr, self.len) } }
 }
+// the buffer is allocated by `tjAlloc()` (which uses `malloc()`), so it can be freed by any thread
+unsafe impl Send for OwnedBuf {}
+unsafe impl Sync for OwnedBuf {}
+
 impl AsRef<[u8]> for OwnedBuf {
     fn as_ref(&self) -> &[u8] { self.deref() }
 }
@@ -177,7 +181,7 @@ impl From<OwnedBuf> for OutputBuf<'static> {
     }
 }
 
-unsafe fn deref<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+pub(crate) unsafe fn deref<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
     if len != 0 {
         debug_assert!(!ptr.is_null());
         slice::from_raw_parts(ptr, len)
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::common::{Error, Result};
+
+/// Token for cancelling long-running operations.
+///
+/// A token can be attached to a [`Compressor`][crate::Compressor],
+/// [`Decompressor`][crate::Decompressor] or [`Transformer`][crate::Transformer]. Cloned tokens
+/// share the same state, so you can keep one clone and call [`cancel()`][Self::cancel] from
+/// another thread (for example, when the user navigates away from an image that is still being
+/// decoded). Operations that observe the cancelled token fail with [`Error::Cancelled`].
+///
+/// TurboJPEG cannot interrupt the compression or decompression of an image, so cancellation
+/// takes effect only between calls into TurboJPEG or between rows:
+///
+/// - Compressors and decompressors check the token before every call into TurboJPEG. A call that
+///   is already running (such as [`Decompressor::decompress()`][crate::Decompressor::decompress])
+///   always finishes.
+/// - Row-based methods also check the token between rows: [`Compressor::compress_with()`]
+///   while the rows are generated (before the image is compressed), and
+///   [`Decompressor::decompress_with()`] while the rows are passed to the callback (after the
+///   image was decompressed).
+/// - Transformers also check the token in the middle of a transformation, between decoding the
+///   DCT coefficients and encoding them again.
+///
+/// [`Compressor::compress_with()`]: crate::Compressor::compress_with
+/// [`Decompressor::decompress_with()`]: crate::Decompressor::decompress_with
+///
+/// # Example
+///
+/// ```
+/// let token = turbojpeg::CancelToken::new();
+/// let mut compressor = turbojpeg::Compressor::new()?;
+/// compressor.set_cancel_token(Some(token.clone()));
+///
+/// // cancel the compression while the rows are rendered (for example, from another thread when
+/// // the image is no longer needed), so the image is never compressed
+/// let mut output = turbojpeg::OutputBuf::new_owned();
+/// let result = compressor.compress_with(640, 480, turbojpeg::PixelFormat::RGB, &mut output, |y, row| {
+///     row.fill(y as u8);
+///     if y == 9 { token.cancel() }
+/// });
+/// assert!(matches!(result, Err(turbojpeg::Error::Cancelled())));
+///
+/// // the token stays cancelled, so later operations fail immediately
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let mut transformer = turbojpeg::Transformer::new()?;
+/// transformer.set_cancel_token(Some(token));
+/// let result = transformer.transform_to_owned(&turbojpeg::Transform::default(), &jpeg_data);
+/// assert!(matches!(result, Err(turbojpeg::Error::Cancelled())));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels all operations that observe this token (or any of its clones).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`cancel()`][Self::cancel] was called on this token or any of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) fn check_cancel(token: &Option<CancelToken>) -> Result<()> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(Error::Cancelled()),
+        _ => Ok(()),
+    }
+}
@@ -3,6 +3,7 @@ use std::ffi::CStr;
 /// Pixel format determines the layout of pixels in memory.
 #[doc(alias = "TJPF")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(i32)]
 pub enum PixelFormat {
     /// RGB pixel format.
@@ -102,8 +103,47 @@ pub enum PixelFormat {
 }
 
 impl PixelFormat {
+    /// All pixel formats, in the order of their TurboJPEG constants (`TJPF_*`).
+    pub const ALL: [PixelFormat; 12] = [
+        PixelFormat::RGB, PixelFormat::BGR, PixelFormat::RGBX, PixelFormat::BGRX,
+        PixelFormat::XBGR, PixelFormat::XRGB, PixelFormat::GRAY, PixelFormat::RGBA,
+        PixelFormat::BGRA, PixelFormat::ABGR, PixelFormat::ARGB, PixelFormat::CMYK,
+    ];
+
+    /// The maximal [size][Self::size] of a pixel in bytes over all pixel formats.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{PixelFormat, MAX_DIMENSION};
+    ///
+    /// // a buffer that can hold any row of any JPEG image
+    /// const MAX_ROW_LEN: usize = MAX_DIMENSION * PixelFormat::MAX_SIZE;
+    /// assert_eq!(MAX_ROW_LEN, 262_000);
+    /// assert!(PixelFormat::ALL.iter().all(|format| format.size() <= PixelFormat::MAX_SIZE));
+    /// ```
+    pub const MAX_SIZE: usize = 4;
+
+    pub(crate) fn from_i32(format: i32) -> Result<PixelFormat> {
+        Ok(match format {
+            raw::TJPF_TJPF_RGB => PixelFormat::RGB,
+            raw::TJPF_TJPF_BGR => PixelFormat::BGR,
+            raw::TJPF_TJPF_RGBX => PixelFormat::RGBX,
+            raw::TJPF_TJPF_BGRX => PixelFormat::BGRX,
+            raw::TJPF_TJPF_XBGR => PixelFormat::XBGR,
+            raw::TJPF_TJPF_XRGB => PixelFormat::XRGB,
+            raw::TJPF_TJPF_GRAY => PixelFormat::GRAY,
+            raw::TJPF_TJPF_RGBA => PixelFormat::RGBA,
+            raw::TJPF_TJPF_BGRA => PixelFormat::BGRA,
+            raw::TJPF_TJPF_ABGR => PixelFormat::ABGR,
+            raw::TJPF_TJPF_ARGB => PixelFormat::ARGB,
+            raw::TJPF_TJPF_CMYK => PixelFormat::CMYK,
+            other => return Err(Error::BadPixelFormat(other)),
+        })
+    }
+
     /// The size of a pixel in bytes.
-    pub fn size(&self) -> usize {
+    pub const fn size(&self) -> usize {
         match self {
             PixelFormat::RGB => 3,
             PixelFormat::BGR => 3,
@@ -119,6 +159,18 @@ impl PixelFormat {
             PixelFormat::CMYK => 4,
         }
     }
+
+    /// The offsets of the red, green and blue components in a pixel, or `None` for
+    /// [`PixelFormat::GRAY`] and [`PixelFormat::CMYK`].
+    pub(crate) fn rgb_offsets(self) -> Option<(usize, usize, usize)> {
+        match self {
+            PixelFormat::RGB | PixelFormat::RGBX | PixelFormat::RGBA => Some((0, 1, 2)),
+            PixelFormat::BGR | PixelFormat::BGRX | PixelFormat::BGRA => Some((2, 1, 0)),
+            PixelFormat::XBGR | PixelFormat::ABGR => Some((3, 2, 1)),
+            PixelFormat::XRGB | PixelFormat::ARGB => Some((1, 2, 3)),
+            PixelFormat::GRAY | PixelFormat::CMYK => None,
+        }
+    }
 }
 
 
@@ -131,6 +183,7 @@ impl PixelFormat {
 /// This is called "chrominance subsampling".
 #[doc(alias = "TJSAMP")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum Subsamp {
     /// No chrominance subsampling (4:4:4);
@@ -187,6 +240,32 @@ pub enum Subsamp {
 }
 
 impl Subsamp {
+    /// All levels of chrominance subsampling, in the order of their TurboJPEG constants
+    /// (`TJSAMP_*`).
+    pub const ALL: [Subsamp; 6] = [
+        Subsamp::None, Subsamp::Sub2x1, Subsamp::Sub2x2, Subsamp::Gray, Subsamp::Sub1x2, Subsamp::Sub4x1,
+    ];
+
+    /// The maximal width of the MCU block over all levels of chrominance subsampling.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::Subsamp;
+    ///
+    /// // the sizes of MCU blocks can be used in constant expressions
+    /// const MCU_PIXELS: usize = Subsamp::Sub2x2.mcu_width() * Subsamp::Sub2x2.mcu_height();
+    /// let block = [0u8; MCU_PIXELS * 3];
+    /// assert_eq!(block.len(), 768);
+    ///
+    /// assert_eq!(Subsamp::MAX_MCU_WIDTH, Subsamp::Sub4x1.mcu_width());
+    /// assert_eq!(Subsamp::MAX_MCU_HEIGHT, 16);
+    /// ```
+    pub const MAX_MCU_WIDTH: usize = 32;
+
+    /// The maximal height of the MCU block over all levels of chrominance subsampling.
+    pub const MAX_MCU_HEIGHT: usize = 16;
+
     pub(crate) fn from_u32(subsamp: u32) -> Result<Self> {
         Ok(match subsamp {
             raw::TJSAMP_TJSAMP_444 => Self::None,
@@ -209,7 +288,7 @@ impl Subsamp {
     /// assert_eq!(turbojpeg::Subsamp::Sub2x1.mcu_width(), 16);
     /// ```
     #[doc(alias = "tjMCUWidth")]
-    pub fn mcu_width(self) -> usize {
+    pub const fn mcu_width(self) -> usize {
         8 * self.width()
     }
 
@@ -223,7 +302,7 @@ impl Subsamp {
     /// assert_eq!(turbojpeg::Subsamp::Sub2x1.mcu_height(), 8);
     /// ```
     #[doc(alias = "tjMCUHeight")]
-    pub fn mcu_height(self) -> usize {
+    pub const fn mcu_height(self) -> usize {
         8 * self.height()
     }
 
@@ -236,7 +315,7 @@ impl Subsamp {
     /// ```
     #[doc(alias = "tjMCUWidth")]
     #[doc(alias = "tjMCUHeight")]
-    pub fn mcu_size(self) -> (usize, usize) {
+    pub const fn mcu_size(self) -> (usize, usize) {
         let (width, height) = self.size();
         (8 * width, 8 * height)
     }
@@ -248,7 +327,7 @@ impl Subsamp {
     /// ```
     /// assert_eq!(turbojpeg::Subsamp::Sub2x1.width(), 2);
     /// ```
-    pub fn width(self) -> usize {
+    pub const fn width(self) -> usize {
         self.size().0
     }
 
@@ -259,7 +338,7 @@ impl Subsamp {
     /// ```
     /// assert_eq!(turbojpeg::Subsamp::Sub2x1.height(), 1);
     /// ```
-    pub fn height(self) -> usize {
+    pub const fn height(self) -> usize {
         self.size().1
     }
 
@@ -270,7 +349,7 @@ impl Subsamp {
     /// ```
     /// assert_eq!(turbojpeg::Subsamp::Sub2x1.size(), (2, 1));
     /// ```
-    pub fn size(self) -> (usize, usize) {
+    pub const fn size(self) -> (usize, usize) {
         match self {
             Self::None => (1, 1),
             Self::Sub2x1 => (2, 1),
@@ -286,6 +365,7 @@ impl Subsamp {
 /// JPEG colorspaces.
 #[doc(alias = "TJCS")]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum Colorspace {
     /// RGB colorspace.
@@ -354,6 +434,33 @@ impl Colorspace {
     }
 }
 
+/// Algorithm of the discrete cosine transform (DCT) used in compression and decompression.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DctMethod {
+    /// Accurate integer DCT (the default).
+    #[default]
+    #[doc(alias = "TJFLAG_ACCURATEDCT")]
+    Accurate,
+
+    /// Fast integer DCT.
+    ///
+    /// This is less accurate than the default method, especially at high quality levels. With the
+    /// SIMD extensions of libjpeg-turbo, it is usually not significantly faster, so you should
+    /// measure whether it helps on your hardware.
+    #[doc(alias = "TJFLAG_FASTDCT")]
+    Fast,
+}
+
+impl DctMethod {
+    pub(crate) fn flags(self) -> libc::c_int {
+        (match self {
+            DctMethod::Accurate => raw::TJFLAG_ACCURATEDCT,
+            DctMethod::Fast => raw::TJFLAG_FASTDCT,
+        }) as libc::c_int
+    }
+}
+
 
 /// Specialized `Result` type for TurboJPEG.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -363,7 +470,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     /// TurboJPEG returned an error message.
     #[error("TurboJPEG error: {0}")]
-    TurboJpegError(String),
+    TurboJpegError(ErrorMessage),
     
     /// TurboJPEG unexpectedly returned a null pointer, prehaps because it ran out of memory.
     #[error("TurboJPEG returned null pointer")]
@@ -373,6 +480,10 @@ pub enum Error {
     #[error("TurboJPEG returned unknown subsampling option: {0}")]
     BadSubsamp(u32),
 
+    /// TurboJPEG returned a pixel format that is not known by this crate.
+    #[error("TurboJPEG returned unknown pixel format: {0}")]
+    BadPixelFormat(i32),
+
     /// TurboJPEG returned a colorspace variant that is not known by this crate.
     #[error("TurboJPEG returned unknown colorspace: {0}")]
     BadColorspace(u32),
@@ -380,9 +491,221 @@ pub enum Error {
     /// The given integer value overflowed when converted into type expected by TurboJPEG.
     #[error("integer value {0:?} overflowed")]
     IntegerOverflow(&'static str),
+
+    /// The operation was cancelled using a [`CancelToken`][crate::CancelToken].
+    #[error("operation was cancelled")]
+    Cancelled(),
+
+    /// The JPEG image exceeds one of the [`DecompressLimits`][crate::DecompressLimits]. The value
+    /// is the name of the limit.
+    #[error("JPEG image exceeds the {0:?} limit")]
+    LimitExceeded(&'static str),
+
+    /// The JPEG data is not structurally valid. The value describes the problem.
+    #[error("malformed JPEG data: {0}")]
+    MalformedJpeg(&'static str),
+
+    /// The lossless transform is not perfect for the MCU geometry of the image, and
+    /// [`EdgeHandling::Perfect`][crate::EdgeHandling::Perfect] was requested. The value is the
+    /// transform operation.
+    #[error("transform {0:?} is not perfect for this image")]
+    ImperfectTransform(crate::TransformOp),
+
+    /// The scaling factor is not supported by TurboJPEG (see
+    /// [`ScalingFactor::supported()`][crate::ScalingFactor::supported]).
+    #[error("scaling factor {}/{} is not supported", .0.num(), .0.denom())]
+    UnsupportedScalingFactor(crate::ScalingFactor),
+
+    /// The output buffer is too small for the image. The value is the required length in bytes.
+    #[error("output buffer is too small, {0} bytes are required")]
+    OutputTooSmall(usize),
+
+    /// The PGM, PPM or PAM data is not valid or not supported. The value describes the problem.
+    #[error("malformed PNM data: {0}")]
+    MalformedPnm(&'static str),
+
+    /// The crop region is outside of the image (for a
+    /// [`TransformPipeline`][crate::TransformPipeline], the image produced by the previous steps).
+    #[error("crop region is empty")]
+    InvalidCrop(),
+
+    /// The image does not have the pixel format required by [`TypedImage`][crate::TypedImage].
+    /// The values are the expected and the actual pixel format.
+    #[error("expected pixel format {0:?}, but the image has pixel format {1:?}")]
+    PixelFormatMismatch(PixelFormat, PixelFormat),
+
+    /// The size or layout of an image is not valid (see [`Geometry`][crate::Geometry]). The value
+    /// describes the problem.
+    #[error("invalid image geometry: {0}")]
+    InvalidGeometry(&'static str),
+
+    /// The width or height of the image exceeds [`max_dimension()`][crate::max_dimension]. The
+    /// values are the width and height of the image.
+    #[error("image size {0}x{1} exceeds the maximal JPEG dimension")]
+    DimensionTooLarge(usize, usize),
+
+    /// The JPEG image has more scans than allowed by
+    /// [`DecompressLimits::max_scan_count`][crate::DecompressLimits::max_scan_count]. The value is
+    /// the limit.
+    #[error("JPEG image has more than {0} scans")]
+    #[doc(alias = "TJFLAG_LIMITSCANS")]
+    TooManyScans(usize),
+
+    /// The post-processing step set by
+    /// [`Decompressor::set_post_process()`][crate::Decompressor::set_post_process] failed.
+    #[error("post-processing failed: {0}")]
+    PostProcess(Box<dyn std::error::Error + Send + Sync>),
+
+    /// The JPEG quality is not between 1 and 100 (see [`Quality::new()`][crate::Quality::new]).
+    #[error("JPEG quality {0} is not between 1 and 100")]
+    InvalidQuality(i32),
+
+    /// The operation is not supported by the JPEG backend (for example, the pure-Rust `fallback`
+    /// backend cannot compress CMYK images).
+    #[error("operation is not supported: {0}")]
+    Unsupported(&'static str),
+
+    /// A JPEG backend other than TurboJPEG (such as the pure-Rust `fallback` backend) failed.
+    #[error("JPEG backend failed: {0}")]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An argument is not valid or not supported (see
+    /// [`CompressorPreset::from_cjpeg_args()`][crate::CompressorPreset::from_cjpeg_args] and
+    /// [`MjpegPlayer::set_frame_skip()`][crate::MjpegPlayer::set_frame_skip]). The value describes
+    /// the problem.
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// Reading or writing of the data failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+// keep `Error` below the size at which clippy warns about large `Err` variants (128 bytes)
+const _: () = assert!(std::mem::size_of::<Error>() <= 120);
+
+// lets the functions that accept `impl TryInto<Quality>` take a `Quality`, which converts
+// infallibly
+impl From<std::convert::Infallible> for Error {
+    fn from(never: std::convert::Infallible) -> Error {
+        match never {}
+    }
+}
+
+/// Error message returned by TurboJPEG (see [`Error::TurboJpegError`]).
+///
+/// The message is stored inline in a fixed buffer, so obtaining the error from TurboJPEG does not
+/// allocate any memory. This keeps the failure paths usable in allocation-sensitive contexts, such
+/// as real-time threads or code running under a custom global allocator that must not be
+/// re-entered.
+///
+/// The message dereferences to [`str`]. The buffer has [`CAPACITY`][Self::CAPACITY] bytes, which
+/// is less than the 200 bytes that libjpeg allows, but more than the longest message of TurboJPEG
+/// (78 bytes), so the message is not truncated in practice; non-ASCII bytes (which TurboJPEG does
+/// not produce) are replaced with `?`. The shorter buffer keeps [`Error`] at 120 bytes, so that
+/// returning it in a [`Result`] stays cheap.
+///
+/// # Example
+///
+/// ```
+/// match turbojpeg::read_header(b"not a jpeg") {
+///     Err(turbojpeg::Error::TurboJpegError(msg)) => {
+///         assert!(msg.starts_with("Not a JPEG file"));
+///         assert_eq!(msg, "Not a JPEG file: starts with 0x6e 0x6f");
+///     },
+///     res => panic!("unexpected result {:?}", res),
+/// }
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorMessage {
+    buf: [u8; ErrorMessage::CAPACITY],
+    len: u8,
+}
+
+impl ErrorMessage {
+    /// Maximal length of the message in bytes.
+    pub const CAPACITY: usize = 112;
+
+    /// Creates a message from `msg`, truncated to [`CAPACITY`][Self::CAPACITY] bytes.
+    pub fn new(msg: &[u8]) -> ErrorMessage {
+        let mut buf = [0; ErrorMessage::CAPACITY];
+        let len = msg.len().min(ErrorMessage::CAPACITY);
+        for (dst, &src) in buf.iter_mut().zip(&msg[..len]) {
+            *dst = if src.is_ascii() { src } else { b'?' };
+        }
+        ErrorMessage { buf, len: len as u8 }
+    }
+
+    /// Returns the message as a string slice.
+    pub fn as_str(&self) -> &str {
+        // the buffer contains only ASCII characters
+        std::str::from_utf8(&self.buf[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+impl std::ops::Deref for ErrorMessage {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for ErrorMessage {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl std::fmt::Debug for ErrorMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl std::fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<str> for ErrorMessage {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ErrorMessage {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
 }
 
 pub(crate) unsafe fn get_error(handle: raw::tjhandle) -> Error {
     let msg = CStr::from_ptr(raw::tjGetErrorStr2(handle));
-    Error::TurboJpegError(msg.to_string_lossy().into_owned())
+    Error::TurboJpegError(ErrorMessage::new(msg.to_bytes()))
+}
+
+/// Returns true if the last error of `handle` was fatal.
+///
+/// libjpeg aborts the operation with a `longjmp()` after a fatal error, which may leave the state
+/// of the handle inconsistent, so the handle should not be reused (see [`recreate_handle()`]).
+pub(crate) unsafe fn is_fatal_error(handle: raw::tjhandle) -> bool {
+    raw::tjGetErrorCode(handle) == raw::TJERR_TJERR_FATAL as libc::c_int
+}
+
+/// Replaces `handle` with a new handle created by `init`, and destroys the old handle.
+pub(crate) unsafe fn recreate_handle(
+    handle: &mut raw::tjhandle,
+    init: unsafe extern "C" fn() -> raw::tjhandle,
+    #[allow(unused_variables)] kind: &'static str,
+) -> Result<()> {
+    let new_handle = init();
+    #[cfg(feature = "tracing")]
+    crate::trace::handle_created(kind, new_handle);
+    if new_handle.is_null() {
+        return Err(get_error(new_handle))
+    }
+    raw::tjDestroy(*handle);
+    *handle = new_handle;
+    Ok(())
 }
@@ -1,18 +1,45 @@
 use std::convert::TryInto as _;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
 use crate::{Image, raw};
-use crate::buf::{OwnedBuf, OutputBuf};
-use crate::common::{Subsamp, Result, Error, get_error};
+use crate::alloc::{ImageAllocator, ScratchBuf};
+use crate::auto_subsamp::choose_subsamp;
+use crate::buf::{OwnedBuf, OutputBuf, deref};
+use crate::cancel::{CancelToken, check_cancel};
+use crate::concurrency;
+use crate::geometry::check_max_dimension;
+use crate::common::{PixelFormat, Subsamp, Colorspace, DctMethod, Result, Error, get_error, is_fatal_error, recreate_handle};
+use crate::quality::Quality;
+use crate::preset::{CompressorPreset, CompressOverrides, SharedPreset};
+use crate::progress::{Progress, ProgressCallback, report_progress};
+use crate::sharp_yuv::{YuvPlanes, sharp_yuv_420};
+#[cfg(feature = "stats")]
+use crate::stats::Stats;
 
 /// Compresses raw pixel data into JPEG.
 #[derive(Debug)]
 #[doc(alias = "tjhandle")]
 pub struct Compressor {
     handle: raw::tjhandle,
-    quality: i32,
+    poisoned: bool,
+    quality: Quality,
     subsamp: Subsamp,
+    auto_subsamp: bool,
+    colorspace: Option<Colorspace>,
+    sharp_yuv: bool,
+    shared_preset: Option<SharedPreset>,
+    overrides: Option<CompressOverrides>,
+    progressive: bool,
+    dct_method: DctMethod,
+    cancel: Option<CancelToken>,
+    progress: Option<ProgressCallback>,
+    allocator: Option<Arc<dyn ImageAllocator>>,
+    scratch: ScratchBuf,
+    #[cfg(feature = "stats")]
+    stats: Stats,
 }
 
-static DEFAULT_QUALITY: i32 = 95;
+static DEFAULT_QUALITY: Quality = Quality::new_const(95);
 static DEFAULT_SUBSAMP: Subsamp = Subsamp::None;
 
 unsafe impl Send for Compressor {}
@@ -23,11 +50,27 @@ impl Compressor {
     pub fn new() -> Result<Compressor> {
         unsafe {
             let handle = raw::tjInitCompress();
+            #[cfg(feature = "tracing")]
+            crate::trace::handle_created("compressor", handle);
             if !handle.is_null() {
                 Ok(Compressor {
                     handle,
+                    poisoned: false,
                     quality: DEFAULT_QUALITY,
                     subsamp: DEFAULT_SUBSAMP,
+                    auto_subsamp: false,
+                    colorspace: None,
+                    sharp_yuv: false,
+                    shared_preset: None,
+                    overrides: None,
+                    progressive: false,
+                    dct_method: DctMethod::default(),
+                    cancel: None,
+                    progress: None,
+                    allocator: None,
+                    scratch: ScratchBuf::default(),
+                    #[cfg(feature = "stats")]
+                    stats: Stats::default(),
                 })
             } else {
                 Err(get_error(handle))
@@ -35,12 +78,99 @@ impl Compressor {
         }
     }
 
+    /// Create a new compressor instance with the settings from a preset.
+    ///
+    /// The `preset` is either a [`Preset`][crate::Preset] or a [`CompressorPreset`] (see
+    /// [`set_preset()`][Self::set_preset]).
+    pub fn with_preset<P: Into<CompressorPreset>>(preset: P) -> Result<Compressor> {
+        let mut compressor = Compressor::new()?;
+        compressor.set_preset(preset);
+        Ok(compressor)
+    }
+
+    /// Set the quality, subsampling, progressive coding and DCT method from a preset.
+    ///
+    /// See [`Preset`][crate::Preset] for the available presets.
+    pub fn set_preset<P: Into<CompressorPreset>>(&mut self, preset: P) {
+        let preset = preset.into();
+        self.quality = preset.quality;
+        self.subsamp = preset.subsamp;
+        self.progressive = preset.progressive;
+        self.dct_method = preset.dct_method;
+    }
+
+    /// Set the settings that are shared with other compressors and may change at runtime (see
+    /// [`SharedPreset`]), or `None` to stop using them.
+    ///
+    /// Every compression takes a snapshot of the current shared settings and uses it instead of the
+    /// quality, subsampling, progressive coding and DCT method set by the other methods. The own
+    /// settings of the compressor are not changed, so they are used again after
+    /// `set_shared_preset(None)`.
+    pub fn set_shared_preset(&mut self, preset: Option<SharedPreset>) {
+        self.shared_preset = preset;
+    }
+
+    /// Calls `f` with this compressor, overriding some of its settings only for the duration of
+    /// the call.
+    ///
+    /// The quality, subsampling, progressive coding and DCT method are saved before `f` is called
+    /// and restored afterwards (also when `f` panics), so the overrides of one request never leak
+    /// into the next one when the compressor is shared (see
+    /// [`HandlePool`][crate::HandlePool]). The overrides also take precedence over the
+    /// [shared preset][Self::set_shared_preset].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{CompressOverrides, Quality, Subsamp};
+    ///
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_quality(90)?;
+    ///
+    /// let overrides = CompressOverrides { quality: Some(Quality::new(30)?), subsamp: Some(Subsamp::Gray), ..Default::default() };
+    /// let jpeg_data = compressor.with_overrides(&overrides, |compressor| {
+    ///     assert_eq!(compressor.quality().get(), 30);
+    ///     compressor.compress_to_vec(image.as_deref())
+    /// })?;
+    /// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, Subsamp::Gray);
+    /// assert_eq!(compressor.quality().get(), 90);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn with_overrides<R, F>(&mut self, overrides: &CompressOverrides, f: F) -> R
+        where F: FnOnce(&mut Compressor) -> R
+    {
+        let guard = OverrideGuard { preset: self.current_preset(), overrides: self.overrides, compressor: self };
+        let mut preset = guard.preset;
+        overrides.apply(&mut preset);
+        guard.compressor.set_preset(preset);
+        guard.compressor.overrides = Some(*overrides);
+        f(&mut *guard.compressor)
+    }
+
+    fn current_preset(&self) -> CompressorPreset {
+        CompressorPreset {
+            quality: self.quality,
+            subsamp: self.subsamp,
+            progressive: self.progressive,
+            dct_method: self.dct_method,
+        }
+    }
+
     /// Set the quality of the compressed JPEG images.
     ///
-    /// The quality ranges from 1 (worst) to 100 (best).
-    pub fn set_quality(&mut self, quality: i32) {
-        // TODO: check whether 1 <= quality <= 100 here?
-        self.quality = quality;
+    /// The quality ranges from 1 (worst) to 100 (best). This accepts a [`Quality`] or an `i32`;
+    /// returns [`Error::InvalidQuality`] (and keeps the previous quality) if the `i32` is out of
+    /// range.
+    pub fn set_quality(&mut self, quality: impl TryInto<Quality, Error: Into<Error>>) -> Result<()> {
+        self.quality = quality.try_into().map_err(Into::into)?;
+        Ok(())
+    }
+
+    /// Get the quality of the compressed JPEG images.
+    pub fn quality(&self) -> Quality {
+        self.quality
     }
 
     /// Set the level of chrominance subsampling of the compressed JPEG images.
@@ -51,6 +181,261 @@ impl Compressor {
         self.subsamp = subsamp;
     }
 
+    /// Enable or disable the automatic selection of chrominance subsampling for every image.
+    ///
+    /// When enabled, the subsampling set by [`set_subsamp()`][Self::set_subsamp] is ignored and
+    /// every image is compressed with the subsampling returned by [`choose_subsamp()`]: 4:4:4 for
+    /// images with sharp color edges (such as screenshots with colored text) and 4:2:0 for images
+    /// with smooth colors (such as photos).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let photo = turbojpeg::decompress(
+    ///     &std::fs::read("examples/parrots.jpg")?, turbojpeg::PixelFormat::RGB)?;
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_auto_subsamp(true);
+    /// let jpeg_data = compressor.compress_to_vec(photo.as_deref())?;
+    /// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, turbojpeg::Subsamp::Sub2x2);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_auto_subsamp(&mut self, auto_subsamp: bool) {
+        self.auto_subsamp = auto_subsamp;
+    }
+
+    /// Set the colorspace of the compressed JPEG images, or `None` to choose it automatically (the
+    /// default).
+    ///
+    /// By default, TurboJPEG compresses grayscale images and images with [`Subsamp::Gray`] into
+    /// [`Colorspace::Gray`], CMYK images into [`Colorspace::YCCK`] and all other images into
+    /// [`Colorspace::YCbCr`]. Setting the colorspace makes this choice explicit: with
+    /// [`Colorspace::Gray`], the subsampling is always [`Subsamp::Gray`], and images that cannot
+    /// be compressed into the requested colorspace are rejected with [`Error::Unsupported`]
+    /// instead of being silently converted.
+    ///
+    /// Note that the TurboJPEG 2 API that this crate uses cannot produce JPEG images in the
+    /// [`Colorspace::RGB`] and [`Colorspace::CMYK`] colorspaces (without the YCbCr or YCCK
+    /// conversion), so these are always rejected with [`Error::Unsupported`]. The colorspace of
+    /// JPEG images is reported in
+    /// [`DecompressHeader::colorspace`][crate::DecompressHeader::colorspace].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{Colorspace, PixelFormat};
+    ///
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, PixelFormat::RGB);
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_colorspace(Some(Colorspace::Gray));
+    /// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    /// assert_eq!(turbojpeg::read_header(&jpeg_data)?.colorspace, Colorspace::Gray);
+    ///
+    /// // CMYK pixels cannot be stored as YCbCr
+    /// let cmyk = turbojpeg::Image::mandelbrot(64, 64, PixelFormat::CMYK);
+    /// compressor.set_colorspace(Some(Colorspace::YCbCr));
+    /// let res = compressor.compress_to_vec(cmyk.as_deref());
+    /// assert!(matches!(res, Err(turbojpeg::Error::Unsupported(_))));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_colorspace(&mut self, colorspace: Option<Colorspace>) {
+        self.colorspace = colorspace;
+    }
+
+    /// Get the colorspace set by [`set_colorspace()`][Self::set_colorspace].
+    pub fn colorspace(&self) -> Option<Colorspace> {
+        self.colorspace
+    }
+
+    /// Enable or disable the sharp chrominance downsampling for images with [`Subsamp::Sub2x2`]
+    /// (disabled by default).
+    ///
+    /// With 4:2:0 subsampling, TurboJPEG averages the chrominance of every 2x2 block of pixels.
+    /// Near sharp edges between saturated colors (such as red text on a blue background in charts
+    /// and other graphics), the decompressed colors then bleed into each other and the edges look
+    /// dark and blurry. When this is enabled, the image is converted to YCbCr before compression
+    /// with an iterative algorithm (like the "sharp YUV" conversion of libwebp), which chooses the
+    /// luminance and chrominance so that the decompressed image is closer to the original. This
+    /// is several times slower than the conversion in TurboJPEG, and it is only applied to images
+    /// in the RGB pixel formats that are compressed with [`Subsamp::Sub2x2`]; other images are
+    /// compressed as usual.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{Image, PixelFormat, Subsamp};
+    ///
+    /// // red and blue stripes that are 2 pixels wide
+    /// let pixels: Vec<u8> = (0..64 * 64).flat_map(|i| if i % 4 < 2 { [255, 0, 0] } else { [0, 0, 255] }).collect();
+    /// let image = Image { pixels, width: 64, pitch: 64 * 3, height: 64, format: PixelFormat::RGB };
+    /// let error = |jpeg_data: &[u8]| -> turbojpeg::Result<u64> {
+    ///     let decompressed = turbojpeg::decompress(jpeg_data, PixelFormat::RGB)?;
+    ///     Ok(image.pixels.iter().zip(&decompressed.pixels).map(|(&a, &b)| a.abs_diff(b) as u64).sum())
+    /// };
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_quality(95)?;
+    /// compressor.set_subsamp(Subsamp::Sub2x2);
+    /// let plain = compressor.compress_to_vec(image.as_deref())?;
+    /// compressor.set_sharp_yuv(true);
+    /// let sharp = compressor.compress_to_vec(image.as_deref())?;
+    ///
+    /// assert_eq!(turbojpeg::read_header(&sharp)?.subsamp, Subsamp::Sub2x2);
+    /// assert!(error(&sharp)? < error(&plain)?);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_sharp_yuv(&mut self, sharp_yuv: bool) {
+        self.sharp_yuv = sharp_yuv;
+    }
+
+    /// Enable or disable progressive entropy coding of the compressed JPEG images.
+    ///
+    /// Progressive images are displayed in increasing quality while they are being downloaded and
+    /// they are usually smaller than baseline images (the default), but they are slower to
+    /// compress and decompress.
+    #[doc(alias = "TJFLAG_PROGRESSIVE")]
+    pub fn set_progressive(&mut self, progressive: bool) {
+        self.progressive = progressive;
+    }
+
+    /// Set the DCT algorithm used for compression (see [`DctMethod`]).
+    pub fn set_dct_method(&mut self, dct_method: DctMethod) {
+        self.dct_method = dct_method;
+    }
+
+    /// Set the token that can be used to cancel the compression (see [`CancelToken`]).
+    ///
+    /// Use `None` to remove the token.
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel = token;
+    }
+
+    /// Set the allocator for temporary buffers (see [`ImageAllocator`]).
+    ///
+    /// Use `None` to use the [`SystemAllocator`][crate::SystemAllocator].
+    pub fn set_allocator(&mut self, allocator: Option<Arc<dyn ImageAllocator>>) {
+        self.allocator = allocator;
+        self.scratch.release();
+    }
+
+    /// Reset all settings of this compressor to their defaults.
+    ///
+    /// This resets the quality, subsampling, progressive coding, DCT method, cancel token and
+    /// progress callback, so that the compressor can be reused for unrelated images (for example,
+    /// when it is returned into a pool). The TurboJPEG handle, the allocator and the temporary
+    /// buffer for methods like [`compress_with()`][Self::compress_with] are kept, so the next
+    /// compression does not need to allocate them again.
+    ///
+    /// Note that the compressor does not need to be reset when the size, pixel format or
+    /// subsampling of the images changes: TurboJPEG reuses the handle for images of any size, and
+    /// the temporary buffer is reused for all images that fit into it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_quality(20)?;
+    /// compressor.set_subsamp(turbojpeg::Subsamp::Gray);
+    ///
+    /// compressor.reset();
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+    /// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, turbojpeg::Subsamp::None);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn reset(&mut self) {
+        self.quality = DEFAULT_QUALITY;
+        self.subsamp = DEFAULT_SUBSAMP;
+        self.auto_subsamp = false;
+        self.colorspace = None;
+        self.sharp_yuv = false;
+        self.shared_preset = None;
+        self.overrides = None;
+        self.progressive = false;
+        self.dct_method = DctMethod::default();
+        self.cancel = None;
+        self.progress = None;
+    }
+
+    /// Returns true if the last compression failed with a fatal error of libjpeg.
+    ///
+    /// libjpeg may leave its state inconsistent after a fatal error, so the compressor does not
+    /// reuse the underlying TurboJPEG instance: it replaces the instance at the start of the next
+    /// compression (keeping all settings), which clears this flag.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Replaces the TurboJPEG instance if the last compression failed with a fatal error.
+    fn recover(&mut self) -> Result<()> {
+        if self.poisoned {
+            unsafe { recreate_handle(&mut self.handle, raw::tjInitCompress, "compressor")? };
+            self.poisoned = false;
+        }
+        Ok(())
+    }
+
+    /// Set the callback that receives the [`Progress`] of compression.
+    ///
+    /// TurboJPEG compresses the whole image in a single call, so the methods that compress a
+    /// complete image (such as [`compress()`][Self::compress]) report the progress only twice: with
+    /// no rows done before the call and with all rows done after it. The row-based methods
+    /// [`compress_with()`][Self::compress_with], [`compress_with_uninit()`][Self::compress_with_uninit]
+    /// and [`compress_rows()`][Self::compress_rows] also report the progress before every row that
+    /// they fill, and report all rows done only after the compression of the assembled image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// compressor.set_progress_callback(move |progress| sender.send(progress).unwrap());
+    ///
+    /// // the whole image is compressed in one call
+    /// let image = turbojpeg::Image::mandelbrot(500, 500, turbojpeg::PixelFormat::RGB);
+    /// compressor.compress_to_owned(image.as_deref())?;
+    /// let rows_done = receiver.try_iter().map(|progress| progress.rows_done).collect::<Vec<_>>();
+    /// assert_eq!(rows_done, [0, 500]);
+    ///
+    /// // the image is filled row by row
+    /// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+    /// compressor.compress_with(500, 500, turbojpeg::PixelFormat::RGB, &mut output_buf, |_y, row| {
+    ///     row.fill(128);
+    /// })?;
+    /// let rows_done = receiver.try_iter().map(|progress| progress.rows_done).collect::<Vec<_>>();
+    /// assert_eq!(rows_done, (0..=500).collect::<Vec<_>>());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+        where F: FnMut(Progress) + Send + 'static
+    {
+        self.progress = Some(ProgressCallback::new(callback));
+    }
+
+    /// Remove the callback set by [`set_progress_callback()`][Self::set_progress_callback].
+    pub fn remove_progress_callback(&mut self) {
+        self.progress = None;
+    }
+
+    /// Get the statistics of compressions made by this compressor (see [`Stats`]).
+    #[cfg(feature = "stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Reset the statistics returned by [`stats()`][Self::stats].
+    #[cfg(feature = "stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
     /// Compresses the `image` into `output` buffer.
     ///
     /// This is the main compression method, which gives you full control of the output buffer. If
@@ -64,7 +449,7 @@ impl Compressor {
     ///
     /// // initialize the compressor
     /// let mut compressor = turbojpeg::Compressor::new()?;
-    /// compressor.set_quality(70);
+    /// compressor.set_quality(70)?;
     /// compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
     ///
     /// // initialize the output buffer
@@ -82,26 +467,190 @@ impl Compressor {
     #[doc(alias = "tjCompress2")]
     #[doc(alias = "tjCompress")]
     pub fn compress(&mut self, image: Image<&[u8]>, output: &mut OutputBuf) -> Result<()> {
+        report_progress(&mut self.progress, 0, image.height);
+        self.compress_image(image, output)?;
+        report_progress(&mut self.progress, image.height, image.height);
+        Ok(())
+    }
+
+    fn compress_image(&mut self, image: Image<&[u8]>, output: &mut OutputBuf) -> Result<()> {
         image.assert_valid(image.pixels.len());
+        check_max_dimension(image.width, image.height)?;
+        check_cancel(&self.cancel)?;
+        self.recover()?;
+        let preset = self.effective_preset();
 
+        let subsamp = self.image_subsamp(image, preset.subsamp)?;
+        let planes = if self.sharp_yuv && subsamp == Subsamp::Sub2x2 { sharp_yuv_420(image) } else { None };
         let Image { pixels, width, pitch, height, format } = image;
         let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
         let pitch = pitch.try_into().map_err(|_| Error::IntegerOverflow("pitch"))?;
         let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "tjCompress2",
+            width, height, pitch, format = ?format, subsamp = ?subsamp, quality = preset.quality.get(),
+            progressive = preset.progressive, dct_method = ?preset.dct_method, sharp_yuv = planes.is_some(),
+        ).entered();
+        #[cfg(any(feature = "stats", feature = "tracing"))]
+        let start = std::time::Instant::now();
+
         let mut output_len = output.len as libc::c_ulong;
+        let permit = concurrency::acquire();
+        let flags = preset.dct_method.flags() |
+            if preset.progressive { raw::TJFLAG_PROGRESSIVE as libc::c_int } else { 0 } |
+            if output.is_owned { 0 } else { raw::TJFLAG_NOREALLOC } as libc::c_int;
         let res = unsafe {
-            raw::tjCompress2(
+            match planes {
+                Some(ref planes) => {
+                    let mut plane_ptrs = [planes.y.as_ptr(), planes.cb.as_ptr(), planes.cr.as_ptr()];
+                    let strides = [planes.y_stride, planes.c_stride, planes.c_stride].map(|stride| stride as libc::c_int);
+                    raw::tjCompressFromYUVPlanes(
+                        self.handle,
+                        plane_ptrs.as_mut_ptr(), width, strides.as_ptr(), height,
+                        subsamp as libc::c_int,
+                        &mut output.ptr, &mut output_len,
+                        preset.quality.get(), flags,
+                    )
+                },
+                None => raw::tjCompress2(
+                    self.handle,
+                    pixels.as_ptr(), width, pitch, height, format as libc::c_int,
+                    &mut output.ptr, &mut output_len,
+                    subsamp as libc::c_int, preset.quality.get(), flags,
+                ),
+            }
+        };
+        drop(permit);
+        output.len = output_len as usize;
+        #[cfg(feature = "tracing")]
+        crate::trace::call_finished(self.handle, res, start, output.len);
+
+        if res != 0 {
+            self.poisoned = unsafe { is_fatal_error(self.handle) };
+            Err(unsafe { get_error(self.handle) })
+        } else if output.ptr.is_null() {
+            output.len = 0;
+            Err(Error::Null())
+        } else {
+            #[cfg(feature = "stats")]
+            self.stats.record(start.elapsed(), pixels.len(), output.len, image.width * image.height);
+            Ok(())
+        }
+    }
+
+    /// Returns the settings for one compression: the shared preset (or the settings of this
+    /// compressor), with the per-call overrides applied.
+    fn effective_preset(&self) -> CompressorPreset {
+        let mut preset = match &self.shared_preset {
+            Some(shared) => shared.load(),
+            None => self.current_preset(),
+        };
+        if let Some(overrides) = self.overrides {
+            overrides.apply(&mut preset);
+        }
+        preset
+    }
+
+    /// Returns the chrominance subsampling that is used to compress `image`.
+    fn image_subsamp(&self, image: Image<&[u8]>, subsamp: Subsamp) -> Result<Subsamp> {
+        let subsamp = if self.auto_subsamp { choose_subsamp(image) } else { subsamp };
+        colorspace_subsamp(self.colorspace, image.format, subsamp)
+    }
+
+    /// Converts `image` into YUV planes with the settings of this compressor, so that the planes
+    /// can be compressed several times (with [`compress_planes()`][Self::compress_planes])
+    /// without repeating the color conversion.
+    pub(crate) fn encode_planes(&mut self, image: Image<&[u8]>) -> Result<(YuvPlanes, Subsamp)> {
+        image.assert_valid(image.pixels.len());
+        check_max_dimension(image.width, image.height)?;
+        check_cancel(&self.cancel)?;
+        self.recover()?;
+        let preset = self.effective_preset();
+
+        let subsamp = self.image_subsamp(image, preset.subsamp)?;
+        if self.sharp_yuv && subsamp == Subsamp::Sub2x2 {
+            if let Some(planes) = sharp_yuv_420(image) {
+                return Ok((planes, subsamp))
+            }
+        }
+        let Image { pixels, width, pitch, height, format } = image;
+        let width: libc::c_int = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+        let pitch: libc::c_int = pitch.try_into().map_err(|_| Error::IntegerOverflow("pitch"))?;
+        let height: libc::c_int = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+        let plane_size = |component| unsafe {
+            let plane_width = raw::tjPlaneWidth(component, width, subsamp as libc::c_int);
+            let plane_height = raw::tjPlaneHeight(component, height, subsamp as libc::c_int);
+            (plane_width.max(0) as usize, plane_height.max(0) as usize)
+        };
+        let (y_stride, y_height) = plane_size(0);
+        let (c_stride, c_height) = if subsamp == Subsamp::Gray { (0, 0) } else { plane_size(1) };
+        let mut planes = YuvPlanes {
+            y: vec![0; y_stride * y_height],
+            cb: vec![0; c_stride * c_height],
+            cr: vec![0; c_stride * c_height],
+            y_stride,
+            c_stride,
+        };
+
+        let permit = concurrency::acquire();
+        let res = unsafe {
+            let mut plane_ptrs = [planes.y.as_mut_ptr(), planes.cb.as_mut_ptr(), planes.cr.as_mut_ptr()];
+            let mut strides = [y_stride, c_stride, c_stride].map(|stride| stride as libc::c_int);
+            raw::tjEncodeYUVPlanes(
                 self.handle,
                 pixels.as_ptr(), width, pitch, height, format as libc::c_int,
+                plane_ptrs.as_mut_ptr(), strides.as_mut_ptr(),
+                subsamp as libc::c_int, preset.dct_method.flags(),
+            )
+        };
+        drop(permit);
+        if res != 0 {
+            self.poisoned = unsafe { is_fatal_error(self.handle) };
+            return Err(unsafe { get_error(self.handle) })
+        }
+        Ok((planes, subsamp))
+    }
+
+    /// Compresses YUV planes from [`encode_planes()`][Self::encode_planes] with the given
+    /// `quality` and the other settings of this compressor.
+    pub(crate) fn compress_planes(
+        &mut self,
+        planes: &YuvPlanes,
+        width: usize,
+        height: usize,
+        subsamp: Subsamp,
+        quality: Quality,
+        output: &mut OutputBuf,
+    ) -> Result<()> {
+        check_cancel(&self.cancel)?;
+        self.recover()?;
+        let preset = self.effective_preset();
+        let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+        let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+
+        let mut output_len = output.len as libc::c_ulong;
+        let permit = concurrency::acquire();
+        let flags = preset.dct_method.flags() |
+            if preset.progressive { raw::TJFLAG_PROGRESSIVE as libc::c_int } else { 0 } |
+            if output.is_owned { 0 } else { raw::TJFLAG_NOREALLOC } as libc::c_int;
+        let res = unsafe {
+            let mut plane_ptrs = [planes.y.as_ptr(), planes.cb.as_ptr(), planes.cr.as_ptr()];
+            let strides = [planes.y_stride, planes.c_stride, planes.c_stride].map(|stride| stride as libc::c_int);
+            raw::tjCompressFromYUVPlanes(
+                self.handle,
+                plane_ptrs.as_mut_ptr(), width, strides.as_ptr(), height,
+                subsamp as libc::c_int,
                 &mut output.ptr, &mut output_len,
-                self.subsamp as libc::c_int, self.quality,
-                if output.is_owned { 0 } else { raw::TJFLAG_NOREALLOC } as libc::c_int,
+                quality.get(), flags,
             )
         };
+        drop(permit);
         output.len = output_len as usize;
 
         if res != 0 {
+            self.poisoned = unsafe { is_fatal_error(self.handle) };
             Err(unsafe { get_error(self.handle) })
         } else if output.ptr.is_null() {
             output.len = 0;
@@ -111,6 +660,44 @@ impl Compressor {
         }
     }
 
+    /// Compresses the rectangular part of the `image` with top left corner at (`x`, `y`) and the
+    /// given `width` and `height` into `output` buffer.
+    ///
+    /// The rectangle is passed to TurboJPEG directly as a pointer into the pixels of `image` with
+    /// the pitch of `image` (see [`Image::subimage()`]), so the pixels are not copied. This is
+    /// useful for screen capture and remote desktop tools, which encode only the dirty rectangles
+    /// of a large frame buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not fit into the image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let frame = turbojpeg::Image::mandelbrot(1920, 1080, turbojpeg::PixelFormat::BGRA);
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+    /// compressor.compress_region(frame.as_deref(), 640, 360, 320, 240, &mut output_buf)?;
+    ///
+    /// let header = turbojpeg::read_header(&output_buf)?;
+    /// assert_eq!((header.width, header.height), (320, 240));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn compress_region(
+        &mut self,
+        image: Image<&[u8]>,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        output: &mut OutputBuf,
+    ) -> Result<()> {
+        self.compress(image.subimage(x, y, width, height), output)
+    }
+
     /// Compresses the `image` into an owned buffer.
     ///
     /// This method automatically allocates the memory and avoids needless copying.
@@ -142,15 +729,326 @@ impl Compressor {
         Ok(buf.len())
     }
 
+    /// Compress an image from raw memory into raw memory.
+    ///
+    /// This is a variant of [`compress_to_slice()`][Self::compress_to_slice] for callers that
+    /// integrate with foreign memory (such as direct buffers from JNI or buffers from Python),
+    /// where creating slices would require promises that the caller cannot check, such as that
+    /// the output memory is initialized. The pixels are read from `image_len` bytes at
+    /// `image.pixels` and the JPEG data is written into the `output_len` bytes at `output_ptr`.
+    ///
+    /// Returns the size of the compressed JPEG data. If the compressed image does not fit into
+    /// the output, this method returns an error. Use [`buf_len()`](Compressor::buf_len) to
+    /// determine the output size that is guaranteed to be large enough.
+    ///
+    /// # Safety
+    ///
+    /// - `image.pixels` must be valid for reads of `image_len` bytes, and the memory must be
+    ///   initialized and must not be modified during the call.
+    /// - `output_ptr` must be valid for writes of `output_len` bytes (the memory may be
+    ///   uninitialized), must not overlap the image, and must not be accessed by other threads
+    ///   during the call. Only the returned number of bytes is initialized afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image_len` is too small for the size, pitch and pixel format of `image`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    ///
+    /// // for example, memory allocated by a foreign runtime
+    /// let capacity = compressor.buf_len(64, 64)?;
+    /// let mut foreign = Vec::<u8>::with_capacity(capacity);
+    /// let raw_image = turbojpeg::Image {
+    ///     pixels: image.pixels.as_ptr(),
+    ///     width: image.width,
+    ///     pitch: image.pitch,
+    ///     height: image.height,
+    ///     format: image.format,
+    /// };
+    /// unsafe {
+    ///     let len = compressor.compress_raw(raw_image, image.pixels.len(), foreign.as_mut_ptr(), capacity)?;
+    ///     foreign.set_len(len);
+    /// }
+    /// assert_eq!(turbojpeg::read_header(&foreign)?.width, 64);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[doc(alias = "tjCompress2")]
+    pub unsafe fn compress_raw(
+        &mut self,
+        image: Image<*const u8>,
+        image_len: usize,
+        output_ptr: *mut u8,
+        output_len: usize,
+    ) -> Result<usize> {
+        let Image { pixels, width, pitch, height, format } = image;
+        let image = Image { pixels: deref(pixels, image_len), width, pitch, height, format };
+        let mut buf = OutputBuf {
+            ptr: output_ptr,
+            len: output_len,
+            is_owned: false,
+            _phantom: std::marker::PhantomData,
+        };
+        self.compress(image, &mut buf)?;
+        Ok(buf.len())
+    }
+
+    /// Compresses an image whose rows are generated by the callback `fill_row`.
+    ///
+    /// The image has the given `width`, `height` and pixel `format`. The callback is called once
+    /// for every row, in order from top to bottom, with the row index `y` and a slice of length
+    /// `width * format.size()`, and it should write the pixels of row `y` into the slice. This is
+    /// useful for procedurally generated or streamed images, because you don't need to manage the
+    /// pixel buffer yourself.
+    ///
+    /// Note that TurboJPEG needs the complete image before it can start the compression, so this
+    /// method still needs a temporary buffer for the whole image. The buffer is kept in the
+    /// compressor and reused by the next calls, so it is allocated again only for a larger image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // initialize the compressor
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    ///
+    /// // compress a gradient, generating the rows on the fly
+    /// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+    /// compressor.compress_with(256, 100, turbojpeg::PixelFormat::RGB, &mut output_buf, |y, row| {
+    ///     for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+    ///         pixel.copy_from_slice(&[x as u8, y as u8, 128]);
+    ///     }
+    /// })?;
+    ///
+    /// let header = turbojpeg::read_header(&output_buf)?;
+    /// assert_eq!((header.width, header.height), (256, 100));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn compress_with<F>(
+        &mut self,
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        output: &mut OutputBuf,
+        mut fill_row: F,
+    ) -> Result<()>
+        where F: FnMut(usize, &mut [u8])
+    {
+        self.compress_scratch(width, height, format, output, |y, row| {
+            // the buffer may contain pixels of the previous image
+            row.fill(0);
+            fill_row(y, row);
+        })
+    }
+
+    /// Compresses an image whose rows are generated by the callback `fill_row` into uninitialized
+    /// rows.
+    ///
+    /// This works like [`compress_with()`][Self::compress_with], but the rows are not zeroed
+    /// before they are passed to `fill_row`, which saves a pass over the temporary buffer when
+    /// the callback overwrites the whole row anyway (for example, when it copies the pixels from a
+    /// camera frame).
+    ///
+    /// # Safety
+    ///
+    /// `fill_row` must initialize every byte of the `row` that it receives (the slice has exactly
+    /// `width * format.size()` bytes). TurboJPEG reads all bytes of the image, so any byte left
+    /// uninitialized (or overwritten with [`MaybeUninit::uninit()`]) is undefined behavior.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+    /// unsafe {
+    ///     compressor.compress_with_uninit(256, 100, turbojpeg::PixelFormat::RGB, &mut output_buf, |y, row| {
+    ///         for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+    ///             pixel[0].write(x as u8);
+    ///             pixel[1].write(y as u8);
+    ///             pixel[2].write(128);
+    ///         }
+    ///     })?;
+    /// }
+    ///
+    /// let header = turbojpeg::read_header(&output_buf)?;
+    /// assert_eq!((header.width, header.height), (256, 100));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub unsafe fn compress_with_uninit<F>(
+        &mut self,
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        output: &mut OutputBuf,
+        mut fill_row: F,
+    ) -> Result<()>
+        where F: FnMut(usize, &mut [MaybeUninit<u8>])
+    {
+        self.compress_scratch(width, height, format, output, |y, row| {
+            let row = std::slice::from_raw_parts_mut(row.as_mut_ptr() as *mut MaybeUninit<u8>, row.len());
+            fill_row(y, row);
+        })
+    }
+
+    /// Compresses an image whose rows are filled by `fill_row` in the temporary buffer, which
+    /// contains the pixels of the previous image.
+    fn compress_scratch<F>(
+        &mut self,
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        output: &mut OutputBuf,
+        mut fill_row: F,
+    ) -> Result<()>
+        where F: FnMut(usize, &mut [u8])
+    {
+        let pitch = width.checked_mul(format.size()).ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(height).ok_or(Error::IntegerOverflow("pixels len"))?;
+        let mut pixels = self.scratch.take(&self.allocator, len)?;
+        let res = (|| {
+            if pitch != 0 {
+                for (y, row) in pixels[..len].chunks_exact_mut(pitch).enumerate() {
+                    check_cancel(&self.cancel)?;
+                    report_progress(&mut self.progress, y, height);
+                    fill_row(y, row);
+                }
+            }
+
+            let image = Image { pixels: &pixels[..len], width, pitch, height, format };
+            self.compress_image(image, output)?;
+            report_progress(&mut self.progress, height, height);
+            Ok(())
+        })();
+        self.scratch.put(pixels);
+        res
+    }
+
+    /// Compresses an image whose rows are scattered in memory.
+    ///
+    /// The iterator `rows` yields the rows of the image from top to bottom, and the number of rows
+    /// is the height of the image. Every row must contain at least `width * format.size()` bytes
+    /// (any bytes after that are ignored). This is useful when the image is stored in a tiled or
+    /// rope-like buffer, or when the rows come from different images.
+    ///
+    /// The rows are assembled into a temporary contiguous buffer (allocated by the allocator of
+    /// this compressor), because TurboJPEG needs the complete image before it can start the
+    /// compression (see [`compress_with()`][Self::compress_with]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a row is too short or if the iterator yields fewer rows than its `len()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let top = turbojpeg::Image::mandelbrot(200, 50, turbojpeg::PixelFormat::RGB);
+    /// let bottom = turbojpeg::Image::mandelbrot(200, 70, turbojpeg::PixelFormat::RGB);
+    ///
+    /// // compress the two images stacked on top of each other, without copying them into a
+    /// // single image first
+    /// let rows = top.pixels.chunks_exact(top.pitch).chain(bottom.pixels.chunks_exact(bottom.pitch));
+    /// let rows = rows.collect::<Vec<_>>();
+    ///
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// let mut output_buf = turbojpeg::OutputBuf::new_owned();
+    /// compressor.compress_rows(200, turbojpeg::PixelFormat::RGB, rows, &mut output_buf)?;
+    ///
+    /// let header = turbojpeg::read_header(&output_buf)?;
+    /// assert_eq!((header.width, header.height), (200, 120));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn compress_rows<'a, I>(
+        &mut self,
+        width: usize,
+        format: PixelFormat,
+        rows: I,
+        output: &mut OutputBuf,
+    ) -> Result<()>
+        where I: IntoIterator<Item = &'a [u8]>, I::IntoIter: ExactSizeIterator
+    {
+        let mut rows = rows.into_iter();
+        let height = rows.len();
+        self.compress_with(width, height, format, output, |y, row| {
+            let src = rows.next()
+                .unwrap_or_else(|| panic!("iterator yielded only {} rows out of {}", y, height));
+            assert!(src.len() >= row.len(),
+                "row {} has length {}, but width {} and pixel format {:?} need {} bytes",
+                y, src.len(), width, format, row.len());
+            row.copy_from_slice(&src[..row.len()]);
+        })
+    }
+
+    /// Prepare this compressor for compressing images with the given size and pixel format.
+    ///
+    /// The first compression with a new compressor is slower than the following ones, because
+    /// TurboJPEG detects the CPU features, the code and lookup tables are paged into memory and
+    /// the buffers are allocated. This method does all of that up front by compressing a black
+    /// image of the given size with the current settings, and it also allocates the temporary
+    /// buffer used by [`compress_with()`][Self::compress_with] and
+    /// [`compress_rows()`][Self::compress_rows]. Call it during the initialization of real-time
+    /// systems (such as video streaming or robotics) to avoid a latency spike on the first frame.
+    ///
+    /// The compressed image is discarded, and it is not counted in the statistics or reported to
+    /// the progress callback. To avoid allocating the output buffer on every compression, compress
+    /// into a buffer of size [`buf_len()`][Self::buf_len] with
+    /// [`compress_to_slice()`][Self::compress_to_slice].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut compressor = turbojpeg::Compressor::new()?;
+    /// compressor.set_quality(80)?;
+    /// compressor.warmup(640, 480, turbojpeg::PixelFormat::RGB)?;
+    ///
+    /// let mut output = vec![0; compressor.buf_len(640, 480)?];
+    /// let frame = turbojpeg::Image::mandelbrot(640, 480, turbojpeg::PixelFormat::RGB);
+    /// let len = compressor.compress_to_slice(frame.as_deref(), &mut output)?;
+    /// assert!(len > 0);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn warmup(&mut self, width: usize, height: usize, format: PixelFormat) -> Result<()> {
+        let pitch = width.checked_mul(format.size()).ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(height).ok_or(Error::IntegerOverflow("pixels len"))?;
+        let mut pixels = self.scratch.take(&self.allocator, len)?;
+        // zeroing the buffer also makes sure that the memory is actually mapped
+        pixels[..len].fill(0);
+
+        #[cfg(feature = "stats")]
+        let stats = self.stats;
+        let image = Image { pixels: &pixels[..len], width, pitch, height, format };
+        let res = self.compress_image(image, &mut OutputBuf::new_owned());
+        #[cfg(feature = "stats")]
+        { self.stats = stats; }
+
+        self.scratch.put(pixels);
+        res
+    }
+
     /// Compute the maximum size of a compressed image.
     ///
     /// This depends on image `width` and `height`, and also on the current setting of chrominance
-    /// subsampling (see [`set_subsamp()`](Compressor::set_subsamp)).
+    /// subsampling (see [`set_subsamp()`](Compressor::set_subsamp)). With automatic subsampling
+    /// (see [`set_auto_subsamp()`](Compressor::set_auto_subsamp)), this is the maximum over the
+    /// subsampling options that can be selected.
     ///
     /// You can also use [`compressed_buf_len()`] directly.
     #[doc(alias = "tjBufSize")]
     pub fn buf_len(&self, width: usize, height: usize) -> Result<usize> {
-        super::compressed_buf_len(width, height, self.subsamp)
+        if self.auto_subsamp || self.shared_preset.is_some() {
+            // the subsampling is chosen for every image
+            let len_444 = super::compressed_buf_len(width, height, Subsamp::None)?;
+            let len_420 = super::compressed_buf_len(width, height, Subsamp::Sub2x2)?;
+            Ok(len_444.max(len_420))
+        } else {
+            super::compressed_buf_len(width, height, self.subsamp)
+        }
     }
 }
 
@@ -160,6 +1058,35 @@ impl Drop for Compressor {
     }
 }
 
+/// Restores the settings saved by [`Compressor::with_overrides()`] when it is dropped.
+struct OverrideGuard<'a> {
+    compressor: &'a mut Compressor,
+    preset: CompressorPreset,
+    overrides: Option<CompressOverrides>,
+}
+
+impl Drop for OverrideGuard<'_> {
+    fn drop(&mut self) {
+        self.compressor.set_preset(self.preset);
+        self.compressor.overrides = self.overrides;
+    }
+}
+
+/// Returns the subsampling that produces a JPEG image in `colorspace` from pixels in `format`, or
+/// an error if TurboJPEG cannot produce such image.
+fn colorspace_subsamp(colorspace: Option<Colorspace>, format: PixelFormat, subsamp: Subsamp) -> Result<Subsamp> {
+    let cmyk = format == PixelFormat::CMYK;
+    match colorspace {
+        None => Ok(subsamp),
+        Some(Colorspace::Gray) if !cmyk => Ok(Subsamp::Gray),
+        Some(Colorspace::YCbCr) if !cmyk && subsamp != Subsamp::Gray => Ok(subsamp),
+        Some(Colorspace::YCCK) if cmyk && subsamp != Subsamp::Gray => Ok(subsamp),
+        Some(Colorspace::RGB) | Some(Colorspace::CMYK) =>
+            Err(Error::Unsupported("RGB and CMYK JPEG colorspaces require the TurboJPEG 3 API")),
+        Some(_) => Err(Error::Unsupported("colorspace is not compatible with the pixel format or subsampling")),
+    }
+}
+
 /// Compress a JPEG image.
 /// 
 /// Uses the given quality and chrominance subsampling option and returns the JPEG data in a buffer
@@ -180,9 +1107,9 @@ impl Drop for Compressor {
 ///
 /// # Ok::<(), Box<dyn std::error::Error>>(())
 /// ```
-pub fn compress(image: Image<&[u8]>, quality: i32, subsamp: Subsamp) -> Result<OwnedBuf> {
+pub fn compress(image: Image<&[u8]>, quality: impl TryInto<Quality, Error: Into<Error>>, subsamp: Subsamp) -> Result<OwnedBuf> {
     let mut compressor = Compressor::new()?;
-    compressor.set_quality(quality);
+    compressor.set_quality(quality)?;
     compressor.set_subsamp(subsamp);
     compressor.compress_to_owned(image)
 }
@@ -0,0 +1,251 @@
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use crate::fork;
+
+/// Priority of TurboJPEG calls under the [concurrency limit][set_concurrency_limit].
+///
+/// When the number of running calls reaches the concurrency limit, the waiting
+/// [`Interactive`][Self::Interactive] calls always get the next free permit before the waiting
+/// [`Batch`][Self::Batch] calls. The batch calls can also be limited separately with
+/// [`set_batch_concurrency_limit()`], which keeps some cores free for the interactive calls. This
+/// lets a server generate thumbnails in the background without delaying the encoding of live
+/// previews.
+///
+/// The priority is set per thread with [`set_thread_priority()`] (or with
+/// [`Pipeline::priority()`][crate::Pipeline::priority] for the worker threads of a pipeline), so
+/// a thread pool for background work sets it once for each of its threads.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::Priority;
+///
+/// // at most 3 thumbnails are decoded at the same time, even if more cores are available
+/// turbojpeg::set_batch_concurrency_limit(Some(3));
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// std::thread::scope(|scope| {
+///     for _ in 0..8 {
+///         scope.spawn(|| {
+///             turbojpeg::set_thread_priority(Priority::Batch);
+///             turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB).unwrap();
+///         });
+///     }
+///
+///     // this call gets the next free permit before the waiting thumbnails
+///     assert_eq!(turbojpeg::thread_priority(), Priority::Interactive);
+///     turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB).unwrap();
+/// });
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Priority {
+    /// Latency-critical work, such as live previews (the default).
+    #[default]
+    Interactive,
+    /// Background work, such as generating thumbnails.
+    Batch,
+}
+
+thread_local! {
+    static PRIORITY: Cell<Priority> = const { Cell::new(Priority::Interactive) };
+}
+
+/// Set the [`Priority`] of the TurboJPEG calls made by the current thread.
+pub fn set_thread_priority(priority: Priority) {
+    PRIORITY.with(|cell| cell.set(priority));
+}
+
+/// Get the [`Priority`] of the TurboJPEG calls made by the current thread.
+pub fn thread_priority() -> Priority {
+    PRIORITY.with(|cell| cell.get())
+}
+
+/// Limits the number of TurboJPEG calls that run at the same time in this process.
+struct Limiter {
+    state: Mutex<LimiterState>,
+    released: Condvar,
+}
+
+struct LimiterState {
+    /// Whether `limit` was set (the default limit is computed lazily).
+    initialized: bool,
+    /// Maximal number of active calls (`None` means unlimited).
+    limit: Option<usize>,
+    /// Maximal number of active calls with [`Priority::Batch`].
+    batch_limit: Option<usize>,
+    /// Number of active calls.
+    active: usize,
+    /// Number of active calls with [`Priority::Batch`].
+    active_batch: usize,
+    /// Number of calls with [`Priority::Interactive`] that wait for a permit.
+    waiting_interactive: usize,
+    /// [Fork generation][fork::generation] of the counters above.
+    generation: u64,
+}
+
+impl LimiterState {
+    fn can_run(&self, priority: Priority) -> bool {
+        let below_limit = |limit: Option<usize>, active| limit.is_none_or(|limit| active < limit);
+        below_limit(self.limit, self.active) && match priority {
+            Priority::Interactive => true,
+            Priority::Batch => self.waiting_interactive == 0 && below_limit(self.batch_limit, self.active_batch),
+        }
+    }
+}
+
+static LIMITER: Limiter = Limiter {
+    state: Mutex::new(LimiterState {
+        initialized: false,
+        limit: None,
+        batch_limit: None,
+        active: 0,
+        active_batch: 0,
+        waiting_interactive: 0,
+        generation: 0,
+    }),
+    released: Condvar::new(),
+};
+
+impl Limiter {
+    fn lock(&self) -> MutexGuard<'_, LimiterState> {
+        // the state is always consistent, so a poisoned lock can be used
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        if !state.initialized {
+            state.initialized = true;
+            state.limit = Some(thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+        }
+        let generation = fork::generation();
+        if state.generation != generation {
+            // the calls that held or waited for the permits run in threads of the parent process,
+            // which do not exist in this process
+            state.active = 0;
+            state.active_batch = 0;
+            state.waiting_interactive = 0;
+            state.generation = generation;
+        }
+        state
+    }
+}
+
+/// Set the maximal number of compressions, decompressions and transforms that run in TurboJPEG
+/// at the same time in this process.
+///
+/// Every call into TurboJPEG that does the heavy work (`tjCompress2()`, `tjDecompress2()`,
+/// `tjDecompressToYUV2()` and `tjTransform()`) holds one permit of a process-wide semaphore. When
+/// all permits are taken, the calling thread blocks until another call finishes. This prevents
+/// oversubscription of the CPU when the crate is used from several thread pools at the same time
+/// (for example, from an async executor and from rayon), where each pool alone would use all the
+/// cores. Reading headers and other cheap calls are not limited.
+///
+/// The default limit is [`std::thread::available_parallelism()`]. Use `None` to remove the
+/// limit. If the limit is lowered while more calls are running, the new calls wait until the
+/// number of running calls drops below the new limit.
+///
+/// Note that the waiting thread is blocked, so in async code, call TurboJPEG from a thread that
+/// may block (such as `tokio::task::spawn_blocking()`).
+///
+/// # Panics
+///
+/// Panics if the limit is `Some(0)`.
+///
+/// # Example
+///
+/// ```
+/// // allow at most two concurrent compressions and decompressions
+/// turbojpeg::set_concurrency_limit(Some(2));
+/// assert_eq!(turbojpeg::concurrency_limit(), Some(2));
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// std::thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB).unwrap());
+///     }
+/// });
+///
+/// turbojpeg::set_concurrency_limit(None);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn set_concurrency_limit(limit: Option<usize>) {
+    assert!(limit != Some(0), "concurrency limit must not be zero");
+    LIMITER.lock().limit = limit;
+    LIMITER.released.notify_all();
+}
+
+/// Get the maximal number of TurboJPEG calls that run at the same time in this process (see
+/// [`set_concurrency_limit()`]).
+pub fn concurrency_limit() -> Option<usize> {
+    LIMITER.lock().limit
+}
+
+/// Set the maximal number of TurboJPEG calls with [`Priority::Batch`] that run at the same time
+/// in this process.
+///
+/// This limit applies in addition to [`set_concurrency_limit()`]. Setting it below the overall
+/// limit reserves the remaining permits for the [`Priority::Interactive`] calls, so they do not
+/// have to wait until a batch call finishes. The default is `None` (only the overall limit
+/// applies).
+///
+/// # Panics
+///
+/// Panics if the limit is `Some(0)`.
+pub fn set_batch_concurrency_limit(limit: Option<usize>) {
+    assert!(limit != Some(0), "concurrency limit must not be zero");
+    LIMITER.lock().batch_limit = limit;
+    LIMITER.released.notify_all();
+}
+
+/// Get the maximal number of TurboJPEG calls with [`Priority::Batch`] that run at the same time
+/// in this process (see [`set_batch_concurrency_limit()`]).
+pub fn batch_concurrency_limit() -> Option<usize> {
+    LIMITER.lock().batch_limit
+}
+
+/// Permit to run one TurboJPEG call, which is released when dropped.
+pub(crate) struct Permit(Priority, u64);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = LIMITER.lock();
+        if state.generation != self.1 {
+            // the permit was acquired before a fork and the counters were reset
+            return
+        }
+        state.active -= 1;
+        if self.0 == Priority::Batch {
+            state.active_batch -= 1;
+        }
+        drop(state);
+        // the waiting calls have different conditions, so all of them must be woken up
+        LIMITER.released.notify_all();
+    }
+}
+
+/// Waits until a TurboJPEG call can run under the concurrency limit.
+///
+/// The permit must be held only for the duration of the call into TurboJPEG, not while calling
+/// user code, which might call TurboJPEG again and deadlock.
+pub(crate) fn acquire() -> Permit {
+    let priority = thread_priority();
+    let mut state = LIMITER.lock();
+    if !state.can_run(priority) {
+        if priority == Priority::Interactive {
+            state.waiting_interactive += 1;
+        }
+        while !state.can_run(priority) {
+            state = LIMITER.released.wait(state).unwrap_or_else(|err| err.into_inner());
+        }
+        if priority == Priority::Interactive {
+            state.waiting_interactive -= 1;
+        }
+    }
+    state.active += 1;
+    if priority == Priority::Batch {
+        state.active_batch += 1;
+    }
+    Permit(priority, state.generation)
+}
@@ -0,0 +1,117 @@
+use crate::Image;
+
+/// Strength of the deblocking filter applied after decompression.
+///
+/// JPEG compresses every 8x8 block of pixels independently, so images compressed with low quality
+/// show visible steps on the block boundaries ("blocking artifacts"), which become even more
+/// visible when the image is upscaled. The deblocking filter smooths small steps across the block
+/// boundaries, while it leaves large steps (which are most likely real edges in the image) intact.
+/// Use [`Decompressor::set_deblock()`][crate::Decompressor::set_deblock] to enable the filter.
+///
+/// Stronger levels remove larger steps, but they also blur more of the fine texture that happens
+/// to lie on the block boundaries. The filter is not useful for images compressed with high
+/// quality (90 or more), which do not have visible blocking artifacts.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeblockLevel {
+    /// No filtering (the default).
+    #[default]
+    Off,
+    /// Smooth only the pixels next to the block boundaries, and only very small steps.
+    Low,
+    /// Smooth the pixels next to the block boundaries.
+    Medium,
+    /// Smooth two pixels on each side of the block boundaries, including larger steps. This is
+    /// suitable for images compressed with very low quality.
+    High,
+}
+
+/// Parameters of the filter for one level.
+#[derive(Debug, Copy, Clone)]
+struct Thresholds {
+    /// Maximal step across the boundary that is smoothed.
+    alpha: i32,
+    /// Maximal step between the pixels on the same side of the boundary.
+    beta: i32,
+    /// Maximal change of a pixel.
+    tc: i32,
+    /// Whether the second pixel on each side is also modified.
+    wide: bool,
+}
+
+impl DeblockLevel {
+    fn thresholds(self) -> Option<Thresholds> {
+        match self {
+            DeblockLevel::Off => None,
+            DeblockLevel::Low => Some(Thresholds { alpha: 6, beta: 2, tc: 2, wide: false }),
+            DeblockLevel::Medium => Some(Thresholds { alpha: 12, beta: 4, tc: 4, wide: false }),
+            DeblockLevel::High => Some(Thresholds { alpha: 24, beta: 8, tc: 8, wide: true }),
+        }
+    }
+}
+
+/// Applies the deblocking filter on the boundaries of blocks of `block_size` pixels.
+///
+/// All channels of the pixels are filtered independently (the filter does not change channels
+/// that are constant, such as an opaque alpha channel).
+pub(crate) fn deblock(image: Image<&mut [u8]>, block_size: usize, level: DeblockLevel) {
+    let thresholds = match level.thresholds() {
+        Some(thresholds) => thresholds,
+        None => return,
+    };
+    if block_size < 2 {
+        return
+    }
+
+    let Image { pixels, width, pitch, height, format } = image;
+    let pixel_size = format.size();
+    let row_len = width * pixel_size;
+
+    // vertical block boundaries, between columns x - 1 and x
+    for y in 0..height {
+        let row = &mut pixels[y*pitch..][..row_len];
+        for x in (block_size..width.saturating_sub(1)).step_by(block_size) {
+            let (left, right) = row.split_at_mut(x * pixel_size);
+            let (left1, left0) = left.split_at_mut((x - 1) * pixel_size);
+            let left1 = &mut left1[(x - 2) * pixel_size..];
+            let (right0, right1) = right.split_at_mut(pixel_size);
+            for c in 0..pixel_size {
+                filter_edge(&mut left1[c], &mut left0[c], &mut right0[c], &mut right1[c], thresholds);
+            }
+        }
+    }
+
+    // horizontal block boundaries, between rows y - 1 and y; the rows are processed as a whole,
+    // so the compiler can vectorize the loop
+    for y in (block_size..height.saturating_sub(1)).step_by(block_size) {
+        let rows = &mut pixels[(y - 2)*pitch..];
+        let (above1, rest) = rows.split_at_mut(pitch);
+        let (above0, rest) = rest.split_at_mut(pitch);
+        let (below0, below1) = rest.split_at_mut(pitch);
+        let pixels = above1[..row_len].iter_mut()
+            .zip(above0[..row_len].iter_mut())
+            .zip(below0[..row_len].iter_mut())
+            .zip(below1[..row_len].iter_mut());
+        for (((p1, p0), q0), q1) in pixels {
+            filter_edge(p1, p0, q0, q1, thresholds);
+        }
+    }
+}
+
+/// Filters one boundary between pixels `p0` and `q0` (`p1` and `q1` are their neighbors on the
+/// other side).
+#[inline(always)]
+fn filter_edge(p1: &mut u8, p0: &mut u8, q0: &mut u8, q1: &mut u8, t: Thresholds) {
+    let (vp1, vp0, vq0, vq1) = (*p1 as i32, *p0 as i32, *q0 as i32, *q1 as i32);
+    if (vp0 - vq0).abs() >= t.alpha || (vp1 - vp0).abs() >= t.beta || (vq1 - vq0).abs() >= t.beta {
+        return
+    }
+
+    let delta = (((vq0 - vp0) * 4 + (vp1 - vq1) + 4) >> 3).clamp(-t.tc, t.tc);
+    *p0 = (vp0 + delta).clamp(0, 255) as u8;
+    *q0 = (vq0 - delta).clamp(0, 255) as u8;
+    if t.wide {
+        *p1 = (vp1 + delta / 2).clamp(0, 255) as u8;
+        *q1 = (vq1 - delta / 2).clamp(0, 255) as u8;
+    }
+}
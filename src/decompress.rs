@@ -1,12 +1,44 @@
 use std::convert::TryInto as _;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
 use crate::{Image, YuvImage, raw};
-use crate::common::{PixelFormat, Subsamp, Colorspace, Result, Error, get_error};
+use crate::alloc::{ImageAllocator, ImageBuf, ScratchBuf, alloc_buf};
+use crate::buf::deref;
+use crate::marker;
+use crate::validate::CodingProcess;
+use crate::cancel::{CancelToken, check_cancel};
+use crate::concurrency;
+use crate::deblock::{DeblockLevel, deblock};
+use crate::geometry::{Geometry, check_max_dimension};
+use crate::dump::{DumpSink, DumpSinkBox, FailureReport};
+use crate::common::{PixelFormat, Subsamp, Colorspace, DctMethod, Result, Error, get_error, is_fatal_error, recreate_handle};
+use crate::postprocess::{PostProcess, PostProcessor};
+use crate::progress::{Progress, ProgressCallback, report_progress};
+use crate::scale::ScalingFactor;
+use crate::siting::ChromaSiting;
+use crate::typed::{PixelType, TypedImage};
+#[cfg(feature = "stats")]
+use crate::stats::Stats;
 
 /// Decompresses JPEG data into raw pixels.
 #[derive(Debug)]
 #[doc(alias = "tjhandle")]
 pub struct Decompressor {
     handle: raw::tjhandle,
+    poisoned: bool,
+    dct_method: DctMethod,
+    deblock: DeblockLevel,
+    chroma_siting: ChromaSiting,
+    cancel: Option<CancelToken>,
+    progress: Option<ProgressCallback>,
+    post_process: Option<PostProcessor>,
+    dump: Option<DumpSinkBox>,
+    limits: DecompressLimits,
+    leading_garbage: usize,
+    allocator: Option<Arc<dyn ImageAllocator>>,
+    scratch: ScratchBuf,
+    #[cfg(feature = "stats")]
+    stats: Stats,
 }
 
 unsafe impl Send for Decompressor {}
@@ -16,6 +48,7 @@ unsafe impl Send for Decompressor {}
 /// The header can be obtained without decompressing the image by calling
 /// [`Decompressor::read_header()`] or [`read_header()`][crate::read_header].
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct DecompressHeader {
     /// Width of the image in pixels (number of columns).
     pub width: usize,
@@ -25,6 +58,97 @@ pub struct DecompressHeader {
     pub subsamp: Subsamp,
     /// Colorspace of the compressed image.
     pub colorspace: Colorspace,
+    /// Coding process of the compressed image (baseline, progressive, ...).
+    ///
+    /// This and the following fields are read by the marker parser of this crate, which is
+    /// stricter than TurboJPEG. If it cannot find the frame header in data that TurboJPEG accepts,
+    /// the process is [`CodingProcess::Unknown`], the image is assumed to have a single scan and
+    /// the number of components is derived from the colorspace.
+    pub process: CodingProcess,
+    /// Whether the compressed image has multiple scans.
+    ///
+    /// Progressive images always have multiple scans, sequential images have multiple scans if the
+    /// first scan does not contain all components. Multi-scan images need a buffer of DCT
+    /// coefficients for the whole image to decompress. Only the header and the first scan header
+    /// are read, use [`validate_jpeg()`](crate::validate_jpeg) to count the scans.
+    pub multi_scan: bool,
+    /// Number of color components in the compressed image.
+    pub components: usize,
+}
+
+/// Limits on the JPEG images that are accepted by a [`Decompressor`].
+///
+/// Servers that decompress untrusted images can use these limits to protect themselves from
+/// "decompression bombs", small JPEG files that take a lot of memory or time to decompress (such as
+/// a 65500x65500 progressive JPEG with hundreds of scans). The limits are checked before the image
+/// is decompressed, and if the image exceeds any of them, the decompression fails with
+/// [`Error::LimitExceeded`].
+///
+/// The default instance does not limit anything.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file (the image has 384x256 pixels)
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// decompressor.set_limits(turbojpeg::DecompressLimits {
+///     max_pixels: Some(50_000),
+///     .. turbojpeg::DecompressLimits::default()
+/// });
+///
+/// let res = decompressor.decompress_with(&jpeg_data, turbojpeg::PixelFormat::RGB, |_, _| {});
+/// assert!(matches!(res, Err(turbojpeg::Error::LimitExceeded("max_pixels"))));
+///
+/// // the image is progressive with 10 scans
+/// decompressor.set_limits(turbojpeg::DecompressLimits {
+///     max_scan_count: Some(5),
+///     .. turbojpeg::DecompressLimits::default()
+/// });
+/// let res = decompressor.decompress_with(&jpeg_data, turbojpeg::PixelFormat::RGB, |_, _| {});
+/// assert!(matches!(res, Err(turbojpeg::Error::TooManyScans(5))));
+///
+/// // the image is progressive, so it needs about 300 kB for the DCT coefficients
+/// decompressor.set_limits(turbojpeg::DecompressLimits {
+///     max_memory: Some(100_000),
+///     .. turbojpeg::DecompressLimits::default()
+/// });
+/// let res = decompressor.decompress_with(&jpeg_data, turbojpeg::PixelFormat::RGB, |_, _| {});
+/// assert!(matches!(res, Err(turbojpeg::Error::LimitExceeded("max_memory"))));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DecompressLimits {
+    /// Maximal number of pixels (width times height) of the JPEG image.
+    pub max_pixels: Option<usize>,
+    /// Maximal size in bytes of the decompressed image.
+    pub max_output_bytes: Option<usize>,
+    /// Maximal number of scans in the JPEG image.
+    ///
+    /// Baseline JPEG images typically have one scan, but progressive images can have many scans,
+    /// and each of them requires a pass over the whole image. Pathological progressive images
+    /// with thousands of tiny scans can take minutes to decompress.
+    ///
+    /// Images that exceed this limit are rejected with [`Error::TooManyScans`] (not with
+    /// [`Error::LimitExceeded`]). When this limit is set, TurboJPEG is also instructed to stop
+    /// decompressing images with more than 500 scans (`TJFLAG_LIMITSCANS`), which catches scans
+    /// that are hidden from the marker parser of this crate by corrupted data.
+    pub max_scan_count: Option<usize>,
+    /// Maximal amount of memory in bytes that TurboJPEG may allocate internally to decompress the
+    /// image, not counting the output image (which is limited by `max_output_bytes`).
+    ///
+    /// This corresponds to the `-maxmemory` option of `djpeg` and `TJPARAM_MAXMEMORY` in
+    /// TurboJPEG 3. The TurboJPEG 2 API that this crate uses cannot limit the allocations of the
+    /// library directly, so the limit is checked against an estimate computed from the JPEG
+    /// header: progressive and other multi-scan images need a buffer for the DCT coefficients of
+    /// the whole image (2 bytes per coefficient, 64 coefficients per 8x8 block of every
+    /// component), while single-scan images are decompressed row by row and need only a small
+    /// amount of memory.
+    pub max_memory: Option<usize>,
 }
 
 impl Decompressor {
@@ -33,16 +157,374 @@ impl Decompressor {
     pub fn new() -> Result<Decompressor> {
         unsafe {
             let handle = raw::tjInitDecompress();
+            #[cfg(feature = "tracing")]
+            crate::trace::handle_created("decompressor", handle);
             if !handle.is_null() {
-                Ok(Decompressor { handle })
+                Ok(Decompressor {
+                    handle,
+                    poisoned: false,
+                    dct_method: DctMethod::default(),
+                    deblock: DeblockLevel::default(),
+                    chroma_siting: ChromaSiting::default(),
+                    cancel: None,
+                    progress: None,
+                    post_process: None,
+                    dump: None,
+                    limits: DecompressLimits::default(),
+                    leading_garbage: 0,
+                    allocator: None,
+                    scratch: ScratchBuf::default(),
+                    #[cfg(feature = "stats")]
+                    stats: Stats::default(),
+                })
             } else {
                 Err(get_error(handle))
             }
         }
     }
 
+    /// Returns true if the last call failed with a fatal error of libjpeg (such as when the JPEG
+    /// data is corrupted).
+    ///
+    /// libjpeg may leave its state inconsistent after a fatal error, so the decompressor does not
+    /// reuse the underlying TurboJPEG instance: it replaces the instance at the start of the next
+    /// call (keeping all settings), which clears this flag.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// // for example, a PNG image
+    /// assert!(decompressor.read_header(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").is_err());
+    /// assert!(decompressor.is_poisoned());
+    ///
+    /// // the next call uses a fresh TurboJPEG instance
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let image = decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    /// assert_eq!((image.width, image.height), (384, 256));
+    /// assert!(!decompressor.is_poisoned());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Replaces the TurboJPEG instance if the last call failed with a fatal error.
+    fn recover(&mut self) -> Result<()> {
+        if self.poisoned {
+            unsafe { recreate_handle(&mut self.handle, raw::tjInitDecompress, "decompressor")? };
+            self.poisoned = false;
+        }
+        Ok(())
+    }
+
+    /// Set the DCT algorithm used for decompression (see [`DctMethod`]).
+    pub fn set_dct_method(&mut self, dct_method: DctMethod) {
+        self.dct_method = dct_method;
+    }
+
+    /// Set the level of the deblocking filter that is applied to decompressed images (see
+    /// [`DeblockLevel`]).
+    ///
+    /// The filter is applied by all methods that decompress into pixels, but not by
+    /// [`decompress_to_yuv()`][Self::decompress_to_yuv]. It runs after TurboJPEG has decompressed
+    /// the whole image, on the block boundaries of the decompressed (possibly scaled) image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // compress an image with very low quality, which produces blocking artifacts
+    /// let image = turbojpeg::Image::mandelbrot(256, 256, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 10, turbojpeg::Subsamp::Sub2x2)?;
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let blocky = decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    /// decompressor.set_deblock(turbojpeg::DeblockLevel::Medium);
+    /// let smooth = decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    ///
+    /// // the pixels next to the block boundaries have changed
+    /// assert_ne!(&blocky.pixels[..], &smooth.pixels[..]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_deblock(&mut self, level: DeblockLevel) {
+        self.deblock = level;
+    }
+
+    /// Set the chroma siting of the output of [`decompress_to_yuv()`][Self::decompress_to_yuv]
+    /// (see [`ChromaSiting`]).
+    ///
+    /// JPEG images have [centered][ChromaSiting::Centered] chrominance samples, which is what
+    /// TurboJPEG produces. If you set another siting, the chrominance planes are resampled after
+    /// decompression, so that you can pass them to a video encoder that expects that siting.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::ChromaSiting;
+    ///
+    /// let image = turbojpeg::Image::mandelbrot(64, 48, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+    /// let mut image = turbojpeg::decompress_to_yuv(&jpeg_data)?;
+    ///
+    /// // decompress for an H.264 encoder, which expects the chrominance sited on the left
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// decompressor.set_chroma_siting(ChromaSiting::Left);
+    /// let mut left = turbojpeg::YuvImage { pixels: vec![0; image.pixels.len()], ..image };
+    /// decompressor.decompress_to_yuv(&jpeg_data, left.as_deref_mut())?;
+    ///
+    /// image.resite_chroma(ChromaSiting::Centered, ChromaSiting::Left);
+    /// assert_eq!(left.pixels, image.pixels);
+    /// assert_eq!(decompressor.chroma_siting().chroma_sample_loc_type(), 0);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_chroma_siting(&mut self, siting: ChromaSiting) {
+        self.chroma_siting = siting;
+    }
+
+    /// Returns the chroma siting of the output of [`decompress_to_yuv()`][Self::decompress_to_yuv].
+    pub fn chroma_siting(&self) -> ChromaSiting {
+        self.chroma_siting
+    }
+
+    /// Set the token that can be used to cancel the decompression (see [`CancelToken`]).
+    ///
+    /// Use `None` to remove the token.
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel = token;
+    }
+
+    /// Set the callback that receives the [`Progress`] of decompression.
+    ///
+    /// TurboJPEG decompresses the whole image in a single call, so the methods that decompress a
+    /// complete image (such as [`decompress()`][Self::decompress] and
+    /// [`decompress_to_yuv()`][Self::decompress_to_yuv]) report the progress only twice: with no
+    /// rows done before the call and with all rows done after it. The row-based method
+    /// [`decompress_with()`][Self::decompress_with] reports no rows done before the decompression
+    /// and then the progress after every row that it passes to its callback.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    /// decompressor.set_progress_callback(move |progress| sender.send(progress).unwrap());
+    ///
+    /// // the whole image is decompressed in one call
+    /// decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    /// let rows_done = receiver.try_iter().map(|progress| progress.rows_done).collect::<Vec<_>>();
+    /// assert_eq!(rows_done, [0, 256]);
+    ///
+    /// // the rows are passed to the callback one by one
+    /// decompressor.decompress_with(&jpeg_data, turbojpeg::PixelFormat::RGB, |_y, _row| {})?;
+    /// let rows_done = receiver.try_iter().map(|progress| progress.rows_done).collect::<Vec<_>>();
+    /// assert_eq!(rows_done, (0..=256).collect::<Vec<_>>());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+        where F: FnMut(Progress) + Send + 'static
+    {
+        self.progress = Some(ProgressCallback::new(callback));
+    }
+
+    /// Remove the callback set by [`set_progress_callback()`][Self::set_progress_callback].
+    pub fn remove_progress_callback(&mut self) {
+        self.progress = None;
+    }
+
+    /// Set the post-processing step that is applied to decompressed images (see [`PostProcess`]).
+    ///
+    /// Like the deblocking filter, the post-processing step is applied by all methods that
+    /// decompress into pixels, but not by [`decompress_to_yuv()`][Self::decompress_to_yuv].
+    pub fn set_post_process<P>(&mut self, post_process: P)
+        where P: PostProcess + 'static
+    {
+        self.post_process = Some(PostProcessor(Box::new(post_process)));
+    }
+
+    /// Remove the post-processing step set by [`set_post_process()`][Self::set_post_process].
+    pub fn remove_post_process(&mut self) {
+        self.post_process = None;
+    }
+
+    /// Set the sink that receives a [`FailureReport`] whenever TurboJPEG fails to decompress an
+    /// image (see [`DumpSink`]).
+    ///
+    /// This is an opt-in diagnostic mode for production systems: the sink receives the offending
+    /// input together with the settings and the error, so that it can be stored for a bug report.
+    pub fn set_dump_sink<S>(&mut self, sink: S)
+        where S: DumpSink + 'static
+    {
+        self.dump = Some(DumpSinkBox(Box::new(sink)));
+    }
+
+    /// Remove the sink set by [`set_dump_sink()`][Self::set_dump_sink].
+    pub fn remove_dump_sink(&mut self) {
+        self.dump = None;
+    }
+
+    /// Set the limits on the images that this decompressor accepts (see [`DecompressLimits`]).
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+    }
+
+    /// Get the limits on the images that this decompressor accepts.
+    pub fn limits(&self) -> DecompressLimits {
+        self.limits
+    }
+
+    /// Allow up to `max_bytes` of junk bytes before the start of the JPEG image.
+    ///
+    /// Files from broken uploads or from some cameras may contain garbage before the SOI marker
+    /// that starts the JPEG image, which TurboJPEG rejects ("Not a JPEG file"). With this setting,
+    /// the decompressor looks for the SOI marker (followed by another marker) in the first
+    /// `max_bytes` bytes and skips everything before it. The default is zero, which accepts only
+    /// data that start with the SOI marker.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut garbled = b"--boundary\r\n\r\n".to_vec();
+    /// garbled.extend_from_slice(&jpeg_data);
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// assert!(decompressor.read_header(&garbled).is_err());
+    ///
+    /// decompressor.set_allow_leading_garbage(1024);
+    /// assert_eq!(decompressor.read_header(&garbled)?.width, 384);
+    /// let image = decompressor.decompress_to_buf(&garbled, turbojpeg::PixelFormat::RGB)?;
+    /// assert_eq!(image.height, 256);
+    ///
+    /// // the garbage must not be longer than the limit
+    /// decompressor.set_allow_leading_garbage(8);
+    /// assert!(decompressor.read_header(&garbled).is_err());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_allow_leading_garbage(&mut self, max_bytes: usize) {
+        self.leading_garbage = max_bytes;
+    }
+
+    /// Skips the garbage before the SOI marker allowed by
+    /// [`set_allow_leading_garbage()`][Self::set_allow_leading_garbage].
+    fn skip_garbage<'a>(&self, jpeg_data: &'a [u8]) -> &'a [u8] {
+        if self.leading_garbage == 0 || jpeg_data.starts_with(&[0xff, marker::SOI]) {
+            return jpeg_data
+        }
+        match marker::find_soi(jpeg_data, self.leading_garbage) {
+            Some(offset) => &jpeg_data[offset..],
+            None => jpeg_data,
+        }
+    }
+
+    /// Get the statistics of decompressions made by this decompressor (see [`Stats`]).
+    ///
+    /// Reading the header is not counted as a decompression.
+    #[cfg(feature = "stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Reset the statistics returned by [`stats()`][Self::stats].
+    #[cfg(feature = "stats")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    /// Set the allocator for decompressed images and temporary buffers (see [`ImageAllocator`]).
+    ///
+    /// Use `None` to use the [`SystemAllocator`][crate::SystemAllocator].
+    pub fn set_allocator(&mut self, allocator: Option<Arc<dyn ImageAllocator>>) {
+        self.allocator = allocator;
+        self.scratch.release();
+    }
+
+    /// Reset all settings of this decompressor to their defaults.
+    ///
+    /// This resets the DCT method, deblocking filter, limits, allowed leading garbage, cancel token,
+    /// progress callback and post-processing step, so that the decompressor can be reused for
+    /// unrelated images (for example, when it is returned into a pool). The TurboJPEG handle, the
+    /// allocator and the temporary buffer for methods like
+    /// [`decompress_with()`][Self::decompress_with] are kept, so the next decompression does not
+    /// need to allocate them again.
+    ///
+    /// Note that the decompressor does not need to be reset when the size or format of the images
+    /// changes: TurboJPEG reuses the handle for images of any size, and the temporary buffer is
+    /// reused for all images that fit into it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// decompressor.set_limits(turbojpeg::DecompressLimits {
+    ///     max_pixels: Some(1000),
+    ///     .. turbojpeg::DecompressLimits::default()
+    /// });
+    /// assert!(decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB).is_err());
+    ///
+    /// decompressor.reset();
+    /// assert_eq!(decompressor.limits(), turbojpeg::DecompressLimits::default());
+    /// decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn reset(&mut self) {
+        self.dct_method = DctMethod::default();
+        self.deblock = DeblockLevel::default();
+        self.chroma_siting = ChromaSiting::default();
+        self.cancel = None;
+        self.progress = None;
+        self.post_process = None;
+        self.dump = None;
+        self.limits = DecompressLimits::default();
+        self.leading_garbage = 0;
+    }
+
+    /// Checks the limits for decompressing `jpeg_data` into `output_len` bytes. Every public
+    /// decompression method calls this exactly once, before it allocates the output.
+    fn check_limits(&mut self, jpeg_data: &[u8], output_len: usize) -> Result<()> {
+        let jpeg_data = self.skip_garbage(jpeg_data);
+        let limits = self.limits;
+        if let Some(max_output_bytes) = limits.max_output_bytes {
+            if output_len > max_output_bytes {
+                return Err(Error::LimitExceeded("max_output_bytes"));
+            }
+        }
+        if let Some(max_pixels) = limits.max_pixels {
+            let header = self.read_header(jpeg_data)?;
+            if header.width.saturating_mul(header.height) > max_pixels {
+                return Err(Error::LimitExceeded("max_pixels"));
+            }
+        }
+        if let Some(max_scan_count) = limits.max_scan_count {
+            if marker::count_scans(jpeg_data) > max_scan_count {
+                return Err(Error::TooManyScans(max_scan_count));
+            }
+        }
+        if let Some(max_memory) = limits.max_memory {
+            let header = self.read_header(jpeg_data)?;
+            if coefficient_buffer_len(&header) > max_memory {
+                return Err(Error::LimitExceeded("max_memory"));
+            }
+        }
+        Ok(())
+    }
+
     /// Read the JPEG header without decompressing the image.
     ///
+    /// Returns [`Error::DimensionTooLarge`] if the width or height of the image exceeds
+    /// [`max_dimension()`][crate::max_dimension].
+    ///
     /// # Example
     ///
     /// ```
@@ -55,10 +537,25 @@ impl Decompressor {
     /// // read the JPEG header
     /// let header = decompressor.read_header(&jpeg_data)?;
     /// assert_eq!((header.width, header.height), (384, 256));
+    /// assert_eq!(header.process, turbojpeg::CodingProcess::Progressive);
+    /// assert!(header.multi_scan);
+    ///
+    /// // images larger than `max_dimension()` are rejected with a typed error
+    /// let mut large = jpeg_data.clone();
+    /// let sof = large.windows(2).position(|marker| marker == [0xff, 0xc2]).unwrap();
+    /// large[sof + 7..sof + 9].copy_from_slice(&65535u16.to_be_bytes());
+    /// let res = decompressor.read_header(&large);
+    /// assert!(matches!(res, Err(turbojpeg::Error::DimensionTooLarge(65535, 256))));
     ///
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn read_header(&mut self, jpeg_data: &[u8]) -> Result<DecompressHeader> {
+        self.recover()?;
+        let jpeg_data = self.skip_garbage(jpeg_data);
+        let info = marker::frame_info(jpeg_data);
+        if let Some(info) = info {
+            check_max_dimension(info.width, info.height)?;
+        }
         let jpeg_data_len = jpeg_data.len().try_into()
             .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
         let mut width = 0;
@@ -78,9 +575,28 @@ impl Decompressor {
             let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
             let subsamp = Subsamp::from_u32(subsamp as u32)?;
             let colorspace = Colorspace::from_u32(colorspace as u32)?;
-            Ok(DecompressHeader { width, height, subsamp, colorspace })
+            let (process, multi_scan, components) = match info {
+                Some(info) => {
+                    let process = CodingProcess::from_sof(info.sof_marker);
+                    let partial_scan = info.first_scan_components != 0 && info.first_scan_components < info.components;
+                    (process, process == CodingProcess::Progressive || partial_scan, info.components)
+                },
+                None => {
+                    // the marker parser of this crate gave up on data that TurboJPEG tolerates
+                    check_max_dimension(width, height)?;
+                    let components = match colorspace {
+                        Colorspace::Gray => 1,
+                        Colorspace::CMYK | Colorspace::YCCK => 4,
+                        _ => 3,
+                    };
+                    (CodingProcess::Unknown, false, components)
+                },
+            };
+            Ok(DecompressHeader { width, height, subsamp, colorspace, process, multi_scan, components })
         } else {
-            Err(unsafe { get_error(self.handle) })
+            self.poisoned = unsafe { is_fatal_error(self.handle) };
+            let err = unsafe { get_error(self.handle) };
+            Err(self.dump_failure("tjDecompressHeader3", jpeg_data, || "header only".into(), err))
         }
     }
 
@@ -120,7 +636,105 @@ impl Decompressor {
     /// ```
     #[doc(alias = "tjDecompress2")]
     pub fn decompress(&mut self, jpeg_data: &[u8], output: Image<&mut [u8]>) -> Result<()> {
+        self.check_limits(jpeg_data, output.pitch.saturating_mul(output.height))?;
+        self.decompress_unchecked(jpeg_data, output)
+    }
+
+    /// Like [`decompress()`][Self::decompress], but without checking the limits. The methods that
+    /// allocate the output check the limits before the allocation and then call this.
+    fn decompress_unchecked(&mut self, jpeg_data: &[u8], output: Image<&mut [u8]>) -> Result<()> {
+        let height = output.height;
+        report_progress(&mut self.progress, 0, height);
+        self.decompress_image(jpeg_data, output)?;
+        report_progress(&mut self.progress, height, height);
+        Ok(())
+    }
+
+    /// Decompress JPEG data from raw memory into raw memory.
+    ///
+    /// This is a variant of [`decompress()`][Self::decompress] for callers that integrate with
+    /// foreign memory (such as direct buffers from JNI or buffers from Python), where creating
+    /// slices would require promises that the caller cannot check, such as that the output memory
+    /// is initialized. The JPEG data is read from `jpeg_len` bytes at `jpeg_ptr` and the image is
+    /// written into the `output_len` bytes at `output.pixels`.
+    ///
+    /// # Safety
+    ///
+    /// - `jpeg_ptr` must be valid for reads of `jpeg_len` bytes (it may be null if `jpeg_len` is
+    ///   zero), and the memory must not be modified during the call.
+    /// - `output.pixels` must be valid for writes of `output_len` bytes, must not overlap the JPEG
+    ///   data, and must not be accessed by other threads during the call.
+    /// - If the output memory is not initialized, the rows must not have padding (`output.pitch`
+    ///   must be equal to `output.width * output.format.size()`) and `output_len` must be equal to
+    ///   `output.pitch * output.height`, so that TurboJPEG writes all bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `output_len` is too small for the size, pitch and pixel format of `output`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let (width, height) = (384, 256);
+    ///
+    /// // for example, memory allocated by a foreign runtime
+    /// let mut foreign = Vec::<u8>::with_capacity(3 * width * height);
+    /// let output = turbojpeg::Image {
+    ///     pixels: foreign.as_mut_ptr(),
+    ///     width,
+    ///     pitch: 3 * width,
+    ///     height,
+    ///     format: turbojpeg::PixelFormat::RGB,
+    /// };
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// unsafe {
+    ///     decompressor.decompress_raw(jpeg_data.as_ptr(), jpeg_data.len(), output, 3 * width * height)?;
+    ///     foreign.set_len(3 * width * height);
+    /// }
+    /// assert_eq!(&foreign[0..3], &[122, 118, 89]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[doc(alias = "tjDecompress2")]
+    pub unsafe fn decompress_raw(
+        &mut self,
+        jpeg_ptr: *const u8,
+        jpeg_len: usize,
+        output: Image<*mut u8>,
+        output_len: usize,
+    ) -> Result<()> {
+        output.assert_valid(output_len);
+        let jpeg_data = deref(jpeg_ptr, jpeg_len);
+        let height = output.height;
+        self.check_limits(jpeg_data, output.pitch.saturating_mul(height))?;
+        report_progress(&mut self.progress, 0, height);
+        self.decompress_ptr(jpeg_data, output, output_len)?;
+        report_progress(&mut self.progress, height, height);
+        Ok(())
+    }
+
+    fn decompress_image(&mut self, jpeg_data: &[u8], output: Image<&mut [u8]>) -> Result<()> {
         output.assert_valid(output.pixels.len());
+        let len = output.pixels.len();
+        let Image { pixels, width, pitch, height, format } = output;
+        let output = Image { pixels: pixels.as_mut_ptr(), width, pitch, height, format };
+        unsafe { self.decompress_ptr(jpeg_data, output, len) }
+    }
+
+    /// Decompresses into the memory at `output.pixels`, which may be uninitialized.
+    ///
+    /// # Safety
+    ///
+    /// `output.pixels` must be valid for writes of `len` bytes, and `len` must be large enough for
+    /// the `output` image. If the memory is not initialized, the pitch must be equal to `width *
+    /// format.size()` and `len` must be equal to `pitch * height`, so that TurboJPEG writes all
+    /// bytes before they are read.
+    unsafe fn decompress_ptr(&mut self, jpeg_data: &[u8], output: Image<*mut u8>, len: usize) -> Result<()> {
+        check_cancel(&self.cancel)?;
+        self.recover()?;
+        let jpeg_data = self.skip_garbage(jpeg_data);
 
         let Image { pixels, width, pitch, height, format } = output;
         let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
@@ -129,27 +743,532 @@ impl Decompressor {
         let jpeg_data_len = jpeg_data.len().try_into()
             .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "tjDecompress2",
+            width, height, pitch, format = ?format, jpeg_len = jpeg_data_len,
+            dct_method = ?self.dct_method, deblock = ?self.deblock,
+        ).entered();
+        #[cfg(any(feature = "stats", feature = "tracing"))]
+        let start = std::time::Instant::now();
+
+        let permit = concurrency::acquire();
         let res = unsafe {
             raw::tjDecompress2(
                 self.handle,
                 jpeg_data.as_ptr(), jpeg_data_len,
-                pixels.as_mut_ptr(), width, pitch, height, format as i32,
-                0,
+                pixels, width, pitch, height, format as i32,
+                self.flags(),
             )
         };
+        drop(permit);
+        #[cfg(feature = "tracing")]
+        crate::trace::call_finished(self.handle, res, start, len);
 
         if res == 0 {
+            // the memory is now initialized
+            let image = Image {
+                pixels: std::slice::from_raw_parts_mut(pixels, len),
+                width: output.width,
+                pitch: output.pitch,
+                height: output.height,
+                format,
+            };
+            if self.deblock != DeblockLevel::Off {
+                // the blocks are scaled together with the image
+                let header = self.read_header(jpeg_data)?;
+                let block_size = 8 * image.width / header.width.max(1);
+                deblock(Image { pixels: &mut *image.pixels, ..image }, block_size, self.deblock);
+            }
+            if let Some(PostProcessor(post_process)) = &mut self.post_process {
+                post_process.process_frame(Image { pixels: &mut *image.pixels, ..image })?;
+            }
+            #[cfg(feature = "stats")]
+            self.stats.record(start.elapsed(), jpeg_data.len(), len, image.width * image.height);
             Ok(())
         } else {
-            Err(unsafe { get_error(self.handle) })
+            let err = self.decompress_error(jpeg_data);
+            let output = || format!("output: {}x{}, pitch {}, {:?}", output.width, output.height, output.pitch, format);
+            Err(self.dump_failure("tjDecompress2", jpeg_data, output, err))
+        }
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into a new buffer.
+    ///
+    /// The image is decoded into pixel `format` and stored in an [`ImageBuf`] allocated by the
+    /// allocator of this decompressor (see [`set_allocator()`][Self::set_allocator]).
+    pub fn decompress_to_buf(&mut self, jpeg_data: &[u8], format: PixelFormat) -> Result<Image<ImageBuf>> {
+        let header = self.read_header(jpeg_data)?;
+        let pitch = header.width.checked_mul(format.size())
+            .ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        self.check_limits(jpeg_data, len)?;
+        let mut image = Image {
+            pixels: alloc_buf(&self.allocator, len)?,
+            width: header.width,
+            pitch,
+            height: header.height,
+            format,
+        };
+        self.decompress_unchecked(jpeg_data, image.as_deref_mut())?;
+        Ok(image)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into a new image with pixel format `P`.
+    ///
+    /// This is a variant of [`decompress_to_buf()`][Self::decompress_to_buf] for
+    /// [`TypedImage`], whose pixel format is checked at compile time.
+    pub fn decompress_typed<P: PixelType>(&mut self, jpeg_data: &[u8]) -> Result<TypedImage<P, Vec<u8>>> {
+        let header = self.read_header(jpeg_data)?;
+        let pitch = header.width.checked_mul(P::FORMAT.size())
+            .ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        self.check_limits(jpeg_data, len)?;
+        let mut image = TypedImage::new(vec![0; len], header.width, header.height);
+        self.decompress_unchecked(jpeg_data, image.as_image_mut())?;
+        Ok(image)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into `image`, reusing its buffer.
+    ///
+    /// The image is decoded into the pixel format of `image`. If the JPEG image has the same size
+    /// as `image`, the pitch of `image` is kept (so rows padded for alignment stay padded);
+    /// otherwise the width, height and pitch of `image` are changed to the size of the JPEG image
+    /// without padding. The pixels are resized to the new length, which only allocates if the
+    /// capacity of the `Vec` is too small, so decompressing a stream of frames of the same size
+    /// into the same image does not allocate at all. The geometry is validated with [`Geometry`],
+    /// so an image with a pitch that is too small is rejected instead of being overrun.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    ///
+    /// // start with an empty image, which is resized for the first frame
+    /// let mut image = turbojpeg::Image {
+    ///     pixels: Vec::new(), width: 0, pitch: 0, height: 0, format: turbojpeg::PixelFormat::RGBA,
+    /// };
+    /// decompressor.decompress_reuse(&jpeg_data, &mut image)?;
+    /// assert_eq!((image.width, image.pitch, image.height), (384, 384 * 4, 256));
+    /// assert_eq!(&image.pixels[..4], &[122, 118, 89, 255]);
+    ///
+    /// // the next frames of the same size reuse the buffer
+    /// let ptr = image.pixels.as_ptr();
+    /// decompressor.decompress_reuse(&jpeg_data, &mut image)?;
+    /// assert_eq!(image.pixels.as_ptr(), ptr);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_reuse(&mut self, jpeg_data: &[u8], image: &mut Image<Vec<u8>>) -> Result<()> {
+        let header = self.read_header(jpeg_data)?;
+        let format = image.format;
+        let same_size = (image.width, image.height) == (header.width, header.height);
+        let geometry = if same_size && image.pitch >= header.width.saturating_mul(format.size()) {
+            Geometry::with_pitch(header.width, header.height, format, image.pitch)?
+        } else {
+            Geometry::new(header.width, header.height, format)?
+        };
+        self.check_limits(jpeg_data, geometry.len())?;
+
+        image.pixels.resize(geometry.len(), 0);
+        image.width = geometry.width();
+        image.pitch = geometry.pitch();
+        image.height = geometry.height();
+        self.decompress_unchecked(jpeg_data, image.as_deref_mut())
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` and scale it by the given `factor`.
+    ///
+    /// The image is scaled during decompression, which is much faster than resizing the
+    /// decompressed image. The size of the returned image is the size of the JPEG image scaled by
+    /// [`ScalingFactor::scale()`]. Returns [`Error::UnsupportedScalingFactor`] if TurboJPEG does
+    /// not support the `factor`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // decompress a thumbnail
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let thumbnail = decompressor.decompress_scaled(
+    ///     &jpeg_data, turbojpeg::PixelFormat::RGB, turbojpeg::ScalingFactor::ONE_EIGHTH)?;
+    /// assert_eq!((thumbnail.width, thumbnail.height), (48, 32));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_scaled(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        factor: ScalingFactor,
+    ) -> Result<Image<Vec<u8>>> {
+        if !factor.is_supported() {
+            return Err(Error::UnsupportedScalingFactor(factor))
+        }
+
+        let header = self.read_header(jpeg_data)?;
+        let (width, height) = factor.scaled_size(&header);
+        let pitch = width.checked_mul(format.size())
+            .ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        self.check_limits(jpeg_data, len)?;
+        let mut image = Image { pixels: vec![0; len], width, pitch, height, format };
+        self.decompress_unchecked(jpeg_data, image.as_deref_mut())?;
+        Ok(image)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` and pass the decoded rows to the callback `use_row`.
+    ///
+    /// The image is decoded into pixel `format` and the callback is called once for every row, in
+    /// order from top to bottom, with the row index `y` and a slice of length `width *
+    /// format.size()` that contains the pixels of row `y`. This is useful for consumers that
+    /// process the image row by row (such as hashers, resizers or network writers), because you
+    /// don't need to manage the pixel buffer yourself.
+    ///
+    /// Note that TurboJPEG always decompresses the complete image, so this method still needs a
+    /// temporary buffer for the whole image. The buffer is kept in the decompressor and reused by
+    /// the next calls, so it is allocated again only for a larger image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // initialize a decompressor
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    ///
+    /// // compute the average brightness of every row
+    /// let mut averages = Vec::new();
+    /// decompressor.decompress_with(&jpeg_data, turbojpeg::PixelFormat::GRAY, |_y, row| {
+    ///     let sum = row.iter().map(|&pixel| pixel as usize).sum::<usize>();
+    ///     averages.push(sum / row.len());
+    /// })?;
+    /// assert_eq!(averages.len(), 256);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_with<F>(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        mut use_row: F,
+    ) -> Result<()>
+        where F: FnMut(usize, &[u8])
+    {
+        let header = self.read_header(jpeg_data)?;
+        let pitch = header.width.checked_mul(format.size())
+            .ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        self.check_limits(jpeg_data, len)?;
+        let mut pixels = self.scratch.take(&self.allocator, len)?;
+        let res = (|| {
+            let image = Image {
+                pixels: &mut pixels[..len],
+                width: header.width,
+                pitch,
+                height: header.height,
+                format,
+            };
+            report_progress(&mut self.progress, 0, header.height);
+            self.decompress_image(jpeg_data, Image { pixels: &mut *image.pixels, ..image })?;
+
+            if pitch != 0 {
+                for (y, row) in image.pixels.chunks_exact(pitch).enumerate() {
+                    check_cancel(&self.cancel)?;
+                    use_row(y, row);
+                    report_progress(&mut self.progress, y + 1, header.height);
+                }
+            } else {
+                report_progress(&mut self.progress, header.height, header.height);
+            }
+            Ok(())
+        })();
+        self.scratch.put(pixels);
+        res
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into `output` with rows aligned to `align` bytes.
+    ///
+    /// The pitch of the decompressed image is the row length rounded up to a multiple of `align`
+    /// (see [`aligned_pitch()`]), so the image can be uploaded to a GPU texture without repacking
+    /// (for example, wgpu requires the row pitch to be a multiple of 256 bytes). The `output` can
+    /// be any memory, such as a mapped GPU buffer, and it must be at least `pitch * height` bytes
+    /// long, otherwise [`Error::OutputTooSmall`] is returned. The padding at the end of the rows
+    /// is left unchanged.
+    ///
+    /// Returns the decompressed image, which borrows `output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // decompress into a buffer with rows aligned to 256 bytes
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut buffer = vec![0; 2 << 20];
+    /// let image = decompressor.decompress_aligned_into(
+    ///     &jpeg_data, turbojpeg::PixelFormat::RGBA, 256, &mut buffer)?;
+    /// assert_eq!(image.pitch, 1536);
+    /// assert_eq!(image.pitch % 256, 0);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_aligned_into<'a>(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        align: usize,
+        output: &'a mut [u8],
+    ) -> Result<Image<&'a mut [u8]>> {
+        let header = self.read_header(jpeg_data)?;
+        let pitch = aligned_pitch(header.width, format, align)?;
+        let len = pitch.checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        if output.len() < len {
+            return Err(Error::OutputTooSmall(len))
+        }
+
+        let image = Image {
+            pixels: &mut output[..len],
+            width: header.width,
+            pitch,
+            height: header.height,
+            format,
+        };
+        self.decompress(jpeg_data, Image { pixels: &mut *image.pixels, ..image })?;
+        Ok(image)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into uninitialized memory.
+    ///
+    /// This works like [`decompress()`][Self::decompress], but the `output` does not need to be
+    /// initialized, so you can avoid the cost of zeroing large buffers (such as a `Vec` created
+    /// with [`Vec::with_capacity()`] and accessed with [`Vec::spare_capacity_mut()`]). The image
+    /// is decoded into pixel `format` with rows of `width * format.size()` bytes without any
+    /// padding, and it must fit into `output`, otherwise [`Error::OutputTooSmall`] is returned.
+    ///
+    /// Returns the decompressed image, which borrows the beginning of `output`. TurboJPEG writes
+    /// every byte of the image before the image is returned, so this method is safe. If the
+    /// decompression fails, the contents of `output` are unspecified (they may be partially
+    /// initialized) and must not be assumed to be initialized.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let mut pixels = Vec::with_capacity(384 * 256 * 3);
+    /// let image = decompressor.decompress_into_uninit(
+    ///     &jpeg_data, turbojpeg::PixelFormat::RGB, pixels.spare_capacity_mut())?;
+    /// assert_eq!((image.width, image.height, image.pitch), (384, 256, 384 * 3));
+    /// let len = image.pixels.len();
+    ///
+    /// // the bytes were initialized by the decompressor
+    /// unsafe { pixels.set_len(len) };
+    /// assert_eq!(&pixels[0..3], &[122, 118, 89]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_into_uninit<'a>(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        output: &'a mut [MaybeUninit<u8>],
+    ) -> Result<Image<&'a mut [u8]>> {
+        let header = self.read_header(jpeg_data)?;
+        let pitch = header.width.checked_mul(format.size())
+            .ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        if output.len() < len {
+            return Err(Error::OutputTooSmall(len))
+        }
+        self.check_limits(jpeg_data, len)?;
+
+        let pixels = output[..len].as_mut_ptr() as *mut u8;
+        let image = Image { pixels, width: header.width, pitch, height: header.height, format };
+        report_progress(&mut self.progress, 0, header.height);
+        // the rows have no padding, so TurboJPEG initializes all `len` bytes
+        unsafe { self.decompress_ptr(jpeg_data, image, len)? };
+        report_progress(&mut self.progress, header.height, header.height);
+
+        Ok(Image {
+            pixels: unsafe { std::slice::from_raw_parts_mut(pixels, len) },
+            width: header.width,
+            pitch,
+            height: header.height,
+            format,
+        })
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` into externally allocated memory.
+    ///
+    /// The memory is described by the raw pointer `pixels`, its length `pixels_len` in bytes and
+    /// the row `pitch`. This is intended for zero-copy pipelines that decode directly into memory
+    /// that is not owned by Rust, such as DMA-BUF mappings or shared memory segments (for example,
+    /// Wayland screencopy or PipeWire buffers). Use [`decompress()`](Decompressor::decompress) if
+    /// you have a slice.
+    ///
+    /// Returns the header of the image. If `pitch` is smaller than `width * format.size()`, or if
+    /// `pixels_len` is smaller than the image, [`Error::OutputTooSmall`] is returned and nothing is
+    /// written. TurboJPEG writes only the pixels of the image: the padding at the end of the rows
+    /// and the memory after the last row are not modified.
+    ///
+    /// # Safety
+    ///
+    /// - `pixels` must be non-null and [valid] for writes of `pixels_len` bytes, and the memory
+    ///   must not be accessed by anything else (including other processes or devices) until this
+    ///   method returns.
+    /// - If `pitch` is larger than `width * format.size()`, the memory must be initialized (for
+    ///   example, allocated with `calloc()`), because the padding of the rows is not written. If
+    ///   the rows have no padding, the memory may be uninitialized.
+    ///
+    /// [valid]: std::ptr#safety
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // allocate zeroed memory outside of Rust, with rows padded to 2048 bytes
+    /// let (pitch, len) = (2048, 2048 * 256);
+    /// let pixels = unsafe { libc::calloc(len, 1) as *mut u8 };
+    /// assert!(!pixels.is_null());
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let header = unsafe {
+    ///     decompressor.decompress_to_ptr(&jpeg_data, pixels, len, pitch, turbojpeg::PixelFormat::RGBA)?
+    /// };
+    /// assert_eq!((header.width, header.height), (384, 256));
+    ///
+    /// unsafe { libc::free(pixels as *mut libc::c_void) };
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub unsafe fn decompress_to_ptr(
+        &mut self,
+        jpeg_data: &[u8],
+        pixels: *mut u8,
+        pixels_len: usize,
+        pitch: usize,
+        format: PixelFormat,
+    ) -> Result<DecompressHeader> {
+        let header = self.read_header(jpeg_data)?;
+        let row_len = header.width.checked_mul(format.size())
+            .ok_or(Error::IntegerOverflow("row len"))?;
+        if pitch < row_len {
+            return Err(Error::OutputTooSmall(row_len))
         }
+        let len = match header.height {
+            0 => 0,
+            height => pitch.checked_mul(height - 1).and_then(|len| len.checked_add(row_len))
+                .ok_or(Error::IntegerOverflow("pixels len"))?,
+        };
+        if pixels_len < len {
+            return Err(Error::OutputTooSmall(len))
+        }
+
+        self.check_limits(jpeg_data, len)?;
+
+        // pass only the bytes of the image, so that the memory after the last row (which may be
+        // uninitialized) is never treated as initialized
+        let image = Image { pixels, width: header.width, pitch, height: header.height, format };
+        report_progress(&mut self.progress, 0, header.height);
+        self.decompress_ptr(jpeg_data, image, len)?;
+        report_progress(&mut self.progress, header.height, header.height);
+        Ok(header)
+    }
+
+    /// Decompress a JPEG image in `jpeg_data` and split it into tiles.
+    ///
+    /// The image is decoded once into pixel `format` and then split into tiles of size
+    /// `tile_width` x `tile_height`, in row-major order (left to right, top to bottom). The tiles
+    /// on the right and bottom edges are smaller if the image size is not divisible by the tile
+    /// size. This is useful for map servers and deep zoom generators.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tile_width` or `tile_height` is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // split the 384x256 image into tiles of 256x256 pixels
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let tiles = decompressor.decompress_tiles(&jpeg_data, turbojpeg::PixelFormat::RGB, 256, 256)?;
+    /// assert_eq!(tiles.len(), 2);
+    /// assert_eq!((tiles[0].width, tiles[0].height), (256, 256));
+    /// assert_eq!((tiles[1].width, tiles[1].height), (128, 256));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn decompress_tiles(
+        &mut self,
+        jpeg_data: &[u8],
+        format: PixelFormat,
+        tile_width: usize,
+        tile_height: usize,
+    ) -> Result<Vec<Image<Vec<u8>>>> {
+        assert!(tile_width > 0 && tile_height > 0,
+            "tile size {}x{} must not be zero", tile_width, tile_height);
+
+        let header = self.read_header(jpeg_data)?;
+        let pitch = header.width.checked_mul(format.size())
+            .ok_or(Error::IntegerOverflow("pitch"))?;
+        let len = pitch.checked_mul(header.height)
+            .ok_or(Error::IntegerOverflow("pixels len"))?;
+        self.check_limits(jpeg_data, len)?;
+        let mut pixels = self.scratch.take(&self.allocator, len)?;
+        let res = (|| {
+            let image = Image {
+                pixels: &mut pixels[..len],
+                width: header.width,
+                pitch,
+                height: header.height,
+                format,
+            };
+            self.decompress_unchecked(jpeg_data, Image { pixels: &mut *image.pixels, ..image })?;
+
+            let image = image.as_deref();
+            let mut tiles = Vec::new();
+            for y in (0..header.height).step_by(tile_height) {
+                for x in (0..header.width).step_by(tile_width) {
+                    let width = tile_width.min(header.width - x);
+                    let height = tile_height.min(header.height - y);
+                    tiles.push(image.subimage(x, y, width, height).to_owned_image());
+                }
+            }
+            Ok(tiles)
+        })();
+        self.scratch.put(pixels);
+        res
     }
 
     /// Decompress a JPEG image in `jpeg_data` into `output` as YUV without changing color space.
     ///
     /// The decompressed image is stored in the pixel data of the given `output` image, which must
     /// be fully initialized by the caller. Use [`read_header()`](Decompressor::read_header) to
-    /// determine the image size before calling this method.
+    /// determine the image size before calling this method. The chrominance samples are
+    /// [centered][ChromaSiting::Centered], unless you set another siting with
+    /// [`set_chroma_siting()`][Self::set_chroma_siting].
     ///
     /// # Example
     ///
@@ -185,30 +1304,103 @@ impl Decompressor {
     #[doc(alias = "tjDecompressToYUV2")]
     pub fn decompress_to_yuv(&mut self, jpeg_data: &[u8], output: YuvImage<&mut [u8]>) -> Result<()> {
         output.assert_valid(output.pixels.len());
+        check_cancel(&self.cancel)?;
+        let yuv_len = yuv_pixels_len(output.width, output.align, output.height, output.subsamp)?;
+        self.check_limits(jpeg_data, yuv_len)?;
+        let rows_total = output.height;
+        report_progress(&mut self.progress, 0, rows_total);
+        self.recover()?;
     
+        let jpeg_data = self.skip_garbage(jpeg_data);
         let YuvImage { pixels, width, align, height , subsamp: _ } = output;
         let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
         let align = align.try_into().map_err(|_| Error::IntegerOverflow("align"))?;
         let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
         let jpeg_data_len = jpeg_data.len().try_into()
             .map_err(|_| Error::IntegerOverflow("jpeg_data.len()"))?;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "tjDecompressToYUV2",
+            width, height, align, subsamp = ?output.subsamp, jpeg_len = jpeg_data_len,
+            dct_method = ?self.dct_method,
+        ).entered();
+        #[cfg(any(feature = "stats", feature = "tracing"))]
+        let start = std::time::Instant::now();
+
+        let permit = concurrency::acquire();
         let res = unsafe {
             raw::tjDecompressToYUV2(
                 self.handle,
                 jpeg_data.as_ptr(), jpeg_data_len,
                 pixels.as_mut_ptr(), width, align, height,
-                0,
+                self.flags(),
             )
         };
+        drop(permit);
+        #[cfg(feature = "tracing")]
+        crate::trace::call_finished(self.handle, res, start, yuv_len);
 
         if res == 0 {
+            if self.chroma_siting != ChromaSiting::Centered {
+                let mut image = YuvImage { pixels, width: output.width, align: output.align, height: output.height, subsamp: output.subsamp };
+                image.resite_chroma(ChromaSiting::Centered, self.chroma_siting);
+            }
+            #[cfg(feature = "stats")]
+            self.stats.record(start.elapsed(), jpeg_data.len(), yuv_len, output.width * output.height);
+            report_progress(&mut self.progress, rows_total, rows_total);
             Ok(())
         } else {
-            Err(unsafe { get_error(self.handle) })
+            let err = self.decompress_error(jpeg_data);
+            let output = || format!("YUV output: {}x{}, align {}, {:?}", output.width, output.height, output.align, output.subsamp);
+            Err(self.dump_failure("tjDecompressToYUV2", jpeg_data, output, err))
         }
     }
+
+    fn flags(&self) -> libc::c_int {
+        let mut flags = self.dct_method.flags();
+        if self.limits.max_scan_count.is_some() {
+            flags |= raw::TJFLAG_LIMITSCANS as libc::c_int;
+        }
+        flags
+    }
+
+    fn decompress_error(&mut self, jpeg_data: &[u8]) -> Error {
+        self.poisoned = unsafe { is_fatal_error(self.handle) };
+        let err = unsafe { get_error(self.handle) };
+        match self.limits.max_scan_count {
+            // with `TJFLAG_LIMITSCANS`, TurboJPEG aborts with a fatal error at the first scan
+            // over its limit, which requires that many SOS markers in the data
+            Some(max_scan_count) if self.poisoned && marker::count_sos_markers(jpeg_data) > LIBRARY_SCAN_LIMIT => {
+                Error::TooManyScans(max_scan_count.min(LIBRARY_SCAN_LIMIT))
+            },
+            _ => err,
+        }
+    }
+
+    /// Passes the failure to the dump sink (if any) and returns the error. The description of the
+    /// `output` is only formatted if there is a sink, so that failures do not allocate otherwise.
+    fn dump_failure(
+        &mut self,
+        operation: &'static str,
+        jpeg_data: &[u8],
+        output: impl FnOnce() -> String,
+        error: Error,
+    ) -> Error {
+        if let Some(DumpSinkBox(sink)) = &mut self.dump {
+            let settings = format!(
+                "{}, dct_method: {:?}, deblock: {:?}, limits: {:?}, leading_garbage: {}",
+                output(), self.dct_method, self.deblock, self.limits, self.leading_garbage,
+            );
+            sink.dump(&FailureReport { operation, jpeg_data, settings, error: &error });
+        }
+        error
+    }
 }
 
+/// Maximal number of scans that TurboJPEG accepts with `TJFLAG_LIMITSCANS`.
+const LIBRARY_SCAN_LIMIT: usize = 500;
+
 impl Drop for Decompressor {
     fn drop(&mut self) {
         unsafe { raw::tjDestroy(self.handle); }
@@ -251,6 +1443,80 @@ pub fn decompress(jpeg_data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>
     Ok(image)
 }
 
+/// Decompress a JPEG image with rows aligned to `align` bytes.
+///
+/// Returns a newly allocated image with the given pixel `format`, whose pitch is a multiple of
+/// `align`. See [`Decompressor::decompress_aligned_into()`] for details.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // decompress with rows aligned to 256 bytes (as required by wgpu)
+/// let image = turbojpeg::decompress_aligned(&jpeg_data, turbojpeg::PixelFormat::RGB, 256)?;
+/// assert_eq!(image.pitch, 1280);
+/// assert_eq!(image.pixels.len(), 1280 * 256);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decompress_aligned(jpeg_data: &[u8], format: PixelFormat, align: usize) -> Result<Image<Vec<u8>>> {
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(jpeg_data)?;
+    let pitch = aligned_pitch(header.width, format, align)?;
+    let len = pitch.checked_mul(header.height)
+        .ok_or(Error::IntegerOverflow("pixels len"))?;
+
+    let mut pixels = vec![0; len];
+    let image = decompressor.decompress_aligned_into(jpeg_data, format, align, &mut pixels)?;
+    let Image { width, pitch, height, format, .. } = image;
+    Ok(Image { pixels, width, pitch, height, format })
+}
+
+/// Determine the pitch of an image row with rows aligned to `align` bytes.
+///
+/// Returns `width * format.size()` rounded up to the nearest multiple of `align`, or an error on
+/// integer overflow.
+///
+/// # Panics
+///
+/// Panics if `align` is zero.
+pub fn aligned_pitch(width: usize, format: PixelFormat, align: usize) -> Result<usize> {
+    assert!(align > 0, "alignment must not be zero");
+    width.checked_mul(format.size())
+        .and_then(|len| len.checked_next_multiple_of(align))
+        .ok_or(Error::IntegerOverflow("pitch"))
+}
+
+/// Decompress a JPEG image and split it into tiles.
+///
+/// Returns newly allocated tiles of size `tile_width` x `tile_height` with the given pixel
+/// `format`, in row-major order. See [`Decompressor::decompress_tiles()`] for details.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // split the 384x256 image into tiles of 100x100 pixels
+/// let tiles = turbojpeg::decompress_tiles(&jpeg_data, turbojpeg::PixelFormat::RGB, 100, 100)?;
+/// assert_eq!(tiles.len(), 4 * 3);
+/// assert_eq!((tiles[11].width, tiles[11].height), (84, 56));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decompress_tiles(
+    jpeg_data: &[u8],
+    format: PixelFormat,
+    tile_width: usize,
+    tile_height: usize,
+) -> Result<Vec<Image<Vec<u8>>>> {
+    let mut decompressor = Decompressor::new()?;
+    decompressor.decompress_tiles(jpeg_data, format, tile_width, tile_height)
+}
+
 /// Decompress a JPEG image to YUV.
 ///
 /// Returns a newly allocated YUV image with row alignment of 4. If you have specific requirements
@@ -343,3 +1609,15 @@ pub fn read_header(jpeg_data: &[u8]) -> Result<DecompressHeader> {
     let mut decompressor = Decompressor::new()?;
     decompressor.read_header(jpeg_data)
 }
+
+/// Estimates the size in bytes of the whole-image DCT coefficient buffer that libjpeg allocates
+/// to decompress multi-scan (such as progressive) images. Returns 0 for single-scan images.
+pub(crate) fn coefficient_buffer_len(header: &DecompressHeader) -> usize {
+    if !header.multi_scan {
+        return 0
+    }
+    let (mcu_width, mcu_height) = header.subsamp.mcu_size();
+    let mcus = header.width.div_ceil(mcu_width).saturating_mul(header.height.div_ceil(mcu_height));
+    let blocks_per_mcu = header.subsamp.width() * header.subsamp.height() + header.components.saturating_sub(1);
+    mcus.saturating_mul(blocks_per_mcu).saturating_mul(64 * 2)
+}
@@ -0,0 +1,211 @@
+use crate::{Image, Compressor};
+use crate::common::{PixelFormat, Subsamp, Error, Result};
+use crate::quality::Quality;
+
+/// Encoder for sequences of frames that compresses only the regions that changed since the
+/// previous frame.
+///
+/// This is useful for screen sharing and remote desktop, where most of the screen usually stays
+/// the same between frames. The frame is split into a grid of MCU blocks (see
+/// [`Subsamp::mcu_size()`]), the blocks are compared with the previous frame, and the changed
+/// blocks are merged into rectangles, which are compressed into separate JPEG images. Because the
+/// rectangles are aligned to MCU blocks, the receiver can paste the decompressed rectangles over
+/// its copy of the previous frame without any visible seams in the block structure.
+///
+/// The first frame (and every frame that has a different size or pixel format than the previous
+/// frame) is compressed as a single region that covers the whole frame.
+///
+/// # Example
+///
+/// ```
+/// let mut encoder = turbojpeg::DiffEncoder::new(80, turbojpeg::Subsamp::Sub2x2)?;
+///
+/// // the first frame is always encoded completely
+/// let mut frame = turbojpeg::Image::mandelbrot(640, 480, turbojpeg::PixelFormat::RGB);
+/// let regions = encoder.encode(frame.as_deref())?;
+/// assert_eq!(regions.len(), 1);
+/// assert_eq!((regions[0].width, regions[0].height), (640, 480));
+///
+/// // change a few pixels
+/// for y in 100..110 {
+///     for x in 200..205 {
+///         frame.pixels[y*frame.pitch + x*3] = 255;
+///     }
+/// }
+///
+/// // only the MCU blocks that contain the changed pixels are encoded
+/// let regions = encoder.encode(frame.as_deref())?;
+/// assert_eq!(regions.len(), 1);
+/// let region = &regions[0];
+/// assert_eq!((region.x, region.y, region.width, region.height), (192, 96, 16, 16));
+/// let header = turbojpeg::read_header(&region.data)?;
+/// assert_eq!((header.width, header.height), (16, 16));
+///
+/// // nothing is encoded when the frame does not change
+/// assert!(encoder.encode(frame.as_deref())?.is_empty());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct DiffEncoder {
+    compressor: Compressor,
+    subsamp: Subsamp,
+    previous: Option<Image<Vec<u8>>>,
+}
+
+/// A changed region of a frame, produced by [`DiffEncoder::encode()`].
+#[derive(Debug, Clone)]
+pub struct ChangedRegion {
+    /// Column of the top left corner of the region in the frame.
+    pub x: usize,
+    /// Row of the top left corner of the region in the frame.
+    pub y: usize,
+    /// Width of the region in pixels.
+    pub width: usize,
+    /// Height of the region in pixels.
+    pub height: usize,
+    /// Compressed JPEG data of the region.
+    pub data: Vec<u8>,
+}
+
+impl DiffEncoder {
+    /// Create a new encoder that compresses the regions with the given `quality` and `subsamp`.
+    ///
+    /// Grayscale frames are always compressed with [`Subsamp::Gray`].
+    pub fn new(quality: impl TryInto<Quality, Error: Into<Error>>, subsamp: Subsamp) -> Result<DiffEncoder> {
+        let mut compressor = Compressor::new()?;
+        compressor.set_quality(quality)?;
+        Ok(DiffEncoder { compressor, subsamp, previous: None })
+    }
+
+    /// Get mutable access to the [`Compressor`] that compresses the regions, so that you can
+    /// change its other settings.
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    /// Forget the previous frame, so that the next frame is encoded completely.
+    ///
+    /// Call this when the receiver lost its copy of the frame (for example, when a new client
+    /// connects).
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Compare `frame` with the previous frame and compress the regions that changed.
+    ///
+    /// The regions are returned in top-to-bottom, left-to-right order and they do not overlap.
+    /// Returns an empty vector if the frame did not change.
+    pub fn encode(&mut self, frame: Image<&[u8]>) -> Result<Vec<ChangedRegion>> {
+        frame.assert_valid(frame.pixels.len());
+        let subsamp = match frame.format {
+            PixelFormat::GRAY => Subsamp::Gray,
+            _ => self.subsamp,
+        };
+        self.compressor.set_subsamp(subsamp);
+
+        let rects = match self.previous {
+            Some(ref previous) if previous.width == frame.width && previous.height == frame.height
+                && previous.format == frame.format =>
+                changed_rects(previous.as_deref(), frame, subsamp.mcu_size()),
+            _ => vec![(0, 0, frame.width, frame.height)],
+        };
+
+        let mut regions = Vec::with_capacity(rects.len());
+        for (x, y, width, height) in rects {
+            if width == 0 || height == 0 {
+                continue
+            }
+            let data = self.compressor.compress_to_vec(frame.subimage(x, y, width, height))?;
+            regions.push(ChangedRegion { x, y, width, height, data });
+        }
+
+        self.store_previous(frame);
+        Ok(regions)
+    }
+
+    /// Copy `frame` into `self.previous`, reusing the allocated memory.
+    fn store_previous(&mut self, frame: Image<&[u8]>) {
+        let row_len = frame.width * frame.format.size();
+        let mut pixels = self.previous.take().map(|previous| previous.pixels).unwrap_or_default();
+        pixels.clear();
+        pixels.reserve(row_len * frame.height);
+        for y in 0..frame.height {
+            pixels.extend_from_slice(&frame.pixels[y*frame.pitch..][..row_len]);
+        }
+        self.previous = Some(Image {
+            pixels,
+            width: frame.width,
+            pitch: row_len,
+            height: frame.height,
+            format: frame.format,
+        });
+    }
+}
+
+/// Returns the rectangles `(x, y, width, height)` of MCU blocks that differ between `previous`
+/// and `frame` (which must have the same size and format).
+fn changed_rects(
+    previous: Image<&[u8]>,
+    frame: Image<&[u8]>,
+    (mcu_width, mcu_height): (usize, usize),
+) -> Vec<(usize, usize, usize, usize)> {
+    let pixel_size = frame.format.size();
+    let columns = frame.width.div_ceil(mcu_width);
+    let rows = frame.height.div_ceil(mcu_height);
+
+    // open rectangles as (first column, end column, first row); a rectangle is extended downwards
+    // while the next block row has a run of changed blocks with exactly the same columns
+    let mut open: Vec<(usize, usize, usize)> = Vec::new();
+    let mut rects = Vec::new();
+    let mut close = |(col_0, col_1, row_0): (usize, usize, usize), row_1: usize| {
+        let x = col_0 * mcu_width;
+        let y = row_0 * mcu_height;
+        rects.push((x, y, (col_1 * mcu_width).min(frame.width) - x, (row_1 * mcu_height).min(frame.height) - y));
+    };
+
+    let mut changed = vec![false; columns];
+    for row in 0..rows {
+        let y_0 = row * mcu_height;
+        let y_1 = (y_0 + mcu_height).min(frame.height);
+        for (column, changed) in changed.iter_mut().enumerate() {
+            let start = column * mcu_width * pixel_size;
+            let end = ((column + 1) * mcu_width).min(frame.width) * pixel_size;
+            *changed = (y_0..y_1).any(|y| {
+                previous.pixels[y*previous.pitch..][start..end] != frame.pixels[y*frame.pitch..][start..end]
+            });
+        }
+
+        let mut runs = Vec::new();
+        let mut column = 0;
+        while column < columns {
+            if changed[column] {
+                let start = column;
+                while column < columns && changed[column] {
+                    column += 1;
+                }
+                runs.push((start, column));
+            } else {
+                column += 1;
+            }
+        }
+
+        let mut next_open = Vec::with_capacity(runs.len());
+        for &(col_0, col_1) in runs.iter() {
+            match open.iter().position(|&(c0, c1, _)| (c0, c1) == (col_0, col_1)) {
+                Some(index) => next_open.push(open.swap_remove(index)),
+                None => next_open.push((col_0, col_1, row)),
+            }
+        }
+        for rect in open.drain(..) {
+            close(rect, row);
+        }
+        open = next_open;
+    }
+    for rect in open.drain(..) {
+        close(rect, rows);
+    }
+
+    rects.sort_by_key(|&(x, y, _, _)| (y, x));
+    rects
+}
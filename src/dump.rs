@@ -0,0 +1,154 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::common::Error;
+
+/// Details about a JPEG image that a [`Decompressor`][crate::Decompressor] failed to decompress,
+/// passed to the [`DumpSink`].
+#[derive(Debug)]
+pub struct FailureReport<'a> {
+    /// The TurboJPEG function that failed (such as `"tjDecompress2"`).
+    pub operation: &'static str,
+    /// The JPEG data passed to TurboJPEG (without the leading garbage allowed by
+    /// [`Decompressor::set_allow_leading_garbage()`][crate::Decompressor::set_allow_leading_garbage]).
+    pub jpeg_data: &'a [u8],
+    /// The settings of the decompressor and the size and format of the output, in a
+    /// human-readable form.
+    pub settings: String,
+    /// The error returned from the decompression.
+    pub error: &'a Error,
+}
+
+impl fmt::Display for FailureReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "operation: {}", self.operation)?;
+        writeln!(f, "error: {}", self.error)?;
+        writeln!(f, "error details: {:?}", self.error)?;
+        writeln!(f, "settings: {}", self.settings)?;
+        writeln!(f, "jpeg length: {}", self.jpeg_data.len())?;
+        writeln!(f, "turbojpeg crate version: {}", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+/// Receiver of [`FailureReport`]s from a [`Decompressor`][crate::Decompressor].
+///
+/// Set the sink with [`Decompressor::set_dump_sink()`][crate::Decompressor::set_dump_sink].
+/// Whenever TurboJPEG fails to decompress an image (or to read its header), the decompressor
+/// passes the offending input, its settings and the error to the sink before it returns the
+/// error, so that images which fail in production can be collected and attached to bug reports.
+/// Errors that are detected before TurboJPEG is called (such as exceeded
+/// [`DecompressLimits`][crate::DecompressLimits] or cancellation) are not reported.
+///
+/// Use [`DumpDir`] to write the reports into a directory, or a closure
+/// `FnMut(&FailureReport)` to handle them yourself.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// let reports = Arc::new(Mutex::new(Vec::new()));
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// decompressor.set_dump_sink({
+///     let reports = reports.clone();
+///     move |report: &turbojpeg::FailureReport| {
+///         reports.lock().unwrap().push((report.operation, report.jpeg_data.len(), report.to_string()));
+///     }
+/// });
+///
+/// // a truncated image fails to decompress
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let truncated = &jpeg_data[..100];
+/// assert!(decompressor.decompress_to_buf(truncated, turbojpeg::PixelFormat::RGB).is_err());
+///
+/// let reports = reports.lock().unwrap();
+/// assert_eq!(reports.len(), 1);
+/// assert_eq!(reports[0].0, "tjDecompressHeader3");
+/// assert_eq!(reports[0].1, 100);
+/// assert!(reports[0].2.contains("error: "));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait DumpSink: Send {
+    /// Receives the report about a failed decompression.
+    ///
+    /// The sink cannot return errors: failures to store the report should be ignored (or logged),
+    /// so that they do not hide the original error.
+    fn dump(&mut self, report: &FailureReport<'_>);
+}
+
+impl<F> DumpSink for F
+    where F: FnMut(&FailureReport<'_>) + Send
+{
+    fn dump(&mut self, report: &FailureReport<'_>) {
+        self(report)
+    }
+}
+
+/// [`DumpSink`] that writes every report into a directory.
+///
+/// For every failure, it writes the input into a file `<prefix>-<time>-<n>.jpg` and the rest of
+/// the [`FailureReport`] into `<prefix>-<time>-<n>.txt`, where `<time>` is the Unix time in
+/// milliseconds and `<n>` is a counter that is unique in the process. Errors from writing the
+/// files are ignored. The directory must exist.
+///
+/// # Example
+///
+/// ```
+/// let dir = std::env::temp_dir().join(format!("turbojpeg-dumps-{}", std::process::id()));
+/// std::fs::create_dir_all(&dir)?;
+///
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// decompressor.set_dump_sink(turbojpeg::DumpDir::new(&dir).with_prefix("thumbnailer"));
+/// assert!(decompressor.read_header(b"\xff\xd8 not really a JPEG").is_err());
+///
+/// let mut files = std::fs::read_dir(&dir)?
+///     .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+///     .collect::<std::io::Result<Vec<_>>>()?;
+/// files.sort();
+/// assert_eq!(files.len(), 2);
+/// assert!(files[0].starts_with("thumbnailer-") && files[0].ends_with(".jpg"));
+/// assert!(files[1].ends_with(".txt"));
+/// std::fs::remove_dir_all(&dir)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct DumpDir {
+    dir: PathBuf,
+    prefix: String,
+}
+
+impl DumpDir {
+    /// Creates a sink that writes the reports into `dir` with the prefix `failure`.
+    pub fn new(dir: impl Into<PathBuf>) -> DumpDir {
+        DumpDir { dir: dir.into(), prefix: "failure".into() }
+    }
+
+    /// Sets the prefix of the file names.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> DumpDir {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+impl DumpSink for DumpDir {
+    fn dump(&mut self, report: &FailureReport<'_>) {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{}-{}-{}", self.prefix, time, n);
+        let _ = std::fs::write(self.dir.join(format!("{}.jpg", name)), report.jpeg_data);
+        let _ = std::fs::write(self.dir.join(format!("{}.txt", name)), report.to_string());
+    }
+}
+
+pub(crate) struct DumpSinkBox(pub(crate) Box<dyn DumpSink>);
+
+impl fmt::Debug for DumpSinkBox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("DumpSink")
+    }
+}
@@ -0,0 +1,113 @@
+use std::borrow::Cow;
+use crate::Image;
+use crate::common::{PixelFormat, Subsamp, Result, Error};
+use crate::quality::Quality;
+use crate::compress::compress;
+use crate::decompress::decompress;
+
+/// Decoder for an image format other than JPEG (such as HEIC), used by [`decompress_any()`] and
+/// [`to_jpeg()`].
+///
+/// This crate does not decode other formats itself; implement this trait with the library of your
+/// choice (for example, the bindings to libheif) and wrap its errors in
+/// [`Error::Backend`][crate::Error::Backend]. Use [`is_heif()`] to detect HEIC/HEIF files.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{ExternalDecoder, Image, PixelFormat};
+///
+/// // a decoder for a made-up format with a 6-byte header: "RAW", width, height and a gray level
+/// struct FlatDecoder;
+///
+/// impl ExternalDecoder for FlatDecoder {
+///     fn can_decode(&self, data: &[u8]) -> bool {
+///         data.starts_with(b"RAW") && data.len() == 6
+///     }
+///
+///     fn decode(&self, data: &[u8], format: PixelFormat) -> turbojpeg::Result<Image<Vec<u8>>> {
+///         let (width, height) = (data[3] as usize, data[4] as usize);
+///         let pixels = vec![data[5]; width * height * format.size()];
+///         Ok(Image { pixels, width, pitch: width * format.size(), height, format })
+///     }
+/// }
+///
+/// let decoders: &[&dyn ExternalDecoder] = &[&FlatDecoder];
+///
+/// // JPEG images are decompressed with TurboJPEG, other images with the first matching decoder
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::decompress_any(&jpeg_data, PixelFormat::RGB, decoders)?;
+/// assert_eq!((image.width, image.height), (384, 256));
+/// let image = turbojpeg::decompress_any(b"RAW\x10\x08\x80", PixelFormat::RGB, decoders)?;
+/// assert_eq!((image.width, image.height), (16, 8));
+/// assert!(turbojpeg::decompress_any(b"GIF89a", PixelFormat::RGB, decoders).is_err());
+///
+/// // JPEG images are passed through, other images are compressed
+/// let jpeg = turbojpeg::to_jpeg(&jpeg_data, 90, turbojpeg::Subsamp::Sub2x2, decoders)?;
+/// assert_eq!(*jpeg, *jpeg_data);
+/// let jpeg = turbojpeg::to_jpeg(b"RAW\x10\x08\x80", 90, turbojpeg::Subsamp::Sub2x2, decoders)?;
+/// assert_eq!(turbojpeg::read_header(&jpeg)?.width, 16);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait ExternalDecoder: Send + Sync {
+    /// Returns true if `data` are in the format of this decoder. This should only check the magic
+    /// bytes of the format.
+    fn can_decode(&self, data: &[u8]) -> bool;
+
+    /// Decodes the image into pixel `format`.
+    fn decode(&self, data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>>>;
+}
+
+/// Decompresses a JPEG image with TurboJPEG, or an image in another format with the first of
+/// `decoders` that can decode it.
+///
+/// Returns [`Error::Unsupported`] if the data are not JPEG and none of the decoders can decode
+/// them. See [`ExternalDecoder`] for an example.
+pub fn decompress_any(data: &[u8], format: PixelFormat, decoders: &[&dyn ExternalDecoder]) -> Result<Image<Vec<u8>>> {
+    if is_jpeg(data) {
+        return decompress(data, format)
+    }
+    match decoders.iter().find(|decoder| decoder.can_decode(data)) {
+        Some(decoder) => decoder.decode(data, format),
+        None => Err(Error::Unsupported("image format without a decoder")),
+    }
+}
+
+/// Returns JPEG data unchanged, or decodes an image in another format with the first of
+/// `decoders` that can decode it and compresses it into JPEG.
+///
+/// This is useful for tools that store JPEG images, but accept other formats (such as HEIC
+/// photos from iPhones) on input. See [`ExternalDecoder`] for an example.
+pub fn to_jpeg<'a>(
+    data: &'a [u8],
+    quality: impl TryInto<Quality, Error: Into<Error>>,
+    subsamp: Subsamp,
+    decoders: &[&dyn ExternalDecoder],
+) -> Result<Cow<'a, [u8]>> {
+    if is_jpeg(data) {
+        return Ok(Cow::Borrowed(data))
+    }
+    let image = decompress_any(data, PixelFormat::RGB, decoders)?;
+    Ok(Cow::Owned(compress(image.as_deref(), quality, subsamp)?.to_vec()))
+}
+
+/// Returns true if `data` start with the HEIF file type box (`ftyp`) with one of the brands used
+/// by HEIC and HEIF images.
+///
+/// # Example
+///
+/// ```
+/// let heic_header = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+/// assert!(turbojpeg::is_heif(heic_header));
+/// assert!(!turbojpeg::is_heif(&std::fs::read("examples/parrots.jpg")?));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn is_heif(data: &[u8]) -> bool {
+    const BRANDS: [&[u8]; 8] = [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"];
+    data.len() >= 12 && &data[4..8] == b"ftyp" && BRANDS.contains(&&data[8..12])
+}
+
+fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xff, 0xd8])
+}
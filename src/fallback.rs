@@ -0,0 +1,218 @@
+//! Pure-Rust fallback backend for targets without the native TurboJPEG library.
+//!
+//! The functions in this module have the same signatures as [`decompress()`][crate::decompress]
+//! and [`compress()`][crate::compress], but they are implemented with the pure-Rust
+//! [`jpeg-decoder`][jpeg-decoder] crate and the JPEG encoder of the [`image`][image-rs] crate, so
+//! they also work on targets such as `wasm32-unknown-unknown`. Downstream crates can select the
+//! implementation at compile time and offer the same API everywhere:
+//!
+//! ```
+//! # use turbojpeg::{Image, PixelFormat};
+//! #[cfg(not(target_arch = "wasm32"))]
+//! use turbojpeg::decompress;
+//! #[cfg(target_arch = "wasm32")]
+//! use turbojpeg::fallback::decompress;
+//!
+//! fn thumbnail_source(jpeg_data: &[u8]) -> turbojpeg::Result<Image<Vec<u8>>> {
+//!     decompress(jpeg_data, PixelFormat::RGB)
+//! }
+//! # thumbnail_source(&std::fs::read("examples/parrots.jpg")?)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The fallback backend is several times slower than TurboJPEG and supports fewer options:
+//!
+//! - Only 8-bit JPEG images can be decompressed, without scaling, and CMYK images can only be
+//!   decompressed to [`PixelFormat::CMYK`] or to the RGB formats.
+//! - Color images are always compressed without chrominance subsampling ([`Subsamp::None`]),
+//!   except that [`Subsamp::Gray`] produces a grayscale image. CMYK images cannot be compressed.
+//!
+//! Unsupported operations return [`Error::Unsupported`], and errors of the underlying crates are
+//! returned as [`Error::Backend`]. The decompressed pixels are not bit-exact with TurboJPEG.
+//! [`FallbackBackend`] implements [`JpegBackend`], so it can also be swapped with TurboJPEG at
+//! runtime.
+//!
+//! Note that this crate itself still links to the native TurboJPEG library, so it does not build
+//! on targets where the library is not available; the fallback is meant for crates that depend on
+//! this crate only on the native targets.
+//!
+//! This module is available with the `fallback` feature.
+//!
+//! [jpeg-decoder]: https://docs.rs/jpeg-decoder
+//! [image-rs]: https://docs.rs/image
+use image::ImageEncoder as _;
+use crate::Image;
+use crate::backend::{JpegBackend, BackendCapabilities};
+use crate::common::{PixelFormat, Subsamp, Result, Error};
+use crate::quality::Quality;
+
+/// Decompresses a JPEG image into pixel `format` with the pure-Rust backend.
+///
+/// This is the pure-Rust counterpart of [`decompress()`][crate::decompress]. The returned image
+/// has no padding between the rows, and the X and alpha components are set to 255.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::fallback::decompress(&jpeg_data, turbojpeg::PixelFormat::RGBA)?;
+/// assert_eq!((image.width, image.height, image.pitch), (384, 256, 384 * 4));
+///
+/// // the result is close to the output of TurboJPEG
+/// let native = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGBA)?;
+/// let max_diff = image.pixels.iter().zip(native.pixels.iter())
+///     .map(|(a, b)| a.abs_diff(*b))
+///     .max();
+/// assert!(max_diff <= Some(8));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn decompress(jpeg_data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>>> {
+    let mut decoder = jpeg_decoder::Decoder::new(jpeg_data);
+    let data = decoder.decode().map_err(|err| Error::Backend(Box::new(err)))?;
+    let info = decoder.info().ok_or(Error::Null())?;
+    let (width, height) = (info.width as usize, info.height as usize);
+
+    let components = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => 1,
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        jpeg_decoder::PixelFormat::CMYK32 => 4,
+        jpeg_decoder::PixelFormat::L16 => return Err(Error::Unsupported("JPEG images with more than 8 bits")),
+    };
+    if components == 4 && format == PixelFormat::GRAY {
+        return Err(Error::Unsupported("decompressing CMYK images to grayscale"))
+    }
+    if components != 4 && format == PixelFormat::CMYK {
+        return Err(Error::Unsupported("decompressing non-CMYK images to CMYK"))
+    }
+
+    let pixel_count = width.checked_mul(height).ok_or(Error::IntegerOverflow("pixel count"))?;
+    let pitch = width.checked_mul(format.size()).ok_or(Error::IntegerOverflow("pitch"))?;
+    let mut pixels = vec![255; pixel_count * format.size()];
+    for (src, dst) in data.chunks_exact(components).zip(pixels.chunks_exact_mut(format.size())) {
+        let (r, g, b) = match *src {
+            [y] => (y, y, y),
+            [r, g, b] => (r, g, b),
+            [c, m, y, k] => {
+                if format == PixelFormat::CMYK {
+                    dst.copy_from_slice(src);
+                    continue
+                }
+                let ink = |v: u8| ((255 - v as u32) * (255 - k as u32) / 255) as u8;
+                (ink(c), ink(m), ink(y))
+            },
+            _ => unreachable!(),
+        };
+        match format.rgb_offsets() {
+            Some((r_off, g_off, b_off)) => {
+                dst[r_off] = r;
+                dst[g_off] = g;
+                dst[b_off] = b;
+            },
+            None => dst[0] = luma(r, g, b),
+        }
+    }
+
+    Ok(Image { pixels, width, pitch, height, format })
+}
+
+/// Compresses an image into JPEG with the pure-Rust backend.
+///
+/// This is the pure-Rust counterpart of [`compress()`][crate::compress]. Note that color images
+/// are always compressed without chrominance subsampling, unless `subsamp` is [`Subsamp::Gray`],
+/// which produces a grayscale JPEG image. CMYK images are not supported.
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(128, 96, turbojpeg::PixelFormat::BGRA);
+/// let jpeg_data = turbojpeg::fallback::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x1)?;
+///
+/// // the output is a regular JPEG image
+/// let header = turbojpeg::read_header(&jpeg_data)?;
+/// assert_eq!((header.width, header.height), (128, 96));
+/// assert_eq!(header.subsamp, turbojpeg::Subsamp::None);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn compress(image: Image<&[u8]>, quality: impl TryInto<Quality, Error: Into<Error>>, subsamp: Subsamp) -> Result<Vec<u8>> {
+    image.assert_valid(image.pixels.len());
+    let quality: Quality = quality.try_into().map_err(Into::into)?;
+    let Image { pixels, width, pitch, height, format } = image;
+    if format == PixelFormat::CMYK {
+        return Err(Error::Unsupported("compressing CMYK images"))
+    }
+    let width_u32 = u32::try_from(width).map_err(|_| Error::IntegerOverflow("width"))?;
+    let height_u32 = u32::try_from(height).map_err(|_| Error::IntegerOverflow("height"))?;
+    let gray = format == PixelFormat::GRAY || subsamp == Subsamp::Gray;
+
+    // the encoder needs packed pixels in the RGB or grayscale format
+    let mut packed = Vec::with_capacity(width * height * if gray { 1 } else { 3 });
+    for row in pixels.chunks(pitch).take(height) {
+        for pixel in row[..width * format.size()].chunks_exact(format.size()) {
+            match (format.rgb_offsets(), gray) {
+                (Some((r, g, b)), false) => packed.extend_from_slice(&[pixel[r], pixel[g], pixel[b]]),
+                (Some((r, g, b)), true) => packed.push(luma(pixel[r], pixel[g], pixel[b])),
+                (None, false) => packed.extend_from_slice(&[pixel[0]; 3]),
+                (None, true) => packed.push(pixel[0]),
+            }
+        }
+    }
+
+    let color_type = if gray { image::ColorType::L8 } else { image::ColorType::Rgb8 };
+    let mut jpeg_data = Vec::new();
+    #[allow(clippy::useless_conversion)] // `write_image()` takes `ExtendedColorType` since image 0.25
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg_data, quality.get() as u8)
+        .write_image(&packed, width_u32, height_u32, color_type.into())
+        .map_err(|err| Error::Backend(Box::new(err)))?;
+    Ok(jpeg_data)
+}
+
+/// Computes the luma of an RGB pixel with the BT.601 weights, like libjpeg.
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((19595 * r as u32 + 38470 * g as u32 + 7471 * b as u32 + 32768) >> 16) as u8
+}
+
+/// [`JpegBackend`] implemented with [`decompress()`] and [`compress()`] from this module.
+///
+/// Lossless transforms are not supported.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FallbackBackend;
+
+impl FallbackBackend {
+    /// Creates the backend.
+    pub fn new() -> FallbackBackend {
+        FallbackBackend
+    }
+}
+
+impl JpegBackend for FallbackBackend {
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            compress_formats: &[
+                PixelFormat::RGB, PixelFormat::BGR, PixelFormat::RGBX, PixelFormat::BGRX,
+                PixelFormat::XBGR, PixelFormat::XRGB, PixelFormat::GRAY, PixelFormat::RGBA,
+                PixelFormat::BGRA, PixelFormat::ABGR, PixelFormat::ARGB,
+            ],
+            decompress_formats: &PixelFormat::ALL,
+            subsamps: &[Subsamp::None, Subsamp::Gray],
+            precisions: &[8],
+            lossless: false,
+            arithmetic: false,
+            transform: false,
+            max_dimension: u16::MAX as usize,
+        }
+    }
+
+    fn decompress(&mut self, jpeg_data: &[u8], format: PixelFormat) -> Result<Image<Vec<u8>>> {
+        decompress(jpeg_data, format)
+    }
+
+    fn compress(&mut self, image: Image<&[u8]>, quality: Quality, subsamp: Subsamp) -> Result<Vec<u8>> {
+        compress(image, quality, subsamp)
+    }
+}
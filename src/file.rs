@@ -0,0 +1,117 @@
+use std::convert::TryInto as _;
+use std::ffi::CString;
+use std::io;
+use std::path::Path;
+use crate::{Image, raw};
+use crate::common::{PixelFormat, Result, Error, get_error};
+
+/// Loads an uncompressed image from a BMP or PPM/PGM file.
+///
+/// This uses the simple image loader that is built into TurboJPEG, so it is useful for small tools
+/// and tests that don't want to depend on the [`image`][image-rs] crate. The pixel format of the
+/// returned image depends on the file: [`PixelFormat::BGR`] for BMP files, [`PixelFormat::RGB`]
+/// for PPM files and [`PixelFormat::GRAY`] for PGM files. Use [`load_image_as()`] to convert the
+/// pixels to a specific format.
+///
+/// The rows of the returned image are tightly packed (`pitch == width * format.size()`).
+///
+/// [image-rs]: https://docs.rs/image/*/image/index.html
+///
+/// # Example
+///
+/// ```
+/// let path = std::env::temp_dir().join("turbojpeg-load-image.ppm");
+/// let image = turbojpeg::Image::mandelbrot(64, 48, turbojpeg::PixelFormat::RGB);
+/// turbojpeg::save_image(&path, image.as_deref())?;
+///
+/// let loaded = turbojpeg::load_image(&path)?;
+/// assert_eq!(loaded.format, turbojpeg::PixelFormat::RGB);
+/// assert_eq!((loaded.width, loaded.height), (64, 48));
+/// assert_eq!(loaded.pixels, image.pixels);
+/// # std::fs::remove_file(&path)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[doc(alias = "tjLoadImage")]
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Image<Vec<u8>>> {
+    load_image_inner(path.as_ref(), raw::TJPF_TJPF_UNKNOWN)
+}
+
+/// Loads an uncompressed image from a BMP or PPM/PGM file and converts it to pixel `format`.
+///
+/// See [`load_image()`] for details. Note that grayscale images can only be loaded from PGM files
+/// and 8-bit BMP files with a grayscale palette, and that the conversion to
+/// [`PixelFormat::CMYK`] is suitable only for testing.
+#[doc(alias = "tjLoadImage")]
+pub fn load_image_as<P: AsRef<Path>>(path: P, format: PixelFormat) -> Result<Image<Vec<u8>>> {
+    load_image_inner(path.as_ref(), format as libc::c_int)
+}
+
+fn load_image_inner(path: &Path, format: libc::c_int) -> Result<Image<Vec<u8>>> {
+    let path = path_to_cstring(path)?;
+    let mut width = 0;
+    let mut height = 0;
+    let mut format = format;
+    let ptr = unsafe {
+        raw::tjLoadImage(path.as_ptr(), &mut width, 1, &mut height, &mut format, 0)
+    };
+    if ptr.is_null() {
+        return Err(unsafe { get_error(std::ptr::null_mut()) })
+    }
+
+    // copy the pixels into a `Vec` and free the buffer allocated by TurboJPEG, even if we fail
+    let res = (|| {
+        let width: usize = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+        let height: usize = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+        let format = PixelFormat::from_i32(format)?;
+        let pitch = width * format.size();
+        let pixels = unsafe { std::slice::from_raw_parts(ptr, pitch * height) }.to_vec();
+        Ok(Image { pixels, width, pitch, height, format })
+    })();
+    unsafe { raw::tjFree(ptr) };
+    res
+}
+
+/// Saves an uncompressed image to a BMP or PPM/PGM file.
+///
+/// The file format is determined by the extension of `path`: files with extension `.bmp` are
+/// saved as BMP, other files as PPM (or PGM for [`PixelFormat::GRAY`] images). This is useful for
+/// writing debugging dumps of images. See [`load_image()`] for an example.
+#[doc(alias = "tjSaveImage")]
+pub fn save_image<P: AsRef<Path>>(path: P, image: Image<&[u8]>) -> Result<()> {
+    image.assert_valid(image.pixels.len());
+    let path = path_to_cstring(path.as_ref())?;
+    let Image { pixels, width, pitch, height, format } = image;
+    let width = width.try_into().map_err(|_| Error::IntegerOverflow("width"))?;
+    let pitch = pitch.try_into().map_err(|_| Error::IntegerOverflow("pitch"))?;
+    let height = height.try_into().map_err(|_| Error::IntegerOverflow("height"))?;
+
+    // TurboJPEG does not modify the buffer, the pointer is mutable only because of an oversight in
+    // the C API
+    let res = unsafe {
+        raw::tjSaveImage(
+            path.as_ptr(),
+            pixels.as_ptr() as *mut libc::c_uchar, width, pitch, height,
+            format as libc::c_int, 0,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(unsafe { get_error(std::ptr::null_mut()) })
+    }
+}
+
+fn path_to_cstring(path: &Path) -> Result<CString> {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt as _;
+        path.as_os_str().as_bytes()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8"))?
+        .as_bytes();
+    CString::new(bytes)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a null byte").into())
+}
@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of `fork()`s that led from the first call of this function to the current
+/// process.
+///
+/// State that is shared by threads (such as the idle instances of a
+/// [`HandlePool`][crate::HandlePool]) remembers the generation in which it was created. When the
+/// generation changes, the state was inherited from the parent process, so it must not be used:
+/// the threads that owned parts of it do not exist in the child.
+///
+/// The first call registers a `pthread_atfork()` handler that increments the generation in the
+/// child. The handler only updates an atomic counter, which is async-signal-safe, so it is
+/// correct even if the parent was multithreaded.
+pub(crate) fn generation() -> u64 {
+    #[cfg(unix)]
+    {
+        static REGISTER: std::sync::Once = std::sync::Once::new();
+        REGISTER.call_once(|| unsafe {
+            pthread_atfork(None, None, Some(after_fork_in_child));
+        });
+    }
+    GENERATION.load(Ordering::Acquire)
+}
+
+#[cfg(unix)]
+unsafe extern "C" fn after_fork_in_child() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+#[cfg(unix)]
+extern "C" {
+    // declared here, because the libc crate does not declare it on all Unix systems
+    fn pthread_atfork(
+        prepare: Option<unsafe extern "C" fn()>,
+        parent: Option<unsafe extern "C" fn()>,
+        child: Option<unsafe extern "C" fn()>,
+    ) -> libc::c_int;
+}
@@ -0,0 +1,193 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::Image;
+use crate::common::{PixelFormat, Result, Error};
+use crate::decompress::{DecompressHeader, aligned_pitch};
+
+/// Maximal width and height of a JPEG image in pixels (`JPEG_MAX_DIMENSION` in libjpeg).
+///
+/// This is the default of [`max_dimension()`].
+pub const MAX_DIMENSION: usize = 65500;
+
+/// Maximal width and height that can be stored in the frame header of a JPEG image.
+const FRAME_MAX_DIMENSION: usize = 65535;
+
+static MAX_DIMENSION_LIMIT: AtomicUsize = AtomicUsize::new(MAX_DIMENSION);
+
+/// Set the maximal width and height of images in pixels that this crate accepts in this process.
+///
+/// The width and height are checked against this limit when an image is created with
+/// [`Image::new()`] or [`Geometry`], before an image is compressed, and when the header of a JPEG
+/// image is read, so images that are too large fail early with [`Error::DimensionTooLarge`]
+/// instead of failing inside TurboJPEG. The default is [`MAX_DIMENSION`] (65500), which is the
+/// limit of libjpeg-turbo. Raise it only if you link against a build of libjpeg-turbo with a
+/// larger `JPEG_MAX_DIMENSION` (otherwise TurboJPEG rejects these images anyway). The limit is
+/// clamped to 65535, the largest dimension that the frame header of a JPEG image can store.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Error, Image, PixelFormat};
+///
+/// assert_eq!(turbojpeg::max_dimension(), turbojpeg::MAX_DIMENSION);
+/// let res = Image::new(vec![0; 3 * 70_000], 70_000, 3 * 70_000, 1, PixelFormat::RGB);
+/// assert!(matches!(res, Err(Error::DimensionTooLarge(70_000, 1))));
+///
+/// turbojpeg::set_max_dimension(100_000);
+/// assert_eq!(turbojpeg::max_dimension(), 65535);
+/// # turbojpeg::set_max_dimension(turbojpeg::MAX_DIMENSION);
+/// ```
+pub fn set_max_dimension(max_dimension: usize) {
+    MAX_DIMENSION_LIMIT.store(max_dimension.min(FRAME_MAX_DIMENSION), Ordering::Relaxed);
+}
+
+/// Get the maximal width and height of images in pixels (see [`set_max_dimension()`]).
+pub fn max_dimension() -> usize {
+    MAX_DIMENSION_LIMIT.load(Ordering::Relaxed)
+}
+
+/// Returns [`Error::DimensionTooLarge`] if the width or height exceeds [`max_dimension()`].
+pub(crate) fn check_max_dimension(width: usize, height: usize) -> Result<()> {
+    let max_dimension = max_dimension();
+    if width > max_dimension || height > max_dimension {
+        return Err(Error::DimensionTooLarge(width, height))
+    }
+    Ok(())
+}
+
+/// Validated size and memory layout of an image.
+///
+/// Computing the pitch and the buffer length by hand (`width * 4 * height`) is easy to get wrong:
+/// the pixel size may not match the pixel format, and the multiplication can silently overflow on
+/// 32-bit targets. A `Geometry` can only be created with a width and height between 1 and
+/// [`max_dimension()`] and with a pitch that is large enough for the pixel format, and all its
+/// derived quantities (row length, buffer length, pixel offsets) are computed with checked
+/// arithmetic, so they are guaranteed not to overflow. Invalid geometries are rejected with
+/// [`Error::InvalidGeometry`] (or [`Error::DimensionTooLarge`] if the image is too large).
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Geometry, PixelFormat};
+///
+/// let geometry = Geometry::with_align(1920, 1080, PixelFormat::RGB, 256)?;
+/// assert_eq!(geometry.pitch(), 5888);
+/// assert_eq!(geometry.len(), 5888 * 1080);
+///
+/// let image = geometry.alloc_image();
+/// assert_eq!(image.pixels.len(), geometry.len());
+///
+/// assert!(Geometry::new(100_000, 10, PixelFormat::RGB).is_err());
+/// assert!(Geometry::with_pitch(100, 10, PixelFormat::RGBA, 300).is_err());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Geometry {
+    width: usize,
+    height: usize,
+    pitch: usize,
+    format: PixelFormat,
+    len: usize,
+}
+
+impl Geometry {
+    /// Creates the geometry of an image with rows of `width * format.size()` bytes without any
+    /// padding.
+    pub fn new(width: usize, height: usize, format: PixelFormat) -> Result<Geometry> {
+        check_dimensions(width, height)?;
+        Geometry::with_pitch(width, height, format, width * format.size())
+    }
+
+    /// Creates the geometry of an image with the given `pitch` (size of one row in bytes).
+    pub fn with_pitch(width: usize, height: usize, format: PixelFormat, pitch: usize) -> Result<Geometry> {
+        check_dimensions(width, height)?;
+        if pitch < width * format.size() {
+            return Err(Error::InvalidGeometry("pitch is smaller than the row length"))
+        }
+        let len = pitch.checked_mul(height).ok_or(Error::IntegerOverflow("pixels len"))?;
+        Ok(Geometry { width, height, pitch, format, len })
+    }
+
+    /// Creates the geometry of an image with rows aligned to `align` bytes (see
+    /// [`aligned_pitch()`][crate::aligned_pitch]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is zero.
+    pub fn with_align(width: usize, height: usize, format: PixelFormat, align: usize) -> Result<Geometry> {
+        check_dimensions(width, height)?;
+        Geometry::with_pitch(width, height, format, aligned_pitch(width, format, align)?)
+    }
+
+    /// Creates the geometry of the image described by a JPEG `header`, decompressed into pixel
+    /// `format`.
+    pub fn from_header(header: &DecompressHeader, format: PixelFormat) -> Result<Geometry> {
+        Geometry::new(header.width, header.height, format)
+    }
+
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the size of one row in memory in bytes, including the padding.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// Returns the pixel format of the image.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Returns the size of the pixels in one row in bytes (`width * format.size()`), without the
+    /// padding.
+    pub fn row_len(&self) -> usize {
+        self.width * self.format.size()
+    }
+
+    /// Returns the length of the buffer for the whole image in bytes (`pitch * height`).
+    #[allow(clippy::len_without_is_empty)] // the geometry is never empty
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the offset of the pixel in column `x` and row `y`, or `None` if the pixel is out of
+    /// the image.
+    pub fn pixel_offset(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.pitch + x * self.format.size())
+        } else {
+            None
+        }
+    }
+
+    /// Creates an image with this geometry from `pixels`.
+    ///
+    /// Returns [`Error::OutputTooSmall`] if `pixels` are shorter than [`len()`][Self::len].
+    pub fn image<T>(&self, pixels: T) -> Result<Image<T>> where T: Deref<Target = [u8]> {
+        if pixels.len() < self.len {
+            return Err(Error::OutputTooSmall(self.len))
+        }
+        Ok(Image { pixels, width: self.width, pitch: self.pitch, height: self.height, format: self.format })
+    }
+
+    /// Allocates a zero-initialized image with this geometry.
+    pub fn alloc_image(&self) -> Image<Vec<u8>> {
+        Image { pixels: vec![0; self.len], width: self.width, pitch: self.pitch, height: self.height, format: self.format }
+    }
+}
+
+fn check_dimensions(width: usize, height: usize) -> Result<()> {
+    if width == 0 || height == 0 {
+        Err(Error::InvalidGeometry("image is empty"))
+    } else {
+        check_max_dimension(width, height)
+    }
+}
@@ -0,0 +1,53 @@
+use sha2::{Digest as _, Sha256};
+use crate::Decompressor;
+use crate::common::{PixelFormat, Colorspace, Result};
+use crate::scale::ScalingFactor;
+
+/// Computes a SHA-256 hash of the decompressed pixels of a JPEG image.
+///
+/// The image is decompressed (and scaled by `factor`) and the pixels are hashed in a canonical
+/// layout: the width and height as little-endian `u64`, followed by tightly packed RGB pixels
+/// (or CMYK pixels for CMYK and YCCK images). The hash does not depend on the metadata (such as
+/// EXIF data or comments) or on the entropy coding (baseline or progressive), so it can be used to
+/// detect files that contain the same image.
+///
+/// Scaling the image down (for example, with [`ScalingFactor::ONE_EIGHTH`]) makes the hash much
+/// faster to compute, but images that differ only in fine details may have the same hash.
+///
+/// Note that the decompressed pixels may differ between versions of libjpeg-turbo (see the
+/// [crate documentation](crate#determinism)), so you should not store the hashes for a long time.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // remove metadata and convert the image to baseline JPEG
+/// let stripped_data = turbojpeg::strip_metadata(&jpeg_data, &turbojpeg::StripMetadata::all())?;
+/// let mut transformer = turbojpeg::Transformer::new()?;
+/// let baseline_data = transformer.reformat(&stripped_data, turbojpeg::EntropyCoding::Baseline)?;
+///
+/// // the files are different, but they contain the same image
+/// assert_ne!(baseline_data.len(), jpeg_data.len());
+/// let factor = turbojpeg::ScalingFactor::ONE;
+/// assert_eq!(turbojpeg::pixel_hash(&jpeg_data, factor)?, turbojpeg::pixel_hash(&baseline_data, factor)?);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "hash")))]
+pub fn pixel_hash(jpeg_data: &[u8], factor: ScalingFactor) -> Result<[u8; 32]> {
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(jpeg_data)?;
+    let format = match header.colorspace {
+        Colorspace::CMYK | Colorspace::YCCK => PixelFormat::CMYK,
+        _ => PixelFormat::RGB,
+    };
+    let image = decompressor.decompress_scaled(jpeg_data, format, factor)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update((image.width as u64).to_le_bytes());
+    hasher.update((image.height as u64).to_le_bytes());
+    hasher.update(&image.pixels);
+    Ok(hasher.finalize().into())
+}
@@ -31,6 +31,32 @@ pub struct Image<T> {
 }
 
 impl<T> Image<T> {
+    /// Creates an image and checks that it is valid.
+    ///
+    /// Returns [`Error::DimensionTooLarge`][crate::Error::DimensionTooLarge] if the width or
+    /// height exceeds [`max_dimension()`][crate::max_dimension], and
+    /// [`Error::InvalidGeometry`][crate::Error::InvalidGeometry] or
+    /// [`Error::OutputTooSmall`][crate::Error::OutputTooSmall] if the image is empty, the `pitch`
+    /// is too small or the `pixels` are too short (see [`Geometry`][crate::Geometry]). The fields
+    /// are public, so you can also create an image without these checks.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{Image, PixelFormat};
+    ///
+    /// let image = Image::new(vec![0; 64 * 48 * 4], 64, 64 * 4, 48, PixelFormat::RGBA)?;
+    /// assert_eq!(image.width, 64);
+    /// assert!(Image::new(vec![0; 100], 64, 64 * 4, 48, PixelFormat::RGBA).is_err());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new(pixels: T, width: usize, pitch: usize, height: usize, format: PixelFormat) -> crate::Result<Image<T>>
+        where T: Deref<Target = [u8]>
+    {
+        crate::Geometry::with_pitch(width, height, format, pitch)?.image(pixels)
+    }
+
     /// Converts from `&Image<T>` to `Image<&T::Target>`.
     ///
     /// In particular, you can use this to get `Image<&[u8]>` from `Image<Vec<u8>>`.
@@ -57,6 +83,62 @@ impl<T> Image<T> {
         }
     }
 
+    /// Returns a view of the rectangular part of the image with top left corner at (`x`, `y`) and
+    /// the given `width` and `height`.
+    ///
+    /// The returned image shares the pixels with `self` (it uses the same pitch), so no data is
+    /// copied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rectangle does not fit into the image.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let image = turbojpeg::Image::mandelbrot(500, 400, turbojpeg::PixelFormat::RGB);
+    ///
+    /// // compress only the center of the image
+    /// let center = image.subimage(100, 100, 300, 200);
+    /// assert_eq!((center.width, center.height), (300, 200));
+    /// let jpeg_data = turbojpeg::compress(center, 95, turbojpeg::Subsamp::Sub2x2)?;
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn subimage(&self, x: usize, y: usize, width: usize, height: usize) -> Image<&[u8]>
+        where T: Deref<Target = [u8]>
+    {
+        assert!(x.checked_add(width).map(|right| right <= self.width).unwrap_or(false),
+            "columns {}..{}+{} are out of image width {}", x, x, width, self.width);
+        assert!(y.checked_add(height).map(|bottom| bottom <= self.height).unwrap_or(false),
+            "rows {}..{}+{} are out of image height {}", y, y, height, self.height);
+        self.assert_valid(self.pixels.len());
+
+        let start = (y*self.pitch + x*self.format.size()).min(self.pixels.len());
+        Image {
+            pixels: &self.pixels[start..],
+            width,
+            pitch: self.pitch,
+            height,
+            format: self.format,
+        }
+    }
+
+    /// Copies the image into a new image with owned pixels.
+    ///
+    /// The rows of the new image are tightly packed (`pitch == width * format.size()`).
+    pub fn to_owned_image(&self) -> Image<Vec<u8>>
+        where T: Deref<Target = [u8]>
+    {
+        self.assert_valid(self.pixels.len());
+        let row_len = self.width * self.format.size();
+        let mut pixels = Vec::with_capacity(row_len * self.height);
+        for y in 0..self.height {
+            pixels.extend_from_slice(&self.pixels[y*self.pitch..][..row_len]);
+        }
+        Image { pixels, width: self.width, pitch: row_len, height: self.height, format: self.format }
+    }
+
     pub(crate) fn assert_valid(&self, pixels_len: usize) {
         let Image { pixels: _, width, pitch, height, format } = *self;
         assert!(pitch >= width*format.size(),
@@ -194,6 +276,10 @@ impl Image<Vec<u8>> {
 /// by the vertical subsampling factor.
 /// - Each row is further padded to the nearest multiple of the [row alignment][Self::align].
 ///
+/// The chrominance samples of subsampled images decompressed from JPEG are
+/// [centered][crate::ChromaSiting::Centered] between the luminance samples. Video encoders may
+/// expect another siting; see [`ChromaSiting`][crate::ChromaSiting].
+///
 /// ## Example
 ///
 /// For example, if the source image is 35 x 35 pixels and [`Sub2x1`][Subsamp::Sub2x1] subsampling
@@ -1,7 +1,8 @@
 use crate::Image;
 use crate::buf::OwnedBuf;
 use crate::compress::Compressor;
-use crate::common::{PixelFormat, Result, Subsamp};
+use crate::common::{Error, PixelFormat, Result, Subsamp};
+use crate::quality::Quality;
 use crate::decompress::Decompressor;
 
 /// Decompresses image from JPEG into an [`image::ImageBuffer`].
@@ -70,7 +71,7 @@ pub fn decompress_image<P>(jpeg_data: &[u8]) -> Result<image::ImageBuffer<P, Vec
 #[cfg_attr(docsrs, doc(cfg(feature = "image")))]
 pub fn compress_image<P>(
     image_buf: &image::ImageBuffer<P, Vec<u8>>,
-    quality: i32,
+    quality: impl TryInto<Quality, Error: Into<Error>>,
     subsamp: Subsamp,
 ) -> Result<OwnedBuf>
     where P: JpegPixel + 'static
@@ -86,7 +87,7 @@ pub fn compress_image<P>(
     };
 
     let mut compressor = Compressor::new()?;
-    compressor.set_quality(quality);
+    compressor.set_quality(quality)?;
     compressor.set_subsamp(subsamp);
     compressor.compress_to_owned(image)
 }
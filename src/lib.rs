@@ -58,6 +58,15 @@
 //! - **Read header** of JPEG image to get its size without decompression using
 //! [`Decompressor::read_header()`] or [`read_header()`].
 //! - **Decompress** images **into YUV** using [`decompress_to_yuv()`] or [`Decompressor`].
+//! - **Validate** the structure of JPEG data without decompression using [`validate_jpeg()`].
+//! - **Generate tile pyramids** for Deep Zoom viewers using [`generate_pyramid()`].
+//! - **Chain transforms** (such as crop, rotate and crop again) into a single pass using
+//!   [`TransformPipeline`].
+//! - **Encode only changed regions** of consecutive frames (for screen sharing) using
+//!   [`DiffEncoder`].
+//! - **Load and save** uncompressed BMP and PPM/PGM files using [`load_image()`] and
+//!   [`save_image()`].
+//! - **Strip metadata** (such as EXIF or XMP) without recompression using [`strip_metadata()`].
 //! 
 //! # The [`OutputBuf`] and [`OwnedBuf`] types
 //!
@@ -78,12 +87,86 @@
 //! To handle both of these cases, this crate provides the [`OutputBuf`] type, which can hold
 //! either a `&mut [u8]` or an `OwnedBuf`.
 //!
+//! # Determinism
+//!
+//! Compression, decompression and lossless transforms are deterministic: the same input with the
+//! same settings always produces byte-identical output, across runs, threads and reused
+//! [`Compressor`], [`Decompressor`] and [`Transformer`] instances. TurboJPEG does not write any
+//! timestamps or other varying data into the JPEG images, so no special options are needed. You
+//! can rely on this, for example, for content-addressed storage.
+//!
+//! The guarantee holds for a single build of libjpeg-turbo running on the same kind of CPU.
+//! Different versions of libjpeg-turbo, or different SIMD code paths selected at runtime (see the
+//! `JSIMD_FORCE*` environment variables of libjpeg-turbo), may produce different output.
+//!
+//! Note that [`generate_pyramid()`] produces deterministic tiles, but the order in which the tiles
+//! are passed to the callback depends on thread scheduling.
+//!
+//! ```
+//! let image = turbojpeg::Image::mandelbrot(400, 300, turbojpeg::PixelFormat::RGB);
+//! let compress = || {
+//!     let mut compressor = turbojpeg::Compressor::new().unwrap();
+//!     compressor.set_quality(85).unwrap();
+//!     compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
+//!     compressor.compress_to_vec(image.as_deref()).unwrap()
+//! };
+//!
+//! // compress the image in multiple threads
+//! let outputs = std::thread::scope(|scope| {
+//!     let threads = (0..4).map(|_| scope.spawn(compress)).collect::<Vec<_>>();
+//!     threads.into_iter().map(|thread| thread.join().unwrap()).collect::<Vec<_>>()
+//! });
+//!
+//! // all outputs are identical
+//! let expected = compress();
+//! assert!(outputs.iter().all(|output| *output == expected));
+//!
+//! // reusing the compressor also produces identical output
+//! let mut compressor = turbojpeg::Compressor::new()?;
+//! compressor.set_quality(85)?;
+//! compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
+//! for _ in 0..3 {
+//!     assert_eq!(compressor.compress_to_vec(image.as_deref())?, expected);
+//! }
+//!
+//! // lossless transforms are deterministic as well
+//! let transform = turbojpeg::Transform { op: turbojpeg::TransformOp::Rot90, .. Default::default() };
+//! let rotated = turbojpeg::transform(&transform, &expected)?;
+//! assert_eq!(*turbojpeg::transform(&transform, &expected)?, *rotated);
+//!
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
 //! # Features
 //!
 //! - `image`: enables the optional dependency on the [`image`][image-rs] crate.
 //! - `pkg-config`: uses pkg-config to find the `libturbojpeg` library.
 //! - `bindgen`: uses [bindgen] to generate the `libturbojpeg` bindings.
+//! - `bench`: enables `run_bench()` to measure the throughput of various settings.
+//! - `differential`: enables the differential tests of this crate against the `djpeg`, `cjpeg` and
+//!   `jpegtran` tools of libjpeg-turbo (it does not change the library).
+//! - `fallback`: enables the `fallback` module with a pure-Rust implementation of
+//!   `decompress()` and `compress()` for targets without the native library.
+//! - `hash`: enables `pixel_hash()` to hash the decompressed pixels of JPEG images.
+//! - `multipart`: enables `MultipartEncoder` to stream JPEG frames as a
+//!   `multipart/x-mixed-replace` HTTP response (MJPEG over HTTP).
+//! - `phash`: enables `phash()` and `dhash()` to compute perceptual hashes of JPEG images.
+//! - `pnm`: enables `decode_pnm()` and `encode_pnm()` to convert images from and to the PGM, PPM
+//!   and PAM formats in memory.
+//! - `rtp`: enables `RtpJpegPacketizer` and `RtpJpegDepacketizer` to send and receive JPEG
+//!   images in RTP packets (RFC 2435).
+//! - `serde`: implements `Serialize` and `Deserialize` from [`serde`][serde-rs] for configuration
+//!   types such as [`PixelFormat`], [`Subsamp`], [`Transform`] and [`DecompressLimits`].
+//! - `stats`: records timing and throughput statistics (`Stats`) in [`Compressor`] and
+//!   [`Decompressor`].
+//! - `testing`: enables the `testing` module with helpers for comparing images and golden-file
+//!   snapshots in tests.
+//! - `tracing`: emits [`tracing`][tracing-rs] spans around the calls into TurboJPEG, with the
+//!   dimensions and settings of the image, and events with the duration and result of every call
+//!   and for the creation of TurboJPEG handles.
 //!
+//! [serde-rs]: https://serde.rs
+//! [tracing-rs]: https://docs.rs/tracing
 //! [bindgen]: https://rust-lang.github.io/rust-bindgen/
 #![warn(missing_docs)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -91,18 +174,140 @@
 pub extern crate turbojpeg_sys as raw;
 pub extern crate libc;
 
+mod alloc;
+mod atlas;
+mod auto_subsamp;
+mod avi;
+mod backend;
+mod batch;
+#[cfg(feature = "bench")]
+mod bench;
 mod buf;
+mod cancel;
 mod common;
 mod compress;
+mod concurrency;
+mod deblock;
 mod decompress;
+mod diff;
+mod dump;
+mod external;
+#[cfg(feature = "fallback")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fallback")))]
+pub mod fallback;
+mod file;
+mod fork;
+mod geometry;
+#[cfg(feature = "hash")]
+mod hash;
 mod image;
+mod marker;
+mod mask;
+mod memory;
+mod metadata;
+mod metrics;
+mod mjpeg;
+#[cfg(feature = "multipart")]
+mod multipart;
+#[cfg(feature = "phash")]
+mod phash;
+mod pacing;
+mod pipeline;
+#[cfg(feature = "pnm")]
+mod pnm;
+mod pool;
+mod postprocess;
+mod predict;
+mod preset;
+mod progress;
+mod pyramid;
+mod quality;
+mod resample;
+mod resize;
+#[cfg(feature = "rtp")]
+mod rtp;
+mod scale;
+mod screenshot;
+mod sharp_yuv;
+mod siting;
+#[cfg(unix)]
+mod shm;
+mod split;
+#[cfg(feature = "stats")]
+mod stats;
+mod tables;
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
+#[cfg(feature = "tracing")]
+mod trace;
+mod transcode;
 mod transform;
+mod transform_pipeline;
+mod two_pass;
+mod typed;
+mod validate;
+pub use self::alloc::{ImageAllocator, ImageBuf, SystemAllocator, AlignedAllocator};
+pub use self::atlas::{AtlasPacker, AtlasRegion};
+pub use self::auto_subsamp::choose_subsamp;
+pub use self::avi::AviWriter;
+pub use self::backend::{JpegBackend, BackendCapabilities, TurboJpegBackend};
+pub use self::batch::{BatchIter, BatchOrder};
+#[cfg(feature = "bench")]
+pub use self::bench::{BenchConfig, BenchResult, run_bench};
 pub use self::buf::{OwnedBuf, OutputBuf};
-pub use self::common::{PixelFormat, Subsamp, Colorspace, Result, Error};
+pub use self::cancel::CancelToken;
+pub use self::common::{PixelFormat, Subsamp, Colorspace, DctMethod, Result, Error, ErrorMessage};
 pub use self::compress::{Compressor, compress, compressed_buf_len};
-pub use self::decompress::{Decompressor, DecompressHeader, decompress, read_header, decompress_to_yuv, yuv_pixels_len};
+pub use self::concurrency::{Priority, set_concurrency_limit, concurrency_limit, set_batch_concurrency_limit, batch_concurrency_limit, set_thread_priority, thread_priority};
+pub use self::deblock::DeblockLevel;
+pub use self::decompress::{Decompressor, DecompressHeader, DecompressLimits, decompress, decompress_aligned, aligned_pitch, decompress_tiles, read_header, decompress_to_yuv, yuv_pixels_len};
+pub use self::diff::{DiffEncoder, ChangedRegion};
+pub use self::dump::{DumpSink, DumpDir, FailureReport};
+pub use self::external::{ExternalDecoder, decompress_any, to_jpeg, is_heif};
+pub use self::file::{load_image, load_image_as, save_image};
+pub use self::geometry::{Geometry, MAX_DIMENSION, set_max_dimension, max_dimension};
+#[cfg(feature = "hash")]
+pub use self::hash::pixel_hash;
 pub use self::image::{Image, YuvImage};
-pub use self::transform::{Transformer, Transform, TransformOp, TransformCrop, transform};
+pub use self::mask::{QualityRegion, compress_with_mask, compress_with_regions};
+pub use self::memory::{estimate_decode_memory, estimate_encode_memory};
+pub use self::metadata::{MarkerKind, StripMetadata, strip_metadata};
+pub use self::metrics::{Metrics, Counter, Histogram, set_metrics};
+pub use self::mjpeg::{MjpegPlayer, FrameSkip, FrameStatus};
+#[cfg(feature = "multipart")]
+pub use self::multipart::{MultipartEncoder, MultipartChunks};
+pub use self::pacing::{TimedFrame, FrameQueue, QueuePolicy, FramePacer};
+#[cfg(feature = "phash")]
+pub use self::phash::{phash, dhash, hash_distance};
+pub use self::pipeline::Pipeline;
+#[cfg(feature = "pnm")]
+pub use self::pnm::{decode_pnm, encode_pnm};
+pub use self::pool::{HandlePool, PoolHandle, Pooled};
+pub use self::postprocess::PostProcess;
+pub use self::predict::predict_compressed_size;
+pub use self::preset::{Preset, CompressorPreset, CompressOverrides, SharedPreset};
+pub use self::progress::Progress;
+pub use self::quality::Quality;
+pub use self::pyramid::{PyramidOptions, PyramidTile, generate_pyramid, generate_pyramid_from_jpeg, dzi_descriptor};
+pub use self::resample::{ResizeFilter, resize_image};
+pub use self::resize::{JpegResizer, resize_jpeg};
+#[cfg(feature = "rtp")]
+pub use self::rtp::{RtpJpegPacket, RtpJpegPacketizer, RtpJpegDepacketizer};
+pub use self::scale::ScalingFactor;
+pub use self::screenshot::{ScreenshotOptions, compress_screenshot};
+#[cfg(unix)]
+pub use self::shm::ShmRing;
+pub use self::siting::ChromaSiting;
+pub use self::split::{SplitJpegs, split_jpegs};
+#[cfg(feature = "stats")]
+pub use self::stats::Stats;
+pub use self::transcode::{PixelEncoder, transcode};
+pub use self::transform::{Transformer, Transform, TransformOp, TransformCrop, EdgeHandling, MarkerCopy, EntropyCoding, transform};
+pub use self::transform_pipeline::TransformPipeline;
+pub use self::two_pass::{TrialEncode, QualityTrial, CoefficientHistogram};
+pub use self::typed::{PixelType, TypedImage, Rgb8, Bgr8, Rgbx8, Bgrx8, Xbgr8, Xrgb8, Gray8, Rgba8, Bgra8, Abgr8, Argb8, Cmyk8};
+pub use self::validate::{CodingProcess, JpegSummary, validate_jpeg};
 
 #[cfg(feature = "image")]
 mod image_rs;
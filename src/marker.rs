@@ -0,0 +1,218 @@
+// Scanner for the marker segments of JPEG data, which allows us to inspect the structure of JPEG
+// images without decompressing them.
+
+pub(crate) const SOF0: u8 = 0xc0;
+pub(crate) const SOF15: u8 = 0xcf;
+pub(crate) const DHT: u8 = 0xc4;
+pub(crate) const JPG: u8 = 0xc8;
+pub(crate) const DAC: u8 = 0xcc;
+pub(crate) const SOI: u8 = 0xd8;
+#[cfg(feature = "rtp")]
+pub(crate) const DQT: u8 = 0xdb;
+#[cfg(feature = "rtp")]
+pub(crate) const DRI: u8 = 0xdd;
+pub(crate) const APP0: u8 = 0xe0;
+pub(crate) const APP1: u8 = 0xe1;
+pub(crate) const APP2: u8 = 0xe2;
+pub(crate) const APP14: u8 = 0xee;
+pub(crate) const APP15: u8 = 0xef;
+pub(crate) const COM: u8 = 0xfe;
+pub(crate) const EOI: u8 = 0xd9;
+pub(crate) const SOS: u8 = 0xda;
+pub(crate) const TEM: u8 = 0x01;
+pub(crate) const RST0: u8 = 0xd0;
+pub(crate) const RST7: u8 = 0xd7;
+
+/// Returns true if the marker is a start of frame (SOFn).
+pub(crate) fn is_sof(marker: u8) -> bool {
+    (SOF0..=SOF15).contains(&marker) && marker != DHT && marker != JPG && marker != DAC
+}
+
+/// A marker segment in JPEG data.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Segment<'a> {
+    /// The marker code (the byte after 0xff).
+    pub marker: u8,
+    /// Offset of the segment (the first 0xff byte of the marker) in the JPEG data.
+    pub offset: usize,
+    /// Total length of the segment (including the marker and the length field), but without the
+    /// entropy-coded data that follows SOS segments.
+    pub len: usize,
+    /// The payload of the segment (without the marker and the length field).
+    pub data: &'a [u8],
+}
+
+/// Iterator over the marker segments in JPEG data.
+///
+/// The iterator starts at the SOI marker at the beginning of the data, skips over entropy-coded
+/// data after SOS segments and stops after the EOI marker. If the data is malformed or truncated,
+/// the iterator returns an error and stops.
+#[derive(Debug, Clone)]
+pub(crate) struct Segments<'a> {
+    data: &'a [u8],
+    pos: usize,
+    in_scan: bool,
+    done: bool,
+}
+
+pub(crate) fn segments(data: &[u8]) -> Segments<'_> {
+    Segments { data, pos: 0, in_scan: false, done: false }
+}
+
+impl<'a> Segments<'a> {
+    /// Returns the offset just after the last returned segment.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn next_segment(&mut self) -> Result<Segment<'a>, &'static str> {
+        let data = self.data;
+        if self.pos == 0 {
+            if data.len() < 2 || data[0] != 0xff || data[1] != SOI {
+                return Err("missing SOI marker");
+            }
+            self.pos = 2;
+            return Ok(Segment { marker: SOI, offset: 0, len: 2, data: &[] });
+        }
+
+        if self.in_scan {
+            self.pos = skip_entropy_data(data, self.pos);
+            self.in_scan = false;
+        }
+
+        if self.pos >= data.len() {
+            return Err("missing EOI marker");
+        } else if data[self.pos] != 0xff {
+            return Err("expected a marker");
+        }
+
+        // markers may be preceded by any number of 0xff fill bytes
+        let offset = self.pos;
+        let mut pos = self.pos;
+        while pos < data.len() && data[pos] == 0xff {
+            pos += 1;
+        }
+        if pos >= data.len() {
+            return Err("truncated marker");
+        }
+
+        let marker = data[pos];
+        pos += 1;
+        if marker == 0x00 {
+            return Err("invalid marker 0x00");
+        } else if marker == SOI || marker == EOI || marker == TEM || (RST0..=RST7).contains(&marker) {
+            self.pos = pos;
+            return Ok(Segment { marker, offset, len: pos - offset, data: &[] });
+        }
+
+        if pos + 2 > data.len() {
+            return Err("truncated segment length");
+        }
+        let seg_len = u16::from_be_bytes([data[pos], data[pos + 1]]) as usize;
+        if seg_len < 2 {
+            return Err("invalid segment length");
+        } else if pos + seg_len > data.len() {
+            return Err("truncated segment");
+        }
+
+        let payload = &data[pos + 2..pos + seg_len];
+        self.pos = pos + seg_len;
+        self.in_scan = marker == SOS;
+        Ok(Segment { marker, offset, len: self.pos - offset, data: payload })
+    }
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Result<Segment<'a>, &'static str>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.next_segment();
+        self.done = match res {
+            Ok(segment) => segment.marker == EOI,
+            Err(_) => true,
+        };
+        Some(res)
+    }
+}
+
+/// Returns the offset of the first SOI marker that is followed by another marker and starts at
+/// most `max_offset` bytes into the data. This is used to resynchronize after junk bytes.
+pub(crate) fn find_soi(data: &[u8], max_offset: usize) -> Option<usize> {
+    let end = data.len().min(max_offset.saturating_add(3));
+    data[..end].windows(3).position(|window| window == [0xff, SOI, 0xff])
+}
+
+/// Skips entropy-coded data starting at `pos` and returns the position of the next marker (or the
+/// end of data).
+fn skip_entropy_data(data: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xff {
+            let next = data[pos + 1];
+            // 0xff00 is a stuffed zero byte, RSTn markers are part of the entropy-coded data and
+            // 0xff may be followed by fill bytes
+            if next != 0x00 && !(RST0..=RST7).contains(&next) && next != 0xff {
+                return pos;
+            }
+            pos += if next == 0xff { 1 } else { 2 };
+        } else {
+            pos += 1;
+        }
+    }
+    data.len()
+}
+
+/// Information about the frame and scans of JPEG data, gathered by [`frame_info()`].
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct FrameInfo {
+    /// The SOFn marker of the frame.
+    pub sof_marker: u8,
+    /// Number of components in the frame.
+    pub components: usize,
+    /// Width of the frame in pixels.
+    pub width: usize,
+    /// Height of the frame in pixels (0 if it is defined by a DNL marker).
+    pub height: usize,
+    /// Number of components in the first scan (0 if there is no scan).
+    pub first_scan_components: usize,
+}
+
+/// Gathers information about the frame and its first scan in the JPEG data, ignoring any errors in
+/// the data. Stops at the first scan, so the entropy-coded data is never read. Returns `None` if
+/// there is no frame header.
+pub(crate) fn frame_info(data: &[u8]) -> Option<FrameInfo> {
+    let mut info: Option<FrameInfo> = None;
+    for segment in segments(data).map_while(|res| res.ok()) {
+        if is_sof(segment.marker) && info.is_none() {
+            let components = segment.data.get(5).copied().unwrap_or(0) as usize;
+            let read_u16 = |offset: usize| match segment.data.get(offset..offset + 2) {
+                Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+                None => 0,
+            };
+            let (height, width) = (read_u16(1), read_u16(3));
+            info = Some(FrameInfo { sof_marker: segment.marker, components, width, height, first_scan_components: 0 });
+        } else if segment.marker == SOS {
+            if let Some(info) = info.as_mut() {
+                info.first_scan_components = segment.data.first().copied().unwrap_or(0) as usize;
+            }
+            break
+        }
+    }
+    info
+}
+
+/// Counts the SOS markers anywhere in the JPEG data, including the ones that [`count_scans()`]
+/// does not reach because of corrupted segments. Byte stuffing keeps the marker out of
+/// entropy-coded data, so libjpeg cannot read more scans than this.
+pub(crate) fn count_sos_markers(data: &[u8]) -> usize {
+    data.windows(2).filter(|pair| *pair == [0xff, SOS]).count()
+}
+
+/// Counts the scans (SOS segments) in the JPEG data, ignoring any errors in the data.
+pub(crate) fn count_scans(data: &[u8]) -> usize {
+    segments(data)
+        .take_while(|res| res.is_ok())
+        .filter(|res| matches!(res, Ok(segment) if segment.marker == SOS))
+        .count()
+}
@@ -0,0 +1,230 @@
+use crate::{Image, Transformer};
+use crate::buf::{OwnedBuf, OutputBuf};
+use crate::common::{PixelFormat, Subsamp, Error, Result};
+use crate::quality::Quality;
+use crate::tables::{STD_CHROMINANCE_QUANT_TABLE, STD_LUMINANCE_QUANT_TABLE, quant_table};
+
+/// Compresses an image into JPEG with quality that varies according to an importance mask.
+///
+/// The `mask` is a [`PixelFormat::GRAY`] image with the same size as `image`, which marks the
+/// regions that should keep their details (such as faces, text or edges) with high values and
+/// the regions that can be compressed harder (such as sky or blurred background) with low values.
+/// The image is first compressed with the given `quality` and `subsamp`, and then the
+/// high-frequency DCT coefficients are dropped from the 8x8 blocks where the mask is low: blocks
+/// with mask 255 are kept intact, while blocks with mask 0 keep only their coarse structure (the
+/// coefficients with horizontal plus vertical frequency at most 2). Each block uses the maximum
+/// of the mask over its pixels, so a small important detail preserves the whole block.
+///
+/// The coefficients are modified in the compressed image (using a lossless transform), so the
+/// result is a standard JPEG image that is smaller than the image compressed with `quality`
+/// everywhere. Grayscale images are always compressed with [`Subsamp::Gray`].
+///
+/// # Panics
+///
+/// Panics if `mask` is not a valid grayscale image with the same width and height as `image`.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+///
+/// // keep the details only in the center of the image
+/// let mut mask = turbojpeg::Image {
+///     pixels: vec![0; image.width * image.height],
+///     width: image.width,
+///     pitch: image.width,
+///     height: image.height,
+///     format: turbojpeg::PixelFormat::GRAY,
+/// };
+/// for y in 64..192 {
+///     mask.pixels[y * mask.pitch + 128..][..128].fill(255);
+/// }
+///
+/// let masked_data = turbojpeg::compress_with_mask(
+///     image.as_deref(), mask.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+/// let plain_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+/// assert!(masked_data.len() < plain_data.len());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn compress_with_mask(
+    image: Image<&[u8]>,
+    mask: Image<&[u8]>,
+    quality: impl TryInto<Quality, Error: Into<Error>>,
+    subsamp: Subsamp,
+) -> Result<OwnedBuf> {
+    assert_eq!(mask.format, PixelFormat::GRAY, "mask must be a grayscale image");
+    assert!(mask.width == image.width && mask.height == image.height,
+        "mask has size {}x{}, but the image has size {}x{}",
+        mask.width, mask.height, image.width, image.height);
+    mask.assert_valid(mask.pixels.len());
+
+    let subsamp = if image.format == PixelFormat::GRAY { Subsamp::Gray } else { subsamp };
+    let jpeg_data = crate::compress(image, quality, subsamp)?;
+
+    // the maximal mask value in every 8x8 block of pixels
+    let mut grid = BlockGrid::new(image.width, image.height, 0u8);
+    for y in 0..mask.height {
+        let row = &mask.pixels[y*mask.pitch..][..mask.width];
+        for (x, &value) in row.iter().enumerate() {
+            grid.raise(x / 8, y / 8, value);
+        }
+    }
+
+    grid.filter_blocks(&jpeg_data, subsamp, |value, _, block| {
+        let cutoff = 2 + (12 * value as usize + 127) / 255;
+        for (i, coef) in block.iter_mut().enumerate() {
+            if i / 8 + i % 8 > cutoff {
+                *coef = 0;
+            }
+        }
+    })
+}
+
+/// Rectangular region of an image with its own quality, used in [`compress_with_regions()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QualityRegion {
+    /// Left boundary of the region in pixels.
+    pub x: usize,
+    /// Upper boundary of the region in pixels.
+    pub y: usize,
+    /// Width of the region in pixels.
+    pub width: usize,
+    /// Height of the region in pixels.
+    pub height: usize,
+    /// Quality of the region.
+    pub quality: Quality,
+}
+
+/// Compresses an image into JPEG with different quality in different regions.
+///
+/// Every 8x8 block of pixels is compressed with the highest quality among the `regions` that
+/// overlap the block, or with `background_quality` if no region overlaps it. Regions may overlap
+/// each other and extend beyond the image. This is useful when only a part of the image matters,
+/// such as the monitored area in a surveillance camera frame.
+///
+/// The image is compressed with the highest quality that is used anywhere, and then the DCT
+/// coefficients of the blocks with lower quality are requantized in the compressed image (using a
+/// lossless transform), so that they have approximately the precision of the lower quality. The
+/// DC coefficients (the average color of the blocks) are kept, which avoids visible steps between
+/// blocks in smooth areas. The output is a standard JPEG image, but it is decompressed as if it
+/// was compressed with the highest quality, so its size is larger than the size of the image
+/// compressed with `background_quality` everywhere. Grayscale images are always compressed with
+/// [`Subsamp::Gray`].
+///
+/// See also [`compress_with_mask()`], which uses a per-pixel importance mask.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+///
+/// // compress the region with the parrot heads with high quality
+/// let quality = turbojpeg::Quality::new(95)?;
+/// let regions = [turbojpeg::QualityRegion { x: 128, y: 32, width: 128, height: 96, quality }];
+/// let roi_data = turbojpeg::compress_with_regions(
+///     image.as_deref(), &regions, turbojpeg::Quality::new(30)?, turbojpeg::Subsamp::Sub2x2)?;
+///
+/// let full_data = turbojpeg::compress(image.as_deref(), 95, turbojpeg::Subsamp::Sub2x2)?;
+/// assert!(roi_data.len() < full_data.len());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn compress_with_regions(
+    image: Image<&[u8]>,
+    regions: &[QualityRegion],
+    background_quality: Quality,
+    subsamp: Subsamp,
+) -> Result<OwnedBuf> {
+    let background_quality = background_quality.get();
+    let mut grid = BlockGrid::new(image.width, image.height, background_quality);
+    for region in regions {
+        let quality = region.quality.get();
+        if region.width == 0 || region.height == 0 {
+            continue
+        }
+        let (x_end, y_end) = (region.x.saturating_add(region.width), region.y.saturating_add(region.height));
+        for by in region.y / 8..y_end.div_ceil(8).min(grid.height) {
+            for bx in region.x / 8..x_end.div_ceil(8).min(grid.width) {
+                grid.raise(bx, by, quality);
+            }
+        }
+    }
+
+    let subsamp = if image.format == PixelFormat::GRAY { Subsamp::Gray } else { subsamp };
+    let max_quality = grid.values.iter().copied().max().unwrap_or(background_quality);
+    let jpeg_data = crate::compress(image, max_quality, subsamp)?;
+
+    let luma_table = quant_table(&STD_LUMINANCE_QUANT_TABLE, max_quality);
+    let chroma_table = quant_table(&STD_CHROMINANCE_QUANT_TABLE, max_quality);
+    grid.filter_blocks(&jpeg_data, subsamp, |quality, component, block| {
+        if quality >= max_quality {
+            return
+        }
+        let (base, table) = if component == 0 {
+            (&luma_table, quant_table(&STD_LUMINANCE_QUANT_TABLE, quality))
+        } else {
+            (&chroma_table, quant_table(&STD_CHROMINANCE_QUANT_TABLE, quality))
+        };
+        // the coefficients are stored in the units of the `base` table
+        for i in 1..64 {
+            let value = block[i] as i32 * base[i];
+            let requantized = div_round(value, table[i]) * table[i];
+            block[i] = div_round(requantized, base[i]) as i16;
+        }
+    })
+}
+
+/// Grid of values for every 8x8 block of pixels in an image.
+struct BlockGrid<T> {
+    width: usize,
+    height: usize,
+    values: Vec<T>,
+}
+
+impl<T: Copy + Ord> BlockGrid<T> {
+    fn new(image_width: usize, image_height: usize, value: T) -> BlockGrid<T> {
+        let (width, height) = (image_width.div_ceil(8), image_height.div_ceil(8));
+        BlockGrid { width, height, values: vec![value; width * height] }
+    }
+
+    fn raise(&mut self, x: usize, y: usize, value: T) {
+        let old = &mut self.values[y*self.width + x];
+        *old = (*old).max(value);
+    }
+
+    /// Losslessly rewrites `jpeg_data` and calls `filter` on the coefficients of every block of
+    /// every component, with the maximal value over the blocks of pixels covered by the block.
+    fn filter_blocks<F>(&self, jpeg_data: &[u8], subsamp: Subsamp, mut filter: F) -> Result<OwnedBuf>
+        where F: FnMut(T, usize, &mut [i16; 64])
+    {
+        // a block of a subsampled chrominance component covers several blocks of pixels
+        let (scale_x, scale_y) = (subsamp.mcu_width() / 8, subsamp.mcu_height() / 8);
+        let mut transformer = Transformer::new()?;
+        let mut output = OutputBuf::new_owned();
+        transformer.filter_coefficients(jpeg_data, &mut output, |component, row, blocks| {
+            let (sx, sy) = if component == 0 { (1, 1) } else { (scale_x, scale_y) };
+            for (col, block) in blocks.iter_mut().enumerate() {
+                let mut value = None;
+                for by in (row*sy..(row + 1)*sy).filter(|&by| by < self.height) {
+                    for bx in (col*sx..(col + 1)*sx).filter(|&bx| bx < self.width) {
+                        let block_value = self.values[by*self.width + bx];
+                        value = Some(value.map_or(block_value, |value: T| value.max(block_value)));
+                    }
+                }
+                // blocks that are completely in the padding of the image are left untouched
+                if let Some(value) = value {
+                    filter(value, component, block);
+                }
+            }
+        })?;
+        Ok(output.into_owned())
+    }
+}
+
+fn div_round(a: i32, b: i32) -> i32 {
+    if a >= 0 { (a + b / 2) / b } else { -((-a + b / 2) / b) }
+}
@@ -0,0 +1,108 @@
+use crate::common::{PixelFormat, Subsamp};
+use crate::compress::compressed_buf_len;
+use crate::decompress::{DecompressHeader, coefficient_buffer_len};
+use crate::geometry::Geometry;
+use crate::preset::CompressorPreset;
+
+/// Memory that libjpeg allocates regardless of the image size (Huffman and quantization tables,
+/// the state of the codec and the memory manager), rounded up.
+const FIXED_OVERHEAD: usize = 64 * 1024;
+
+/// Estimates the peak memory in bytes needed to decompress the image described by `header` into
+/// pixel `format`.
+///
+/// The estimate includes the decompressed image, the whole-image buffer for the DCT coefficients
+/// of progressive and other multi-scan images (see
+/// [`DecompressLimits::max_memory`][crate::DecompressLimits::max_memory]), and the buffers for the
+/// rows that libjpeg processes at once. It is computed only from the header, so servers can use
+/// it for admission control before they commit any resources to the image. The estimate is
+/// meant to be conservative, but it is not exact: it does not include the memory of the
+/// [`Decompressor`][crate::Decompressor] itself or of post-processing steps.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let header = turbojpeg::read_header(&jpeg_data)?;
+///
+/// // the image is progressive, so the DCT coefficients of the whole image are kept in memory
+/// let estimate = turbojpeg::estimate_decode_memory(&header, turbojpeg::PixelFormat::RGB);
+/// assert!(estimate > 384 * 256 * 3 + 384 * 256 * 3 * 2);
+/// assert!(estimate < 2_000_000);
+///
+/// // a server can reject images that would take too much memory
+/// let budget = 100_000;
+/// if turbojpeg::estimate_decode_memory(&header, turbojpeg::PixelFormat::RGB) > budget {
+///     // reject the image
+/// }
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn estimate_decode_memory(header: &DecompressHeader, format: PixelFormat) -> usize {
+    let output = header.width.saturating_mul(header.height).saturating_mul(format.size());
+    let rows = row_buffers_len(header.width, header.subsamp, header.components);
+    output
+        .saturating_add(coefficient_buffer_len(header))
+        .saturating_add(rows)
+        .saturating_add(FIXED_OVERHEAD)
+}
+
+/// Estimates the peak memory in bytes needed to compress an image with the given `geometry` and
+/// `settings`, not counting the input image.
+///
+/// The estimate includes the output buffer (whose size is given by
+/// [`compressed_buf_len()`][crate::compressed_buf_len]), the whole-image buffer for the DCT
+/// coefficients of progressive images, and the buffers for the rows that libjpeg processes at
+/// once. See [`estimate_decode_memory()`] for details.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{CompressorPreset, Geometry, PixelFormat, estimate_encode_memory};
+///
+/// let geometry = Geometry::new(1920, 1080, PixelFormat::RGB)?;
+/// let baseline = estimate_encode_memory(&geometry, &CompressorPreset::ARCHIVE);
+/// let settings = CompressorPreset { progressive: true, .. CompressorPreset::ARCHIVE };
+/// let progressive = estimate_encode_memory(&geometry, &settings);
+///
+/// // progressive images keep the DCT coefficients of the whole image in memory (2 bytes per
+/// // coefficient, 3 components without subsampling)
+/// assert!(progressive >= baseline + 1920 * 1080 * 3 * 2);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn estimate_encode_memory(geometry: &Geometry, settings: &CompressorPreset) -> usize {
+    let (width, height) = (geometry.width(), geometry.height());
+    let subsamp = match geometry.format() {
+        PixelFormat::GRAY => Subsamp::Gray,
+        _ => settings.subsamp,
+    };
+    let components = match (geometry.format(), subsamp) {
+        (PixelFormat::CMYK, _) => 4,
+        (_, Subsamp::Gray) => 1,
+        _ => 3,
+    };
+
+    let output = compressed_buf_len(width, height, subsamp).unwrap_or(usize::MAX);
+    let coefficients = if settings.progressive {
+        let (mcu_width, mcu_height) = subsamp.mcu_size();
+        let mcus = width.div_ceil(mcu_width).saturating_mul(height.div_ceil(mcu_height));
+        let blocks_per_mcu = subsamp.width() * subsamp.height() + components - 1;
+        mcus.saturating_mul(blocks_per_mcu).saturating_mul(64 * 2)
+    } else {
+        0
+    };
+    let rows = row_buffers_len(width, subsamp, components);
+    output
+        .saturating_add(coefficients)
+        .saturating_add(rows)
+        .saturating_add(FIXED_OVERHEAD)
+}
+
+/// Estimates the size of the buffers for the rows of one MCU row (and its neighbors, which are
+/// used for context-based upsampling and downsampling) of all components.
+fn row_buffers_len(width: usize, subsamp: Subsamp, components: usize) -> usize {
+    let (mcu_width, mcu_height) = subsamp.mcu_size();
+    let padded_width = width.div_ceil(mcu_width).saturating_mul(mcu_width);
+    padded_width.saturating_mul(mcu_height).saturating_mul(components).saturating_mul(3)
+}
@@ -0,0 +1,205 @@
+use crate::common::{Error, Result};
+use crate::marker::{self, APP0, APP1, APP2, APP14, APP15, COM};
+
+/// Kind of a metadata marker segment in JPEG images.
+///
+/// This is used to select the markers that are copied by lossless transforms (see
+/// [`MarkerCopy`][crate::MarkerCopy]).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MarkerKind {
+    /// EXIF data (APP1 segments starting with `Exif`).
+    Exif,
+    /// ICC color profile (APP2 segments starting with `ICC_PROFILE`).
+    Icc,
+    /// XMP data (APP1 segments starting with the XMP namespace URI).
+    Xmp,
+    /// Comment (COM segment).
+    Comment,
+    /// Any other application segment APPn, where the value is `n` (from 0 to 15).
+    App(u8),
+}
+
+impl MarkerKind {
+    /// Returns the kind of a metadata segment, or `None` if the segment is not metadata.
+    ///
+    /// The JFIF (APP0) and Adobe (APP14) segments are not considered metadata, because they
+    /// determine how the image data is decoded.
+    pub(crate) fn of_segment(marker: u8, data: &[u8]) -> Option<MarkerKind> {
+        if marker == COM {
+            Some(MarkerKind::Comment)
+        } else if (marker == APP0 && data.starts_with(b"JFIF\0")) ||
+            (marker == APP14 && data.starts_with(b"Adobe"))
+        {
+            None
+        } else if marker == APP1 && data.starts_with(b"Exif\0") {
+            Some(MarkerKind::Exif)
+        } else if marker == APP1 && (data.starts_with(b"http://ns.adobe.com/xap/1.0/\0") ||
+            data.starts_with(b"http://ns.adobe.com/xmp/extension/\0"))
+        {
+            Some(MarkerKind::Xmp)
+        } else if marker == APP2 && data.starts_with(b"ICC_PROFILE\0") {
+            Some(MarkerKind::Icc)
+        } else if (APP0..=APP15).contains(&marker) {
+            Some(MarkerKind::App(marker - APP0))
+        } else {
+            None
+        }
+    }
+}
+
+/// Selection of metadata that should be removed from JPEG images.
+///
+/// Metadata is stored in JPEG images in marker segments, which are independent of the image data,
+/// so removing them is lossless. Use this with [`strip_metadata()`] or with
+/// [`Transform::strip`][crate::Transform::strip].
+///
+/// The default instance does not remove anything.
+///
+/// # Example
+///
+/// ```
+/// // remove location and other EXIF data and comments, but keep the color profile
+/// let strip = turbojpeg::StripMetadata {
+///     exif: true,
+///     comments: true,
+///     .. turbojpeg::StripMetadata::default()
+/// };
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct StripMetadata {
+    /// Remove EXIF data (APP1 segments starting with `Exif`), which often includes the GPS
+    /// location and camera details.
+    pub exif: bool,
+    /// Remove ICC color profiles (APP2 segments starting with `ICC_PROFILE`).
+    ///
+    /// Note that removing the color profile may change how the image is displayed.
+    pub icc: bool,
+    /// Remove XMP data (APP1 segments starting with the XMP namespace URI).
+    pub xmp: bool,
+    /// Remove comments (COM segments).
+    pub comments: bool,
+    /// Remove all application segments (APP0 to APP15), including EXIF, ICC and XMP.
+    ///
+    /// The JFIF (APP0) and Adobe (APP14) segments are kept, because they determine how the image
+    /// data is decoded.
+    pub all_appn: bool,
+}
+
+impl StripMetadata {
+    /// Selection that removes all metadata that can be removed without changing the image.
+    ///
+    /// Note that this also removes the ICC color profile.
+    pub fn all() -> StripMetadata {
+        StripMetadata { exif: true, icc: true, xmp: true, comments: true, all_appn: true }
+    }
+
+    /// Returns true if this selection does not remove anything.
+    pub fn is_none(&self) -> bool {
+        *self == StripMetadata::default()
+    }
+
+    pub(crate) fn should_strip(&self, kind: MarkerKind) -> bool {
+        match kind {
+            MarkerKind::Exif => self.exif || self.all_appn,
+            MarkerKind::Icc => self.icc || self.all_appn,
+            MarkerKind::Xmp => self.xmp || self.all_appn,
+            MarkerKind::Comment => self.comments,
+            MarkerKind::App(_) => self.all_appn,
+        }
+    }
+}
+
+/// Losslessly removes the selected metadata from JPEG data.
+///
+/// Returns a copy of `jpeg_data` without the marker segments selected by `strip`. The image data
+/// is not changed.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file (the image contains EXIF data and an ICC profile)
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // remove all metadata
+/// let stripped_data = turbojpeg::strip_metadata(&jpeg_data, &turbojpeg::StripMetadata::all())?;
+/// assert!(stripped_data.len() < jpeg_data.len() - 10_000);
+///
+/// // the image itself is preserved
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// let stripped_image = turbojpeg::decompress(&stripped_data, turbojpeg::PixelFormat::RGB)?;
+/// assert_eq!(image.pixels, stripped_image.pixels);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn strip_metadata(jpeg_data: &[u8], strip: &StripMetadata) -> Result<Vec<u8>> {
+    let mut data = jpeg_data.to_vec();
+    let len = remove_metadata_in_place(&mut data, |kind| strip.should_strip(kind))?;
+    data.truncate(len);
+    Ok(data)
+}
+
+/// Removes the metadata segments for which `remove` returns true, by moving the remaining data to
+/// the front of `data`. Returns the new length of the data.
+pub(crate) fn remove_metadata_in_place<F>(data: &mut [u8], remove: F) -> Result<usize>
+    where F: Fn(MarkerKind) -> bool
+{
+    let mut removed = Vec::new();
+    for segment in marker::segments(data) {
+        let segment = segment.map_err(Error::MalformedJpeg)?;
+        if let Some(kind) = MarkerKind::of_segment(segment.marker, segment.data) {
+            if remove(kind) {
+                removed.push((segment.offset, segment.len));
+            }
+        }
+    }
+
+    let mut write_pos = match removed.first() {
+        Some(&(offset, _)) => offset,
+        None => return Ok(data.len()),
+    };
+    for (i, &(offset, len)) in removed.iter().enumerate() {
+        let keep_start = offset + len;
+        let keep_end = removed.get(i + 1).map(|&(next_offset, _)| next_offset).unwrap_or(data.len());
+        data.copy_within(keep_start..keep_end, write_pos);
+        write_pos += keep_end - keep_start;
+    }
+    Ok(write_pos)
+}
+
+/// Copies the metadata segments of `from` for which `keep` returns true into `to`, after the SOI
+/// marker and the JFIF or Adobe segment that follows it.
+pub(crate) fn copy_metadata<F>(from: &[u8], to: &[u8], keep: F) -> Result<Vec<u8>>
+    where F: Fn(MarkerKind) -> bool
+{
+    let mut metadata = Vec::new();
+    for segment in marker::segments(from) {
+        let segment = segment.map_err(Error::MalformedJpeg)?;
+        if segment.marker == marker::SOS {
+            break
+        }
+        if MarkerKind::of_segment(segment.marker, segment.data).is_some_and(&keep) {
+            metadata.extend_from_slice(&from[segment.offset..][..segment.len]);
+        }
+    }
+
+    let mut insert_pos = 0;
+    for segment in marker::segments(to) {
+        let segment = segment.map_err(Error::MalformedJpeg)?;
+        let is_header = segment.marker == marker::SOI ||
+            ((segment.marker == APP0 || segment.marker == APP14) &&
+                MarkerKind::of_segment(segment.marker, segment.data).is_none());
+        if !is_header {
+            break
+        }
+        insert_pos = segment.offset + segment.len;
+    }
+
+    let mut data = Vec::with_capacity(to.len() + metadata.len());
+    data.extend_from_slice(&to[..insert_pos]);
+    data.extend_from_slice(&metadata);
+    data.extend_from_slice(&to[insert_pos..]);
+    Ok(data)
+}
@@ -0,0 +1,151 @@
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use crate::common::Result;
+
+/// Counter reported to [`Metrics`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Counter {
+    /// Number of processed images (including the failed ones).
+    Frames,
+    /// Number of images that failed to process.
+    Errors,
+    /// Number of bytes of the input images (only reported when the input is JPEG data).
+    InputBytes,
+    /// Number of bytes of the output images (only reported when the output is JPEG data).
+    OutputBytes,
+}
+
+impl Counter {
+    /// Returns the Prometheus-style name of the counter, such as `turbojpeg_frames_total`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::Frames => "turbojpeg_frames_total",
+            Counter::Errors => "turbojpeg_errors_total",
+            Counter::InputBytes => "turbojpeg_input_bytes_total",
+            Counter::OutputBytes => "turbojpeg_output_bytes_total",
+        }
+    }
+}
+
+/// Histogram reported to [`Metrics`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Histogram {
+    /// Time to process one image, in seconds.
+    Duration,
+    /// Size of one output image in bytes (only reported when the output is JPEG data).
+    OutputSize,
+}
+
+impl Histogram {
+    /// Returns the Prometheus-style name of the histogram, such as `turbojpeg_duration_seconds`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Histogram::Duration => "turbojpeg_duration_seconds",
+            Histogram::OutputSize => "turbojpeg_output_size_bytes",
+        }
+    }
+}
+
+/// Receiver of metrics from the subsystems of this crate that process many images.
+///
+/// Implement this trait to plug the crate into your metrics system (such as Prometheus or
+/// StatsD) and install it with [`set_metrics()`]. The [`Pipeline`][crate::Pipeline] and the batch
+/// transforms ([`Transformer::transform_batch()`][crate::Transformer::transform_batch] and
+/// [`Transformer::transform_batch_iter()`][crate::Transformer::transform_batch_iter]) report
+/// every processed image, labelled with the name of the subsystem (`"pipeline"` or
+/// `"transform_batch"`). The methods are called from the worker threads, so they should be cheap
+/// (such as incrementing atomic counters).
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use turbojpeg::{Counter, Histogram, Metrics};
+///
+/// #[derive(Default)]
+/// struct FrameCounter {
+///     frames: AtomicU64,
+///     output_bytes: AtomicU64,
+/// }
+///
+/// impl Metrics for FrameCounter {
+///     fn increment(&self, counter: Counter, _subsystem: &'static str, value: u64) {
+///         match counter {
+///             Counter::Frames => self.frames.fetch_add(value, Ordering::Relaxed),
+///             Counter::OutputBytes => self.output_bytes.fetch_add(value, Ordering::Relaxed),
+///             _ => 0,
+///         };
+///     }
+///
+///     fn observe(&self, histogram: Histogram, subsystem: &'static str, value: f64) {
+///         println!("{}{{subsystem=\"{}\"}} {}", histogram.name(), subsystem, value);
+///     }
+/// }
+///
+/// let metrics = Arc::new(FrameCounter::default());
+/// turbojpeg::set_metrics(Some(metrics.clone()));
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let transform = turbojpeg::Transform {
+///     op: turbojpeg::TransformOp::Rot90,
+///     .. turbojpeg::Transform::default()
+/// };
+/// let outputs = turbojpeg::Transformer::transform_batch(&[&jpeg_data, &jpeg_data], &transform);
+/// assert_eq!(metrics.frames.load(Ordering::Relaxed), 2);
+/// let output_bytes = outputs.iter().map(|output| output.as_ref().unwrap().len() as u64).sum();
+/// assert_eq!(metrics.output_bytes.load(Ordering::Relaxed), output_bytes);
+///
+/// turbojpeg::set_metrics(None);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait Metrics: Send + Sync {
+    /// Increments the `counter` of `subsystem` by `value`.
+    fn increment(&self, counter: Counter, subsystem: &'static str, value: u64);
+
+    /// Records an observation of `value` in the `histogram` of `subsystem`.
+    fn observe(&self, histogram: Histogram, subsystem: &'static str, value: f64);
+}
+
+static METRICS: RwLock<Option<Arc<dyn Metrics>>> = RwLock::new(None);
+
+/// Set the receiver of metrics for this process (see [`Metrics`]).
+///
+/// Use `None` to stop reporting metrics (this is the default).
+pub fn set_metrics(metrics: Option<Arc<dyn Metrics>>) {
+    *METRICS.write().unwrap_or_else(|err| err.into_inner()) = metrics;
+}
+
+fn current() -> Option<Arc<dyn Metrics>> {
+    METRICS.read().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+/// Runs `work` on one image and reports its duration and result.
+pub(crate) fn measure<R>(subsystem: &'static str, work: impl FnOnce() -> Result<R>) -> Result<R> {
+    let metrics = match current() {
+        Some(metrics) => metrics,
+        None => return work(),
+    };
+    let start = Instant::now();
+    let res = work();
+    metrics.observe(Histogram::Duration, subsystem, start.elapsed().as_secs_f64());
+    metrics.increment(Counter::Frames, subsystem, 1);
+    if res.is_err() {
+        metrics.increment(Counter::Errors, subsystem, 1);
+    }
+    res
+}
+
+/// Reports the sizes of the input and output JPEG data of one image.
+pub(crate) fn record_bytes(subsystem: &'static str, input_len: usize, output_len: Option<usize>) {
+    let metrics = match current() {
+        Some(metrics) => metrics,
+        None => return,
+    };
+    metrics.increment(Counter::InputBytes, subsystem, input_len as u64);
+    if let Some(output_len) = output_len {
+        metrics.increment(Counter::OutputBytes, subsystem, output_len as u64);
+        metrics.observe(Histogram::OutputSize, subsystem, output_len as f64);
+    }
+}
@@ -0,0 +1,232 @@
+use std::time::Instant;
+use crate::Image;
+use crate::common::{PixelFormat, Result, Error};
+use crate::decompress::Decompressor;
+use crate::marker::{self, RST0, RST7, SOS};
+use crate::pacing::TimedFrame;
+
+/// Policy of [`MjpegPlayer`] for frames that did not change.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum FrameSkip {
+    /// Decode every frame.
+    Never,
+    /// Skip frames whose JPEG data are byte-identical to the last decoded frame. The skipped
+    /// frames would decode to exactly the same image, so this is always safe.
+    #[default]
+    Identical,
+    /// Skip frames that are byte-identical to the last decoded frame, or that differ from it in at
+    /// most `max_changed` (a fraction from 0 to 1) of the restart intervals.
+    ///
+    /// This is a heuristic in the compressed domain: JPEG images with restart markers (which
+    /// most IP cameras emit) are coded in independent intervals of a few MCUs, so an interval
+    /// whose bytes did not change decodes to the same pixels. Frames are only compared if their
+    /// headers (including the quantization and Huffman tables) are identical, and they are always
+    /// compared with the last decoded frame, so small changes cannot accumulate over many skipped
+    /// frames. Frames without restart markers consist of a single interval, so they are skipped
+    /// only if they are identical.
+    NearIdentical {
+        /// Maximal fraction of changed restart intervals.
+        max_changed: f64,
+    },
+}
+
+/// Result of [`MjpegPlayer::decode_frame()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameStatus {
+    /// The frame was decoded into the [image][MjpegPlayer::image].
+    Decoded,
+    /// The frame was skipped, because it did not change (see [`FrameSkip`]). The
+    /// [image][MjpegPlayer::image] still contains the last decoded frame.
+    Skipped,
+}
+
+/// Decoder of MJPEG streams that skips frames which did not change.
+///
+/// Static camera scenes produce long runs of frames that are identical (or nearly identical) to
+/// the previous frame. The player compares every frame with the last decoded frame in the
+/// compressed domain, which is much cheaper than decompressing it, and decodes only the frames
+/// that changed (see [`FrameSkip`]). The frames are decoded with
+/// [`Decompressor::decompress_reuse()`] into one image, so the player does not allocate for frames
+/// of the same size.
+///
+/// Use [`split_jpegs()`][crate::split_jpegs] to get the frames of an MJPEG stream dumped to a
+/// file. Frames from a live source can be buffered in a [`FrameQueue`][crate::FrameQueue] and
+/// decoded with [`decode_timed()`][Self::decode_timed], which keeps their capture time.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{FrameStatus, MjpegPlayer, PixelFormat};
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let other = turbojpeg::compress(
+///     turbojpeg::Image::mandelbrot(384, 256, PixelFormat::RGB).as_deref(), 80, turbojpeg::Subsamp::None)?;
+///
+/// let mut player = MjpegPlayer::new(PixelFormat::RGB)?;
+/// assert_eq!(player.decode_frame(&jpeg_data)?, FrameStatus::Decoded);
+/// assert_eq!(player.decode_frame(&jpeg_data)?, FrameStatus::Skipped);
+/// assert_eq!(player.decode_frame(&other)?, FrameStatus::Decoded);
+/// assert_eq!(player.skipped_frames(), 1);
+///
+/// let image = player.image();
+/// assert_eq!((image.width, image.height), (384, 256));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MjpegPlayer {
+    decompressor: Decompressor,
+    skip: FrameSkip,
+    image: Image<Vec<u8>>,
+    reference: Option<Vec<u8>>,
+    skipped: u64,
+    timestamp: Option<Instant>,
+}
+
+impl MjpegPlayer {
+    /// Creates a player that decodes frames into pixel `format` and skips identical frames.
+    pub fn new(format: PixelFormat) -> Result<MjpegPlayer> {
+        Ok(MjpegPlayer {
+            decompressor: Decompressor::new()?,
+            skip: FrameSkip::default(),
+            image: Image { pixels: Vec::new(), width: 0, pitch: 0, height: 0, format },
+            reference: None,
+            skipped: 0,
+            timestamp: None,
+        })
+    }
+
+    /// Set the policy for frames that did not change (see [`FrameSkip`]).
+    ///
+    /// Returns [`Error::InvalidArgument`] and keeps the current policy if `max_changed` of
+    /// [`FrameSkip::NearIdentical`] is not between 0 and 1 (or if it is NaN).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{FrameSkip, MjpegPlayer, PixelFormat};
+    ///
+    /// let mut player = MjpegPlayer::new(PixelFormat::RGB)?;
+    /// player.set_frame_skip(FrameSkip::NearIdentical { max_changed: 0.05 })?;
+    /// assert!(player.set_frame_skip(FrameSkip::NearIdentical { max_changed: 5. }).is_err());
+    /// assert!(player.set_frame_skip(FrameSkip::NearIdentical { max_changed: f64::NAN }).is_err());
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn set_frame_skip(&mut self, skip: FrameSkip) -> Result<()> {
+        if let FrameSkip::NearIdentical { max_changed } = skip {
+            if !(0. ..=1.).contains(&max_changed) {
+                return Err(Error::InvalidArgument(format!("max_changed {} is not between 0 and 1", max_changed)))
+            }
+        }
+        self.skip = skip;
+        Ok(())
+    }
+
+    /// Returns a mutable reference to the decompressor, which you can use to change its settings.
+    pub fn decompressor_mut(&mut self) -> &mut Decompressor {
+        &mut self.decompressor
+    }
+
+    /// Decodes the frame in `jpeg_data`, unless it can be skipped.
+    ///
+    /// If the frame cannot be decoded, the image is left in an unspecified state and the next
+    /// frame is always decoded.
+    pub fn decode_frame(&mut self, jpeg_data: &[u8]) -> Result<FrameStatus> {
+        self.timestamp = None;
+        if let Some(reference) = &self.reference {
+            if frame_unchanged(self.skip, reference, jpeg_data) {
+                self.skipped += 1;
+                return Ok(FrameStatus::Skipped)
+            }
+        }
+
+        let mut reference = self.reference.take().unwrap_or_default();
+        self.decompressor.decompress_reuse(jpeg_data, &mut self.image)?;
+        if self.skip != FrameSkip::Never {
+            reference.clear();
+            reference.extend_from_slice(jpeg_data);
+            self.reference = Some(reference);
+        }
+        Ok(FrameStatus::Decoded)
+    }
+
+    /// Decodes a timestamped frame, unless it can be skipped (see
+    /// [`decode_frame()`][Self::decode_frame]), and records its timestamp as the
+    /// [timestamp of the image][Self::image_timestamp].
+    pub fn decode_timed<T: AsRef<[u8]>>(&mut self, frame: &TimedFrame<T>) -> Result<FrameStatus> {
+        let status = self.decode_frame(frame.data.as_ref())?;
+        self.timestamp = Some(frame.timestamp);
+        Ok(status)
+    }
+
+    /// Returns the timestamp of the last frame passed to [`decode_timed()`][Self::decode_timed],
+    /// which the image shows (also if the frame was skipped), or `None` if the last frame was
+    /// passed to [`decode_frame()`][Self::decode_frame] or could not be decoded.
+    pub fn image_timestamp(&self) -> Option<Instant> {
+        self.timestamp
+    }
+
+    /// Returns the last decoded frame.
+    pub fn image(&self) -> &Image<Vec<u8>> {
+        &self.image
+    }
+
+    /// Returns the number of frames that were skipped.
+    pub fn skipped_frames(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Forgets the last decoded frame, so that the next frame is always decoded.
+    pub fn reset(&mut self) {
+        self.reference = None;
+    }
+}
+
+fn frame_unchanged(skip: FrameSkip, reference: &[u8], frame: &[u8]) -> bool {
+    match skip {
+        FrameSkip::Never => false,
+        FrameSkip::Identical => reference == frame,
+        FrameSkip::NearIdentical { max_changed } => {
+            if reference == frame {
+                return true
+            }
+            let (reference_header, reference_intervals) = split_intervals(reference);
+            let (header, intervals) = split_intervals(frame);
+            if header.is_empty() || header != reference_header || intervals.len() != reference_intervals.len() {
+                return false
+            }
+            let changed = intervals.iter().zip(&reference_intervals).filter(|(a, b)| a != b).count();
+            changed as f64 <= max_changed * intervals.len() as f64
+        },
+    }
+}
+
+/// Splits JPEG data into the header (up to the end of the first SOS segment) and the intervals of
+/// entropy-coded data between restart markers. Returns an empty header if there is no SOS
+/// segment.
+fn split_intervals(data: &[u8]) -> (&[u8], Vec<&[u8]>) {
+    let mut segments = marker::segments(data);
+    let found_sos = segments.by_ref()
+        .map_while(|res| res.ok())
+        .any(|segment| segment.marker == SOS);
+    if !found_sos {
+        return (&[], Vec::new())
+    }
+    let (header, body) = data.split_at(segments.pos());
+
+    let mut intervals = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    while pos + 1 < body.len() {
+        if body[pos] == 0xff && (RST0..=RST7).contains(&body[pos + 1]) {
+            intervals.push(&body[start..pos]);
+            start = pos + 2;
+            pos += 2;
+        } else {
+            pos += 1;
+        }
+    }
+    intervals.push(&body[start..]);
+    (header, intervals)
+}
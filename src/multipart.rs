@@ -0,0 +1,148 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use crate::common::{Error, Result};
+
+/// Encoder of JPEG frames into a `multipart/x-mixed-replace` HTTP response (MJPEG over HTTP).
+///
+/// Browsers and most video players display a `multipart/x-mixed-replace` response as a video:
+/// every part of the response body replaces the previous one. The encoder produces the chunks of
+/// the body, one chunk per frame, each with the boundary delimiter and the `Content-Type` and
+/// `Content-Length` headers of the part. Send [`content_type()`][Self::content_type] as the
+/// `Content-Type` header of the response.
+///
+/// The encoder does not depend on an HTTP library. [`encode_part()`][Self::encode_part] and
+/// [`write_part()`][Self::write_part] encode single frames (for example in the `map` of an async
+/// stream), and [`chunks()`][Self::chunks] adapts an iterator of frames into an iterator of
+/// chunks, which you can turn into the body of a response. With `hyper` and `futures`, for
+/// example:
+///
+/// ```ignore
+/// let encoder = turbojpeg::MultipartEncoder::new();
+/// let response = hyper::Response::builder()
+///     .header(hyper::header::CONTENT_TYPE, encoder.content_type())
+///     .body(hyper::Body::wrap_stream(futures::stream::iter(
+///         encoder.chunks(frames).map(Ok::<_, std::convert::Infallible>),
+///     )))?;
+/// ```
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::MultipartEncoder;
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let encoder = MultipartEncoder::with_boundary("frame")?;
+/// assert_eq!(encoder.content_type(), "multipart/x-mixed-replace; boundary=frame");
+///
+/// let chunks: Vec<Vec<u8>> = encoder.chunks(vec![&jpeg_data, &jpeg_data]).collect();
+/// assert_eq!(chunks.len(), 3);
+/// let header = format!("--frame\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", jpeg_data.len());
+/// assert!(chunks[0].starts_with(header.as_bytes()));
+/// assert!(chunks[0].ends_with(b"\r\n"));
+/// // the last chunk closes the body
+/// assert_eq!(chunks[2], b"--frame--\r\n");
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultipartEncoder {
+    boundary: String,
+}
+
+/// Maximal length of a boundary (RFC 2046).
+const MAX_BOUNDARY_LEN: usize = 70;
+
+impl MultipartEncoder {
+    /// Creates an encoder with a random boundary.
+    pub fn new() -> MultipartEncoder {
+        let random = RandomState::new().build_hasher().finish();
+        MultipartEncoder { boundary: format!("turbojpeg-{:016x}", random) }
+    }
+
+    /// Creates an encoder with the given boundary.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the boundary is not valid according to RFC 2046: it
+    /// must have 1 to 70 characters, which must be ASCII letters, digits or one of `'()+_,-./:=?`
+    /// (or spaces, but not at the end).
+    pub fn with_boundary(boundary: impl Into<String>) -> Result<MultipartEncoder> {
+        let boundary = boundary.into();
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c);
+        if boundary.is_empty() || boundary.len() > MAX_BOUNDARY_LEN || !boundary.chars().all(valid_char) || boundary.ends_with(' ') {
+            return Err(Error::InvalidArgument(format!("invalid multipart boundary {:?}", boundary)))
+        }
+        Ok(MultipartEncoder { boundary })
+    }
+
+    /// Returns the boundary that delimits the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Returns the value of the `Content-Type` header of the response.
+    pub fn content_type(&self) -> String {
+        format!("multipart/x-mixed-replace; boundary={}", self.boundary)
+    }
+
+    /// Returns the chunk of the response body for one JPEG frame.
+    pub fn encode_part(&self, jpeg_data: &[u8]) -> Vec<u8> {
+        let mut chunk = Vec::with_capacity(jpeg_data.len() + self.boundary.len() + 64);
+        self.write_part(&mut chunk, jpeg_data).unwrap();
+        chunk
+    }
+
+    /// Writes the chunk of the response body for one JPEG frame into `writer`.
+    ///
+    /// This is useful to send the response directly over a socket, without an HTTP library.
+    pub fn write_part<W: Write>(&self, mut writer: W, jpeg_data: &[u8]) -> std::io::Result<()> {
+        write!(writer, "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", self.boundary, jpeg_data.len())?;
+        writer.write_all(jpeg_data)?;
+        writer.write_all(b"\r\n")
+    }
+
+    /// Returns the last chunk of the response body, which closes the multipart body.
+    pub fn encode_end(&self) -> Vec<u8> {
+        format!("--{}--\r\n", self.boundary).into_bytes()
+    }
+
+    /// Adapts an iterator of JPEG frames into an iterator of the chunks of the response body.
+    ///
+    /// The iterator yields a chunk for every frame and the [closing chunk][Self::encode_end] after
+    /// the last frame.
+    pub fn chunks<I>(&self, frames: I) -> MultipartChunks<I::IntoIter>
+        where I: IntoIterator, I::Item: AsRef<[u8]>
+    {
+        MultipartChunks { encoder: self.clone(), frames: frames.into_iter(), done: false }
+    }
+}
+
+impl Default for MultipartEncoder {
+    fn default() -> MultipartEncoder {
+        MultipartEncoder::new()
+    }
+}
+
+/// Iterator over the chunks of a `multipart/x-mixed-replace` response body, created by
+/// [`MultipartEncoder::chunks()`].
+#[derive(Debug, Clone)]
+pub struct MultipartChunks<I> {
+    encoder: MultipartEncoder,
+    frames: I,
+    done: bool,
+}
+
+impl<I> Iterator for MultipartChunks<I> where I: Iterator, I::Item: AsRef<[u8]> {
+    type Item = Vec<u8>;
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.done {
+            return None
+        }
+        match self.frames.next() {
+            Some(frame) => Some(self.encoder.encode_part(frame.as_ref())),
+            None => {
+                self.done = true;
+                Some(self.encoder.encode_end())
+            },
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A frame with the time when it was captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedFrame<T> {
+    /// The frame, such as JPEG data or an [`Image`][crate::Image].
+    pub data: T,
+    /// The time when the frame was captured.
+    pub timestamp: Instant,
+}
+
+impl<T> TimedFrame<T> {
+    /// Creates a frame captured now.
+    pub fn now(data: T) -> TimedFrame<T> {
+        TimedFrame { data, timestamp: Instant::now() }
+    }
+
+    /// Returns the time of the frame relative to `epoch` (such as the start of the stream), or
+    /// zero if the frame is older than `epoch`.
+    pub fn elapsed_since(&self, epoch: Instant) -> Duration {
+        self.timestamp.saturating_duration_since(epoch)
+    }
+}
+
+/// Policy of a full [`FrameQueue`] for new frames.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum QueuePolicy {
+    /// Drop the oldest frame in the queue to make room for the new frame. This keeps the latency
+    /// low, which is what live streams need.
+    #[default]
+    DropOldest,
+    /// Drop the new frame. This keeps the frames in the queue contiguous.
+    DropNewest,
+}
+
+/// Bounded queue of timestamped frames between a producer (such as a camera) and a consumer
+/// (such as an encoder) that may run at different rates.
+///
+/// When the consumer falls behind, the queue does not grow: a full queue drops a frame according
+/// to its [`QueuePolicy`] and counts it in [`dropped_frames()`][Self::dropped_frames]. The queue is
+/// not synchronized; wrap it in a [`Mutex`][std::sync::Mutex] to share it between threads.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::FrameQueue;
+///
+/// let mut queue = FrameQueue::new(2);
+/// queue.push(1);
+/// queue.push(2);
+/// // the queue is full, so the oldest frame is dropped
+/// assert_eq!(queue.push(3).map(|frame| frame.data), Some(1));
+/// assert_eq!(queue.dropped_frames(), 1);
+///
+/// assert_eq!(queue.pop().map(|frame| frame.data), Some(2));
+/// assert_eq!(queue.pop().map(|frame| frame.data), Some(3));
+/// assert!(queue.is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameQueue<T> {
+    frames: VecDeque<TimedFrame<T>>,
+    capacity: usize,
+    policy: QueuePolicy,
+    dropped: u64,
+}
+
+impl<T> FrameQueue<T> {
+    /// Creates a queue that holds at most `capacity` frames and drops the oldest frame when it is
+    /// full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> FrameQueue<T> {
+        assert!(capacity > 0, "queue capacity must not be zero");
+        FrameQueue {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            policy: QueuePolicy::default(),
+            dropped: 0,
+        }
+    }
+
+    /// Set the policy for new frames when the queue is full.
+    pub fn set_policy(&mut self, policy: QueuePolicy) {
+        self.policy = policy;
+    }
+
+    /// Adds a frame captured now. Returns the frame that was dropped if the queue was full.
+    pub fn push(&mut self, data: T) -> Option<TimedFrame<T>> {
+        self.push_timed(TimedFrame::now(data))
+    }
+
+    /// Adds a timestamped frame. Returns the frame that was dropped if the queue was full.
+    pub fn push_timed(&mut self, frame: TimedFrame<T>) -> Option<TimedFrame<T>> {
+        let dropped = if self.frames.len() < self.capacity {
+            None
+        } else {
+            match self.policy {
+                QueuePolicy::DropOldest => self.frames.pop_front(),
+                QueuePolicy::DropNewest => {
+                    self.dropped += 1;
+                    return Some(frame)
+                },
+            }
+        };
+        self.dropped += dropped.is_some() as u64;
+        self.frames.push_back(frame);
+        dropped
+    }
+
+    /// Removes and returns the oldest frame.
+    pub fn pop(&mut self) -> Option<TimedFrame<T>> {
+        self.frames.pop_front()
+    }
+
+    /// Removes and returns the newest frame, dropping all older frames.
+    ///
+    /// Use this instead of [`pop()`][Self::pop] if the consumer only cares about the most recent
+    /// frame, such as a preview.
+    pub fn pop_latest(&mut self) -> Option<TimedFrame<T>> {
+        let latest = self.frames.pop_back();
+        self.dropped += self.frames.len() as u64;
+        self.frames.clear();
+        latest
+    }
+
+    /// Returns the number of frames in the queue.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns true if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns the maximal number of frames in the queue.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of frames that were dropped, because the queue was full or because of
+    /// [`pop_latest()`][Self::pop_latest].
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Removes all frames (without counting them as dropped).
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Paces a stream of frames to a target frame rate.
+///
+/// The pacer keeps a schedule of frame deadlines, one every `1 / fps` seconds. It can be used in
+/// two ways:
+///
+/// - A producer that is faster than the target rate (such as a camera at 30 fps feeding a stream
+///   at 10 fps) calls [`admit()`][Self::admit] for every frame, which selects the frames that
+///   keep to the target rate, so the others can be dropped before they are compressed.
+/// - A loop that should run at the target rate (such as a sender of frames from a
+///   [`FrameQueue`]) calls [`wait()`][Self::wait] before every frame, which sleeps until the next
+///   deadline.
+///
+/// The schedule does not drift: the deadlines are spaced by exactly one interval, so jitter of
+/// individual frames does not change the average rate. If the stream stalls for longer than an
+/// interval, the schedule restarts instead of catching up with a burst of frames.
+///
+/// # Example
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use turbojpeg::FramePacer;
+///
+/// // a camera delivers 30 frames per second, but we want to stream 10
+/// let mut pacer = FramePacer::new(10.);
+/// let start = Instant::now();
+/// let admitted = (0..30)
+///     .map(|i| start + Duration::from_secs(i) / 30)
+///     .filter(|&timestamp| pacer.admit(timestamp))
+///     .count();
+/// assert_eq!(admitted, 10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl FramePacer {
+    /// Creates a pacer for `fps` frames per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fps` is not positive and finite.
+    pub fn new(fps: f64) -> FramePacer {
+        assert!(fps > 0. && fps.is_finite(), "frame rate {} is not positive", fps);
+        FramePacer { interval: Duration::from_secs_f64(1. / fps), next: None }
+    }
+
+    /// Returns the interval between frames.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Returns the deadline of the next frame, or `None` if no frame was admitted yet.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /// Returns true if the frame captured at `timestamp` should be kept to stream at the target
+    /// rate, and schedules the next frame in that case. Returns false if the frame comes too early
+    /// and should be dropped.
+    pub fn admit(&mut self, timestamp: Instant) -> bool {
+        match self.next {
+            Some(next) if timestamp < next => false,
+            _ => {
+                self.schedule(timestamp);
+                true
+            },
+        }
+    }
+
+    /// Sleeps until the deadline of the next frame, schedules the frame after it and returns the
+    /// deadline. Returns immediately for the first frame and if the deadline has passed.
+    pub fn wait(&mut self) -> Instant {
+        let now = Instant::now();
+        let deadline = match self.next {
+            Some(next) if next > now => {
+                std::thread::sleep(next - now);
+                next
+            },
+            _ => now,
+        };
+        self.schedule(deadline);
+        deadline
+    }
+
+    /// Forgets the schedule, so that the next frame is admitted immediately.
+    pub fn reset(&mut self) {
+        self.next = None;
+    }
+
+    fn schedule(&mut self, timestamp: Instant) {
+        let next = self.next.map(|next| next + self.interval);
+        self.next = match next {
+            Some(next) if next > timestamp => Some(next),
+            // the first frame, or the stream stalled: restart the schedule
+            _ => Some(timestamp + self.interval),
+        };
+    }
+}
@@ -0,0 +1,140 @@
+use std::f64::consts::PI;
+use crate::Decompressor;
+use crate::common::{PixelFormat, Result};
+use crate::scale::ScalingFactor;
+
+/// Computes the perceptual hash (pHash) of a JPEG image.
+///
+/// The hash is computed from the low frequencies of the discrete cosine transform of a 32x32
+/// grayscale thumbnail of the image. Similar images (for example, resized or recompressed copies)
+/// have hashes that differ in few bits, so you can use [`hash_distance()`] to detect
+/// near-duplicates. A distance of 10 or less usually means that the images are similar.
+///
+/// The thumbnail is decompressed with the smallest scaling factor (typically 1/8), so this is only
+/// a little slower than reading the JPEG header.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // recompress the image with low quality
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// let recompressed_data = turbojpeg::compress(image.as_deref(), 30, turbojpeg::Subsamp::Sub2x2)?;
+///
+/// // the images are perceptually similar
+/// let hash = turbojpeg::phash(&jpeg_data)?;
+/// let recompressed_hash = turbojpeg::phash(&recompressed_data)?;
+/// assert!(turbojpeg::hash_distance(hash, recompressed_hash) <= 4);
+///
+/// // but a different image is not
+/// let other_image = turbojpeg::Image::mandelbrot(384, 256, turbojpeg::PixelFormat::RGB);
+/// let other_data = turbojpeg::compress(other_image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+/// assert!(turbojpeg::hash_distance(hash, turbojpeg::phash(&other_data)?) > 10);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "phash")))]
+pub fn phash(jpeg_data: &[u8]) -> Result<u64> {
+    const SIZE: usize = 32;
+    let pixels = gray_thumbnail(jpeg_data, SIZE, SIZE)?;
+
+    // compute the 8x8 lowest frequencies of the 2D DCT-II
+    let cos_table = (0..8)
+        .flat_map(|u| (0..SIZE).map(move |x| {
+            (PI * (2 * x + 1) as f64 * u as f64 / (2 * SIZE) as f64).cos()
+        }))
+        .collect::<Vec<_>>();
+    let mut coefs = [0.; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += pixels[y*SIZE + x] * cos_table[u*SIZE + x] * cos_table[v*SIZE + y];
+                }
+            }
+            coefs[v*8 + u] = sum;
+        }
+    }
+
+    // the DC coefficient is excluded from the median, because it is much larger than the others
+    let mut sorted = coefs[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    Ok(to_bits(coefs.iter().map(|&coef| coef > median)))
+}
+
+/// Computes the difference hash (dHash) of a JPEG image.
+///
+/// The hash compares the brightness of neighboring pixels in a 9x8 grayscale thumbnail of the
+/// image. It is faster but less robust than [`phash()`]. Use [`hash_distance()`] to compare the
+/// hashes.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let hash = turbojpeg::dhash(&jpeg_data)?;
+/// assert_eq!(turbojpeg::hash_distance(hash, hash), 0);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "phash")))]
+pub fn dhash(jpeg_data: &[u8]) -> Result<u64> {
+    let pixels = gray_thumbnail(jpeg_data, 9, 8)?;
+    Ok(to_bits((0..8).flat_map(|y| {
+        let row = &pixels[y*9..][..9];
+        (0..8).map(move |x| row[x] < row[x + 1])
+    })))
+}
+
+/// Returns the number of bits that differ between two perceptual hashes (the Hamming distance).
+///
+/// This is used to compare the hashes computed by [`phash()`] and [`dhash()`].
+#[cfg_attr(docsrs, doc(cfg(feature = "phash")))]
+pub fn hash_distance(hash_1: u64, hash_2: u64) -> u32 {
+    (hash_1 ^ hash_2).count_ones()
+}
+
+/// Decompresses the JPEG image into grayscale with the smallest scaling factor that gives at least
+/// `width` x `height` pixels, and resizes it to exactly `width` x `height` pixels.
+fn gray_thumbnail(jpeg_data: &[u8], width: usize, height: usize) -> Result<Vec<f64>> {
+    let mut decompressor = Decompressor::new()?;
+    let header = decompressor.read_header(jpeg_data)?;
+    let factor = [ScalingFactor::ONE_EIGHTH, ScalingFactor::ONE_QUARTER, ScalingFactor::ONE_HALF]
+        .into_iter()
+        .find(|factor| factor.scale(header.width) >= width && factor.scale(header.height) >= height)
+        .unwrap_or(ScalingFactor::ONE);
+    let image = decompressor.decompress_scaled(jpeg_data, PixelFormat::GRAY, factor)?;
+
+    // resize the image by averaging the pixels in every area
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let (y0, y1) = area(y, height, image.height);
+        for x in 0..width {
+            let (x0, x1) = area(x, width, image.width);
+            let mut sum = 0;
+            for src_y in y0..y1 {
+                for src_x in x0..x1 {
+                    sum += image.pixels[src_y*image.pitch + src_x] as u32;
+                }
+            }
+            pixels.push(sum as f64 / ((y1 - y0) * (x1 - x0)) as f64);
+        }
+    }
+    Ok(pixels)
+}
+
+/// Returns the range of source pixels that are averaged into target pixel `i` (the range is never
+/// empty, unless the source is empty).
+fn area(i: usize, target_len: usize, source_len: usize) -> (usize, usize) {
+    let start = i * source_len / target_len;
+    let end = ((i + 1) * source_len / target_len).max(start + 1).min(source_len);
+    (start.min(end.saturating_sub(1)), end)
+}
+
+fn to_bits<I: Iterator<Item = bool>>(bits: I) -> u64 {
+    bits.fold(0, |hash, bit| (hash << 1) | bit as u64)
+}
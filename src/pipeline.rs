@@ -0,0 +1,143 @@
+use crate::batch::{BatchIter, BatchOrder};
+use crate::cancel::{CancelToken, check_cancel};
+use crate::common::{Error, Result};
+use crate::concurrency::{Priority, set_thread_priority};
+
+/// Builder of a parallel pipeline that connects a producer, worker threads and a consumer.
+///
+/// Decoding or encoding a stream of images in parallel needs a producer (such as a directory
+/// walker), several worker threads with their own [`Decompressor`][crate::Decompressor] or
+/// [`Compressor`][crate::Compressor], and a consumer (such as a file writer), connected with
+/// bounded queues so that a slow stage does not make the others pile up images in memory. This
+/// type implements that pattern:
+///
+/// - The producer is an iterator of inputs. The workers pull the next input only when they are
+///   ready for it, so the producer never runs ahead of the workers.
+/// - Every worker thread creates its own worker (typically a TurboJPEG handle) with `make_worker`
+///   and calls `work` on every input that it pulls.
+/// - The results are passed to the consumer through a queue of
+///   [`queue_len`][Self::queue_len] results, so the workers wait when the consumer is slow.
+///
+/// The pipeline shuts down gracefully when the producer is exhausted, when the consumer returns
+/// an error, or when the [cancel token][Self::cancel_token] is cancelled: the workers finish the
+/// inputs that they are processing, and all threads are joined before [`run()`][Self::run]
+/// returns.
+///
+/// Every processed input is reported to the [`Metrics`][crate::Metrics] set by
+/// [`set_metrics()`][crate::set_metrics] (with the subsystem `"pipeline"`).
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Decompressor, PixelFormat, Pipeline};
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let inputs = std::iter::repeat(jpeg_data).take(20);
+///
+/// // decompress the images in 4 threads and compute their average brightness in order
+/// let mut averages = Vec::new();
+/// Pipeline::new().workers(4).queue_len(8).run(
+///     inputs,
+///     Decompressor::new,
+///     |decompressor, jpeg_data: Vec<u8>| decompressor.decompress_to_buf(&jpeg_data, PixelFormat::GRAY),
+///     |_index, image| -> turbojpeg::Result<()> {
+///         let image = image?;
+///         let sum = image.pixels.iter().map(|&pixel| pixel as usize).sum::<usize>();
+///         averages.push(sum / image.pixels.len());
+///         Ok(())
+///     },
+/// )?;
+/// assert_eq!(averages.len(), 20);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    workers: usize,
+    queue_len: usize,
+    order: BatchOrder,
+    priority: Priority,
+    cancel: Option<CancelToken>,
+}
+
+impl Pipeline {
+    /// Create a pipeline with the default settings.
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Set the number of worker threads. Zero means that the number of threads is determined by
+    /// [`std::thread::available_parallelism()`] (this is the default).
+    pub fn workers(mut self, workers: usize) -> Pipeline {
+        self.workers = workers;
+        self
+    }
+
+    /// Set the maximal number of results that wait for the consumer. Zero means twice the number
+    /// of worker threads (this is the default).
+    pub fn queue_len(mut self, queue_len: usize) -> Pipeline {
+        self.queue_len = queue_len;
+        self
+    }
+
+    /// Set the order in which the results are passed to the consumer (see [`BatchOrder`]).
+    pub fn order(mut self, order: BatchOrder) -> Pipeline {
+        self.order = order;
+        self
+    }
+
+    /// Set the [`Priority`] of the TurboJPEG calls made by the worker threads (the default is
+    /// [`Priority::Interactive`]).
+    pub fn priority(mut self, priority: Priority) -> Pipeline {
+        self.priority = priority;
+        self
+    }
+
+    /// Set the token that stops the pipeline (see [`CancelToken`]).
+    pub fn cancel_token(mut self, token: CancelToken) -> Pipeline {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Start the worker threads and return an iterator over the results.
+    ///
+    /// The iterator yields pairs of the index of the input and its result. Dropping the iterator
+    /// shuts down the pipeline (see [`BatchIter`]).
+    pub fn spawn<I, W, R, M, F>(&self, inputs: I, make_worker: M, work: F) -> BatchIter<R>
+        where I: IntoIterator,
+              I::IntoIter: Send + 'static,
+              R: Send + 'static,
+              M: Fn() -> Result<W> + Send + Sync + 'static,
+              F: Fn(&mut W, I::Item) -> Result<R> + Send + Sync + 'static,
+    {
+        // the workers are created in the worker threads
+        let priority = self.priority;
+        let make_worker = move || {
+            set_thread_priority(priority);
+            make_worker()
+        };
+        BatchIter::spawn("pipeline", inputs, self.workers, self.queue_len, self.order, self.cancel.clone(), make_worker, work)
+    }
+
+    /// Run the pipeline until all inputs are processed, passing the results to `consumer`.
+    ///
+    /// The `consumer` receives the index of the input and its result. If it returns an error, the
+    /// pipeline is shut down and the error is returned. If the pipeline is cancelled,
+    /// [`Error::Cancelled`] is returned.
+    pub fn run<I, W, R, M, F, C, E>(&self, inputs: I, make_worker: M, work: F, mut consumer: C) -> std::result::Result<(), E>
+        where I: IntoIterator,
+              I::IntoIter: Send + 'static,
+              R: Send + 'static,
+              M: Fn() -> Result<W> + Send + Sync + 'static,
+              F: Fn(&mut W, I::Item) -> Result<R> + Send + Sync + 'static,
+              C: FnMut(usize, Result<R>) -> std::result::Result<(), E>,
+              E: From<Error>,
+    {
+        // if the consumer fails, the results are dropped, which stops the threads
+        for (index, res) in self.spawn(inputs, make_worker, work) {
+            consumer(index, res)?;
+        }
+        check_cancel(&self.cancel)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+use crate::Image;
+use crate::common::{PixelFormat, Result, Error};
+
+/// Decodes an image in the binary PGM (`P5`), PPM (`P6`) or PAM (`P7`) format.
+///
+/// The pixel format of the returned image depends on the file: [`PixelFormat::GRAY`] for PGM
+/// files and PAM files with `TUPLTYPE GRAYSCALE`, [`PixelFormat::RGB`] for PPM files and PAM
+/// files with `TUPLTYPE RGB`, [`PixelFormat::RGBA`] for PAM files with `TUPLTYPE RGB_ALPHA`, and
+/// [`PixelFormat::CMYK`] for PAM files with `TUPLTYPE CMYK`. Only 8-bit images (with maximal value
+/// 255) are supported. The rows of the returned image are tightly packed.
+///
+/// This is a small pure-Rust implementation, which is useful for tests, fuzzing corpora and
+/// debugging. To load PPM files from disk, you can also use [`load_image()`][crate::load_image].
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(64, 48, turbojpeg::PixelFormat::RGB);
+/// let ppm_data = turbojpeg::encode_pnm(image.as_deref());
+/// assert!(ppm_data.starts_with(b"P6\n64 48\n255\n"));
+///
+/// let decoded = turbojpeg::decode_pnm(&ppm_data)?;
+/// assert_eq!(decoded.format, turbojpeg::PixelFormat::RGB);
+/// assert_eq!((decoded.width, decoded.height), (64, 48));
+/// assert_eq!(decoded.pixels, image.pixels);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "pnm")))]
+pub fn decode_pnm(data: &[u8]) -> Result<Image<Vec<u8>>> {
+    let mut parser = Parser { data, pos: 0 };
+    let (width, height, format) = match parser.token()? {
+        b"P5" | b"P6" => {
+            let format = if data[1] == b'5' { PixelFormat::GRAY } else { PixelFormat::RGB };
+            let width = parser.number()?;
+            let height = parser.number()?;
+            let maxval = parser.number()?;
+            if maxval != 255 {
+                return Err(Error::MalformedPnm("only images with maximal value 255 are supported"))
+            }
+            // exactly one whitespace character separates the header from the pixels
+            parser.pos += 1;
+            (width, height, format)
+        },
+        b"P7" => parser.pam_header()?,
+        _ => return Err(Error::MalformedPnm("unsupported magic number")),
+    };
+
+    let pitch = width.checked_mul(format.size())
+        .ok_or(Error::MalformedPnm("image is too large"))?;
+    let len = pitch.checked_mul(height)
+        .ok_or(Error::MalformedPnm("image is too large"))?;
+    let pixels = data.get(parser.pos..)
+        .and_then(|pixels| pixels.get(..len))
+        .ok_or(Error::MalformedPnm("pixel data is truncated"))?;
+    Ok(Image { pixels: pixels.to_vec(), width, pitch, height, format })
+}
+
+/// Encodes an image into the binary PGM (`P5`), PPM (`P6`) or PAM (`P7`) format.
+///
+/// Grayscale images are encoded as PGM, images without alpha channel as PPM (the pixels are
+/// converted to RGB), images with alpha channel as PAM with `TUPLTYPE RGB_ALPHA` and CMYK images as
+/// PAM with `TUPLTYPE CMYK`. See [`decode_pnm()`] for an example.
+#[cfg_attr(docsrs, doc(cfg(feature = "pnm")))]
+pub fn encode_pnm(image: Image<&[u8]>) -> Vec<u8> {
+    image.assert_valid(image.pixels.len());
+    let Image { pixels, width, pitch, height, format } = image;
+    let (header, channels): (String, &[usize]) = match format {
+        PixelFormat::GRAY => (format!("P5\n{} {}\n255\n", width, height), &[0]),
+        PixelFormat::CMYK => (pam_header(width, height, 4, "CMYK"), &[0, 1, 2, 3]),
+        PixelFormat::RGB => (ppm_header(width, height), &[0, 1, 2]),
+        PixelFormat::BGR | PixelFormat::BGRX => (ppm_header(width, height), &[2, 1, 0]),
+        PixelFormat::RGBX => (ppm_header(width, height), &[0, 1, 2]),
+        PixelFormat::XBGR => (ppm_header(width, height), &[3, 2, 1]),
+        PixelFormat::XRGB => (ppm_header(width, height), &[1, 2, 3]),
+        PixelFormat::RGBA => (pam_header(width, height, 4, "RGB_ALPHA"), &[0, 1, 2, 3]),
+        PixelFormat::BGRA => (pam_header(width, height, 4, "RGB_ALPHA"), &[2, 1, 0, 3]),
+        PixelFormat::ABGR => (pam_header(width, height, 4, "RGB_ALPHA"), &[3, 2, 1, 0]),
+        PixelFormat::ARGB => (pam_header(width, height, 4, "RGB_ALPHA"), &[1, 2, 3, 0]),
+    };
+
+    let pixel_size = format.size();
+    let mut data = Vec::with_capacity(header.len() + width * height * channels.len());
+    data.extend_from_slice(header.as_bytes());
+    for y in 0..height {
+        let row = &pixels[y*pitch..][..width*pixel_size];
+        if channels.len() == pixel_size && channels.iter().enumerate().all(|(i, &c)| i == c) {
+            data.extend_from_slice(row);
+        } else {
+            for pixel in row.chunks_exact(pixel_size) {
+                data.extend(channels.iter().map(|&c| pixel[c]));
+            }
+        }
+    }
+    data
+}
+
+fn ppm_header(width: usize, height: usize) -> String {
+    format!("P6\n{} {}\n255\n", width, height)
+}
+
+fn pam_header(width: usize, height: usize, depth: usize, tuple_type: &str) -> String {
+    format!("P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL 255\nTUPLTYPE {}\nENDHDR\n",
+        width, height, depth, tuple_type)
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the next whitespace-separated token, skipping comments.
+    fn token(&mut self) -> Result<&'a [u8]> {
+        loop {
+            match self.data.get(self.pos) {
+                Some(b'#') => {
+                    while !matches!(self.data.get(self.pos), Some(b'\n') | None) {
+                        self.pos += 1;
+                    }
+                },
+                Some(byte) if byte.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(Error::MalformedPnm("header is truncated")),
+            }
+        }
+        let start = self.pos;
+        while matches!(self.data.get(self.pos), Some(byte) if !byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        Ok(&self.data[start..self.pos])
+    }
+
+    fn number(&mut self) -> Result<usize> {
+        std::str::from_utf8(self.token()?).ok()
+            .and_then(|token| token.parse().ok())
+            .ok_or(Error::MalformedPnm("invalid number in header"))
+    }
+
+    fn pam_header(&mut self) -> Result<(usize, usize, PixelFormat)> {
+        let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+        let mut tuple_type = None;
+        loop {
+            match self.token()? {
+                b"WIDTH" => width = Some(self.number()?),
+                b"HEIGHT" => height = Some(self.number()?),
+                b"DEPTH" => depth = Some(self.number()?),
+                b"MAXVAL" => maxval = Some(self.number()?),
+                b"TUPLTYPE" => tuple_type = Some(self.token()?),
+                b"ENDHDR" => break,
+                _ => return Err(Error::MalformedPnm("unknown PAM header field")),
+            }
+        }
+        // the header ends with a newline after ENDHDR
+        self.pos += 1;
+
+        let (width, height) = width.zip(height)
+            .ok_or(Error::MalformedPnm("missing PAM width or height"))?;
+        if maxval != Some(255) {
+            return Err(Error::MalformedPnm("only images with maximal value 255 are supported"))
+        }
+        let format = match (depth, tuple_type) {
+            (Some(1), Some(b"GRAYSCALE") | None) => PixelFormat::GRAY,
+            (Some(3), Some(b"RGB") | None) => PixelFormat::RGB,
+            (Some(4), Some(b"RGB_ALPHA")) => PixelFormat::RGBA,
+            (Some(4), Some(b"CMYK")) => PixelFormat::CMYK,
+            _ => return Err(Error::MalformedPnm("unsupported PAM depth or tuple type")),
+        };
+        Ok((width, height, format))
+    }
+}
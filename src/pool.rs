@@ -0,0 +1,199 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard};
+use crate::common::Result;
+use crate::compress::Compressor;
+use crate::decompress::Decompressor;
+use crate::fork;
+use crate::transform::Transformer;
+
+/// TurboJPEG instance that can be kept in a [`HandlePool`].
+///
+/// This is implemented for [`Compressor`], [`Decompressor`] and [`Transformer`].
+pub trait PoolHandle: Send + Sized {
+    /// Creates a new instance.
+    fn create() -> Result<Self>;
+
+    /// Resets all settings of the instance to their defaults, before it is returned into the pool.
+    fn reset_handle(&mut self);
+}
+
+impl PoolHandle for Compressor {
+    fn create() -> Result<Compressor> {
+        Compressor::new()
+    }
+
+    fn reset_handle(&mut self) {
+        self.reset()
+    }
+}
+
+impl PoolHandle for Decompressor {
+    fn create() -> Result<Decompressor> {
+        Decompressor::new()
+    }
+
+    fn reset_handle(&mut self) {
+        self.reset()
+    }
+}
+
+impl PoolHandle for Transformer {
+    fn create() -> Result<Transformer> {
+        Transformer::new()
+    }
+
+    fn reset_handle(&mut self) {
+        self.set_cancel_token(None)
+    }
+}
+
+/// Pool of TurboJPEG instances that can be shared by many threads.
+///
+/// Creating a TurboJPEG instance allocates memory, so services that process many small images
+/// keep the instances in a pool. [`get()`][Self::get] takes an idle instance from the pool (or
+/// creates a new one), and the returned [`Pooled`] guard puts it back when it is dropped. The
+/// instance is [reset][Compressor::reset] before it goes back, so settings that one request
+/// changes (such as the quality or a cancel token) never leak into an unrelated request. Use
+/// [`Compressor::with_overrides()`] to change the settings only for one call.
+///
+/// # Fork safety
+///
+/// The pool detects that the process was forked (with `pthread_atfork()`). The child process
+/// never uses the instances inherited from the parent: it drops the idle instances and creates
+/// new ones, and the instances that were taken from the pool before the fork are dropped instead
+/// of being returned into it. So a pre-forking server may create the pool before it forks its
+/// workers. Do not fork while another thread is inside [`get()`][Self::get] or drops a
+/// [`Pooled`] guard, because the child would inherit the locked pool (this applies to all
+/// mutexes in a multithreaded process). None of the methods are async-signal-safe, so do not
+/// use the pool from signal handlers.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{CompressOverrides, Compressor, HandlePool, Subsamp};
+///
+/// let pool = HandlePool::<Compressor>::new(8);
+/// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+///
+/// let mut compressor = pool.get()?;
+/// compressor.set_subsamp(Subsamp::Gray);
+/// let gray = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(turbojpeg::read_header(&gray)?.subsamp, Subsamp::Gray);
+/// drop(compressor);
+/// assert_eq!(pool.idle_count(), 1);
+///
+/// // the instance was reset, so the next request gets the default settings
+/// let mut compressor = pool.get()?;
+/// let color = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(turbojpeg::read_header(&color)?.subsamp, Subsamp::None);
+///
+/// // per-call overrides are restored after the call
+/// let overrides = CompressOverrides { subsamp: Some(Subsamp::Sub2x2), ..CompressOverrides::default() };
+/// let jpeg_data = compressor.with_overrides(&overrides, |c| c.compress_to_vec(image.as_deref()))?;
+/// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, Subsamp::Sub2x2);
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, Subsamp::None);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct HandlePool<T> {
+    idle: Mutex<Idle<T>>,
+    max_idle: usize,
+}
+
+/// Idle instances created in the given [fork generation][fork::generation].
+struct Idle<T> {
+    handles: Vec<T>,
+    generation: u64,
+}
+
+impl<T: PoolHandle> HandlePool<T> {
+    /// Creates an empty pool that keeps at most `max_idle` idle instances. Instances that are
+    /// returned into a full pool are dropped.
+    pub fn new(max_idle: usize) -> HandlePool<T> {
+        HandlePool { idle: Mutex::new(Idle { handles: Vec::new(), generation: fork::generation() }), max_idle }
+    }
+
+    /// Takes an idle instance from the pool, or creates a new one if the pool is empty.
+    pub fn get(&self) -> Result<Pooled<'_, T>> {
+        let (idle, generation) = {
+            let mut idle = self.lock_idle();
+            (idle.handles.pop(), idle.generation)
+        };
+        let handle = match idle {
+            Some(handle) => handle,
+            None => T::create()?,
+        };
+        Ok(Pooled { handle: Some(handle), generation, pool: self })
+    }
+
+    /// Returns the number of idle instances in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.lock_idle().handles.len()
+    }
+
+    /// Locks the idle instances, dropping them if they were inherited from the parent process.
+    fn lock_idle(&self) -> MutexGuard<'_, Idle<T>> {
+        let mut idle = self.idle.lock().unwrap_or_else(|err| err.into_inner());
+        let generation = fork::generation();
+        if idle.generation != generation {
+            idle.handles.clear();
+            idle.generation = generation;
+        }
+        idle
+    }
+
+    fn put(&self, mut handle: T, generation: u64) {
+        handle.reset_handle();
+        let mut idle = self.lock_idle();
+        if idle.generation == generation && idle.handles.len() < self.max_idle {
+            idle.handles.push(handle);
+        }
+    }
+}
+
+impl<T> fmt::Debug for HandlePool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let idle = self.idle.lock().map_or(0, |idle| idle.handles.len());
+        f.debug_struct("HandlePool")
+            .field("idle", &idle)
+            .field("max_idle", &self.max_idle)
+            .finish()
+    }
+}
+
+/// TurboJPEG instance taken from a [`HandlePool`], which is reset and returned into the pool when
+/// this guard is dropped.
+pub struct Pooled<'a, T: PoolHandle> {
+    handle: Option<T>,
+    generation: u64,
+    pool: &'a HandlePool<T>,
+}
+
+impl<T: PoolHandle> Deref for Pooled<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.handle.as_ref().expect("handle is present until drop")
+    }
+}
+
+impl<T: PoolHandle> DerefMut for Pooled<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.handle.as_mut().expect("handle is present until drop")
+    }
+}
+
+impl<T: PoolHandle> Drop for Pooled<'_, T> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.pool.put(handle, self.generation);
+        }
+    }
+}
+
+impl<T: PoolHandle + fmt::Debug> fmt::Debug for Pooled<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Pooled").field(&self.handle).finish()
+    }
+}
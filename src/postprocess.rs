@@ -0,0 +1,92 @@
+use std::fmt;
+use crate::Image;
+use crate::common::{PixelFormat, Result};
+
+/// Post-processing step that a [`Decompressor`][crate::Decompressor] applies to decompressed
+/// images.
+///
+/// Set the post-processing step with
+/// [`Decompressor::set_post_process()`][crate::Decompressor::set_post_process]. It is applied in
+/// place on the buffer that TurboJPEG decompressed into (after the deblocking filter, see
+/// [`Decompressor::set_deblock()`][crate::Decompressor::set_deblock]), so sharpening, denoising
+/// or color grading does not need any extra copy of the image.
+///
+/// Implement [`process_frame()`][Self::process_frame] for filters that need the whole image
+/// (such as ML models), or [`process_row()`][Self::process_row] for filters that work on
+/// individual rows. Closures `FnMut(Image<&mut [u8]>) -> Result<()>` also implement this trait.
+///
+/// Note that TurboJPEG always decompresses the complete image, so the rows are processed only
+/// after the whole image has been decompressed. Errors returned by the post-processing step are
+/// returned from the decompression method; use [`Error::PostProcess`][crate::Error::PostProcess]
+/// to wrap your own errors.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{PixelFormat, PostProcess};
+///
+/// // a row-based filter that converts the image to its negative
+/// struct Negative;
+///
+/// impl PostProcess for Negative {
+///     fn process_row(&mut self, _y: usize, row: &mut [u8], _format: PixelFormat) -> turbojpeg::Result<()> {
+///         row.iter_mut().for_each(|value| *value = 255 - *value);
+///         Ok(())
+///     }
+/// }
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// let image = decompressor.decompress_to_buf(&jpeg_data, PixelFormat::RGB)?;
+///
+/// decompressor.set_post_process(Negative);
+/// let negative = decompressor.decompress_to_buf(&jpeg_data, PixelFormat::RGB)?;
+/// assert_eq!(negative.pixels[0], 255 - image.pixels[0]);
+///
+/// // a frame-based filter defined by a closure
+/// decompressor.set_post_process(|image: turbojpeg::Image<&mut [u8]>| {
+///     assert_eq!((image.width, image.height), (384, 256));
+///     Ok(())
+/// });
+/// decompressor.decompress_to_buf(&jpeg_data, PixelFormat::RGB)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait PostProcess: Send {
+    /// Processes the whole decompressed image in place.
+    ///
+    /// The default implementation calls [`process_row()`][Self::process_row] for every row of the
+    /// image, from top to bottom.
+    fn process_frame(&mut self, image: Image<&mut [u8]>) -> Result<()> {
+        let Image { pixels, width, pitch, height, format } = image;
+        for y in 0..height {
+            self.process_row(y, &mut pixels[y*pitch..][..width*format.size()], format)?;
+        }
+        Ok(())
+    }
+
+    /// Processes row `y` of the decompressed image in place.
+    ///
+    /// The `row` contains exactly `width * format.size()` bytes. The default implementation does
+    /// nothing.
+    fn process_row(&mut self, y: usize, row: &mut [u8], format: PixelFormat) -> Result<()> {
+        let _ = (y, row, format);
+        Ok(())
+    }
+}
+
+impl<F> PostProcess for F
+    where F: FnMut(Image<&mut [u8]>) -> Result<()> + Send
+{
+    fn process_frame(&mut self, image: Image<&mut [u8]>) -> Result<()> {
+        self(image)
+    }
+}
+
+pub(crate) struct PostProcessor(pub(crate) Box<dyn PostProcess>);
+
+impl fmt::Debug for PostProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PostProcessor")
+    }
+}
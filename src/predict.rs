@@ -0,0 +1,227 @@
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+use crate::Image;
+use crate::common::{PixelFormat, Subsamp};
+use crate::preset::CompressorPreset;
+use crate::sharp_yuv::rgb_to_ycc;
+use crate::tables::{STD_CHROMINANCE_QUANT_TABLE, STD_LUMINANCE_QUANT_TABLE, ZIGZAG, quant_table};
+
+/// Maximal number of MCUs that are transformed to predict the size.
+const MAX_SAMPLES: usize = 1024;
+
+/// Size of the markers and tables of a color JPEG image with the default Huffman tables.
+const HEADER_BYTES: usize = 620;
+
+/// Size of the markers and tables of a grayscale JPEG image, which has only the luminance tables.
+const GRAY_HEADER_BYTES: usize = 330;
+
+/// Predicts the size in bytes of the JPEG image that compressing `image` with `settings` would
+/// produce, without compressing it.
+///
+/// The prediction transforms a sample of at most 1024 MCUs spread over the image with the DCT,
+/// quantizes the coefficients with the tables that TurboJPEG uses for the quality, and estimates
+/// the number of bits that the entropy coder needs for them. This takes a small fraction of the
+/// time of a compression (and does not depend on the image size for large images), so it is
+/// suitable for admission control and as the initial guess of a search for the quality that hits
+/// a target size. The prediction is usually within 20 % of the actual size, but it is only an
+/// estimate: images with very few or very different sampled blocks may be predicted poorly.
+///
+/// For images in [`PixelFormat::GRAY`], `settings.subsamp` is ignored (the image is always
+/// compressed as grayscale).
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{CompressorPreset, PixelFormat, Quality, Subsamp};
+///
+/// // read JPEG data from file and decompress it (the image has 384x256 pixels)
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::decompress(&jpeg_data, PixelFormat::RGB)?;
+///
+/// let settings = CompressorPreset { quality: Quality::new(75)?, subsamp: Subsamp::Sub2x2, ..CompressorPreset::THUMBNAIL };
+/// let predicted = turbojpeg::predict_compressed_size(image.as_deref(), &settings);
+///
+/// let mut compressor = turbojpeg::Compressor::with_preset(settings)?;
+/// let actual = compressor.compress_to_vec(image.as_deref())?.len();
+/// assert!(predicted.abs_diff(actual) < actual / 4);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn predict_compressed_size(image: Image<&[u8]>, settings: &CompressorPreset) -> usize {
+    image.assert_valid(image.pixels.len());
+    if image.width == 0 || image.height == 0 {
+        return HEADER_BYTES
+    }
+    let subsamp = match image.format {
+        PixelFormat::GRAY => Subsamp::Gray,
+        _ => settings.subsamp,
+    };
+    let quality = settings.quality.get();
+    let luma_table = quant_table(&STD_LUMINANCE_QUANT_TABLE, quality);
+    let chroma_table = quant_table(&STD_CHROMINANCE_QUANT_TABLE, quality);
+
+    let (mcu_width, mcu_height) = subsamp.mcu_size();
+    let (mcus_x, mcus_y) = (image.width.div_ceil(mcu_width), image.height.div_ceil(mcu_height));
+    // sample every `step`-th MCU in both directions
+    let mut step = 1;
+    while mcus_x.div_ceil(step) * mcus_y.div_ceil(step) > MAX_SAMPLES {
+        step += 1;
+    }
+
+    let mut coder = BitEstimator::default();
+    let mut samples = 0;
+    for mcu_y in (0..mcus_y).step_by(step) {
+        for mcu_x in (0..mcus_x).step_by(step) {
+            let (x0, y0) = (mcu_x * mcu_width, mcu_y * mcu_height);
+            let components = mcu_components(image, x0, y0, mcu_width, mcu_height);
+            for (index, component) in components.iter().enumerate() {
+                let chroma = index == 1 || index == 2;
+                if chroma && subsamp == Subsamp::Gray {
+                    continue
+                }
+                let (sx, sy) = if chroma { (mcu_width / 8, mcu_height / 8) } else { (1, 1) };
+                for by in (0..mcu_height / 8).step_by(sy) {
+                    for bx in (0..mcu_width / 8).step_by(sx) {
+                        let block = sample_block(component, mcu_width, 8*bx, 8*by, sx, sy);
+                        let table = if chroma { &chroma_table } else { &luma_table };
+                        coder.add_block(index, &block, table);
+                    }
+                }
+            }
+            samples += 1;
+        }
+    }
+
+    let (mut bits, mut eob_bits) = (coder.bits as f64, coder.eob_bits as f64);
+    if settings.progressive {
+        // progressive images use optimized Huffman tables, and the ends of blocks in consecutive
+        // blocks are coded together as runs
+        bits *= 0.92;
+        eob_bits *= 0.1;
+    }
+    let mcus = mcus_x * mcus_y;
+    let header = if subsamp == Subsamp::Gray { GRAY_HEADER_BYTES } else { HEADER_BYTES };
+    header + ((bits + eob_bits) * mcus as f64 / samples as f64 / 8.) as usize
+}
+
+/// Returns the samples of the components of the MCU with top left corner at (`x0`, `y0`), with
+/// the level shift applied and the pixels outside of the image replicated from the edges.
+fn mcu_components(image: Image<&[u8]>, x0: usize, y0: usize, mcu_width: usize, mcu_height: usize) -> Vec<Vec<f32>> {
+    let pixel_size = image.format.size();
+    let component_count = match image.format {
+        PixelFormat::GRAY => 1,
+        PixelFormat::CMYK => 4,
+        _ => 3,
+    };
+    let mut components = vec![Vec::with_capacity(mcu_width * mcu_height); component_count];
+    for y in y0..y0 + mcu_height {
+        let row = &image.pixels[y.min(image.height - 1) * image.pitch..];
+        for x in x0..x0 + mcu_width {
+            let pixel = &row[x.min(image.width - 1) * pixel_size..][..pixel_size];
+            let (luma, cb, cr) = match image.format.rgb_offsets() {
+                Some((r, g, b)) => rgb_to_ycc([pixel[r], pixel[g], pixel[b]].map(|value| value as f32)),
+                // CMYK images are compressed as YCCK, from the inverted CMY components
+                None if image.format == PixelFormat::CMYK => rgb_to_ycc([0, 1, 2].map(|i| 255. - pixel[i] as f32)),
+                None => (pixel[0] as f32, 128., 128.),
+            };
+            let values = [luma - 128., cb, cr, pixel.get(3).map_or(0., |&k| k as f32 - 128.)];
+            for (component, value) in components.iter_mut().zip(values) {
+                component.push(value);
+            }
+        }
+    }
+    components
+}
+
+/// Returns the 8x8 block at (`x0`, `y0`) of a component with the given `stride`, averaging
+/// `sx` x `sy` samples into one.
+fn sample_block(component: &[f32], stride: usize, x0: usize, y0: usize, sx: usize, sy: usize) -> [f32; 64] {
+    let mut block = [0.; 64];
+    for (i, value) in block.iter_mut().enumerate() {
+        let (x, y) = (x0 + (i % 8) * sx, y0 + (i / 8) * sy);
+        let mut sum = 0.;
+        for dy in 0..sy {
+            for dx in 0..sx {
+                sum += component[(y + dy) * stride + x + dx];
+            }
+        }
+        *value = sum / (sx * sy) as f32;
+    }
+    block
+}
+
+/// Estimates the number of bits that the entropy coder with the default Huffman tables spends on
+/// quantized blocks.
+#[derive(Default)]
+struct BitEstimator {
+    bits: u64,
+    eob_bits: u64,
+    last_dc: [i32; 4],
+}
+
+impl BitEstimator {
+    fn add_block(&mut self, component: usize, block: &[f32; 64], table: &[i32; 64]) {
+        let coefs = dct(block);
+        let chroma = component == 1 || component == 2;
+
+        let dc = (coefs[0] / table[0] as f32).round() as i32;
+        let diff = dc - std::mem::replace(&mut self.last_dc[component], dc);
+        let size = category(diff);
+        // the codes of small DC categories have 2 or 3 bits, larger ones grow by a bit each
+        self.bits += size + if size < 6 { 3 } else { size - 2 };
+
+        let mut run = 0;
+        for &index in &ZIGZAG[1..] {
+            let value = (coefs[index] / table[index] as f32).round() as i32;
+            if value == 0 {
+                run += 1;
+                continue
+            }
+            let size = category(value);
+            // ZRL codes for runs of 16 zeros
+            self.bits += 11 * (run / 16);
+            // the length of the code of (run, size) grows with both, and the code is followed by
+            // `size` bits of the value
+            self.bits += 2 + size + (run % 16) / 2 + size;
+            run = 0;
+        }
+        if run > 0 {
+            // end of block
+            self.eob_bits += if chroma { 2 } else { 4 };
+        }
+    }
+}
+
+/// Returns the magnitude category of a coefficient (the number of bits of its absolute value).
+fn category(value: i32) -> u64 {
+    (32 - value.unsigned_abs().leading_zeros()) as u64
+}
+
+/// Computes the 2D DCT of an 8x8 block with the scaling of the JPEG standard.
+fn dct(block: &[f32; 64]) -> [f32; 64] {
+    static COS: OnceLock<[[f32; 8]; 8]> = OnceLock::new();
+    let cos = COS.get_or_init(|| {
+        let mut cos = [[0.; 8]; 8];
+        for (u, row) in cos.iter_mut().enumerate() {
+            let scale = if u == 0 { 0.5 / 2f32.sqrt() } else { 0.5 };
+            for (x, value) in row.iter_mut().enumerate() {
+                *value = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.).cos();
+            }
+        }
+        cos
+    });
+
+    let mut rows = [0.; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y*8 + u] = (0..8).map(|x| cos[u][x] * block[y*8 + x]).sum();
+        }
+    }
+    let mut coefs = [0.; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefs[v*8 + u] = (0..8).map(|y| cos[v][y] * rows[y*8 + u]).sum();
+        }
+    }
+    coefs
+}
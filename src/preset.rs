@@ -0,0 +1,322 @@
+use std::sync::{Arc, RwLock};
+use crate::common::{Subsamp, DctMethod, Error, Result};
+use crate::quality::Quality;
+
+/// Predefined compression settings for common scenarios.
+///
+/// Use [`Compressor::with_preset()`][crate::Compressor::with_preset] to create a compressor with
+/// these settings, or [`CompressorPreset`] if you need to tweak them.
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(400, 300, turbojpeg::PixelFormat::RGB);
+///
+/// let mut compressor = turbojpeg::Compressor::with_preset(turbojpeg::Preset::Web)?;
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+///
+/// let header = turbojpeg::read_header(&jpeg_data)?;
+/// assert_eq!(header.subsamp, turbojpeg::Subsamp::Sub2x2);
+/// assert!(header.multi_scan); // progressive
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Preset {
+    /// Photos served on the web: quality 80, 4:2:0 subsampling and progressive coding (see
+    /// [`CompressorPreset::WEB`]).
+    Web,
+    /// Long-term storage of photos: quality 95, no subsampling and baseline coding (see
+    /// [`CompressorPreset::ARCHIVE`]).
+    Archive,
+    /// Small previews: quality 75, 4:2:0 subsampling, baseline coding and the fast DCT (see
+    /// [`CompressorPreset::THUMBNAIL`]).
+    Thumbnail,
+    /// Screenshots with text and sharp edges: quality 90, no subsampling and progressive coding
+    /// (see [`CompressorPreset::SCREENSHOT`]).
+    Screenshot,
+}
+
+/// Settings of a [`Compressor`][crate::Compressor].
+///
+/// The constants correspond to the variants of [`Preset`]. Progressive JPEG images always use
+/// optimized Huffman tables, so progressive presets also produce smaller files than baseline
+/// presets with the same quality. The TurboJPEG API does not support Huffman optimization of
+/// baseline images or input smoothing, so these cannot be configured.
+///
+/// # Example
+///
+/// ```
+/// // start from the web preset, but use a higher quality
+/// let preset = turbojpeg::CompressorPreset {
+///     quality: turbojpeg::Quality::new(90)?,
+///     .. turbojpeg::CompressorPreset::WEB
+/// };
+/// let mut compressor = turbojpeg::Compressor::with_preset(preset)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompressorPreset {
+    /// Quality from 1 (worst) to 100 (best) (see
+    /// [`Compressor::set_quality()`][crate::Compressor::set_quality]).
+    pub quality: Quality,
+    /// Chrominance subsampling (see [`Compressor::set_subsamp()`][crate::Compressor::set_subsamp]).
+    pub subsamp: Subsamp,
+    /// Progressive entropy coding (see
+    /// [`Compressor::set_progressive()`][crate::Compressor::set_progressive]).
+    pub progressive: bool,
+    /// DCT algorithm (see [`Compressor::set_dct_method()`][crate::Compressor::set_dct_method]).
+    pub dct_method: DctMethod,
+}
+
+impl CompressorPreset {
+    /// Settings of [`Preset::Web`].
+    pub const WEB: CompressorPreset = CompressorPreset {
+        quality: Quality::default_web(),
+        subsamp: Subsamp::Sub2x2,
+        progressive: true,
+        dct_method: DctMethod::Accurate,
+    };
+
+    /// Settings of [`Preset::Archive`].
+    pub const ARCHIVE: CompressorPreset = CompressorPreset {
+        quality: Quality::new_const(95),
+        subsamp: Subsamp::None,
+        progressive: false,
+        dct_method: DctMethod::Accurate,
+    };
+
+    /// Settings of [`Preset::Thumbnail`].
+    pub const THUMBNAIL: CompressorPreset = CompressorPreset {
+        quality: Quality::new_const(75),
+        subsamp: Subsamp::Sub2x2,
+        progressive: false,
+        dct_method: DctMethod::Fast,
+    };
+
+    /// Settings of [`Preset::Screenshot`].
+    pub const SCREENSHOT: CompressorPreset = CompressorPreset {
+        quality: Quality::new_const(90),
+        subsamp: Subsamp::None,
+        progressive: true,
+        dct_method: DctMethod::Accurate,
+    };
+
+    /// Parses the compression switches of `cjpeg` (from libjpeg-turbo or mozjpeg).
+    ///
+    /// This eases the migration of shell pipelines that call `cjpeg`: the switches are parsed like
+    /// `cjpeg` does, starting from its defaults (quality 75, 4:2:0 subsampling, baseline coding and
+    /// the accurate DCT). Like in `cjpeg`, switches can be abbreviated (such as `-q 85` or
+    /// `-prog`), and the parsing stops at the first argument that is not a switch (the file
+    /// names). The supported switches are:
+    ///
+    /// - `-quality N`: the quality. Lists of qualities for individual quantization tables (such as
+    ///   `-quality 85,70`) are not supported.
+    /// - `-sample HxV`: the sampling factors of the luminance, which determine the subsampling:
+    ///   `1x1`, `2x1`, `2x2`, `1x2` and `4x1` are supported. The sampling factors of the
+    ///   chrominance may be given too, but they must be `1x1` (such as `-sample 2x2,1x1,1x1`).
+    /// - `-grayscale`: compress into a grayscale image ([`Subsamp::Gray`]).
+    /// - `-progressive`: progressive coding.
+    /// - `-dct int`, `-dct fast`: the DCT method. `-dct float` is not available in TurboJPEG, so
+    ///   it selects the accurate integer DCT, which is as accurate.
+    /// - `-optimize` and `-baseline` are accepted and ignored. The TurboJPEG API cannot optimize
+    ///   the Huffman tables of baseline images (progressive images always use optimized tables),
+    ///   and the quantization tables are always baseline-compatible for quality 25 and higher.
+    ///
+    /// Returns [`Error::InvalidArgument`] for other switches and for invalid values, and
+    /// [`Error::InvalidQuality`] for qualities outside of the range from 1 to 100.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::{CompressorPreset, Subsamp};
+    ///
+    /// // from `cjpeg -quality 85 -sample 1x1 -progressive -optimize input.ppm`
+    /// let preset = CompressorPreset::from_cjpeg_args(&["-quality", "85", "-sample", "1x1", "-progressive", "-optimize", "input.ppm"])?;
+    /// assert_eq!(preset.quality.get(), 85);
+    /// assert_eq!(preset.subsamp, Subsamp::None);
+    /// assert!(preset.progressive);
+    ///
+    /// // abbreviated switches and the defaults of cjpeg
+    /// let preset = CompressorPreset::from_cjpeg_args(&["-q", "60", "-gray"])?;
+    /// assert_eq!((preset.quality.get(), preset.subsamp, preset.progressive), (60, Subsamp::Gray, false));
+    /// assert_eq!(CompressorPreset::from_cjpeg_args(&[])?.quality.get(), 75);
+    ///
+    /// assert!(CompressorPreset::from_cjpeg_args(&["-arithmetic"]).is_err());
+    /// assert!(CompressorPreset::from_cjpeg_args(&["-quality"]).is_err());
+    ///
+    /// let mut compressor = turbojpeg::Compressor::with_preset(preset)?;
+    /// # let _ = &mut compressor;
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_cjpeg_args(args: &[&str]) -> Result<CompressorPreset> {
+        let mut preset = CompressorPreset {
+            quality: Quality::new_const(75),
+            subsamp: Subsamp::Sub2x2,
+            progressive: false,
+            dct_method: DctMethod::Accurate,
+        };
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                break
+            }
+            let switch = arg.trim_start_matches('-');
+            let mut value = || args.next().copied()
+                .ok_or_else(|| Error::InvalidArgument(format!("missing value for {}", arg)));
+
+            if keymatch(switch, "baseline", 1) || keymatch(switch, "optimize", 1) || keymatch(switch, "optimise", 1) {
+                // see above
+            } else if keymatch(switch, "dct", 2) {
+                preset.dct_method = match value()? {
+                    "int" | "float" => DctMethod::Accurate,
+                    "fast" => DctMethod::Fast,
+                    other => return Err(Error::InvalidArgument(format!("unknown DCT method {}", other))),
+                };
+            } else if keymatch(switch, "grayscale", 2) || keymatch(switch, "greyscale", 2) {
+                preset.subsamp = Subsamp::Gray;
+            } else if keymatch(switch, "progressive", 1) {
+                preset.progressive = true;
+            } else if keymatch(switch, "quality", 1) {
+                let value = value()?;
+                let quality = value.parse::<i32>()
+                    .map_err(|_| Error::InvalidArgument(format!("invalid quality {}", value)))?;
+                preset.quality = Quality::new(quality)?;
+            } else if keymatch(switch, "sample", 2) {
+                let value = value()?;
+                let mut factors = value.split(',');
+                preset.subsamp = match factors.next().unwrap_or_default().to_ascii_lowercase().as_str() {
+                    "1x1" => Subsamp::None,
+                    "2x1" => Subsamp::Sub2x1,
+                    "2x2" => Subsamp::Sub2x2,
+                    "1x2" => Subsamp::Sub1x2,
+                    "4x1" => Subsamp::Sub4x1,
+                    _ => return Err(Error::InvalidArgument(format!("unsupported sampling factors {}", value))),
+                };
+                if !factors.all(|factor| factor.eq_ignore_ascii_case("1x1")) {
+                    return Err(Error::InvalidArgument(format!("unsupported sampling factors {}", value)))
+                }
+            } else {
+                return Err(Error::InvalidArgument(format!("unknown or unsupported switch {}", arg)))
+            }
+        }
+        Ok(preset)
+    }
+}
+
+/// Returns true if `arg` is an abbreviation of `keyword` with at least `min_len` characters, like
+/// `keymatch()` in `cjpeg`.
+fn keymatch(arg: &str, keyword: &str, min_len: usize) -> bool {
+    arg.len() >= min_len && keyword.starts_with(&*arg.to_ascii_lowercase())
+}
+
+impl From<Preset> for CompressorPreset {
+    fn from(preset: Preset) -> CompressorPreset {
+        match preset {
+            Preset::Web => CompressorPreset::WEB,
+            Preset::Archive => CompressorPreset::ARCHIVE,
+            Preset::Thumbnail => CompressorPreset::THUMBNAIL,
+            Preset::Screenshot => CompressorPreset::SCREENSHOT,
+        }
+    }
+}
+
+/// Compressor settings that can be changed at runtime and are shared by many compressors.
+///
+/// Long-running services often need to change the quality or subsampling without restarting or
+/// rebuilding their pools of compressors. Install a `SharedPreset` into the compressors with
+/// [`Compressor::set_shared_preset()`][crate::Compressor::set_shared_preset]: every compressor
+/// then takes a snapshot of the settings at the start of every compression, so a change made
+/// with [`store()`][Self::store] or [`update()`][Self::update] applies to all compressions that
+/// start after it, and a compression never sees a mix of old and new settings.
+///
+/// Cloning a `SharedPreset` is cheap and the clones share the same settings.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Compressor, CompressorPreset, SharedPreset, Subsamp};
+///
+/// let settings = SharedPreset::new(CompressorPreset::WEB);
+/// let mut compressor = Compressor::new()?;
+/// compressor.set_shared_preset(Some(settings.clone()));
+///
+/// let image = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, Subsamp::Sub2x2);
+///
+/// // for example, when the configuration of the service is reloaded
+/// settings.update(|preset| preset.subsamp = Subsamp::None);
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// assert_eq!(turbojpeg::read_header(&jpeg_data)?.subsamp, Subsamp::None);
+/// assert_eq!(settings.load().quality.get(), 80);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedPreset(Arc<RwLock<CompressorPreset>>);
+
+impl SharedPreset {
+    /// Creates shared settings from a [`Preset`] or a [`CompressorPreset`].
+    pub fn new<P: Into<CompressorPreset>>(preset: P) -> SharedPreset {
+        SharedPreset(Arc::new(RwLock::new(preset.into())))
+    }
+
+    /// Returns a snapshot of the current settings.
+    pub fn load(&self) -> CompressorPreset {
+        *self.0.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Replaces the settings.
+    pub fn store<P: Into<CompressorPreset>>(&self, preset: P) {
+        *self.0.write().unwrap_or_else(|err| err.into_inner()) = preset.into();
+    }
+
+    /// Modifies the settings in place, atomically with respect to other updates.
+    pub fn update<F: FnOnce(&mut CompressorPreset)>(&self, f: F) {
+        f(&mut self.0.write().unwrap_or_else(|err| err.into_inner()));
+    }
+}
+
+/// Settings that override the settings of a [`Compressor`][crate::Compressor] for a single call.
+///
+/// Use this with [`Compressor::with_overrides()`][crate::Compressor::with_overrides] when the
+/// compressor is shared by unrelated requests (for example, when it comes from a
+/// [`HandlePool`][crate::HandlePool] or a thread-local variable), so that the settings of one
+/// request cannot leak into the next one. The fields that are `None` keep the settings of the
+/// compressor.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompressOverrides {
+    /// Quality from 1 (worst) to 100 (best) (see
+    /// [`Compressor::set_quality()`][crate::Compressor::set_quality]).
+    pub quality: Option<Quality>,
+    /// Chrominance subsampling (see [`Compressor::set_subsamp()`][crate::Compressor::set_subsamp]).
+    pub subsamp: Option<Subsamp>,
+    /// Progressive entropy coding (see
+    /// [`Compressor::set_progressive()`][crate::Compressor::set_progressive]).
+    pub progressive: Option<bool>,
+    /// DCT algorithm (see [`Compressor::set_dct_method()`][crate::Compressor::set_dct_method]).
+    pub dct_method: Option<DctMethod>,
+}
+
+impl CompressOverrides {
+    /// Applies the overrides to `preset`.
+    pub(crate) fn apply(&self, preset: &mut CompressorPreset) {
+        if let Some(quality) = self.quality {
+            preset.quality = quality;
+        }
+        if let Some(subsamp) = self.subsamp {
+            preset.subsamp = subsamp;
+        }
+        if let Some(progressive) = self.progressive {
+            preset.progressive = progressive;
+        }
+        if let Some(dct_method) = self.dct_method {
+            preset.dct_method = dct_method;
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+
+/// Progress of a compression or decompression.
+///
+/// Progress is reported to the callback set by
+/// [`Compressor::set_progress_callback()`][crate::Compressor::set_progress_callback] or
+/// [`Decompressor::set_progress_callback()`][crate::Decompressor::set_progress_callback].
+///
+/// TurboJPEG processes the whole image in a single call into the C library, so the methods that
+/// work with the complete image report progress only when the operation starts and when it
+/// finishes. Row-based methods such as [`Compressor::compress_with()`][crate::Compressor::compress_with]
+/// and [`Decompressor::decompress_with()`][crate::Decompressor::decompress_with] also report the
+/// progress after every row.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Progress {
+    /// Number of image rows that have been processed.
+    pub rows_done: usize,
+    /// Total number of image rows.
+    pub rows_total: usize,
+}
+
+impl Progress {
+    /// Returns the progress as a fraction between 0.0 and 1.0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let progress = turbojpeg::Progress { rows_done: 25, rows_total: 100 };
+    /// assert_eq!(progress.fraction(), 0.25);
+    /// ```
+    pub fn fraction(&self) -> f64 {
+        if self.rows_total == 0 {
+            1.
+        } else {
+            self.rows_done as f64 / self.rows_total as f64
+        }
+    }
+
+    /// Returns true if all rows have been processed.
+    pub fn is_done(&self) -> bool {
+        self.rows_done >= self.rows_total
+    }
+}
+
+pub(crate) struct ProgressCallback(Box<dyn FnMut(Progress) + Send>);
+
+impl ProgressCallback {
+    pub(crate) fn new<F>(callback: F) -> ProgressCallback
+        where F: FnMut(Progress) + Send + 'static
+    {
+        ProgressCallback(Box::new(callback))
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+pub(crate) fn report_progress(callback: &mut Option<ProgressCallback>, rows_done: usize, rows_total: usize) {
+    if let Some(ProgressCallback(callback)) = callback {
+        callback(Progress { rows_done, rows_total });
+    }
+}
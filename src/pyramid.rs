@@ -0,0 +1,247 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use crate::{Image, Compressor, OutputBuf};
+use crate::common::{PixelFormat, Subsamp, Result};
+
+/// Options for generating tile pyramids with [`generate_pyramid()`].
+///
+/// The default options produce tiles that are compatible with the Deep Zoom viewers (such as
+/// OpenSeadragon).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PyramidOptions {
+    /// Size of tiles in pixels, without the overlap (default is 254).
+    pub tile_size: usize,
+    /// Number of pixels that each tile shares with its neighbors on every side (default is 1).
+    pub overlap: usize,
+    /// Quality of the compressed tiles, from 1 (worst) to 100 (best) (default is 90).
+    pub quality: i32,
+    /// Chrominance subsampling of the compressed tiles (default is [`Subsamp::Sub2x2`]).
+    ///
+    /// This is ignored for grayscale images, which are always compressed with [`Subsamp::Gray`].
+    pub subsamp: Subsamp,
+    /// Number of threads that compress the tiles. Zero means that the number of threads is
+    /// determined by [`std::thread::available_parallelism()`] (this is the default).
+    pub threads: usize,
+}
+
+impl Default for PyramidOptions {
+    fn default() -> Self {
+        PyramidOptions {
+            tile_size: 254,
+            overlap: 1,
+            quality: 90,
+            subsamp: Subsamp::Sub2x2,
+            threads: 0,
+        }
+    }
+}
+
+/// A single compressed tile of a pyramid produced by [`generate_pyramid()`].
+#[derive(Debug, Clone)]
+pub struct PyramidTile {
+    /// Level of the tile. Level 0 has size 1x1 pixels, and the image size doubles with every level
+    /// (the last level has the full resolution).
+    pub level: usize,
+    /// Column of the tile in its level.
+    pub column: usize,
+    /// Row of the tile in its level.
+    pub row: usize,
+    /// Compressed JPEG data of the tile.
+    pub data: Vec<u8>,
+}
+
+impl PyramidTile {
+    /// Returns the path of the tile in the Deep Zoom layout, relative to the `_files` directory
+    /// (such as `"12/3_5.jpg"`).
+    pub fn dzi_path(&self) -> String {
+        format!("{}/{}_{}.jpg", self.level, self.column, self.row)
+    }
+}
+
+/// Generates a multi-resolution tile pyramid from the `image`.
+///
+/// The image is repeatedly downsampled by a factor of 2 (using a box filter) until it has size
+/// 1x1 pixels, and every level is split into tiles as described by `options`. The tiles are
+/// compressed in parallel and passed to the callback `use_tile` (in the thread that called this
+/// function), from the highest level (full resolution) to level 0. Every thread reuses its
+/// [`Compressor`] and output buffer for all tiles.
+///
+/// If the callback returns an error, the generation is stopped and the error is returned.
+///
+/// # Panics
+///
+/// Panics if `options.tile_size` is zero.
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(1000, 600, turbojpeg::PixelFormat::RGB);
+/// let options = turbojpeg::PyramidOptions::default();
+///
+/// // write the tiles into a Deep Zoom directory
+/// let dir = std::env::temp_dir().join("mandelbrot_files");
+/// let mut tile_count = 0;
+/// turbojpeg::generate_pyramid(image.as_deref(), &options, |tile| {
+///     let path = dir.join(tile.dzi_path());
+///     std::fs::create_dir_all(path.parent().unwrap())?;
+///     std::fs::write(path, &tile.data)?;
+///     tile_count += 1;
+///     Ok::<(), Box<dyn std::error::Error>>(())
+/// })?;
+/// assert_eq!(tile_count, 4*3 + 2*2 + 9); // 11 levels, the last 9 levels are single tiles
+///
+/// // write the descriptor
+/// let dzi = turbojpeg::dzi_descriptor(image.width, image.height, &options);
+/// std::fs::write(std::env::temp_dir().join("mandelbrot.dzi"), dzi)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn generate_pyramid<F, E>(image: Image<&[u8]>, options: &PyramidOptions, mut use_tile: F)
+    -> std::result::Result<(), E>
+    where F: FnMut(PyramidTile) -> std::result::Result<(), E>,
+          E: From<crate::Error>,
+{
+    assert!(options.tile_size > 0, "tile size must not be zero");
+    image.assert_valid(image.pixels.len());
+
+    let levels = level_count(image.width, image.height);
+    let mut level_image: Option<Image<Vec<u8>>> = None;
+    for level in (0..levels).rev() {
+        let view = match level_image {
+            Some(ref level_image) => level_image.as_deref(),
+            None => image,
+        };
+        compress_level(view, level, options, &mut use_tile)?;
+        if level > 0 {
+            level_image = Some(downsample(view));
+        }
+    }
+    Ok(())
+}
+
+/// Decompresses the JPEG image in `jpeg_data` and generates a tile pyramid from it.
+///
+/// See [`generate_pyramid()`] for details.
+pub fn generate_pyramid_from_jpeg<F, E>(jpeg_data: &[u8], options: &PyramidOptions, use_tile: F)
+    -> std::result::Result<(), E>
+    where F: FnMut(PyramidTile) -> std::result::Result<(), E>,
+          E: From<crate::Error>,
+{
+    let image = crate::decompress(jpeg_data, PixelFormat::RGB)?;
+    generate_pyramid(image.as_deref(), options, use_tile)
+}
+
+/// Returns the Deep Zoom descriptor (the `.dzi` XML file) for a pyramid generated with
+/// [`generate_pyramid()`] from an image of size `width` x `height`.
+pub fn dzi_descriptor(width: usize, height: usize, options: &PyramidOptions) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" \
+        Format=\"jpg\" Overlap=\"{}\" TileSize=\"{}\">\n\
+        \x20 <Size Width=\"{}\" Height=\"{}\"/>\n\
+        </Image>\n",
+        options.overlap, options.tile_size, width, height,
+    )
+}
+
+fn level_count(width: usize, height: usize) -> usize {
+    let mut size = width.max(height).max(1);
+    let mut levels = 1;
+    while size > 1 {
+        size = size.div_ceil(2);
+        levels += 1;
+    }
+    levels
+}
+
+fn compress_level<F, E>(image: Image<&[u8]>, level: usize, options: &PyramidOptions, use_tile: &mut F)
+    -> std::result::Result<(), E>
+    where F: FnMut(PyramidTile) -> std::result::Result<(), E>,
+          E: From<crate::Error>,
+{
+    let tile_size = options.tile_size;
+    let columns = image.width.div_ceil(tile_size);
+    let rows = image.height.div_ceil(tile_size);
+    let tile_count = columns * rows;
+
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        threads => threads,
+    }.min(tile_count).max(1);
+    let subsamp = if image.format == PixelFormat::GRAY { Subsamp::Gray } else { options.subsamp };
+
+    let next_tile = AtomicUsize::new(0);
+    let (tile_tx, tile_rx) = mpsc::sync_channel::<Result<PyramidTile>>(2 * threads);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let tile_tx = tile_tx.clone();
+            let next_tile = &next_tile;
+            scope.spawn(move || {
+                let mut compressor = match Compressor::new() {
+                    Ok(compressor) => compressor,
+                    Err(err) => { let _ = tile_tx.send(Err(err)); return },
+                };
+                if let Err(err) = compressor.set_quality(options.quality) {
+                    let _ = tile_tx.send(Err(err));
+                    return
+                }
+                compressor.set_subsamp(subsamp);
+                let mut output = OutputBuf::new_owned();
+
+                loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if index >= tile_count {
+                        break
+                    }
+                    let (column, row) = (index % columns, index / columns);
+                    let x0 = (column * tile_size).saturating_sub(options.overlap);
+                    let y0 = (row * tile_size).saturating_sub(options.overlap);
+                    let x1 = ((column + 1) * tile_size + options.overlap).min(image.width);
+                    let y1 = ((row + 1) * tile_size + options.overlap).min(image.height);
+
+                    let tile = image.subimage(x0, y0, x1 - x0, y1 - y0);
+                    let res = compressor.compress(tile, &mut output)
+                        .map(|()| PyramidTile { level, column, row, data: output.to_vec() });
+                    let failed = res.is_err();
+                    if tile_tx.send(res).is_err() || failed {
+                        break
+                    }
+                }
+            });
+        }
+        drop(tile_tx);
+
+        // if this loop returns early, `tile_rx` is dropped and the threads stop
+        for tile in tile_rx {
+            use_tile(tile?)?;
+        }
+        Ok(())
+    })
+}
+
+fn downsample(image: Image<&[u8]>) -> Image<Vec<u8>> {
+    let pixel_size = image.format.size();
+    let width = image.width.div_ceil(2);
+    let height = image.height.div_ceil(2);
+    let pitch = width * pixel_size;
+    let mut pixels = vec![0; pitch * height];
+
+    for y in 0..height {
+        let src_ys = [2*y, (2*y + 1).min(image.height - 1)];
+        for x in 0..width {
+            let src_xs = [2*x, (2*x + 1).min(image.width - 1)];
+            for c in 0..pixel_size {
+                let mut sum = 0;
+                for &src_y in src_ys.iter() {
+                    for &src_x in src_xs.iter() {
+                        sum += image.pixels[src_y*image.pitch + src_x*pixel_size + c] as u32;
+                    }
+                }
+                pixels[y*pitch + x*pixel_size + c] = ((sum + 2) / 4) as u8;
+            }
+        }
+    }
+
+    Image { pixels, width, pitch, height, format: image.format }
+}
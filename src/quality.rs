@@ -0,0 +1,129 @@
+use std::fmt;
+use crate::common::{Error, Result};
+
+/// Quality of JPEG compression, from 1 (worst) to 100 (best).
+///
+/// A `Quality` is always in the valid range: [`Quality::new()`] returns
+/// [`Error::InvalidQuality`] for other values, and [`Quality::new_const()`] fails to compile when
+/// it is used in a constant with an invalid value. The methods that compress images, such as
+/// [`Compressor::set_quality()`][crate::Compressor::set_quality] and
+/// [`compress()`][crate::compress], accept either a `Quality` or a plain `i32`; integers are
+/// converted with [`TryFrom`], which returns [`Error::InvalidQuality`] if they are out of range.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::Quality;
+///
+/// const HIGH: Quality = Quality::new_const(90);
+/// assert_eq!(HIGH.get(), 90);
+/// assert_eq!(Quality::default_web().get(), 80);
+/// assert!(Quality::new(0).is_err());
+/// assert!(Quality::new(101).is_err());
+///
+/// // plain integers are validated
+/// assert_eq!(Quality::try_from(75)?.get(), 75);
+/// assert!(Quality::try_from(150).is_err());
+///
+/// let image = turbojpeg::Image::mandelbrot(64, 48, turbojpeg::PixelFormat::RGB);
+/// let mut compressor = turbojpeg::Compressor::new()?;
+/// compressor.set_quality(HIGH)?;
+/// let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+/// compressor.set_quality(75)?;
+/// assert_eq!(compressor.quality(), Quality::new(75)?);
+/// assert!(compressor.set_quality(150).is_err());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quality(u8);
+
+impl Quality {
+    /// The worst quality (1), which produces the smallest images.
+    pub const WORST: Quality = Quality(1);
+    /// The best quality (100), which produces the largest images.
+    pub const BEST: Quality = Quality(100);
+
+    /// Creates a quality from a value between 1 and 100.
+    ///
+    /// Returns [`Error::InvalidQuality`] if the value is out of range.
+    pub fn new(quality: i32) -> Result<Quality> {
+        if (1..=100).contains(&quality) {
+            Ok(Quality(quality as u8))
+        } else {
+            Err(Error::InvalidQuality(quality))
+        }
+    }
+
+    /// Creates a quality from a value between 1 and 100 in a constant expression.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is out of range. When this is used to initialize a constant, the panic
+    /// is reported as a compile-time error.
+    pub const fn new_const(quality: u8) -> Quality {
+        assert!(quality >= 1 && quality <= 100, "JPEG quality must be between 1 and 100");
+        Quality(quality)
+    }
+
+    /// The quality of photos served on the web (80), which is also used by
+    /// [`CompressorPreset::WEB`][crate::CompressorPreset::WEB].
+    pub const fn default_web() -> Quality {
+        Quality(80)
+    }
+
+    /// Returns the quality as an integer between 1 and 100.
+    pub const fn get(self) -> i32 {
+        self.0 as i32
+    }
+}
+
+impl Default for Quality {
+    /// Returns the default quality of [`Compressor`][crate::Compressor] (95).
+    fn default() -> Quality {
+        Quality(95)
+    }
+}
+
+impl TryFrom<i32> for Quality {
+    type Error = Error;
+
+    /// Same as [`Quality::new()`].
+    fn try_from(quality: i32) -> Result<Quality> {
+        Quality::new(quality)
+    }
+}
+
+impl From<Quality> for i32 {
+    fn from(quality: Quality) -> i32 {
+        quality.get()
+    }
+}
+
+impl fmt::Debug for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Quality").field(&self.0).finish()
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// the quality is serialized as an integer, and invalid values are rejected when it is deserialized
+#[cfg(feature = "serde")]
+impl serde::Serialize for Quality {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.get())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Quality {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Quality, D::Error> {
+        let quality = <i32 as serde::Deserialize>::deserialize(deserializer)?;
+        Quality::new(quality).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,168 @@
+use crate::Image;
+use crate::common::{Error, Result};
+
+/// Filter used to resample images with [`resize_image()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResizeFilter {
+    /// Box filter, which averages the source pixels covered by each destination pixel.
+    ///
+    /// This is the fastest filter, and it is equivalent to area averaging when downscaling.
+    Box,
+    /// Bilinear (triangle) filter, which produces smooth results with little ringing.
+    Bilinear,
+    /// Lanczos filter with 3 lobes, which produces the sharpest results (the default).
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    /// Returns the radius of the filter in source pixels (for scale 1).
+    fn support(self) -> f64 {
+        match self {
+            ResizeFilter::Box => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ResizeFilter::Box => if x < 0.5 { 1.0 } else { 0.0 },
+            ResizeFilter::Bilinear => (1.0 - x).max(0.0),
+            ResizeFilter::Lanczos3 => if x < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 },
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * std::f64::consts::PI;
+        x.sin() / x
+    }
+}
+
+/// Fixed-point precision of the filter weights.
+const WEIGHT_BITS: u32 = 14;
+
+/// Weights of the source pixels that contribute to each destination pixel along one axis.
+struct Weights {
+    /// The first source pixel and the offset of its weight in `weights`, for every destination
+    /// pixel.
+    spans: Vec<(usize, usize, usize)>,
+    weights: Vec<i32>,
+}
+
+impl Weights {
+    fn new(src_len: usize, dst_len: usize, filter: ResizeFilter) -> Weights {
+        let scale = src_len as f64 / dst_len as f64;
+        let filter_scale = scale.max(1.0);
+        let support = filter.support() * filter_scale;
+
+        let mut spans = Vec::with_capacity(dst_len);
+        let mut weights = Vec::new();
+        let mut float_weights = Vec::new();
+        for i in 0..dst_len {
+            let center = (i as f64 + 0.5) * scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(src_len).max(start + 1);
+
+            float_weights.clear();
+            float_weights.extend((start..end).map(|j| filter.weight((j as f64 + 0.5 - center) / filter_scale)));
+            let sum: f64 = float_weights.iter().sum();
+            let offset = weights.len();
+            if sum == 0.0 {
+                // the filter is too narrow to cover any source pixel, use the nearest one
+                let nearest = (center as usize).min(src_len - 1);
+                spans.push((nearest, 1, offset));
+                weights.push(1 << WEIGHT_BITS);
+            } else {
+                spans.push((start, end - start, offset));
+                weights.extend(float_weights.iter().map(|w| (w / sum * (1 << WEIGHT_BITS) as f64).round() as i32));
+            }
+        }
+        Weights { spans, weights }
+    }
+}
+
+fn clamp_pixel(value: i32) -> u8 {
+    ((value + (1 << (WEIGHT_BITS - 1))) >> WEIGHT_BITS).clamp(0, 255) as u8
+}
+
+/// Resizes `src` into `dst`, which determines the size of the output.
+///
+/// Both images must have the same pixel format; any format is supported, and the images may have
+/// any pitch. The image is resampled with a separable filter (first horizontally, then
+/// vertically) in fixed-point arithmetic, so that the inner loops are easy to vectorize. Note that
+/// all components are resampled independently, so images with an alpha channel should have
+/// premultiplied alpha.
+///
+/// Returns [`Error::PixelFormatMismatch`] if the pixel formats differ.
+///
+/// # Panics
+///
+/// Panics if the images are empty or if their pixels are too short.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Image, PixelFormat, ResizeFilter};
+///
+/// let image = turbojpeg::decompress(&std::fs::read("examples/parrots.jpg")?, PixelFormat::BGRA)?;
+/// let mut thumbnail = Image {
+///     pixels: vec![0; 100 * 4 * 67],
+///     width: 100,
+///     pitch: 100 * 4,
+///     height: 67,
+///     format: PixelFormat::BGRA,
+/// };
+/// turbojpeg::resize_image(image.as_deref(), thumbnail.as_deref_mut(), ResizeFilter::Lanczos3)?;
+///
+/// // the average color is preserved
+/// let mean = |pixels: &[u8]| pixels.iter().map(|&v| v as f64).sum::<f64>() / pixels.len() as f64;
+/// assert!((mean(&image.pixels) - mean(&thumbnail.pixels)).abs() < 1.0);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn resize_image(src: Image<&[u8]>, dst: Image<&mut [u8]>, filter: ResizeFilter) -> Result<()> {
+    if src.format != dst.format {
+        return Err(Error::PixelFormatMismatch(dst.format, src.format))
+    }
+    assert!(src.width > 0 && src.height > 0 && dst.width > 0 && dst.height > 0, "images must not be empty");
+    src.assert_valid(src.pixels.len());
+    dst.assert_valid(dst.pixels.len());
+    let pixel_size = src.format.size();
+
+    // horizontal pass into a temporary image with the source height and the destination width
+    let x_weights = Weights::new(src.width, dst.width, filter);
+    let tmp_pitch = dst.width * pixel_size;
+    let mut tmp = vec![0u8; tmp_pitch * src.height];
+    let mut sums = vec![0i32; pixel_size];
+    for (src_row, tmp_row) in src.pixels.chunks(src.pitch).zip(tmp.chunks_exact_mut(tmp_pitch)) {
+        for (&(start, len, offset), tmp_pixel) in x_weights.spans.iter().zip(tmp_row.chunks_exact_mut(pixel_size)) {
+            sums.iter_mut().for_each(|sum| *sum = 0);
+            let weights = &x_weights.weights[offset..][..len];
+            let pixels = src_row[start * pixel_size..][..len * pixel_size].chunks_exact(pixel_size);
+            for (&weight, pixel) in weights.iter().zip(pixels) {
+                sums.iter_mut().zip(pixel).for_each(|(sum, &value)| *sum += weight * value as i32);
+            }
+            tmp_pixel.iter_mut().zip(&sums).for_each(|(value, &sum)| *value = clamp_pixel(sum));
+        }
+    }
+
+    // vertical pass, which processes whole rows at once
+    let y_weights = Weights::new(src.height, dst.height, filter);
+    let mut row_sums = vec![0i32; tmp_pitch];
+    for (&(start, len, offset), dst_row) in y_weights.spans.iter().zip(dst.pixels.chunks_mut(dst.pitch)) {
+        row_sums.iter_mut().for_each(|sum| *sum = 0);
+        let weights = &y_weights.weights[offset..][..len];
+        for (&weight, tmp_row) in weights.iter().zip(tmp[start * tmp_pitch..].chunks_exact(tmp_pitch)) {
+            row_sums.iter_mut().zip(tmp_row).for_each(|(sum, &value)| *sum += weight * value as i32);
+        }
+        dst_row[..tmp_pitch].iter_mut().zip(&row_sums).for_each(|(value, &sum)| *value = clamp_pixel(sum));
+    }
+    Ok(())
+}
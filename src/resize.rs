@@ -0,0 +1,190 @@
+use crate::Image;
+use crate::common::{Colorspace, PixelFormat, Error, Result};
+use crate::compress::Compressor;
+use crate::decompress::Decompressor;
+use crate::metadata::copy_metadata;
+use crate::quality::Quality;
+use crate::resample::{ResizeFilter, resize_image};
+use crate::scale::ScalingFactor;
+use crate::transform::MarkerCopy;
+
+/// Resizes JPEG images into smaller JPEG images.
+///
+/// This combines the steps that thumbnailing services usually perform: the image is decompressed
+/// with the smallest [`ScalingFactor`] that still produces an image at least as large as the
+/// target (DCT scaling is much faster than decompressing the full image), then it is resampled to
+/// the exact target size (this can be disabled with [`set_exact()`][Self::set_exact]), and finally
+/// it is compressed again, with the same chrominance subsampling as the original image, and the
+/// metadata (such as EXIF data and the ICC profile) of the original image are copied into it.
+///
+/// The images are resized to fit into the target box while preserving their aspect ratio, and
+/// they are never enlarged. The resizer keeps its TurboJPEG instances and pixel buffers, so reuse
+/// it for multiple images; use [`resize_jpeg()`] to resize a single image.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// let mut resizer = turbojpeg::JpegResizer::new()?;
+/// let thumbnail = resizer.resize(&jpeg_data, (100, 100), 85)?;
+/// let header = turbojpeg::read_header(&thumbnail)?;
+/// assert_eq!((header.width, header.height), (100, 67));
+/// assert_eq!(header.subsamp, turbojpeg::Subsamp::None);
+///
+/// // without the exact resampling, the image is only scaled by 3/8 during decompression
+/// resizer.set_exact(false);
+/// let thumbnail = resizer.resize(&jpeg_data, (100, 100), 85)?;
+/// let header = turbojpeg::read_header(&thumbnail)?;
+/// assert_eq!((header.width, header.height), (144, 96));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct JpegResizer {
+    decompressor: Decompressor,
+    compressor: Compressor,
+    exact: bool,
+    filter: ResizeFilter,
+    markers: MarkerCopy,
+    scaled: Vec<u8>,
+    resized: Vec<u8>,
+}
+
+impl JpegResizer {
+    /// Creates a resizer that resizes the images exactly and copies all metadata.
+    pub fn new() -> Result<JpegResizer> {
+        Ok(JpegResizer {
+            decompressor: Decompressor::new()?,
+            compressor: Compressor::new()?,
+            exact: true,
+            filter: ResizeFilter::default(),
+            markers: MarkerCopy::All,
+            scaled: Vec::new(),
+            resized: Vec::new(),
+        })
+    }
+
+    /// Enable or disable the resampling to the exact target size (enabled by default).
+    ///
+    /// When disabled, the image is only scaled during decompression, so the output is faster to
+    /// produce, but it may be up to twice as large as the target.
+    pub fn set_exact(&mut self, exact: bool) {
+        self.exact = exact;
+    }
+
+    /// Set the filter used to resample the image to the exact target size ([`ResizeFilter::Lanczos3`]
+    /// by default).
+    pub fn set_filter(&mut self, filter: ResizeFilter) {
+        self.filter = filter;
+    }
+
+    /// Set which metadata segments are copied from the original image (all by default, see
+    /// [`MarkerCopy`]).
+    pub fn set_markers(&mut self, markers: MarkerCopy) {
+        self.markers = markers;
+    }
+
+    /// Get mutable access to the [`Decompressor`], so that you can set its limits or other
+    /// settings.
+    pub fn decompressor_mut(&mut self) -> &mut Decompressor {
+        &mut self.decompressor
+    }
+
+    /// Get mutable access to the [`Compressor`], so that you can enable progressive coding or
+    /// change other settings. Note that the quality and subsampling are set for every image.
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    /// Resizes the JPEG image to fit into `target` (width and height) and compresses it with the
+    /// given `quality`.
+    pub fn resize(&mut self, jpeg_data: &[u8], target: (usize, usize), quality: impl TryInto<Quality, Error: Into<Error>>) -> Result<Vec<u8>> {
+        let quality: Quality = quality.try_into().map_err(Into::into)?;
+        let header = self.decompressor.read_header(jpeg_data)?;
+        let (width, height) = fit_size((header.width, header.height), target);
+        let factor = ScalingFactor::closest_for(&header, width, height);
+
+        let format = match header.colorspace {
+            Colorspace::Gray => PixelFormat::GRAY,
+            Colorspace::CMYK | Colorspace::YCCK => PixelFormat::CMYK,
+            Colorspace::RGB | Colorspace::YCbCr => PixelFormat::RGB,
+        };
+        let (scaled_width, scaled_height) = factor.scaled_size(&header);
+        self.scaled.resize(scaled_width * scaled_height * format.size(), 0);
+        let scaled = Image {
+            pixels: &mut self.scaled[..],
+            width: scaled_width,
+            pitch: scaled_width * format.size(),
+            height: scaled_height,
+            format,
+        };
+        self.decompressor.decompress(jpeg_data, scaled)?;
+        let scaled = Image {
+            pixels: &self.scaled[..],
+            width: scaled_width,
+            pitch: scaled_width * format.size(),
+            height: scaled_height,
+            format,
+        };
+
+        self.compressor.set_quality(quality)?;
+        self.compressor.set_subsamp(header.subsamp);
+        let resized_data = if self.exact && (scaled_width, scaled_height) != (width, height) {
+            self.resized.resize(width * height * format.size(), 0);
+            let resized = Image { pixels: &mut self.resized[..], width, pitch: width * format.size(), height, format };
+            resize_image(scaled, resized, self.filter)?;
+            let resized = Image { pixels: &self.resized[..], width, pitch: width * format.size(), height, format };
+            self.compressor.compress_to_vec(resized)?
+        } else {
+            self.compressor.compress_to_vec(scaled)?
+        };
+
+        match self.markers {
+            MarkerCopy::None => Ok(resized_data),
+            ref markers => copy_metadata(jpeg_data, &resized_data, |kind| match markers {
+                MarkerCopy::Selected(kinds) => kinds.contains(&kind),
+                _ => true,
+            }),
+        }
+    }
+}
+
+/// Resizes a JPEG image to fit into `target` (width and height) and compresses it with the given
+/// `quality`.
+///
+/// See [`JpegResizer`] for details; use it instead of this function to resize multiple images.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let thumbnail = turbojpeg::resize_jpeg(&jpeg_data, (200, 200), 80)?;
+/// let header = turbojpeg::read_header(&thumbnail)?;
+/// assert_eq!((header.width, header.height), (200, 133));
+///
+/// // the metadata are copied
+/// let stripped = turbojpeg::strip_metadata(&thumbnail, &turbojpeg::StripMetadata::all())?;
+/// assert!(stripped.len() < thumbnail.len());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn resize_jpeg(jpeg_data: &[u8], target: (usize, usize), quality: impl TryInto<Quality, Error: Into<Error>>) -> Result<Vec<u8>> {
+    JpegResizer::new()?.resize(jpeg_data, target, quality)
+}
+
+/// Returns the largest size with the aspect ratio of `size` that fits into `target`, but not larger
+/// than `size`.
+fn fit_size(size: (usize, usize), target: (usize, usize)) -> (usize, usize) {
+    let (width, height) = size;
+    let (max_width, max_height) = (target.0.max(1), target.1.max(1));
+    if width <= max_width && height <= max_height {
+        return size
+    }
+    // compare max_width / width with max_height / height without rounding
+    if max_width * height <= max_height * width {
+        (max_width, ((height * max_width + width / 2) / width).clamp(1, max_height))
+    } else {
+        (((width * max_height + height / 2) / height).clamp(1, max_width), max_height)
+    }
+}
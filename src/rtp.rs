@@ -0,0 +1,642 @@
+use std::collections::HashMap;
+use std::time::Instant;
+use crate::common::{Error, Result};
+use crate::marker::{self, DHT, DQT, DRI, EOI, SOF0, SOI, SOS};
+use crate::pacing::TimedFrame;
+use crate::tables::{STD_CHROMINANCE_QUANT_TABLE, STD_LUMINANCE_QUANT_TABLE, ZIGZAG, quant_table};
+
+/// Size of the main RTP/JPEG header.
+const MAIN_HEADER_LEN: usize = 8;
+
+/// Size of the restart marker header, which follows the main header in frames with restart
+/// markers.
+const RESTART_HEADER_LEN: usize = 4;
+
+/// Size of the quantization table header (without the tables).
+const QUANT_HEADER_LEN: usize = 4;
+
+/// Size of the two 8-bit quantization tables of a frame.
+const QUANT_TABLES_LEN: usize = 128;
+
+/// Maximal width and height of RTP/JPEG frames, which are sent in units of 8 pixels.
+const MAX_FRAME_DIMENSION: usize = 2040;
+
+/// Maximal fragment offset (a 24-bit field).
+const MAX_FRAGMENT_OFFSET: usize = (1 << 24) - 1;
+
+/// Offset of the types of frames with restart markers.
+const RESTART_TYPE: u8 = 64;
+
+/// Q value of frames whose quantization tables are sent in every frame and must not be cached.
+const Q_DYNAMIC: u8 = 255;
+
+/// Clock rate of the timestamps of RTP/JPEG streams in Hz.
+const CLOCK_RATE: u128 = 90_000;
+
+/// Payload of an RTP packet with a fragment of a JPEG image, produced by [`RtpJpegPacketizer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpJpegPacket {
+    /// The RTP payload: the RTP/JPEG headers followed by a fragment of the entropy-coded data.
+    pub payload: Vec<u8>,
+    /// Whether the marker bit of the RTP header must be set. This is true for the last packet of
+    /// a frame.
+    pub marker: bool,
+}
+
+/// Fragments JPEG images into RTP payloads in the RTP/JPEG format (RFC 2435).
+///
+/// RTP/JPEG does not send the JPEG headers: every packet starts with a small header that
+/// describes the frame (its type, size and quantization), and the receiver reconstructs the
+/// JPEG headers from it. The format is therefore limited to the kind of images that IP cameras
+/// produce, and [`packetize()`][Self::packetize] returns [`Error::Unsupported`] for others:
+///
+/// - the images must be baseline JPEG images with a single scan,
+/// - they must be YCbCr images with 4:2:2 ([`Subsamp::Sub2x1`][crate::Subsamp::Sub2x1]) or 4:2:0
+///   ([`Subsamp::Sub2x2`][crate::Subsamp::Sub2x2]) subsampling,
+/// - their width and height must be multiples of 8 and at most 2040,
+/// - they must use 8-bit quantization tables and the standard Huffman tables (so they must not
+///   be compressed with optimized Huffman tables or with
+///   [progressive][crate::Compressor::set_progressive] coding).
+///
+/// The quantization tables are sent in the first packet of every frame (with Q = 255), so the
+/// frames may use arbitrary tables. Restart markers (such as those of `cjpeg -restart`) are
+/// supported.
+///
+/// The packetizer only produces the payloads; the RTP header (with the sequence number, the
+/// timestamp and the marker bit) must be added by your RTP stack. The timestamp of a
+/// [`TimedFrame`] is converted to the RTP clock with [`TimedFrame::rtp_timestamp()`], and a
+/// [`FramePacer`][crate::FramePacer] can limit the frame rate of the stream.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{PixelFormat, RtpJpegDepacketizer, RtpJpegPacketizer, Subsamp};
+///
+/// let image = turbojpeg::Image::mandelbrot(320, 240, PixelFormat::RGB);
+/// let jpeg_data = turbojpeg::compress(image.as_deref(), 80, Subsamp::Sub2x2)?;
+///
+/// // fragment the image into payloads of at most 1400 bytes
+/// let packetizer = RtpJpegPacketizer::new(1400);
+/// let packets = packetizer.packetize(&jpeg_data)?;
+/// assert!(packets.len() > 1);
+/// assert!(packets.iter().all(|packet| packet.payload.len() <= 1400));
+/// assert!(packets.last().unwrap().marker);
+///
+/// // reassemble the image on the receiver
+/// let mut depacketizer = RtpJpegDepacketizer::new();
+/// let mut received = None;
+/// for packet in &packets {
+///     received = depacketizer.push(&packet.payload, packet.marker)?;
+/// }
+///
+/// // the reconstructed JPEG headers differ, but the image is the same
+/// let received = received.unwrap();
+/// let original = turbojpeg::decompress(&jpeg_data, PixelFormat::RGB)?;
+/// assert_eq!(turbojpeg::decompress(&received, PixelFormat::RGB)?.pixels, original.pixels);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct RtpJpegPacketizer {
+    max_payload_len: usize,
+}
+
+impl RtpJpegPacketizer {
+    /// Creates a packetizer that produces payloads of at most `max_payload_len` bytes.
+    ///
+    /// The limit must leave room for the RTP header and the headers of the lower layers within
+    /// the MTU of the network; 1400 bytes is a common choice for Ethernet.
+    pub fn new(max_payload_len: usize) -> RtpJpegPacketizer {
+        RtpJpegPacketizer { max_payload_len }
+    }
+
+    /// Returns the maximal length of the payloads.
+    pub fn max_payload_len(&self) -> usize {
+        self.max_payload_len
+    }
+
+    /// Fragments the JPEG image in `jpeg_data` into RTP payloads.
+    ///
+    /// Returns [`Error::Unsupported`] if the image cannot be sent in the RTP/JPEG format (see
+    /// [`RtpJpegPacketizer`]), and [`Error::InvalidArgument`] if the maximal payload length is too
+    /// small for the headers of the first packet.
+    pub fn packetize(&self, jpeg_data: &[u8]) -> Result<Vec<RtpJpegPacket>> {
+        let frame = parse_frame(jpeg_data)?;
+        let restart_len = if frame.restart_interval.is_some() { RESTART_HEADER_LEN } else { 0 };
+        let first_header_len = MAIN_HEADER_LEN + restart_len + QUANT_HEADER_LEN + QUANT_TABLES_LEN;
+        if self.max_payload_len <= first_header_len {
+            return Err(Error::InvalidArgument(format!(
+                "maximal payload length {} is too small for the RTP/JPEG headers of {} bytes",
+                self.max_payload_len, first_header_len,
+            )))
+        }
+        if frame.scan.len() > MAX_FRAGMENT_OFFSET {
+            return Err(Error::Unsupported("RTP/JPEG frames are limited to 16 MiB of entropy-coded data"))
+        }
+
+        let kind = frame.kind + if frame.restart_interval.is_some() { RESTART_TYPE } else { 0 };
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        loop {
+            let mut payload = Vec::with_capacity(self.max_payload_len);
+            payload.push(0);
+            payload.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            payload.extend_from_slice(&[kind, Q_DYNAMIC, (frame.width / 8) as u8, (frame.height / 8) as u8]);
+            if let Some(interval) = frame.restart_interval {
+                // F = 1, L = 1 and the restart count 0x3fff: the packets are not aligned with the
+                // restart intervals, so the receiver must reassemble the whole frame
+                payload.extend_from_slice(&interval.to_be_bytes());
+                payload.extend_from_slice(&[0xff, 0xff]);
+            }
+            if offset == 0 {
+                payload.extend_from_slice(&[0, 0]);
+                payload.extend_from_slice(&(QUANT_TABLES_LEN as u16).to_be_bytes());
+                payload.extend_from_slice(&frame.tables[0]);
+                payload.extend_from_slice(&frame.tables[1]);
+            }
+
+            let len = (self.max_payload_len - payload.len()).min(frame.scan.len() - offset);
+            payload.extend_from_slice(&frame.scan[offset..offset + len]);
+            offset += len;
+            let marker = offset == frame.scan.len();
+            packets.push(RtpJpegPacket { payload, marker });
+            if marker {
+                return Ok(packets)
+            }
+        }
+    }
+}
+
+impl<T> TimedFrame<T> {
+    /// Returns the RTP timestamp of the frame: the time since `epoch` (the start of the stream)
+    /// in the 90 kHz clock of RTP/JPEG, wrapped to 32 bits.
+    ///
+    /// RTP streams should start at a random timestamp, so add a random offset (with wrapping) to
+    /// the result.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    /// use turbojpeg::TimedFrame;
+    ///
+    /// let epoch = Instant::now();
+    /// let frame = TimedFrame { data: (), timestamp: epoch + Duration::from_millis(40) };
+    /// assert_eq!(frame.rtp_timestamp(epoch), 3600);
+    /// ```
+    pub fn rtp_timestamp(&self, epoch: Instant) -> u32 {
+        (self.elapsed_since(epoch).as_nanos() * CLOCK_RATE / 1_000_000_000) as u32
+    }
+}
+
+/// Reassembles JPEG images from RTP payloads in the RTP/JPEG format (RFC 2435).
+///
+/// Pass the payloads of the received RTP packets to [`push()`][Self::push] in the order of their
+/// sequence numbers, together with the marker bit of their RTP header. The depacketizer collects
+/// the fragments of a frame and returns the JPEG image, with headers reconstructed from the
+/// RTP/JPEG headers, when the last packet of the frame arrives.
+///
+/// Frames with lost packets cannot be decoded, so they are dropped and counted in
+/// [`dropped_frames()`][Self::dropped_frames]. The quantization tables are computed from the Q
+/// factor of the frame (for Q from 1 to 99), taken from the first packet of the frame, or taken
+/// from an earlier frame with the same Q (for Q from 128 to 254, whose tables may be sent only
+/// once). Types 0 and 1 (4:2:2 and 4:2:0 subsampling) with and without restart markers are
+/// supported.
+///
+/// See [`RtpJpegPacketizer`] for an example.
+#[derive(Debug, Default)]
+pub struct RtpJpegDepacketizer {
+    frame: Option<PartialFrame>,
+    skipping: bool,
+    dropped: u64,
+    tables: HashMap<u8, [u8; QUANT_TABLES_LEN]>,
+}
+
+/// A frame whose fragments are being collected by [`RtpJpegDepacketizer`].
+#[derive(Debug)]
+struct PartialFrame {
+    /// The type, Q, width and height fields of the main header, which must be the same in all
+    /// packets of the frame.
+    params: [u8; 4],
+    restart_interval: Option<u16>,
+    tables: [u8; QUANT_TABLES_LEN],
+    scan: Vec<u8>,
+}
+
+impl RtpJpegDepacketizer {
+    /// Creates a depacketizer.
+    pub fn new() -> RtpJpegDepacketizer {
+        RtpJpegDepacketizer::default()
+    }
+
+    /// Adds the payload of a received RTP packet, and `marker`, the marker bit of its RTP header.
+    ///
+    /// Returns the JPEG image when the packet completes a frame. Returns an error if the payload
+    /// is malformed or uses features of RTP/JPEG that are not supported; the current frame is
+    /// dropped in that case.
+    pub fn push(&mut self, payload: &[u8], marker: bool) -> Result<Option<Vec<u8>>> {
+        let res = self.push_packet(payload, marker);
+        if res.is_err() {
+            self.drop_frame();
+        }
+        res
+    }
+
+    fn push_packet(&mut self, payload: &[u8], marker: bool) -> Result<Option<Vec<u8>>> {
+        if payload.len() < MAIN_HEADER_LEN {
+            return Err(Error::MalformedJpeg("truncated RTP/JPEG header"))
+        }
+        let offset = u32::from_be_bytes([0, payload[1], payload[2], payload[3]]) as usize;
+        let params = [payload[4], payload[5], payload[6], payload[7]];
+        let [kind, q, width, height] = params;
+        let mut data = &payload[MAIN_HEADER_LEN..];
+        if kind & !RESTART_TYPE > 1 {
+            return Err(Error::Unsupported("RTP/JPEG frames must have type 0 or 1, with or without restart markers"))
+        } else if width == 0 || height == 0 {
+            return Err(Error::MalformedJpeg("RTP/JPEG frame has zero width or height"))
+        }
+
+        let mut restart_interval = None;
+        if kind & RESTART_TYPE != 0 {
+            if data.len() < RESTART_HEADER_LEN {
+                return Err(Error::MalformedJpeg("truncated RTP/JPEG restart marker header"))
+            }
+            restart_interval = Some(u16::from_be_bytes([data[0], data[1]]));
+            data = &data[RESTART_HEADER_LEN..];
+        }
+
+        if offset == 0 {
+            if self.frame.is_some() {
+                // the last packet of the previous frame was lost
+                self.drop_frame();
+            }
+            self.skipping = false;
+            let tables = self.read_tables(q, &mut data)?;
+            self.frame = Some(PartialFrame { params, restart_interval, tables, scan: Vec::new() });
+        } else {
+            let in_sequence = match &self.frame {
+                Some(frame) => frame.params == params && frame.scan.len() == offset,
+                None => false,
+            };
+            if !in_sequence {
+                // a packet of the frame was lost, or the first packet of the frame was lost
+                self.drop_frame();
+            }
+        }
+
+        let frame = match &mut self.frame {
+            Some(frame) => frame,
+            None => {
+                if marker {
+                    self.skipping = false;
+                }
+                return Ok(None)
+            },
+        };
+        frame.scan.extend_from_slice(data);
+        if !marker {
+            return Ok(None)
+        }
+        let frame = self.frame.take().unwrap();
+        Ok(Some(frame.to_jpeg()))
+    }
+
+    /// Reads the quantization tables of a frame with the given `q` from the first packet (or
+    /// computes them, or takes them from the cache).
+    fn read_tables(&mut self, q: u8, data: &mut &[u8]) -> Result<[u8; QUANT_TABLES_LEN]> {
+        match q {
+            1..=99 => return Ok(default_tables(q)),
+            0 | 100..=127 => return Err(Error::MalformedJpeg("RTP/JPEG frame has a reserved Q value")),
+            _ => {},
+        }
+
+        if data.len() < QUANT_HEADER_LEN {
+            return Err(Error::MalformedJpeg("truncated RTP/JPEG quantization table header"))
+        }
+        let precision = data[1];
+        let len = u16::from_be_bytes([data[2], data[3]]) as usize;
+        *data = &data[QUANT_HEADER_LEN..];
+        if len == 0 {
+            return match self.tables.get(&q) {
+                Some(tables) if q != Q_DYNAMIC => Ok(*tables),
+                _ => Err(Error::Unsupported("RTP/JPEG frame refers to quantization tables that were not received")),
+            }
+        } else if precision != 0 {
+            return Err(Error::Unsupported("RTP/JPEG frames with 16-bit quantization tables are not supported"))
+        } else if len != QUANT_TABLES_LEN {
+            return Err(Error::MalformedJpeg("RTP/JPEG frame must have two 8-bit quantization tables"))
+        } else if data.len() < len {
+            return Err(Error::MalformedJpeg("truncated RTP/JPEG quantization tables"))
+        }
+
+        let mut tables = [0; QUANT_TABLES_LEN];
+        tables.copy_from_slice(&data[..len]);
+        *data = &data[len..];
+        if q != Q_DYNAMIC {
+            self.tables.insert(q, tables);
+        }
+        Ok(tables)
+    }
+
+    /// Drops the current frame (and the following packets up to the start of the next frame).
+    fn drop_frame(&mut self) {
+        self.frame = None;
+        if !self.skipping {
+            self.dropped += 1;
+            self.skipping = true;
+        }
+    }
+
+    /// Returns the number of frames that were dropped, because some of their packets were lost or
+    /// malformed.
+    ///
+    /// Frames whose packets were all lost cannot be detected from the payloads, so they are not
+    /// counted (use the sequence numbers of the RTP headers to detect them).
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Drops the current frame and forgets the cached quantization tables.
+    pub fn reset(&mut self) {
+        self.frame = None;
+        self.skipping = false;
+        self.tables.clear();
+    }
+}
+
+impl PartialFrame {
+    /// Reconstructs the JPEG image from the fragments and the RTP/JPEG headers (RFC 2435,
+    /// appendix B).
+    fn to_jpeg(&self) -> Vec<u8> {
+        let [kind, _, width, height] = self.params;
+        let (width, height) = (width as u16 * 8, height as u16 * 8);
+        let mut jpeg = Vec::with_capacity(self.scan.len() + 1024);
+        jpeg.extend_from_slice(&[0xff, SOI]);
+
+        jpeg.extend_from_slice(&[0xff, DQT, 0, 2 + 2 * 65]);
+        for (id, table) in self.tables.chunks_exact(64).enumerate() {
+            jpeg.push(id as u8);
+            jpeg.extend_from_slice(table);
+        }
+
+        let luma_sampling = if kind & !RESTART_TYPE == 0 { 0x21 } else { 0x22 };
+        jpeg.extend_from_slice(&[0xff, SOF0, 0, 17, 8]);
+        jpeg.extend_from_slice(&height.to_be_bytes());
+        jpeg.extend_from_slice(&width.to_be_bytes());
+        jpeg.extend_from_slice(&[3, 0, luma_sampling, 0, 1, 0x11, 1, 2, 0x11, 1]);
+
+        let dht_len = 2 + STD_HUFFMAN_TABLES.iter().map(|table| 17 + table.values.len()).sum::<usize>();
+        jpeg.extend_from_slice(&[0xff, DHT]);
+        jpeg.extend_from_slice(&(dht_len as u16).to_be_bytes());
+        for table in &STD_HUFFMAN_TABLES {
+            jpeg.push(table.class_id);
+            jpeg.extend_from_slice(&table.bits);
+            jpeg.extend_from_slice(table.values);
+        }
+
+        if let Some(interval) = self.restart_interval {
+            jpeg.extend_from_slice(&[0xff, DRI, 0, 4]);
+            jpeg.extend_from_slice(&interval.to_be_bytes());
+        }
+
+        jpeg.extend_from_slice(&[0xff, SOS, 0, 12, 3, 0, 0x00, 1, 0x11, 2, 0x11, 0, 63, 0]);
+        jpeg.extend_from_slice(&self.scan);
+        if !self.scan.ends_with(&[0xff, EOI]) {
+            jpeg.extend_from_slice(&[0xff, EOI]);
+        }
+        jpeg
+    }
+}
+
+/// Returns the quantization tables (in zigzag order) of a frame with Q from 1 to 99 (RFC 2435,
+/// appendix A).
+fn default_tables(q: u8) -> [u8; QUANT_TABLES_LEN] {
+    let luma = quant_table(&STD_LUMINANCE_QUANT_TABLE, q as i32);
+    let chroma = quant_table(&STD_CHROMINANCE_QUANT_TABLE, q as i32);
+    let mut tables = [0; QUANT_TABLES_LEN];
+    for (i, &index) in ZIGZAG.iter().enumerate() {
+        tables[i] = luma[index] as u8;
+        tables[64 + i] = chroma[index] as u8;
+    }
+    tables
+}
+
+/// The parts of a JPEG image that are sent in RTP/JPEG packets.
+struct Frame<'a> {
+    /// The RTP/JPEG type (without the restart marker offset).
+    kind: u8,
+    width: usize,
+    height: usize,
+    restart_interval: Option<u16>,
+    /// The luminance and chrominance quantization tables, in zigzag order.
+    tables: [[u8; 64]; 2],
+    /// The entropy-coded data of the scan.
+    scan: &'a [u8],
+}
+
+/// Parses a JPEG image and checks that it can be sent in the RTP/JPEG format.
+fn parse_frame(data: &[u8]) -> Result<Frame<'_>> {
+    let mut tables = [None; 2];
+    let mut sof = None;
+    let mut restart_interval = None;
+    let mut scan_start = None;
+    let mut scan = None;
+    for segment in marker::segments(data) {
+        let segment = segment.map_err(Error::MalformedJpeg)?;
+        match segment.marker {
+            DQT => read_dqt(segment.data, &mut tables)?,
+            DHT => check_dht(segment.data)?,
+            DRI => {
+                if segment.data.len() != 2 {
+                    return Err(Error::MalformedJpeg("invalid DRI segment"))
+                }
+                let interval = u16::from_be_bytes([segment.data[0], segment.data[1]]);
+                restart_interval = if interval != 0 { Some(interval) } else { None };
+            },
+            SOS => {
+                let (_, _, component_ids) = match sof {
+                    Some(sof) => sof,
+                    None => return Err(Error::MalformedJpeg("missing SOF marker before SOS marker")),
+                };
+                if scan_start.is_some() {
+                    return Err(Error::Unsupported("RTP/JPEG supports only JPEG images with a single scan"))
+                }
+                check_sos(segment.data, component_ids)?;
+                scan_start = Some(segment.offset + segment.len);
+            },
+            EOI => scan = scan_start.map(|start| &data[start..segment.offset]),
+            sof_marker if marker::is_sof(sof_marker) => {
+                if sof_marker != SOF0 {
+                    return Err(Error::Unsupported("RTP/JPEG supports only baseline JPEG images"))
+                }
+                sof = Some(read_sof(segment.data)?);
+            },
+            _ => {},
+        }
+    }
+
+    let (kind, (width, height), _) = match sof {
+        Some(sof) => sof,
+        None => return Err(Error::MalformedJpeg("missing SOF marker")),
+    };
+    let scan = match scan {
+        Some(scan) => scan,
+        None => return Err(Error::MalformedJpeg("missing SOS marker")),
+    };
+    let tables = match tables {
+        [Some(luma), Some(chroma)] => [luma, chroma],
+        _ => return Err(Error::MalformedJpeg("missing quantization table")),
+    };
+    Ok(Frame { kind, width, height, restart_interval, tables, scan })
+}
+
+/// Reads the 8-bit quantization tables 0 and 1 from a DQT segment.
+fn read_dqt(mut data: &[u8], tables: &mut [Option<[u8; 64]>; 2]) -> Result<()> {
+    while let Some(&precision_id) = data.first() {
+        if precision_id >> 4 != 0 {
+            return Err(Error::Unsupported("RTP/JPEG supports only 8-bit quantization tables"))
+        } else if data.len() < 65 {
+            return Err(Error::MalformedJpeg("truncated DQT segment"))
+        }
+        if let Some(table) = tables.get_mut((precision_id & 0x0f) as usize) {
+            let mut values = [0; 64];
+            values.copy_from_slice(&data[1..65]);
+            *table = Some(values);
+        }
+        data = &data[65..];
+    }
+    Ok(())
+}
+
+/// Checks that a DHT segment defines only the standard Huffman tables, which RTP/JPEG receivers
+/// assume.
+fn check_dht(mut data: &[u8]) -> Result<()> {
+    while let Some(&class_id) = data.first() {
+        if data.len() < 17 {
+            return Err(Error::MalformedJpeg("truncated DHT segment"))
+        }
+        let count = data[1..17].iter().map(|&count| count as usize).sum::<usize>();
+        if data.len() < 17 + count {
+            return Err(Error::MalformedJpeg("truncated DHT segment"))
+        }
+        let standard = STD_HUFFMAN_TABLES.iter().any(|table| {
+            table.class_id == class_id && table.bits[..] == data[1..17] && table.values == &data[17..17 + count]
+        });
+        if !standard {
+            return Err(Error::Unsupported("RTP/JPEG supports only JPEG images with the standard Huffman tables"))
+        }
+        data = &data[17 + count..];
+    }
+    Ok(())
+}
+
+/// Reads a SOF0 segment and returns the RTP/JPEG type, the size and the component ids of the
+/// frame.
+fn read_sof(data: &[u8]) -> Result<(u8, (usize, usize), [u8; 3])> {
+    if data.len() < 6 || data.len() < 6 + 3 * data[5] as usize {
+        return Err(Error::MalformedJpeg("truncated SOF segment"))
+    }
+    let height = u16::from_be_bytes([data[1], data[2]]) as usize;
+    let width = u16::from_be_bytes([data[3], data[4]]) as usize;
+    let components = &data[6..];
+    if data[5] != 3 || components[2] != 0 || components[4..6] != [0x11, 1] || components[7..9] != [0x11, 1] {
+        return Err(Error::Unsupported("RTP/JPEG supports only YCbCr images with 4:2:2 or 4:2:0 subsampling"))
+    }
+    let kind = match components[1] {
+        0x21 => 0,
+        0x22 => 1,
+        _ => return Err(Error::Unsupported("RTP/JPEG supports only YCbCr images with 4:2:2 or 4:2:0 subsampling")),
+    };
+    if width == 0 || height == 0 || !width.is_multiple_of(8) || !height.is_multiple_of(8) {
+        return Err(Error::Unsupported("RTP/JPEG supports only images whose width and height are multiples of 8"))
+    } else if width > MAX_FRAME_DIMENSION || height > MAX_FRAME_DIMENSION {
+        return Err(Error::Unsupported("RTP/JPEG supports only images with at most 2040x2040 pixels"))
+    }
+    Ok((kind, (width, height), [components[0], components[3], components[6]]))
+}
+
+/// Checks that a SOS segment codes all components with the Huffman tables that RTP/JPEG receivers
+/// assume.
+fn check_sos(data: &[u8], component_ids: [u8; 3]) -> Result<()> {
+    if data.len() != 10 || data[0] != 3 {
+        return Err(Error::Unsupported("RTP/JPEG supports only JPEG images with a single interleaved scan"))
+    }
+    let expected = [component_ids[0], 0x00, component_ids[1], 0x11, component_ids[2], 0x11, 0, 63, 0];
+    if data[1..] != expected {
+        return Err(Error::Unsupported("RTP/JPEG supports only JPEG images with the standard Huffman tables"))
+    }
+    Ok(())
+}
+
+/// A Huffman table of a DHT segment.
+struct HuffmanTable {
+    /// The table class (0 for DC, 1 for AC) in the high nibble and the table id in the low
+    /// nibble.
+    class_id: u8,
+    /// The number of codes of each length from 1 to 16 bits.
+    bits: [u8; 16],
+    /// The symbols in the order of their codes.
+    values: &'static [u8],
+}
+
+/// The Huffman tables from the JPEG standard (section K.3), which RTP/JPEG images use.
+static STD_HUFFMAN_TABLES: [HuffmanTable; 4] = [
+    // DC luminance
+    HuffmanTable {
+        class_id: 0x00,
+        bits: [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        values: &[
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+        ],
+    },
+    // AC luminance
+    HuffmanTable {
+        class_id: 0x10,
+        bits: [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+        values: &[
+            0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06,
+            0x13, 0x51, 0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08,
+            0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72,
+            0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+            0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45,
+            0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59,
+            0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75,
+            0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+            0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3,
+            0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+            0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9,
+            0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+            0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4,
+            0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+        ],
+    },
+    // DC chrominance
+    HuffmanTable {
+        class_id: 0x01,
+        bits: [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+        values: &[
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11,
+        ],
+    },
+    // AC chrominance
+    HuffmanTable {
+        class_id: 0x11,
+        bits: [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+        values: &[
+            0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41,
+            0x51, 0x07, 0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91,
+            0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1,
+            0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+            0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44,
+            0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58,
+            0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74,
+            0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+            0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a,
+            0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+            0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+            0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+            0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4,
+            0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+        ],
+    },
+];
@@ -0,0 +1,154 @@
+use crate::raw;
+use crate::decompress::DecompressHeader;
+
+/// Scaling factor for decompression.
+///
+/// TurboJPEG can scale the image during decompression, which is much faster than decompressing
+/// the full image and resizing it, because the scaling is done in the DCT domain. Only the factors
+/// returned by [`ScalingFactor::supported()`] can be used; these are multiples of 1/8 from 1/8 to
+/// 2.
+///
+/// # Example
+///
+/// ```
+/// let factor = turbojpeg::ScalingFactor::ONE_QUARTER;
+/// assert_eq!(factor.scale(384), 96);
+/// assert_eq!(factor.scale(257), 65); // rounded up
+/// assert!(factor.is_supported());
+/// assert!(!turbojpeg::ScalingFactor::new(1, 3).is_supported());
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[doc(alias = "tjscalingfactor")]
+pub struct ScalingFactor {
+    num: usize,
+    denom: usize,
+}
+
+impl ScalingFactor {
+    /// No scaling.
+    pub const ONE: ScalingFactor = ScalingFactor { num: 1, denom: 1 };
+    /// Scaling to 1/2 of the original size.
+    pub const ONE_HALF: ScalingFactor = ScalingFactor { num: 1, denom: 2 };
+    /// Scaling to 1/4 of the original size.
+    pub const ONE_QUARTER: ScalingFactor = ScalingFactor { num: 1, denom: 4 };
+    /// Scaling to 1/8 of the original size.
+    pub const ONE_EIGHTH: ScalingFactor = ScalingFactor { num: 1, denom: 8 };
+
+    /// Create a scaling factor `num / denom`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num` or `denom` is zero.
+    pub fn new(num: usize, denom: usize) -> ScalingFactor {
+        assert!(num > 0 && denom > 0, "scaling factor {}/{} must not be zero", num, denom);
+        ScalingFactor { num, denom }
+    }
+
+    /// Returns the numerator of the scaling factor.
+    pub fn num(self) -> usize {
+        self.num
+    }
+
+    /// Returns the denominator of the scaling factor.
+    pub fn denom(self) -> usize {
+        self.denom
+    }
+
+    /// Scales the image dimension `dim` by this factor, rounding up.
+    #[doc(alias = "TJSCALED")]
+    pub fn scale(self, dim: usize) -> usize {
+        (dim * self.num).div_ceil(self.denom)
+    }
+
+    /// Returns the size of the image described by `header` scaled by this factor, which is the
+    /// size of the image that the [`Decompressor`][crate::Decompressor] produces with this
+    /// factor.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read the header of the image (which has 384x256 pixels)
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let header = turbojpeg::read_header(&jpeg_data)?;
+    ///
+    /// let factor = turbojpeg::ScalingFactor::new(3, 8);
+    /// assert_eq!(factor.scaled_size(&header), (144, 96));
+    ///
+    /// let mut decompressor = turbojpeg::Decompressor::new()?;
+    /// let image = decompressor.decompress_scaled(&jpeg_data, turbojpeg::PixelFormat::RGB, factor)?;
+    /// assert_eq!((image.width, image.height), factor.scaled_size(&header));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn scaled_size(self, header: &DecompressHeader) -> (usize, usize) {
+        (self.scale(header.width), self.scale(header.height))
+    }
+
+    /// Returns the supported scaling factor that scales the image described by `header` closest to
+    /// `target_width` x `target_height` without making it smaller.
+    ///
+    /// This is the smallest [supported][Self::supported] factor whose [scaled
+    /// size][Self::scaled_size] is at least the target size in both dimensions, so you can
+    /// decompress the image with this factor and then downsample it to the exact target size
+    /// without losing detail. If even the largest supported factor does not reach the target size,
+    /// it returns the largest factor.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::ScalingFactor;
+    ///
+    /// // read the header of the image (which has 384x256 pixels)
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let header = turbojpeg::read_header(&jpeg_data)?;
+    ///
+    /// let factor = ScalingFactor::closest_for(&header, 100, 60);
+    /// assert_eq!(factor.scaled_size(&header), (144, 96));
+    /// assert_eq!(ScalingFactor::closest_for(&header, 384, 256), ScalingFactor::ONE);
+    /// assert_eq!(ScalingFactor::closest_for(&header, 48, 10).scaled_size(&header), (48, 32));
+    /// assert_eq!(ScalingFactor::closest_for(&header, 5000, 5000).scaled_size(&header), (768, 512));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn closest_for(header: &DecompressHeader, target_width: usize, target_height: usize) -> ScalingFactor {
+        let factors = ScalingFactor::supported();
+        let covering = factors.iter()
+            .filter(|factor| {
+                let (width, height) = factor.scaled_size(header);
+                width >= target_width && height >= target_height
+            })
+            .min_by_key(|factor| factor.scale(header.width));
+        let largest = || factors.iter().max_by(|a, b| (a.num * b.denom).cmp(&(b.num * a.denom)));
+        covering.or_else(largest).copied().unwrap_or(ScalingFactor::ONE)
+    }
+
+    /// Returns true if TurboJPEG supports this scaling factor for decompression.
+    pub fn is_supported(self) -> bool {
+        ScalingFactor::supported().into_iter().any(|factor| factor.ratio_eq(self))
+    }
+
+    /// Returns all scaling factors that TurboJPEG supports for decompression.
+    #[doc(alias = "tjGetScalingFactors")]
+    pub fn supported() -> Vec<ScalingFactor> {
+        let mut count = 0;
+        let factors = unsafe { raw::tjGetScalingFactors(&mut count) };
+        if factors.is_null() {
+            return Vec::new()
+        }
+        let factors = unsafe { std::slice::from_raw_parts(factors, count.max(0) as usize) };
+        factors.iter()
+            .map(|factor| ScalingFactor::new(factor.num as usize, factor.denom as usize))
+            .collect()
+    }
+
+    fn ratio_eq(self, other: ScalingFactor) -> bool {
+        self.num * other.denom == other.num * self.denom
+    }
+}
+
+impl Default for ScalingFactor {
+    fn default() -> Self {
+        ScalingFactor::ONE
+    }
+}
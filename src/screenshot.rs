@@ -0,0 +1,67 @@
+use crate::{Image, Compressor, OwnedBuf};
+use crate::common::{PixelFormat, Subsamp, DctMethod, Result};
+
+/// Options for compressing screenshots with [`compress_screenshot()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ScreenshotOptions {
+    /// Quality of the compressed image, from 1 (worst) to 100 (best) (default is 90).
+    pub quality: i32,
+    /// Compress with quality 100, ignoring [`quality`][Self::quality] (default is false).
+    ///
+    /// Together with 4:4:4 subsampling and the accurate DCT, this is visually lossless even for
+    /// small text, but the images are considerably larger than with the default quality.
+    pub near_lossless: bool,
+    /// Use progressive entropy coding (default is true).
+    ///
+    /// TurboJPEG computes optimized Huffman tables only for progressive images, which makes
+    /// screenshots (with large flat areas) noticeably smaller. Disable this if the decoding speed
+    /// on the receiving side is more important than the size, such as in remote desktop tools on
+    /// a fast network.
+    pub progressive: bool,
+}
+
+impl Default for ScreenshotOptions {
+    fn default() -> Self {
+        ScreenshotOptions {
+            quality: 90,
+            near_lossless: false,
+            progressive: true,
+        }
+    }
+}
+
+/// Compresses a screenshot (or other image with text and user interface elements) into JPEG.
+///
+/// Screenshots contain sharp edges and colored text, which are damaged by the settings that work
+/// well for photos. This function always uses 4:4:4 subsampling (no chrominance subsampling) and
+/// the accurate DCT, and TurboJPEG does not apply any smoothing, so the edges stay sharp. See
+/// [`ScreenshotOptions`] for the settings that can be changed. Grayscale images are compressed
+/// as grayscale JPEG images.
+///
+/// This uses the same settings as [`Preset::Screenshot`][crate::Preset::Screenshot], so use
+/// [`Compressor::with_preset()`] if you compress many screenshots and want to reuse the
+/// compressor.
+///
+/// # Example
+///
+/// ```
+/// let image = turbojpeg::Image::mandelbrot(640, 480, turbojpeg::PixelFormat::BGRA);
+///
+/// let jpeg_data = turbojpeg::compress_screenshot(image.as_deref(), &Default::default())?;
+/// let header = turbojpeg::read_header(&jpeg_data)?;
+/// assert_eq!(header.subsamp, turbojpeg::Subsamp::None);
+///
+/// let options = turbojpeg::ScreenshotOptions { near_lossless: true, .. Default::default() };
+/// let lossless_data = turbojpeg::compress_screenshot(image.as_deref(), &options)?;
+/// assert!(lossless_data.len() > jpeg_data.len());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn compress_screenshot(image: Image<&[u8]>, options: &ScreenshotOptions) -> Result<OwnedBuf> {
+    let mut compressor = Compressor::new()?;
+    compressor.set_quality(if options.near_lossless { 100 } else { options.quality })?;
+    compressor.set_subsamp(if image.format == PixelFormat::GRAY { Subsamp::Gray } else { Subsamp::None });
+    compressor.set_progressive(options.progressive);
+    compressor.set_dct_method(DctMethod::Accurate);
+    compressor.compress_to_owned(image)
+}
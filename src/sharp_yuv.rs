@@ -0,0 +1,123 @@
+use crate::Image;
+
+/// Number of refinement iterations. The error drops quickly, so more iterations rarely help.
+const ITERATIONS: usize = 4;
+
+/// Y, Cb and Cr planes of an image with 4:2:0 subsampling, in the layout expected by
+/// `tjCompressFromYUVPlanes()`.
+pub(crate) struct YuvPlanes {
+    pub y: Vec<u8>,
+    pub cb: Vec<u8>,
+    pub cr: Vec<u8>,
+    pub y_stride: usize,
+    pub c_stride: usize,
+}
+
+/// Converts an RGB image into YCbCr with 4:2:0 subsampling, choosing the chrominance samples so
+/// that the image decompressed with fancy upsampling is as close to the original as possible.
+///
+/// Plain downsampling averages the chrominance of every 2x2 block, which shifts the colors near
+/// sharp edges between saturated colors (the reconstructed pixels are clamped and lose
+/// luminance). Like the "sharp YUV" conversion of libwebp, this starts from the plain averages and
+/// then repeatedly reconstructs the RGB image the way the decompressor does (with the triangle
+/// filter used by libjpeg for 4:2:0 images), and corrects both the luminance and the chrominance
+/// by the remaining error.
+///
+/// Returns `None` if the image is not in one of the RGB pixel formats.
+pub(crate) fn sharp_yuv_420(image: Image<&[u8]>) -> Option<YuvPlanes> {
+    let (r_offset, g_offset, b_offset) = image.format.rgb_offsets()?;
+    let (width, height) = (image.width, image.height);
+    let pixel_size = image.format.size();
+    let (c_width, c_height) = (width.div_ceil(2), height.div_ceil(2));
+
+    let mut target = Vec::with_capacity(width * height);
+    for row in image.pixels.chunks(image.pitch).take(height) {
+        for pixel in row[..width * pixel_size].chunks_exact(pixel_size) {
+            target.push([pixel[r_offset] as f32, pixel[g_offset] as f32, pixel[b_offset] as f32]);
+        }
+    }
+
+    let mut luma: Vec<f32> = target.iter().map(|&rgb| rgb_to_ycc(rgb).0).collect();
+    let mut chroma = vec![(0f32, 0f32); c_width * c_height];
+    let mut counts = vec![0f32; c_width * c_height];
+    for y in 0..height {
+        for x in 0..width {
+            let (_, cb, cr) = rgb_to_ycc(target[y * width + x]);
+            let c = &mut chroma[(y / 2) * c_width + x / 2];
+            c.0 += cb;
+            c.1 += cr;
+            counts[(y / 2) * c_width + x / 2] += 1.0;
+        }
+    }
+    for (c, &count) in chroma.iter_mut().zip(&counts) {
+        *c = (c.0 / count, c.1 / count);
+    }
+
+    let mut errors = vec![(0f32, 0f32); c_width * c_height];
+    for _ in 0..ITERATIONS {
+        errors.iter_mut().for_each(|e| *e = (0.0, 0.0));
+        for y in 0..height {
+            let (cy, ny) = (y / 2, neighbor(y, c_height));
+            for x in 0..width {
+                let (cx, nx) = (x / 2, neighbor(x, c_width));
+                let sample = |cy: usize, cx: usize| chroma[cy * c_width + cx];
+                let (near, horiz, vert, diag) = (sample(cy, cx), sample(cy, nx), sample(ny, cx), sample(ny, nx));
+                let cb = (9.0 * near.0 + 3.0 * horiz.0 + 3.0 * vert.0 + diag.0) / 16.0;
+                let cr = (9.0 * near.1 + 3.0 * horiz.1 + 3.0 * vert.1 + diag.1) / 16.0;
+
+                let i = y * width + x;
+                let decoded = ycc_to_rgb(luma[i].round(), cb.round(), cr.round());
+                let original = target[i];
+                let diff = [original[0] - decoded[0], original[1] - decoded[1], original[2] - decoded[2]];
+                let (y_error, cb_error, cr_error) = rgb_to_ycc(diff);
+                luma[i] = (luma[i] + y_error).clamp(0.0, 255.0);
+                let e = &mut errors[cy * c_width + cx];
+                e.0 += cb_error;
+                e.1 += cr_error;
+            }
+        }
+        for ((c, e), &count) in chroma.iter_mut().zip(&errors).zip(&counts) {
+            c.0 = (c.0 + e.0 / count).clamp(-128.0, 127.0);
+            c.1 = (c.1 + e.1 / count).clamp(-128.0, 127.0);
+        }
+    }
+
+    // the planes are padded to whole 2x2 blocks by repeating the last column and row
+    let y_stride = 2 * c_width;
+    let mut y_plane = Vec::with_capacity(y_stride * 2 * c_height);
+    for y in 0..2 * c_height {
+        let row = &luma[y.min(height - 1) * width..][..width];
+        y_plane.extend((0..y_stride).map(|x| row[x.min(width - 1)].round() as u8));
+    }
+    let cb = chroma.iter().map(|c| (c.0 + 128.0).round().clamp(0.0, 255.0) as u8).collect();
+    let cr = chroma.iter().map(|c| (c.1 + 128.0).round().clamp(0.0, 255.0) as u8).collect();
+    Some(YuvPlanes { y: y_plane, cb, cr, y_stride, c_stride: c_width })
+}
+
+/// Returns the chrominance sample that libjpeg's fancy upsampling mixes into full-resolution pixel
+/// `i` besides the nearest one.
+fn neighbor(i: usize, c_len: usize) -> usize {
+    if i.is_multiple_of(2) {
+        (i / 2).saturating_sub(1)
+    } else {
+        (i / 2 + 1).min(c_len - 1)
+    }
+}
+
+/// Converts RGB to YCbCr with the JPEG (full range BT.601) equations, without the chrominance
+/// offset of 128.
+pub(crate) fn rgb_to_ycc([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+    (
+        0.299 * r + 0.587 * g + 0.114 * b,
+        -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+        0.5 * r - 0.418_688 * g - 0.081_312 * b,
+    )
+}
+
+fn ycc_to_rgb(y: f32, cb: f32, cr: f32) -> [f32; 3] {
+    [
+        (y + 1.402 * cr).round().clamp(0.0, 255.0),
+        (y - 0.344_136 * cb - 0.714_136 * cr).round().clamp(0.0, 255.0),
+        (y + 1.772 * cb).round().clamp(0.0, 255.0),
+    ]
+}
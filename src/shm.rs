@@ -0,0 +1,256 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::AsRawFd as _;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::{Compressor, Image};
+use crate::common::{Error, Result};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"TJRING01");
+const HEADER_LEN: usize = 64;
+
+// layout of the header (all fields are u64)
+const MAGIC_OFFSET: usize = 0;
+const SLOTS_OFFSET: usize = 8;
+const SLOT_LEN_OFFSET: usize = 16;
+const WRITE_SEQ_OFFSET: usize = 24;
+const READ_SEQ_OFFSET: usize = 32;
+
+/// Ring buffer of compressed frames in shared memory.
+///
+/// The ring buffer is stored in a file that is mapped into memory by two processes: the producer,
+/// which creates the ring with [`create()`][Self::create] and writes frames with
+/// [`push()`][Self::push] or [`push_image()`][Self::push_image], and the consumer, which opens the
+/// ring with [`open()`][Self::open] and reads the frames with [`pop()`][Self::pop] or
+/// [`pop_with()`][Self::pop_with]. On Linux, you should place the file in `/dev/shm` so that it
+/// is never written to disk.
+///
+/// The ring has a fixed number of slots, and every slot can hold one frame of at most `slot_len`
+/// bytes. When all slots are full, new frames are dropped until the consumer catches up.
+///
+/// There must be at most one producer and one consumer for every ring.
+///
+/// # Example
+///
+/// ```
+/// let path = std::env::temp_dir().join("turbojpeg-ring");
+///
+/// // the producer compresses frames directly into the shared memory
+/// let mut producer = turbojpeg::ShmRing::create(&path, 4, 1 << 20)?;
+/// let mut compressor = turbojpeg::Compressor::new()?;
+/// let image = turbojpeg::Image::mandelbrot(300, 200, turbojpeg::PixelFormat::RGB);
+/// assert!(producer.push_image(&mut compressor, image.as_deref())?);
+///
+/// // the consumer (typically in another process) decompresses the frames
+/// let mut consumer = turbojpeg::ShmRing::open(&path)?;
+/// let header = consumer.pop_with(|jpeg_data| turbojpeg::read_header(jpeg_data)).unwrap()?;
+/// assert_eq!((header.width, header.height), (300, 200));
+/// assert!(consumer.pop().is_none());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct ShmRing {
+    ptr: *mut u8,
+    len: usize,
+    slots: usize,
+    slot_len: usize,
+    _file: File,
+}
+
+unsafe impl Send for ShmRing {}
+
+impl ShmRing {
+    /// Create a new ring buffer in the file at `path` with `slots` slots of `slot_len` bytes.
+    ///
+    /// If the file exists, it is truncated and all frames in it are discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slots` or `slot_len` is zero.
+    pub fn create<P: AsRef<Path>>(path: P, slots: usize, slot_len: usize) -> Result<ShmRing> {
+        assert!(slots > 0 && slot_len > 0, "ring must have non-zero number and length of slots");
+        let stride = slot_stride(slot_len).ok_or(Error::IntegerOverflow("slot_len"))?;
+        let len = stride.checked_mul(slots)
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .ok_or(Error::IntegerOverflow("ring len"))?;
+
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(len as u64)?;
+        let ring = ShmRing::map(file, len, slots, slot_len)?;
+        ring.header(SLOTS_OFFSET).store(slots as u64, Ordering::Relaxed);
+        ring.header(SLOT_LEN_OFFSET).store(slot_len as u64, Ordering::Relaxed);
+        ring.header(WRITE_SEQ_OFFSET).store(0, Ordering::Relaxed);
+        ring.header(READ_SEQ_OFFSET).store(0, Ordering::Relaxed);
+        ring.header(MAGIC_OFFSET).store(MAGIC, Ordering::Release);
+        Ok(ring)
+    }
+
+    /// Open an existing ring buffer in the file at `path`, which was created by
+    /// [`create()`][Self::create].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ShmRing> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.metadata()?.len() as usize;
+        if file_len < HEADER_LEN {
+            return Err(invalid_ring())
+        }
+
+        // map only the header first to read the size of the ring
+        let header_ptr = mmap(&file, HEADER_LEN)?;
+        let (magic, slots, slot_len) = unsafe {
+            (
+                header_at(header_ptr, MAGIC_OFFSET).load(Ordering::Acquire),
+                header_at(header_ptr, SLOTS_OFFSET).load(Ordering::Relaxed) as usize,
+                header_at(header_ptr, SLOT_LEN_OFFSET).load(Ordering::Relaxed) as usize,
+            )
+        };
+        unsafe { libc::munmap(header_ptr as *mut libc::c_void, HEADER_LEN); }
+        if magic != MAGIC || slots == 0 || slot_len == 0 {
+            return Err(invalid_ring())
+        }
+
+        let len = slot_stride(slot_len)
+            .and_then(|stride| stride.checked_mul(slots))
+            .and_then(|len| len.checked_add(HEADER_LEN))
+            .filter(|&len| len <= file_len)
+            .ok_or_else(invalid_ring)?;
+        ShmRing::map(file, len, slots, slot_len)
+    }
+
+    /// Returns the maximal length of a frame in bytes.
+    pub fn slot_len(&self) -> usize {
+        self.slot_len
+    }
+
+    /// Returns the number of frames that are waiting for the consumer.
+    pub fn len(&self) -> usize {
+        let write_seq = self.header(WRITE_SEQ_OFFSET).load(Ordering::Acquire);
+        let read_seq = self.header(READ_SEQ_OFFSET).load(Ordering::Acquire);
+        write_seq.wrapping_sub(read_seq) as usize
+    }
+
+    /// Returns true if there are no frames waiting for the consumer.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the `frame` into the ring (producer side).
+    ///
+    /// Returns `false` if the ring is full and the frame was dropped. If the frame is longer than
+    /// [`slot_len()`][Self::slot_len], returns [`Error::OutputTooSmall`].
+    pub fn push(&mut self, frame: &[u8]) -> Result<bool> {
+        if frame.len() > self.slot_len {
+            return Err(Error::OutputTooSmall(frame.len()))
+        }
+        self.push_with(|slot| {
+            slot[..frame.len()].copy_from_slice(frame);
+            Ok(frame.len())
+        })
+    }
+
+    /// Compress the `image` directly into the ring (producer side).
+    ///
+    /// Returns `false` if the ring is full and the frame was dropped. If the compressed image does
+    /// not fit into a slot, an error is returned.
+    pub fn push_image(&mut self, compressor: &mut Compressor, image: Image<&[u8]>) -> Result<bool> {
+        self.push_with(|slot| compressor.compress_to_slice(image, slot))
+    }
+
+    fn push_with<F>(&mut self, write: F) -> Result<bool>
+        where F: FnOnce(&mut [u8]) -> Result<usize>
+    {
+        let write_seq = self.header(WRITE_SEQ_OFFSET).load(Ordering::Relaxed);
+        let read_seq = self.header(READ_SEQ_OFFSET).load(Ordering::Acquire);
+        if write_seq.wrapping_sub(read_seq) as usize >= self.slots {
+            return Ok(false)
+        }
+
+        let (len, data) = self.slot(write_seq);
+        let frame_len = write(data)?;
+        len.store(frame_len as u64, Ordering::Relaxed);
+        self.header(WRITE_SEQ_OFFSET).store(write_seq.wrapping_add(1), Ordering::Release);
+        Ok(true)
+    }
+
+    /// Read the oldest frame from the ring and pass it to `read` (consumer side).
+    ///
+    /// Returns `None` if the ring is empty.
+    pub fn pop_with<F, R>(&mut self, read: F) -> Option<R>
+        where F: FnOnce(&[u8]) -> R
+    {
+        let read_seq = self.header(READ_SEQ_OFFSET).load(Ordering::Relaxed);
+        let write_seq = self.header(WRITE_SEQ_OFFSET).load(Ordering::Acquire);
+        if read_seq == write_seq {
+            return None
+        }
+
+        let (len, data) = self.slot(read_seq);
+        let frame_len = (len.load(Ordering::Relaxed) as usize).min(data.len());
+        let res = read(&data[..frame_len]);
+        self.header(READ_SEQ_OFFSET).store(read_seq.wrapping_add(1), Ordering::Release);
+        Some(res)
+    }
+
+    /// Read the oldest frame from the ring into a new `Vec` (consumer side).
+    ///
+    /// Returns `None` if the ring is empty.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.pop_with(|frame| frame.to_vec())
+    }
+
+    fn map(file: File, len: usize, slots: usize, slot_len: usize) -> Result<ShmRing> {
+        let ptr = mmap(&file, len)?;
+        Ok(ShmRing { ptr, len, slots, slot_len, _file: file })
+    }
+
+    fn header(&self, offset: usize) -> &AtomicU64 {
+        unsafe { header_at(self.ptr, offset) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn slot(&self, seq: u64) -> (&AtomicU64, &mut [u8]) {
+        let stride = slot_stride(self.slot_len).unwrap();
+        let offset = HEADER_LEN + (seq % self.slots as u64) as usize * stride;
+        debug_assert!(offset + stride <= self.len);
+        unsafe {
+            let len = &*(self.ptr.add(offset) as *const AtomicU64);
+            let data = std::slice::from_raw_parts_mut(self.ptr.add(offset + 8), self.slot_len);
+            (len, data)
+        }
+    }
+}
+
+impl Drop for ShmRing {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len); }
+    }
+}
+
+fn mmap(file: &File, len: usize) -> Result<*mut u8> {
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(), len,
+            libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED,
+            file.as_raw_fd(), 0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        Err(io::Error::last_os_error().into())
+    } else {
+        Ok(ptr as *mut u8)
+    }
+}
+
+unsafe fn header_at<'a>(ptr: *mut u8, offset: usize) -> &'a AtomicU64 {
+    debug_assert!(offset + 8 <= HEADER_LEN);
+    &*(ptr.add(offset) as *const AtomicU64)
+}
+
+fn slot_stride(slot_len: usize) -> Option<usize> {
+    slot_len.checked_add(8)?.checked_next_multiple_of(8)
+}
+
+fn invalid_ring() -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, "file is not a valid frame ring").into()
+}
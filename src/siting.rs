@@ -0,0 +1,143 @@
+use std::ops::DerefMut;
+use crate::common::Subsamp;
+use crate::image::YuvImage;
+
+/// Position of the chrominance samples relative to the luminance samples in a subsampled YUV
+/// image (chroma siting).
+///
+/// JPEG images always use [`Centered`][Self::Centered] chroma: libjpeg averages the
+/// chrominance of all pixels that share a chrominance sample when compressing, and the JFIF
+/// standard places the sample in the center of these pixels. Video codecs usually expect a
+/// different siting (H.264 and MPEG-2 use [`Left`][Self::Left] by default), so YUV images that
+/// are passed between JPEG and a video pipeline without resampling the chrominance planes have
+/// their colors shifted by a fraction of a pixel, which shows as color fringes on sharp edges.
+///
+/// [`Decompressor::set_chroma_siting()`][crate::Decompressor::set_chroma_siting] resamples the
+/// output of [`decompress_to_yuv()`][crate::Decompressor::decompress_to_yuv] to the siting that
+/// the consumer expects, and [`YuvImage::resite_chroma()`] converts other YUV images.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::ChromaSiting;
+///
+/// // the values of `chroma_sample_loc_type` in the VUI of H.264 and H.265
+/// assert_eq!(ChromaSiting::Left.chroma_sample_loc_type(), 0);
+/// assert_eq!(ChromaSiting::Centered.chroma_sample_loc_type(), 1);
+/// assert_eq!(ChromaSiting::TopLeft.chroma_sample_loc_type(), 2);
+/// assert_eq!(ChromaSiting::default(), ChromaSiting::Centered);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaSiting {
+    /// Chrominance samples are centered between the luminance samples, both horizontally and
+    /// vertically. This is the siting of JPEG (JFIF) images.
+    #[default]
+    Centered,
+    /// Chrominance samples are co-sited with the left luminance sample horizontally and centered
+    /// vertically. This is the default of H.264, H.265 and MPEG-2.
+    Left,
+    /// Chrominance samples are co-sited with the top left luminance sample, both horizontally and
+    /// vertically. This is used by BT.2020 and BT.2100 content.
+    TopLeft,
+}
+
+impl ChromaSiting {
+    /// Returns the value of `chroma_sample_loc_type` that signals this siting in the video
+    /// usability information (VUI) of H.264 and H.265 streams.
+    pub fn chroma_sample_loc_type(self) -> u8 {
+        match self {
+            ChromaSiting::Left => 0,
+            ChromaSiting::Centered => 1,
+            ChromaSiting::TopLeft => 2,
+        }
+    }
+
+    /// Returns the position of the first chrominance sample in units of luminance samples, for
+    /// the given horizontal and vertical subsampling factors.
+    fn offset(self, factor_x: usize, factor_y: usize) -> (f32, f32) {
+        let centered = |factor: usize| (factor as f32 - 1.) / 2.;
+        match self {
+            ChromaSiting::Centered => (centered(factor_x), centered(factor_y)),
+            ChromaSiting::Left => (0., centered(factor_y)),
+            ChromaSiting::TopLeft => (0., 0.),
+        }
+    }
+}
+
+impl<T: DerefMut<Target = [u8]>> YuvImage<T> {
+    /// Resamples the chrominance planes from the siting `from` to the siting `to` (see
+    /// [`ChromaSiting`]).
+    ///
+    /// The chrominance samples are linearly interpolated between their neighbors, and the
+    /// samples on the edges are extended. Images without subsampling (or without chrominance) are
+    /// not changed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use turbojpeg::ChromaSiting;
+    ///
+    /// // compress an image with 4:2:0 subsampling and decompress it into YUV
+    /// let image = turbojpeg::Image::mandelbrot(64, 48, turbojpeg::PixelFormat::RGB);
+    /// let jpeg_data = turbojpeg::compress(image.as_deref(), 90, turbojpeg::Subsamp::Sub2x2)?;
+    /// let mut image = turbojpeg::decompress_to_yuv(&jpeg_data)?;
+    /// let centered = image.pixels.clone();
+    ///
+    /// // the chrominance shifts by a quarter of a chrominance sample to the left
+    /// image.resite_chroma(ChromaSiting::Centered, ChromaSiting::Left);
+    /// let y_len = image.y_width() * image.y_height();
+    /// assert_eq!(&image.pixels[..y_len], &centered[..y_len]);
+    /// assert_ne!(&image.pixels[y_len..], &centered[y_len..]);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn resite_chroma(&mut self, from: ChromaSiting, to: ChromaSiting) {
+        self.assert_valid(self.pixels.len());
+        let (factor_x, factor_y) = (self.subsamp.width(), self.subsamp.height());
+        if from == to || self.subsamp == Subsamp::Gray || (factor_x, factor_y) == (1, 1) || self.width == 0 || self.height == 0 {
+            return
+        }
+        let (from_x, from_y) = from.offset(factor_x, factor_y);
+        let (to_x, to_y) = to.offset(factor_x, factor_y);
+        // position of the new samples in the units of the old samples
+        let (shift_x, shift_y) = ((to_x - from_x) / factor_x as f32, (to_y - from_y) / factor_y as f32);
+
+        let y_len = self.y_width() * self.y_height();
+        let (stride, height) = self.uv_size();
+        let width = self.width.div_ceil(factor_x);
+        let plane_len = stride * height;
+        for plane in self.pixels[y_len..y_len + 2 * plane_len].chunks_exact_mut(plane_len) {
+            if shift_x != 0. {
+                let mut old = vec![0; width];
+                for row in plane.chunks_exact_mut(stride) {
+                    old.copy_from_slice(&row[..width]);
+                    for (x, value) in row[..width].iter_mut().enumerate() {
+                        *value = interpolate(&old, x as f32 + shift_x);
+                    }
+                }
+            }
+            if shift_y != 0. {
+                let mut old = vec![0; height];
+                for x in 0..width {
+                    for (y, value) in old.iter_mut().enumerate() {
+                        *value = plane[y * stride + x];
+                    }
+                    for y in 0..height {
+                        plane[y * stride + x] = interpolate(&old, y as f32 + shift_y);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Linearly interpolates `samples` at the fractional index `pos`, extending the edges.
+fn interpolate(samples: &[u8], pos: f32) -> u8 {
+    let last = samples.len() - 1;
+    let pos = pos.clamp(0., last as f32);
+    let index = pos.floor() as usize;
+    let frac = pos - index as f32;
+    let next = samples[(index + 1).min(last)] as f32;
+    (samples[index] as f32 * (1. - frac) + next * frac).round() as u8
+}
@@ -0,0 +1,63 @@
+use std::iter::FusedIterator;
+use crate::marker::{self, EOI};
+
+/// Splits data that contain several concatenated JPEG images into the individual images.
+///
+/// Camera "motion photo" files, MJPEG streams dumped to a file, and multi-picture (MPO) images
+/// contain several JPEG images one after another, possibly with other data in between. This
+/// function finds every SOI marker, follows the marker segments (skipping the entropy-coded data,
+/// so SOI markers inside the images, such as EXIF thumbnails, are not mistaken for new images) up
+/// to the EOI marker, and returns the images in the order in which they appear. Data between the
+/// images are skipped, and truncated or malformed images are skipped as well.
+///
+/// The returned slices borrow from `data`, so no image is copied.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let mut blob = b"garbage".to_vec();
+/// blob.extend_from_slice(&jpeg_data);
+/// blob.extend_from_slice(b"--frame\r\n");
+/// blob.extend_from_slice(&jpeg_data);
+/// blob.extend_from_slice(&jpeg_data[..1000]); // truncated frame
+///
+/// let frames = turbojpeg::split_jpegs(&blob).collect::<Vec<_>>();
+/// assert_eq!(frames.len(), 2);
+/// assert!(frames.iter().all(|frame| *frame == &jpeg_data[..]));
+/// assert_eq!(turbojpeg::read_header(frames[1])?.width, 384);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn split_jpegs(data: &[u8]) -> SplitJpegs<'_> {
+    SplitJpegs { data, pos: 0 }
+}
+
+/// Iterator over the JPEG images in concatenated data, returned by [`split_jpegs()`].
+#[derive(Debug, Clone)]
+pub struct SplitJpegs<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for SplitJpegs<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            let start = self.pos + marker::find_soi(&self.data[self.pos..], usize::MAX)?;
+            let mut segments = marker::segments(&self.data[start..]);
+            let complete = segments.by_ref()
+                .take_while(|segment| segment.is_ok())
+                .any(|segment| matches!(segment, Ok(segment) if segment.marker == EOI));
+            if complete {
+                self.pos = start + segments.pos();
+                return Some(&self.data[start..self.pos])
+            }
+            // the image is truncated or malformed, look for the next SOI marker inside it
+            self.pos = start + 2;
+        }
+    }
+}
+
+impl FusedIterator for SplitJpegs<'_> {}
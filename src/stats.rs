@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// Statistics of calls into TurboJPEG made by a [`Compressor`][crate::Compressor] or
+/// [`Decompressor`][crate::Decompressor].
+///
+/// Statistics are recorded only for successful calls. Use the `stats()` and `reset_stats()`
+/// methods to read and reset them.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// for _ in 0..3 {
+///     decompressor.decompress_to_buf(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// }
+///
+/// let stats = decompressor.stats();
+/// assert_eq!(stats.calls, 3);
+/// assert_eq!(stats.bytes_in, 3 * jpeg_data.len() as u64);
+/// assert_eq!(stats.pixels, 3 * 384 * 256);
+/// println!("decompressed {:.1} Mpx/s", stats.megapixels_per_sec());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Stats {
+    /// Number of calls.
+    pub calls: u64,
+    /// Total time spent in TurboJPEG.
+    pub time: Duration,
+    /// Total number of input bytes (pixel data for compression, JPEG data for decompression).
+    pub bytes_in: u64,
+    /// Total number of output bytes (JPEG data for compression, pixel data for decompression).
+    pub bytes_out: u64,
+    /// Total number of processed pixels.
+    pub pixels: u64,
+}
+
+impl Stats {
+    /// Returns the throughput in megapixels per second.
+    pub fn megapixels_per_sec(&self) -> f64 {
+        let secs = self.time.as_secs_f64();
+        if secs == 0. {
+            0.
+        } else {
+            self.pixels as f64 / secs / 1e6
+        }
+    }
+
+    pub(crate) fn record(&mut self, time: Duration, bytes_in: usize, bytes_out: usize, pixels: usize) {
+        self.calls += 1;
+        self.time += time;
+        self.bytes_in += bytes_in as u64;
+        self.bytes_out += bytes_out as u64;
+        self.pixels += pixels as u64;
+    }
+}
@@ -0,0 +1,48 @@
+// Tables and helpers from the JPEG standard, which are shared by the modules that inspect or
+// estimate the DCT coefficients of JPEG images.
+
+/// Scales a quantization table to the quality in the same way as `jpeg_set_quality()` in libjpeg.
+pub(crate) fn quant_table(base: &[u16; 64], quality: i32) -> [i32; 64] {
+    let scale = if quality < 50 { 5000 / quality } else { 200 - 2*quality };
+    let mut table = [0; 64];
+    for (value, &base) in table.iter_mut().zip(base.iter()) {
+        *value = ((base as i32 * scale + 50) / 100).clamp(1, 255);
+    }
+    table
+}
+
+/// The luminance quantization table from the JPEG standard (in natural order).
+pub(crate) static STD_LUMINANCE_QUANT_TABLE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// The chrominance quantization table from the JPEG standard (in natural order).
+pub(crate) static STD_CHROMINANCE_QUANT_TABLE: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Indices of the coefficients of a block (in natural order) in the zigzag order.
+pub(crate) static ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10,
+    17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
@@ -0,0 +1,531 @@
+//! Helpers for tests that work with decompressed images.
+//!
+//! JPEG compression is lossy and the exact output of TurboJPEG depends on its version and on the
+//! SIMD extensions of the CPU, so tests should compare decompressed images with a tolerance
+//! instead of exactly. The functions in this module compare the pixels of two images, even if
+//! they have different pixel formats or pitches, and ignore any metadata.
+//!
+//! [`Snapshots`] stores decompressed images and compressed outputs as golden files and compares
+//! them with a tolerance in later test runs, which detects regressions (for example, after
+//! upgrading libjpeg-turbo).
+//!
+//! [`RoundTripCase`] generates random image geometries, pixel formats and compression settings
+//! from a seed and checks the round-trip invariants for them, either with [`check_round_trips()`]
+//! or with a property-based testing framework that supplies the seeds.
+//!
+//! This module is available with the `testing` feature, which you would typically enable only in
+//! `[dev-dependencies]`.
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::Image;
+use crate::common::{PixelFormat, Subsamp, Result};
+use crate::compress::Compressor;
+use crate::decompress::Decompressor;
+use crate::pnm::{decode_pnm, encode_pnm};
+use crate::quality::Quality;
+
+/// Environment variable that switches [`Snapshots`] to the update mode.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "TURBOJPEG_UPDATE_SNAPSHOTS";
+
+/// Golden-file snapshots of images in a directory.
+///
+/// Every snapshot has a name, which determines the names of its files in the directory:
+///
+/// - `<name>.pnm` is the snapshot of the image (in the PGM, PPM or PAM format, see
+///   [`encode_pnm()`][crate::encode_pnm]). This is the file that you commit into your
+///   repository.
+/// - `<name>.jpg` is the compressed JPEG data, which is stored by
+///   [`assert_jpeg()`][Self::assert_jpeg] in the update mode for inspection.
+/// - `<name>.actual.pnm` is the image from the last failed comparison, which you can compare
+///   with the snapshot in an image viewer.
+///
+/// The images are compared with [`images_max_abs_diff()`], so small differences caused by other
+/// versions of libjpeg-turbo or by different SIMD code do not make the tests fail. If the
+/// environment variable `TURBOJPEG_UPDATE_SNAPSHOTS` is set (or if you call
+/// [`set_update(true)`][Self::set_update]), the snapshots are overwritten instead of compared.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::testing::Snapshots;
+///
+/// let dir = std::env::temp_dir().join("turbojpeg-snapshots");
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // create the snapshot
+/// let mut snapshots = Snapshots::new(&dir);
+/// snapshots.set_update(true);
+/// snapshots.assert_jpeg("parrots", &jpeg_data);
+///
+/// // compare the recompressed image with the snapshot
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// let recompressed = turbojpeg::compress(image.as_deref(), 95, turbojpeg::Subsamp::None)?;
+/// snapshots.set_update(false);
+/// snapshots.set_tolerance(64);
+/// snapshots.assert_jpeg("parrots", &recompressed);
+/// # std::fs::remove_dir_all(&dir)?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+    tolerance: u8,
+    update: bool,
+}
+
+impl Snapshots {
+    /// Creates snapshots stored in directory `dir`.
+    ///
+    /// The default tolerance is 8 (see [`set_tolerance()`][Self::set_tolerance]). The update
+    /// mode is enabled if the environment variable `TURBOJPEG_UPDATE_SNAPSHOTS` is set.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Snapshots {
+        Snapshots {
+            dir: dir.as_ref().to_path_buf(),
+            tolerance: 8,
+            update: std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some(),
+        }
+    }
+
+    /// Sets the maximal difference of every channel between the image and the snapshot.
+    pub fn set_tolerance(&mut self, tolerance: u8) {
+        self.tolerance = tolerance;
+    }
+
+    /// Enables or disables the update mode, in which the snapshots are overwritten.
+    pub fn set_update(&mut self, update: bool) {
+        self.update = update;
+    }
+
+    /// Returns the path of file `<name>.<extension>` in the snapshot directory.
+    pub fn path(&self, name: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, extension))
+    }
+
+    /// Compares the image with snapshot `name`, or stores it in the update mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image differs from the snapshot by more than the tolerance, if the snapshot
+    /// does not exist, or if the snapshot cannot be read or written.
+    #[track_caller]
+    pub fn assert_image(&self, name: &str, image: Image<&[u8]>) {
+        let path = self.path(name, "pnm");
+        if self.update {
+            self.write(&path, &encode_pnm(image));
+            return
+        }
+
+        let snapshot_data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(err) => {
+                self.write(&self.path(name, "actual.pnm"), &encode_pnm(image));
+                panic!("could not read snapshot {:?} ({}); set {}=1 to create it",
+                    path, err, UPDATE_SNAPSHOTS_VAR);
+            },
+        };
+        let snapshot = decode_pnm(&snapshot_data)
+            .unwrap_or_else(|err| panic!("could not decode snapshot {:?}: {}", path, err));
+
+        let same_size = (snapshot.width, snapshot.height) == (image.width, image.height);
+        let diff = if same_size { Some(max_abs_diff(snapshot.as_deref(), image)) } else { None };
+        if matches!(diff, Some((diff, _)) if diff <= self.tolerance) {
+            let _ = fs::remove_file(self.path(name, "actual.pnm"));
+            return
+        }
+
+        self.write(&self.path(name, "actual.pnm"), &encode_pnm(image));
+        match diff {
+            Some((diff, (x, y))) => panic!(
+                "image differs from snapshot {:?} by {} (tolerance is {}) at pixel ({}, {}); \
+                set {}=1 to update the snapshot",
+                path, diff, self.tolerance, x, y, UPDATE_SNAPSHOTS_VAR),
+            None => panic!(
+                "image has size {}x{}, but snapshot {:?} has size {}x{}; \
+                set {}=1 to update the snapshot",
+                image.width, image.height, path, snapshot.width, snapshot.height,
+                UPDATE_SNAPSHOTS_VAR),
+        }
+    }
+
+    /// Decompresses the JPEG data and compares it with snapshot `name`, or stores it in the
+    /// update mode.
+    ///
+    /// The image is decompressed into [`PixelFormat::GRAY`] if the JPEG image is grayscale, or
+    /// into [`PixelFormat::RGB`] otherwise. In the update mode, the JPEG data is also stored in
+    /// `<name>.jpg`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the JPEG data cannot be decompressed, and in the same cases as
+    /// [`assert_image()`][Self::assert_image].
+    #[track_caller]
+    pub fn assert_jpeg(&self, name: &str, jpeg_data: &[u8]) {
+        let header = crate::read_header(jpeg_data)
+            .unwrap_or_else(|err| panic!("could not read JPEG header of {:?}: {}", name, err));
+        let format = match header.colorspace {
+            crate::Colorspace::Gray => PixelFormat::GRAY,
+            _ => PixelFormat::RGB,
+        };
+        let image = crate::decompress(jpeg_data, format)
+            .unwrap_or_else(|err| panic!("could not decompress {:?}: {}", name, err));
+        if self.update {
+            self.write(&self.path(name, "jpg"), jpeg_data);
+        }
+        self.assert_image(name, image.as_deref());
+    }
+
+    #[track_caller]
+    fn write(&self, path: &Path, data: &[u8]) {
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(path, data))
+            .unwrap_or_else(|err| panic!("could not write {:?}: {}", path, err));
+    }
+}
+
+/// Returns the maximal absolute difference between the channels of two images.
+///
+/// The images can have different pixel formats and pitches: the pixels are compared as RGBA (the
+/// alpha of formats without alpha channel is 255, grayscale pixels have equal red, green and blue
+/// channels), so for example an [`PixelFormat::RGB`] image can be compared with a
+/// [`PixelFormat::BGRA`] image. [`PixelFormat::CMYK`] images can be compared only with other CMYK
+/// images.
+///
+/// # Panics
+///
+/// Panics if the images have different sizes, if only one of them is a CMYK image, or if one of
+/// them is not valid.
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let rgb = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+/// let bgra = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::BGRA)?;
+/// assert_eq!(turbojpeg::testing::images_max_abs_diff(rgb.as_deref(), bgra.as_deref()), 0);
+///
+/// // recompression changes the pixels slightly
+/// let recompressed = turbojpeg::compress(rgb.as_deref(), 95, turbojpeg::Subsamp::None)?;
+/// let decompressed = turbojpeg::decompress(&recompressed, turbojpeg::PixelFormat::RGB)?;
+/// assert!(turbojpeg::testing::images_max_abs_diff(rgb.as_deref(), decompressed.as_deref()) > 0);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn images_max_abs_diff(a: Image<&[u8]>, b: Image<&[u8]>) -> u8 {
+    max_abs_diff(a, b).0
+}
+
+/// Asserts that the pixels of two images differ by at most `tolerance` in every channel.
+///
+/// The images are compared by [`images_max_abs_diff()`]. If the assertion fails, the panic
+/// message contains the position of the pixel with the largest difference and the values of the
+/// pixel in both images.
+///
+/// # Panics
+///
+/// Panics if the images are not similar, or if they cannot be compared (see
+/// [`images_max_abs_diff()`]).
+///
+/// # Example
+///
+/// ```
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+///
+/// let recompressed = turbojpeg::compress(image.as_deref(), 95, turbojpeg::Subsamp::None)?;
+/// let decompressed = turbojpeg::decompress(&recompressed, turbojpeg::PixelFormat::RGBA)?;
+/// turbojpeg::testing::assert_images_similar(image.as_deref(), decompressed.as_deref(), 64);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[track_caller]
+pub fn assert_images_similar(a: Image<&[u8]>, b: Image<&[u8]>, tolerance: u8) {
+    let (diff, position) = max_abs_diff(a, b);
+    if diff > tolerance {
+        let (x, y) = position;
+        panic!("images differ by {} (tolerance is {}) at pixel ({}, {}): {:?} ({:?}) vs {:?} ({:?})",
+            diff, tolerance, x, y,
+            pixel(a, x, y), a.format, pixel(b, x, y), b.format);
+    }
+}
+
+/// Returns the maximal difference and the position of the first pixel with this difference.
+fn max_abs_diff(a: Image<&[u8]>, b: Image<&[u8]>) -> (u8, (usize, usize)) {
+    a.assert_valid(a.pixels.len());
+    b.assert_valid(b.pixels.len());
+    assert!(a.width == b.width && a.height == b.height,
+        "images have different sizes: {}x{} vs {}x{}", a.width, a.height, b.width, b.height);
+    assert!((a.format == PixelFormat::CMYK) == (b.format == PixelFormat::CMYK),
+        "CMYK image cannot be compared with {:?} image",
+        if a.format == PixelFormat::CMYK { b.format } else { a.format });
+
+    let mut max = (0, (0, 0));
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let (pa, pb) = (pixel(a, x, y), pixel(b, x, y));
+            let diff = pa.iter().zip(pb.iter()).map(|(&ca, &cb)| ca.abs_diff(cb)).max().unwrap();
+            if diff > max.0 {
+                max = (diff, (x, y));
+            }
+        }
+    }
+    max
+}
+
+/// Returns the pixel as RGBA (or CMYK for CMYK images).
+fn pixel(image: Image<&[u8]>, x: usize, y: usize) -> [u8; 4] {
+    let p = &image.pixels[y*image.pitch + x*image.format.size()..];
+    match image.format {
+        PixelFormat::RGB | PixelFormat::RGBX => [p[0], p[1], p[2], 255],
+        PixelFormat::BGR | PixelFormat::BGRX => [p[2], p[1], p[0], 255],
+        PixelFormat::XBGR => [p[3], p[2], p[1], 255],
+        PixelFormat::XRGB => [p[1], p[2], p[3], 255],
+        PixelFormat::GRAY => [p[0], p[0], p[0], 255],
+        PixelFormat::RGBA => [p[0], p[1], p[2], p[3]],
+        PixelFormat::BGRA => [p[2], p[1], p[0], p[3]],
+        PixelFormat::ABGR => [p[3], p[2], p[1], p[0]],
+        PixelFormat::ARGB => [p[1], p[2], p[3], p[0]],
+        PixelFormat::CMYK => [p[0], p[1], p[2], p[3]],
+    }
+}
+
+/// Peak signal-to-noise ratio of two images in decibels.
+///
+/// The images are compared like in [`images_max_abs_diff()`] (over the RGBA or CMYK values of the
+/// pixels). Identical images have an infinite PSNR; JPEG images compressed with the default
+/// quality usually have a PSNR above 35 dB.
+///
+/// # Panics
+///
+/// Panics if the images cannot be compared (see [`images_max_abs_diff()`]).
+pub fn psnr(a: Image<&[u8]>, b: Image<&[u8]>) -> f64 {
+    max_abs_diff(a, b);
+    let mut squared_error = 0u64;
+    for y in 0..a.height {
+        for x in 0..a.width {
+            for (&ca, &cb) in pixel(a, x, y).iter().zip(pixel(b, x, y).iter()) {
+                squared_error += (ca.abs_diff(cb) as u64).pow(2);
+            }
+        }
+    }
+    let mse = squared_error as f64 / (a.width * a.height * 4) as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Randomly generated settings for a compression round trip.
+///
+/// A case is generated from a seed by [`from_seed()`][Self::from_seed], so it can be reproduced
+/// from the seed alone. [`check()`][Self::check] compresses a smooth test image with the settings,
+/// decompresses it again and checks the invariants of the round trip:
+///
+/// - the header of the JPEG image has the dimensions and the subsampling of the case,
+/// - the decompressed image has the dimensions of the original image,
+/// - the PSNR of the decompressed image is at least [`min_psnr()`][Self::min_psnr].
+///
+/// Use [`check_round_trips()`] to check many cases, or plug the seeds into a property-based
+/// testing framework, which then also shrinks failing cases to their seeds. With `proptest`,
+/// for example:
+///
+/// ```ignore
+/// proptest::proptest! {
+///     #[test]
+///     fn round_trip(seed: u64) {
+///         turbojpeg::testing::RoundTripCase::from_seed(seed).check().unwrap();
+///     }
+/// }
+/// ```
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::testing::RoundTripCase;
+///
+/// let case = RoundTripCase::from_seed(42);
+/// assert_eq!(case, RoundTripCase::from_seed(42));
+/// let psnr = case.check()?;
+/// assert!(psnr >= case.min_psnr());
+///
+/// // cases can also be written by hand
+/// let case = RoundTripCase {
+///     width: 1,
+///     height: 1,
+///     pitch_padding: 3,
+///     format: turbojpeg::PixelFormat::CMYK,
+///     subsamp: turbojpeg::Subsamp::Sub4x1,
+///     quality: turbojpeg::Quality::WORST,
+///     progressive: true,
+/// };
+/// case.check()?;
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundTripCase {
+    /// Width of the image.
+    pub width: usize,
+    /// Height of the image.
+    pub height: usize,
+    /// Number of bytes added to the pitch of the image (and of the decompressed image).
+    pub pitch_padding: usize,
+    /// Pixel format of the image (and of the decompressed image).
+    pub format: PixelFormat,
+    /// Chrominance subsampling. This is always [`Subsamp::Gray`] for [`PixelFormat::GRAY`] and
+    /// never for other formats.
+    pub subsamp: Subsamp,
+    /// Quality of compression.
+    pub quality: Quality,
+    /// Whether to use progressive coding.
+    pub progressive: bool,
+}
+
+impl RoundTripCase {
+    /// The maximal width and height of generated images.
+    pub const MAX_SIZE: usize = 300;
+
+    /// Generates a case from `seed`.
+    ///
+    /// Small images (which have partial MCU blocks) are generated as often as larger ones.
+    pub fn from_seed(seed: u64) -> RoundTripCase {
+        let mut rng = SplitMix64(seed);
+        let size = |rng: &mut SplitMix64| match rng.below(4) {
+            0 => 1 + rng.below(16) as usize,
+            _ => 1 + rng.below(RoundTripCase::MAX_SIZE as u64) as usize,
+        };
+        let width = size(&mut rng);
+        let height = size(&mut rng);
+        let pitch_padding = [0, 0, 1, 3, 64][rng.below(5) as usize];
+        let format = PixelFormat::ALL[rng.below(PixelFormat::ALL.len() as u64) as usize];
+        let subsamp = if format == PixelFormat::GRAY {
+            Subsamp::Gray
+        } else {
+            const COLOR: [Subsamp; 5] = [Subsamp::None, Subsamp::Sub2x1, Subsamp::Sub2x2, Subsamp::Sub1x2, Subsamp::Sub4x1];
+            COLOR[rng.below(COLOR.len() as u64) as usize]
+        };
+        let quality = Quality::new(1 + rng.below(100) as i32).unwrap();
+        let progressive = rng.below(2) == 1;
+        RoundTripCase { width, height, pitch_padding, format, subsamp, quality, progressive }
+    }
+
+    /// Returns the smooth test image for this case.
+    ///
+    /// The image contains smooth gradients in all channels. The padding bytes at the end of rows
+    /// and the unused bytes of pixels (such as X in [`PixelFormat::RGBX`]) are 255, like alpha.
+    pub fn image(&self) -> Image<Vec<u8>> {
+        let format = self.format;
+        let pitch = self.width * format.size() + self.pitch_padding;
+        let mut pixels = vec![255; pitch * self.height];
+        // the gradients do not depend on the size of the image, so small images are smooth too
+        let scale = (RoundTripCase::MAX_SIZE - 1) as f64;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (u, v) = (x as f64 / scale, y as f64 / scale);
+                let rgb = [
+                    255.0 * u,
+                    255.0 * v,
+                    127.5 + 100.0 * (3.0 * u + 2.0 * v).sin(),
+                ].map(|value| value.round() as u8);
+                let pixel = &mut pixels[y * pitch + x * format.size()..][..format.size()];
+                match format.rgb_offsets() {
+                    Some((r, g, b)) => {
+                        pixel[r] = rgb[0];
+                        pixel[g] = rgb[1];
+                        pixel[b] = rgb[2];
+                    },
+                    None if format == PixelFormat::GRAY => pixel[0] = rgb[0] / 2 + rgb[1] / 2,
+                    None => pixel.copy_from_slice(&[255 - rgb[0], 255 - rgb[1], 255 - rgb[2], 255 - rgb[2] / 2]),
+                }
+            }
+        }
+        Image { pixels, width: self.width, pitch, height: self.height, format }
+    }
+
+    /// Returns the minimal PSNR in decibels that the round trip must reach with the quality of
+    /// this case.
+    ///
+    /// The floor increases linearly from 20 dB for the worst quality to 40 dB for the best
+    /// quality, which holds for the smooth test images with a margin.
+    pub fn min_psnr(&self) -> f64 {
+        20.0 + 20.0 * self.quality.get() as f64 / 100.0
+    }
+
+    /// Compresses and decompresses the image of this case and checks the invariants of the round
+    /// trip.
+    ///
+    /// Returns the PSNR of the decompressed image, or an error if compression or decompression
+    /// failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an invariant does not hold.
+    #[track_caller]
+    pub fn check(&self) -> Result<f64> {
+        let image = self.image();
+        let mut compressor = Compressor::new()?;
+        compressor.set_quality(self.quality)?;
+        compressor.set_subsamp(self.subsamp);
+        compressor.set_progressive(self.progressive);
+        let jpeg_data = compressor.compress_to_vec(image.as_deref())?;
+
+        let mut decompressor = Decompressor::new()?;
+        let header = decompressor.read_header(&jpeg_data)?;
+        assert_eq!((header.width, header.height), (self.width, self.height), "dimensions of {:?}", self);
+        assert_eq!(header.subsamp, self.subsamp, "subsampling of {:?}", self);
+
+        let mut decompressed = Image {
+            pixels: vec![0; image.pixels.len()],
+            width: header.width,
+            pitch: image.pitch,
+            height: header.height,
+            format: self.format,
+        };
+        decompressor.decompress(&jpeg_data, decompressed.as_deref_mut())?;
+        let psnr = psnr(image.as_deref(), decompressed.as_deref());
+        assert!(psnr >= self.min_psnr(), "PSNR of {:?} is {:.1} dB (minimum is {:.1} dB)", self, psnr, self.min_psnr());
+        Ok(psnr)
+    }
+}
+
+/// Checks `count` round-trip cases generated from seeds derived from `seed` (see
+/// [`RoundTripCase`]).
+///
+/// # Panics
+///
+/// Panics if an invariant does not hold or if a round trip fails; the message contains the seed
+/// of the failing case, so that it can be reproduced with [`RoundTripCase::from_seed()`].
+///
+/// # Example
+///
+/// ```
+/// turbojpeg::testing::check_round_trips(0x5eed, 20);
+/// ```
+#[track_caller]
+pub fn check_round_trips(seed: u64, count: usize) {
+    let mut seeds = SplitMix64(seed);
+    for _ in 0..count {
+        let case_seed = seeds.next();
+        let case = RoundTripCase::from_seed(case_seed);
+        match std::panic::catch_unwind(|| case.check()) {
+            Ok(Ok(_)) => (),
+            Ok(Err(err)) => panic!("round trip of {:?} (seed {:#x}) failed: {}", case, case_seed, err),
+            Err(_) => panic!("invariant of round trip of {:?} (seed {:#x}) does not hold", case, case_seed),
+        }
+    }
+}
+
+/// The SplitMix64 generator, which is good enough for generating test cases.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number below `n` (with a negligible bias).
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::Instant;
+use crate::raw;
+use crate::common::get_error;
+
+/// Emits an event for the creation of a TurboJPEG handle of the given `kind`.
+pub(crate) fn handle_created(kind: &'static str, handle: raw::tjhandle) {
+    if handle.is_null() {
+        let error = unsafe { get_error(handle) };
+        tracing::warn!(kind, %error, "failed to create TurboJPEG handle");
+    } else {
+        tracing::debug!(kind, "created TurboJPEG handle");
+    }
+}
+
+/// Emits an event for a call into TurboJPEG that returned `res` and produced `output_len` bytes.
+pub(crate) fn call_finished(handle: raw::tjhandle, res: libc::c_int, start: Instant, output_len: usize) {
+    let elapsed_us = start.elapsed().as_micros() as u64;
+    if res == 0 {
+        tracing::debug!(elapsed_us, output_len, "TurboJPEG call succeeded");
+    } else {
+        let error = unsafe { get_error(handle) };
+        tracing::warn!(elapsed_us, %error, "TurboJPEG call failed");
+    }
+}
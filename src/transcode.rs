@@ -0,0 +1,83 @@
+use crate::Image;
+use crate::common::{PixelFormat, Result};
+use crate::decompress::decompress;
+
+/// Encoder for another image format (such as AVIF, JPEG XL or WebP) used by [`transcode()`].
+///
+/// Implement this trait with the encoder of your choice (wrap its errors in
+/// [`Error::Backend`][crate::Error::Backend]). Closures `FnMut(Image<&[u8]>) -> Result<Vec<u8>>`
+/// also implement this trait; they receive [`PixelFormat::RGB`] images.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Image, PixelEncoder, PixelFormat};
+///
+/// // an "encoder" that stores the raw RGBA pixels prefixed with the size of the image
+/// struct RawRgba;
+///
+/// impl PixelEncoder for RawRgba {
+///     fn pixel_format(&self) -> PixelFormat {
+///         PixelFormat::RGBA
+///     }
+///
+///     fn encode(&mut self, image: Image<&[u8]>) -> turbojpeg::Result<Vec<u8>> {
+///         let mut data = Vec::new();
+///         data.extend_from_slice(&(image.width as u32).to_le_bytes());
+///         data.extend_from_slice(&(image.height as u32).to_le_bytes());
+///         data.extend_from_slice(image.pixels);
+///         Ok(data)
+///     }
+/// }
+///
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let data = turbojpeg::transcode(&jpeg_data, &mut RawRgba)?;
+/// assert_eq!(data.len(), 8 + 384 * 256 * 4);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait PixelEncoder {
+    /// Returns the pixel format of the images passed to [`encode()`][Self::encode]. The default
+    /// implementation returns [`PixelFormat::RGB`].
+    fn pixel_format(&self) -> PixelFormat {
+        PixelFormat::RGB
+    }
+
+    /// Encodes the decompressed `image`, which has no padding between the rows.
+    fn encode(&mut self, image: Image<&[u8]>) -> Result<Vec<u8>>;
+}
+
+impl<F> PixelEncoder for F
+    where F: FnMut(Image<&[u8]>) -> Result<Vec<u8>>
+{
+    fn encode(&mut self, image: Image<&[u8]>) -> Result<Vec<u8>> {
+        self(image)
+    }
+}
+
+/// Decompresses a JPEG image with TurboJPEG and encodes it into another format with `encoder`.
+///
+/// This is a bridge to encoders of other image formats: the JPEG image is decompressed into the
+/// pixel format requested by [`PixelEncoder::pixel_format()`] and the pixels are passed to
+/// [`PixelEncoder::encode()`]. Note that this always re-encodes the pixels; lossless
+/// recompression of the JPEG coefficients (as offered by JPEG XL) must be done by the encoder from
+/// the original JPEG data.
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Image, PixelFormat};
+///
+/// // re-encode the image into JPEG with lower quality
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let data = turbojpeg::transcode(&jpeg_data, &mut |image: Image<&[u8]>| {
+///     turbojpeg::compress(image, 50, turbojpeg::Subsamp::Sub2x2).map(|buf| buf.to_vec())
+/// })?;
+/// assert!(data.len() < jpeg_data.len());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn transcode<E: PixelEncoder + ?Sized>(jpeg_data: &[u8], encoder: &mut E) -> Result<Vec<u8>> {
+    let image = decompress(jpeg_data, encoder.pixel_format())?;
+    encoder.encode(image.as_deref())
+}
@@ -1,7 +1,13 @@
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use std::convert::TryInto as _;
+use crate::batch::{BatchIter, BatchOrder};
 use crate::buf::{OwnedBuf, OutputBuf};
-use crate::common::{Error, Result, get_error};
+use crate::cancel::{CancelToken, check_cancel};
+use crate::concurrency;
+use crate::common::{Colorspace, Error, Result, Subsamp, get_error, is_fatal_error, recreate_handle};
+use crate::metadata::{MarkerKind, StripMetadata, remove_metadata_in_place};
+use crate::metrics;
 
 /// Transforms JPEG images without recompression.
 ///
@@ -11,8 +17,12 @@ use crate::common::{Error, Result, get_error};
 #[doc(alias = "tjhandle")]
 pub struct Transformer {
     handle: raw::tjhandle,
+    poisoned: bool,
+    cancel: Option<CancelToken>,
 }
 
+unsafe impl Send for Transformer {}
+
 /// Lossless transform of a JPEG image.
 ///
 /// When constructing an instance, you may start from the default transform
@@ -28,19 +38,27 @@ pub struct Transformer {
 /// ```
 ///
 /// Rotate image counterclockwise by 90 degrees and fail if the transform is not
-/// [perfect][Self::perfect]:
+/// [perfect][EdgeHandling::Perfect]:
 ///
 /// ```
-/// # use turbojpeg::{Transform, TransformOp};
-/// let transform = Transform { op: TransformOp::Rot270, perfect: true, .. Transform::default() };
+/// # use turbojpeg::{Transform, TransformOp, EdgeHandling};
+/// let transform = Transform {
+///     op: TransformOp::Rot270,
+///     edge: EdgeHandling::Perfect,
+///     .. Transform::default()
+/// };
 /// ```
 ///
-/// Flip image vertically and [trim][Self::trim] the image on the right edge if the transform is
-/// imperfect:
+/// Flip image vertically and [trim][EdgeHandling::Trim] the image on the bottom edge if the
+/// transform is imperfect:
 ///
 /// ```
-/// # use turbojpeg::{Transform, TransformOp};
-/// let transform = Transform { op: TransformOp::Vflip, trim: true, .. Transform::default() };
+/// # use turbojpeg::{Transform, TransformOp, EdgeHandling};
+/// let transform = Transform {
+///     op: TransformOp::Vflip,
+///     edge: EdgeHandling::Trim,
+///     .. Transform::default()
+/// };
 /// ```
 ///
 /// Crop image to size (200, 100) starting at pixel (16, 32), without applying any transform:
@@ -53,7 +71,23 @@ pub struct Transformer {
 ///     .. Transform::default()
 /// };
 /// ```
+///
+/// With the `serde` feature, transforms can be loaded from configuration files (missing fields
+/// take their default values):
+///
+/// ```
+/// # #[cfg(feature = "serde")] {
+/// # use turbojpeg::{Transform, TransformOp, EdgeHandling};
+/// let transform: Transform = serde_json::from_str(r#"{"op": "Rot90", "edge": "Trim"}"#)?;
+/// assert_eq!(transform.op, TransformOp::Rot90);
+/// assert_eq!(transform.edge, EdgeHandling::Trim);
+/// assert!(!transform.progressive);
+/// # }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 #[doc(alias = "tjtransform")]
 pub struct Transform {
     /// Transform operation that is applied.
@@ -63,26 +97,14 @@ pub struct Transform {
     #[doc(alias = "TJXOPT_CROP")]
     pub crop: Option<TransformCrop>,
 
-    /// Return an error if the transform is not perfect.
-    ///
-    /// Lossless transforms operate on MCU blocks, whose size depends on the level of chrominance
-    /// subsampling used (see [`Subsamp::mcu_width()`][crate::Subsamp::mcu_width] and
-    /// [`Subsamp::mcu_height()`][crate::Subsamp::mcu_height]). If the image width or height is not
-    /// evenly divisible by the MCU block size, then there will be partial MCU blocks on the right
-    /// and bottom edges. It is not possible to move these partial MCU blocks to the top or left of
-    /// the image, so any transform that would require that is "imperfect".
-    ///
-    /// If this option is not specified and [`trim`][Self::trim] is not enabled, then any partial
-    /// MCU blocks that cannot be transformed will be left in place, which will create odd-looking
-    /// strips on the right or bottom edge of the image.
-    #[doc(alias = "TJXOPT_PERFECT")]
-    pub perfect: bool,
-
-    /// Discard any partial MCU blocks that cannot be transformed.
-    #[doc(alias = "TJXOPT_TRIM")]
-    pub trim: bool,
+    /// How to handle partial MCU blocks on the edges of the image (see [`EdgeHandling`]).
+    pub edge: EdgeHandling,
 
     /// Discard the color data in the input image and produce a grayscale output image.
+    ///
+    /// This is lossless with respect to the luminance: the chrominance components are simply
+    /// dropped, so the image is not recompressed. See also
+    /// [`Transformer::to_grayscale()`].
     #[doc(alias = "TJXOPT_GRAY")]
     pub gray: bool,
 
@@ -95,14 +117,109 @@ pub struct Transform {
     #[doc(alias = "TJXOPT_PROGRESSIVE")]
     pub progressive: bool,
 
-    /// Do not copy any extra markers (including EXIF and ICC profile data) from the input image to
-    /// the output image.
+    /// Extra markers (such as EXIF and ICC profile data) that are copied from the input image to
+    /// the output image (see [`MarkerCopy`]).
+    pub markers: MarkerCopy,
+
+    /// Remove selected metadata (such as EXIF or XMP data) from the output image.
+    ///
+    /// This is applied after [`markers`][Self::markers]. See also
+    /// [`strip_metadata()`][crate::strip_metadata].
+    pub strip: StripMetadata,
+}
+
+/// Handling of partial MCU blocks in lossless transforms.
+///
+/// Lossless transforms operate on MCU blocks, whose size depends on the level of chrominance
+/// subsampling used (see [`Subsamp::mcu_width()`][crate::Subsamp::mcu_width] and
+/// [`Subsamp::mcu_height()`][crate::Subsamp::mcu_height]). If the image width or height is not
+/// evenly divisible by the MCU block size, then there will be partial MCU blocks on the right and
+/// bottom edges. It is not possible to move these partial MCU blocks to the top or left of the
+/// image, so any transform that would require that is "imperfect".
+///
+/// # Example
+///
+/// ```
+/// // create an image whose size is not divisible by the MCU size (16x16 pixels)
+/// let image = turbojpeg::Image::mandelbrot(500, 500, turbojpeg::PixelFormat::RGB);
+/// let jpeg_data = turbojpeg::compress(image.as_deref(), 95, turbojpeg::Subsamp::Sub2x2)?;
+///
+/// // rotation by 180 degrees would need to move the partial MCU blocks, so it is not perfect
+/// let transform = turbojpeg::Transform {
+///     op: turbojpeg::TransformOp::Rot180,
+///     edge: turbojpeg::EdgeHandling::Perfect,
+///     .. turbojpeg::Transform::default()
+/// };
+/// let res = turbojpeg::transform(&transform, &jpeg_data);
+/// assert!(matches!(res, Err(turbojpeg::Error::ImperfectTransform(turbojpeg::TransformOp::Rot180))));
+///
+/// // with trimming, the partial MCU blocks are discarded
+/// let transform = turbojpeg::Transform { edge: turbojpeg::EdgeHandling::Trim, .. transform };
+/// let rotated_data = turbojpeg::transform(&transform, &jpeg_data)?;
+/// let header = turbojpeg::read_header(&rotated_data)?;
+/// assert_eq!((header.width, header.height), (496, 496));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EdgeHandling {
+    /// Leave the partial MCU blocks that cannot be transformed in place.
+    ///
+    /// This creates odd-looking strips on the right or bottom edge of the image, but it does not
+    /// lose any pixels. This is the default.
+    #[default]
+    Keep,
+
+    /// Discard any partial MCU blocks that cannot be transformed.
+    #[doc(alias = "TJXOPT_TRIM")]
+    Trim,
+
+    /// Return [`Error::ImperfectTransform`] if the transform is not perfect.
+    #[doc(alias = "TJXOPT_PERFECT")]
+    Perfect,
+}
+
+/// Policy for copying extra markers in lossless transforms.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file (the image contains EXIF data, XMP data and an ICC profile)
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // rotate the image, but keep only the color profile
+/// let transform = turbojpeg::Transform {
+///     op: turbojpeg::TransformOp::Rot90,
+///     markers: turbojpeg::MarkerCopy::Selected(vec![turbojpeg::MarkerKind::Icc]),
+///     .. turbojpeg::Transform::default()
+/// };
+/// let rotated_data = turbojpeg::transform(&transform, &jpeg_data)?;
+/// assert!(rotated_data.len() < jpeg_data.len() - 10_000);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MarkerCopy {
+    /// Copy all extra markers. This is the default.
+    #[default]
+    All,
+
+    /// Do not copy any extra markers.
     #[doc(alias = "TJXOPT_COPYNONE")]
-    pub copy_none: bool,
+    None,
+
+    /// Copy only the markers of the given kinds.
+    ///
+    /// The JFIF and Adobe markers are always kept, because they determine how the image data is
+    /// decoded.
+    Selected(Vec<MarkerKind>),
 }
 
 /// Transform operation.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[doc(alias = "TJXOP")]
 #[repr(u32)]
 pub enum TransformOp {
@@ -113,14 +230,14 @@ pub enum TransformOp {
     /// Flip (mirror) image horizontally.
     ///
     /// This transform is imperfect if there are any partial MCU blocks on the right edge (see
-    /// [`Transform::perfect`].)
+    /// [`EdgeHandling`].)
     #[doc(alias = "TJXOP_HFLIP")]
     Hflip = raw::TJXOP_TJXOP_HFLIP,
 
     /// Flip (mirror) image vertically.
     ///
     /// This transform is imperfect if there are any partial MCU blocks on the bottom edge (see
-    /// [`Transform::perfect`].)
+    /// [`EdgeHandling`].)
     #[doc(alias = "TJXOP_VFLIP")]
     Vflip = raw::TJXOP_TJXOP_VFLIP,
 
@@ -133,28 +250,28 @@ pub enum TransformOp {
     /// Transverse transpose image (flip/mirror along upper right to lower left axis).
     ///
     /// This transform is imperfect if there are any partial MCU blocks in the image (see
-    /// [`Transform::perfect`].)
+    /// [`EdgeHandling`].)
     #[doc(alias = "TJXOP_TRANSVERSE")]
     Transverse = raw::TJXOP_TJXOP_TRANSVERSE,
 
     /// Rotate image clockwise by 90 degrees.
     ///
     /// This transform is imperfect if there are any partial MCU blocks on the bottom edge (see
-    /// [`Transform::perfect`].)
+    /// [`EdgeHandling`].)
     #[doc(alias = "TJXOP_ROT90")]
     Rot90 = raw::TJXOP_TJXOP_ROT90,
 
     /// Rotate image 180 degrees.
     ///
     /// This transform is imperfect if there are any partial MCU blocks in the image (see
-    /// [`Transform::perfect`].)
+    /// [`EdgeHandling`].)
     #[doc(alias = "TJXOP_ROT180")]
     Rot180 = raw::TJXOP_TJXOP_ROT180,
 
     /// Rotate image counter-clockwise by 90 degrees.
     ///
     /// This transform is imperfect if there are any partial MCU blocks on the right edge (see
-    /// [`Transform::perfect`].)
+    /// [`EdgeHandling`].)
     Rot270 = raw::TJXOP_TJXOP_ROT270,
 }
 
@@ -164,6 +281,19 @@ impl Default for TransformOp {
     }
 }
 
+/// Entropy coding of JPEG images produced by [`Transformer::reformat()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EntropyCoding {
+    /// Baseline (sequential) entropy coding, where the image is stored in a single scan. This is
+    /// supported by all decoders.
+    Baseline,
+    /// Progressive entropy coding, where the image is stored in multiple scans that gradually
+    /// refine the image. This usually produces smaller files.
+    #[doc(alias = "TJXOPT_PROGRESSIVE")]
+    Progressive,
+}
+
 /// Transform cropping region.
 ///
 /// The [`x`][Self::x] and [`y`][Self::y] position of the region must be aligned on MCU boundaries.
@@ -172,6 +302,7 @@ impl Default for TransformOp {
 ///
 /// The default instance performs no cropping.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[doc(alias = "tjregion")]
 pub struct TransformCrop {
     /// Left boundary of the region. This must be divisible by the MCU width (see
@@ -193,14 +324,41 @@ impl Transformer {
     pub fn new() -> Result<Transformer> {
         unsafe {
             let handle = raw::tjInitTransform();
+            #[cfg(feature = "tracing")]
+            crate::trace::handle_created("transformer", handle);
             if !handle.is_null() {
-                Ok(Transformer { handle })
+                Ok(Transformer { handle, poisoned: false, cancel: None })
             } else {
                 Err(get_error(handle))
             }
         }
     }
 
+    /// Returns true if the last transformation failed with a fatal error of libjpeg.
+    ///
+    /// libjpeg may leave its state inconsistent after a fatal error, so the transformer does not
+    /// reuse the underlying TurboJPEG instance: it replaces the instance at the start of the next
+    /// transformation, which clears this flag.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Replaces the TurboJPEG instance if the last transformation failed with a fatal error.
+    fn recover(&mut self) -> Result<()> {
+        if self.poisoned {
+            unsafe { recreate_handle(&mut self.handle, raw::tjInitTransform, "transformer")? };
+            self.poisoned = false;
+        }
+        Ok(())
+    }
+
+    /// Set the token that can be used to cancel the transformation (see [`CancelToken`]).
+    ///
+    /// Use `None` to remove the token.
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel = token;
+    }
+
     /// Apply a transformation to the compressed JPEG.
     ///
     /// This is the main transformation method, which gives you full control of the output buffer. If
@@ -218,7 +376,7 @@ impl Transformer {
     /// // define the transformation: flip vertically, trim partial MCU blocks on the bottom edge 
     /// let transform = turbojpeg::Transform {
     ///     op: turbojpeg::TransformOp::Vflip,
-    ///     trim: true,
+    ///     edge: turbojpeg::EdgeHandling::Trim,
     ///     .. turbojpeg::Transform::default()
     /// };
     ///
@@ -240,12 +398,28 @@ impl Transformer {
         jpeg_data: &[u8],
         output: &mut OutputBuf,
     ) -> Result<()> {
+        check_cancel(&self.cancel)?;
+        self.recover()?;
+
+        let strip = transform.strip;
+        let selected = match transform.markers {
+            MarkerCopy::Selected(ref kinds) => Some(kinds.clone()),
+            _ => None,
+        };
+        let op = transform.op;
+        if transform.edge == EdgeHandling::Perfect && !self.is_perfect(transform, jpeg_data) {
+            return Err(Error::ImperfectTransform(op))
+        }
         let mut options = 0;
-        if transform.perfect { options |= raw::TJXOPT_PERFECT }
-        if transform.trim { options |= raw::TJXOPT_TRIM }
+        match transform.edge {
+            EdgeHandling::Keep => {},
+            EdgeHandling::Trim => options |= raw::TJXOPT_TRIM,
+            // checked above, TurboJPEG checks it again in case its rules differ
+            EdgeHandling::Perfect => options |= raw::TJXOPT_PERFECT,
+        }
         if transform.gray { options |= raw::TJXOPT_GRAY }
         if transform.progressive { options |= raw::TJXOPT_PROGRESSIVE }
-        if transform.copy_none { options |= raw::TJXOPT_COPYNONE }
+        if transform.markers == MarkerCopy::None { options |= raw::TJXOPT_COPYNONE }
 
         let mut region = raw::tjregion {
             x: 0, y: 0,
@@ -263,15 +437,39 @@ impl Transformer {
             options |= raw::TJXOPT_CROP;
         }
 
+        unsafe extern "C" fn cancel_filter(
+            _coeffs: *mut libc::c_short,
+            _array_region: raw::tjregion,
+            _plane_region: raw::tjregion,
+            _component_index: libc::c_int,
+            _transform_index: libc::c_int,
+            transform: *mut raw::tjtransform,
+        ) -> libc::c_int {
+            let token = &*((*transform).data as *const CancelToken);
+            if token.is_cancelled() { -1 } else { 0 }
+        }
+
+        // TurboJPEG calls the filter for every row of blocks between decoding and encoding the
+        // coefficients, which lets a cancelled token abort the transformation in the middle
+        let cancel = self.cancel.as_ref().map_or(ptr::null(), |token| token as *const CancelToken);
         let mut transform = raw::tjtransform {
             r: region,
             op: transform.op as libc::c_int,
             options: options as libc::c_int,
-            data: ptr::null_mut(),
-            customFilter: None,
+            data: cancel as *mut libc::c_void,
+            customFilter: if cancel.is_null() { None } else { Some(cancel_filter) },
         };
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "tjTransform",
+            op = ?op, crop = ?transform.r, options = transform.options, jpeg_len = jpeg_data.len(),
+        ).entered();
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+
         let mut output_len = output.len as libc::c_ulong;
+        let permit = concurrency::acquire();
         let res = unsafe {
             raw::tjTransform(
                 self.handle,
@@ -281,19 +479,128 @@ impl Transformer {
                 if output.is_owned { 0 } else { raw::TJFLAG_NOREALLOC } as libc::c_int,
             )
         };
+        drop(permit);
+        #[cfg(feature = "tracing")]
+        crate::trace::call_finished(self.handle, res, start, output_len as usize);
 
         output.len = output_len as usize;
 
         if res != 0 {
+            self.poisoned = unsafe { is_fatal_error(self.handle) };
+            check_cancel(&self.cancel)?;
             Err(unsafe { get_error(self.handle) })
         } else if output.ptr.is_null() {
             output.len = 0;
             Err(Error::Null())
+        } else if selected.is_some() || !strip.is_none() {
+            output.len = remove_metadata_in_place(output, |kind| {
+                strip.should_strip(kind) ||
+                    selected.as_ref().map(|kinds| !kinds.contains(&kind)).unwrap_or(false)
+            })?;
+            Ok(())
         } else {
             Ok(())
         }
     }
 
+    /// Returns true if the transform does not need to move the partial MCU blocks on the right or
+    /// bottom edge of the image (the check of `jtransform_perfect_transform()` in libjpeg-turbo,
+    /// which considers the whole image even if the transform crops it). Returns true if the header
+    /// cannot be read, so that TurboJPEG reports the error.
+    fn is_perfect(&mut self, transform: &Transform, jpeg_data: &[u8]) -> bool {
+        let jpeg_data_len = match jpeg_data.len().try_into() {
+            Ok(len) => len,
+            Err(_) => return true,
+        };
+        let (mut width, mut height, mut subsamp, mut colorspace) = (0, 0, 0, 0);
+        let res = unsafe {
+            raw::tjDecompressHeader3(
+                self.handle,
+                jpeg_data.as_ptr(), jpeg_data_len,
+                &mut width, &mut height, &mut subsamp, &mut colorspace,
+            )
+        };
+        let subsamp = match Subsamp::from_u32(subsamp as u32) {
+            Ok(subsamp) if res == 0 => subsamp,
+            _ => return true,
+        };
+
+        // grayscale output is transformed in MCUs of a single block
+        let gray = transform.gray && colorspace as u32 == Colorspace::YCbCr as u32;
+        let (mcu_width, mcu_height) = if gray { (8, 8) } else { subsamp.mcu_size() };
+        let partial_x = !(width as usize).is_multiple_of(mcu_width);
+        let partial_y = !(height as usize).is_multiple_of(mcu_height);
+        match transform.op {
+            TransformOp::None | TransformOp::Transpose => true,
+            TransformOp::Hflip | TransformOp::Rot270 => !partial_x,
+            TransformOp::Vflip | TransformOp::Rot90 => !partial_y,
+            TransformOp::Transverse | TransformOp::Rot180 => !partial_x && !partial_y,
+        }
+    }
+
+    /// Losslessly rewrite the JPEG image with the given entropy coding.
+    ///
+    /// This converts progressive JPEG images into baseline images and vice versa, without
+    /// decompressing and requantizing the image (it is equivalent to `jpegtran` with or without
+    /// `-progressive`). You can use this to make progressive images readable by old decoders or to
+    /// reduce the size of baseline images. Extra markers (such as EXIF data) are preserved.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file (the image is progressive)
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // rewrite the image as baseline JPEG
+    /// let mut transformer = turbojpeg::Transformer::new()?;
+    /// let baseline_data = transformer.reformat(&jpeg_data, turbojpeg::EntropyCoding::Baseline)?;
+    ///
+    /// let header = turbojpeg::read_header(&baseline_data)?;
+    /// assert_eq!(header.process, turbojpeg::CodingProcess::Baseline);
+    /// assert!(!header.multi_scan);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn reformat(&mut self, jpeg_data: &[u8], coding: EntropyCoding) -> Result<OwnedBuf> {
+        let transform = Transform {
+            progressive: coding == EntropyCoding::Progressive,
+            .. Transform::default()
+        };
+        self.transform_to_owned(&transform, jpeg_data)
+    }
+
+    /// Convert the JPEG image to grayscale without recompression.
+    ///
+    /// The chrominance components are discarded and the luminance is copied unchanged, which is
+    /// faster than decompressing and compressing the image and does not lose any quality. This is
+    /// useful for example in document processing pipelines. Extra markers (such as EXIF data) are
+    /// preserved.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // read JPEG data from file
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    ///
+    /// // convert the image to grayscale
+    /// let mut transformer = turbojpeg::Transformer::new()?;
+    /// let gray_data = transformer.to_grayscale(&jpeg_data)?;
+    ///
+    /// let header = turbojpeg::read_header(&gray_data)?;
+    /// assert_eq!(header.colorspace, turbojpeg::Colorspace::Gray);
+    /// assert_eq!(header.components, 1);
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[doc(alias = "TJXOPT_GRAY")]
+    pub fn to_grayscale(&mut self, jpeg_data: &[u8]) -> Result<OwnedBuf> {
+        let transform = Transform {
+            gray: true,
+            .. Transform::default()
+        };
+        self.transform_to_owned(&transform, jpeg_data)
+    }
+
     /// Transforms the `image` into an owned buffer.
     ///
     /// This method automatically allocates the memory and avoids needless copying.
@@ -334,6 +641,177 @@ impl Transformer {
         Ok(buf.len())
     }
 
+    /// Losslessly rewrites the JPEG image and lets `filter` modify the quantized DCT coefficients.
+    ///
+    /// The filter is called for every row of 8x8 blocks in every component, with the index of the
+    /// component, the index of the row and the coefficients of the blocks in the row (in natural
+    /// order, not in zigzag order). Rows of subsampled components cover more than 8 rows of pixels.
+    pub(crate) fn filter_coefficients<F>(
+        &mut self,
+        jpeg_data: &[u8],
+        output: &mut OutputBuf,
+        filter: F,
+    ) -> Result<()>
+        where F: FnMut(usize, usize, &mut [[i16; 64]])
+    {
+        check_cancel(&self.cancel)?;
+        self.recover()?;
+
+        struct FilterData<F> {
+            filter: F,
+            cancel: Option<CancelToken>,
+            panic: Option<Box<dyn std::any::Any + Send>>,
+        }
+
+        unsafe extern "C" fn callback<F>(
+            coeffs: *mut libc::c_short,
+            array_region: raw::tjregion,
+            _plane_region: raw::tjregion,
+            component_index: libc::c_int,
+            _transform_index: libc::c_int,
+            transform: *mut raw::tjtransform,
+        ) -> libc::c_int
+            where F: FnMut(usize, usize, &mut [[i16; 64]])
+        {
+            let data = &mut *((*transform).data as *mut FilterData<F>);
+            if data.panic.is_some() || check_cancel(&data.cancel).is_err() {
+                return -1
+            }
+            let blocks = std::slice::from_raw_parts_mut(
+                coeffs as *mut [i16; 64], array_region.w as usize / 8);
+            let row = array_region.y as usize / 8;
+            let filter = &mut data.filter;
+            match panic::catch_unwind(AssertUnwindSafe(|| filter(component_index as usize, row, blocks))) {
+                Ok(()) => 0,
+                Err(payload) => {
+                    // the panic is resumed after TurboJPEG returns, it must not unwind through C
+                    data.panic = Some(payload);
+                    -1
+                },
+            }
+        }
+
+        let mut data = FilterData { filter, cancel: self.cancel.clone(), panic: None };
+        let mut transform = raw::tjtransform {
+            r: raw::tjregion { x: 0, y: 0, w: 0, h: 0 },
+            op: raw::TJXOP_TJXOP_NONE as libc::c_int,
+            options: 0,
+            data: &mut data as *mut FilterData<F> as *mut libc::c_void,
+            customFilter: Some(callback::<F>),
+        };
+
+        let mut output_len = output.len as libc::c_ulong;
+        let permit = concurrency::acquire();
+        let res = unsafe {
+            raw::tjTransform(
+                self.handle,
+                jpeg_data.as_ptr(), jpeg_data.len() as libc::c_ulong,
+                1, &mut output.ptr, &mut output_len,
+                &mut transform,
+                if output.is_owned { 0 } else { raw::TJFLAG_NOREALLOC } as libc::c_int,
+            )
+        };
+        drop(permit);
+        output.len = output_len as usize;
+
+        if res != 0 {
+            // a panic in the filter also aborts libjpeg with a fatal error
+            self.poisoned = unsafe { is_fatal_error(self.handle) } || data.panic.is_some();
+        }
+        if let Some(payload) = data.panic {
+            panic::resume_unwind(payload)
+        } else if res != 0 {
+            check_cancel(&self.cancel)?;
+            Err(unsafe { get_error(self.handle) })
+        } else if output.ptr.is_null() {
+            output.len = 0;
+            Err(Error::Null())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Applies the same `transform` to many JPEG images in parallel.
+    ///
+    /// The images are distributed among [`std::thread::available_parallelism()`] threads, each
+    /// of them with its own `Transformer`. Returns the results in the same order as `inputs`; a
+    /// failure of one image does not stop the transformation of the others. This is useful for
+    /// photo library tools that rotate or strip metadata from thousands of images.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let inputs = vec![jpeg_data.clone(), b"not a jpeg".to_vec(), jpeg_data];
+    ///
+    /// let transform = turbojpeg::Transform {
+    ///     op: turbojpeg::TransformOp::Rot90,
+    ///     .. turbojpeg::Transform::default()
+    /// };
+    /// let outputs = turbojpeg::Transformer::transform_batch(&inputs, &transform);
+    /// assert_eq!(outputs.len(), 3);
+    /// assert!(outputs[1].is_err());
+    ///
+    /// let header = turbojpeg::read_header(outputs[2].as_ref().unwrap())?;
+    /// assert_eq!((header.width, header.height), (256, 384));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn transform_batch<D>(inputs: &[D], transform: &Transform) -> Vec<Result<OwnedBuf>>
+        where D: AsRef<[u8]> + Sync
+    {
+        crate::batch::run_ordered("transform_batch", inputs, 0, Transformer::new, |transformer, jpeg_data| {
+            transformer.transform_batch_item(transform, jpeg_data.as_ref())
+        })
+    }
+
+    /// Applies the same `transform` to many JPEG images in background threads and yields the
+    /// results as they complete.
+    ///
+    /// This is a streaming variant of [`transform_batch()`][Self::transform_batch]: the `inputs`
+    /// are consumed lazily, and the results are yielded by the returned [`BatchIter`] as pairs of
+    /// the index of the input and its result, either in the order of the inputs or in the order
+    /// of completion (see [`BatchOrder`]). This lets the next stage of a pipeline start working
+    /// before the whole batch is finished.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+    /// let inputs = vec![jpeg_data; 10];
+    ///
+    /// let transform = turbojpeg::Transform {
+    ///     op: turbojpeg::TransformOp::Rot180,
+    ///     .. turbojpeg::Transform::default()
+    /// };
+    /// let results = turbojpeg::Transformer::transform_batch_iter(
+    ///     inputs, &transform, turbojpeg::BatchOrder::Unordered);
+    ///
+    /// let mut done = vec![false; 10];
+    /// for (index, res) in results {
+    ///     let rotated = res?;
+    ///     // ... write `rotated` to a file
+    ///     # let _ = rotated;
+    ///     done[index] = true;
+    /// }
+    /// assert!(done.iter().all(|&done| done));
+    ///
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn transform_batch_iter<I>(inputs: I, transform: &Transform, order: BatchOrder) -> BatchIter<OwnedBuf>
+        where I: IntoIterator, I::Item: AsRef<[u8]>, I::IntoIter: Send + 'static
+    {
+        let transform = transform.clone();
+        BatchIter::spawn("transform_batch", inputs, 0, 0, order, None, Transformer::new, move |transformer, jpeg_data: I::Item| {
+            transformer.transform_batch_item(&transform, jpeg_data.as_ref())
+        })
+    }
+
+    fn transform_batch_item(&mut self, transform: &Transform, jpeg_data: &[u8]) -> Result<OwnedBuf> {
+        let res = self.transform_to_owned(transform, jpeg_data);
+        metrics::record_bytes("transform_batch", jpeg_data.len(), res.as_ref().ok().map(|output| output.len()));
+        res
+    }
 }
 
 impl Drop for Transformer {
@@ -0,0 +1,265 @@
+use crate::{OwnedBuf, Transformer};
+use crate::common::{Error, Result};
+use crate::metadata::StripMetadata;
+use crate::transform::{Transform, TransformOp, TransformCrop, EdgeHandling, MarkerCopy};
+
+/// Chain of lossless transform operations that is executed in a single pass.
+///
+/// TurboJPEG can apply only a single [`TransformOp`] and a single crop in one
+/// [`Transformer::transform()`] call, and the crop region is always specified in the coordinates
+/// of the transformed image. This type lets you describe the operations in the order in which you
+/// think about them (for example, "crop, then rotate, then crop again"), and it composes them
+/// into a single [`Transform`]: the rotations and flips are combined into one operation and the
+/// crop regions are remapped into the coordinates of the output image and nested (every crop
+/// selects a region of the image cropped by the previous crops).
+///
+/// The remapping assumes that the transforms are perfect (see [`EdgeHandling`]); partial MCU
+/// blocks on the edges may shift the remapped crop regions.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file (the image has 384x256 pixels)
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// // crop the left half of the image, rotate it, flip it and strip the metadata
+/// let pipeline = turbojpeg::TransformPipeline::new()
+///     .crop(turbojpeg::TransformCrop { x: 0, y: 0, width: Some(192), height: None })
+///     .op(turbojpeg::TransformOp::Rot90)
+///     .op(turbojpeg::TransformOp::Hflip)
+///     .strip(turbojpeg::StripMetadata::all());
+///
+/// // the pipeline is executed as a single transform
+/// let transform = pipeline.to_transform(&jpeg_data)?;
+/// assert_eq!(transform.op, turbojpeg::TransformOp::Transpose);
+/// assert_eq!(transform.crop, Some(turbojpeg::TransformCrop {
+///     x: 0, y: 0, width: Some(256), height: Some(192),
+/// }));
+///
+/// let mut transformer = turbojpeg::Transformer::new()?;
+/// let output_data = transformer.transform_pipeline(&pipeline, &jpeg_data)?;
+/// let header = turbojpeg::read_header(&output_data)?;
+/// assert_eq!((header.width, header.height), (256, 192));
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TransformPipeline {
+    steps: Vec<Step>,
+    gray: bool,
+    progressive: bool,
+    edge: EdgeHandling,
+    markers: MarkerCopy,
+    strip: StripMetadata,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Step {
+    Op(TransformOp),
+    Crop(TransformCrop),
+}
+
+impl TransformPipeline {
+    /// Create an empty pipeline, which copies the image without changes.
+    pub fn new() -> TransformPipeline {
+        TransformPipeline::default()
+    }
+
+    /// Append a rotation or flip operation.
+    pub fn op(mut self, op: TransformOp) -> TransformPipeline {
+        self.steps.push(Step::Op(op));
+        self
+    }
+
+    /// Append a crop, specified in the coordinates of the image produced by the previous steps.
+    pub fn crop(mut self, crop: TransformCrop) -> TransformPipeline {
+        self.steps.push(Step::Crop(crop));
+        self
+    }
+
+    /// Convert the output image to grayscale (see [`Transform::gray`]).
+    pub fn gray(mut self) -> TransformPipeline {
+        self.gray = true;
+        self
+    }
+
+    /// Use progressive entropy coding in the output image (see [`Transform::progressive`]).
+    pub fn progressive(mut self) -> TransformPipeline {
+        self.progressive = true;
+        self
+    }
+
+    /// Set the handling of partial MCU blocks (see [`Transform::edge`]).
+    pub fn edge(mut self, edge: EdgeHandling) -> TransformPipeline {
+        self.edge = edge;
+        self
+    }
+
+    /// Set the markers copied to the output image (see [`Transform::markers`]).
+    pub fn markers(mut self, markers: MarkerCopy) -> TransformPipeline {
+        self.markers = markers;
+        self
+    }
+
+    /// Remove metadata from the output image (see [`Transform::strip`]).
+    pub fn strip(mut self, strip: StripMetadata) -> TransformPipeline {
+        self.strip = strip;
+        self
+    }
+
+    /// Compose the pipeline into a single [`Transform`] for the JPEG image in `jpeg_data`.
+    ///
+    /// The JPEG header is read to obtain the image size, which is needed to remap the crop regions.
+    /// Returns [`Error::InvalidCrop`] if a crop region is outside of the image produced by the
+    /// previous steps.
+    pub fn to_transform(&self, jpeg_data: &[u8]) -> Result<Transform> {
+        let header = crate::read_header(jpeg_data)?;
+        self.to_transform_for_size(header.width, header.height)
+    }
+
+    /// Compose the pipeline into a single [`Transform`] for an image of size `width` x `height`.
+    ///
+    /// See [`to_transform()`][Self::to_transform] for details.
+    pub fn to_transform_for_size(&self, width: usize, height: usize) -> Result<Transform> {
+        // the size of the transformed (uncropped) image, and the accumulated crop region in its
+        // coordinates
+        let mut dims = (width, height);
+        let mut op = D4::from_op(TransformOp::None);
+        let mut crop: Option<Rect> = None;
+
+        for step in self.steps.iter() {
+            match *step {
+                Step::Op(step_op) => {
+                    let step_op = D4::from_op(step_op);
+                    crop = crop.map(|rect| step_op.map_rect(rect, dims));
+                    dims = step_op.map_dims(dims);
+                    op = op.then(step_op);
+                },
+                Step::Crop(step_crop) => {
+                    // the crop is relative to the image cropped by the previous crops
+                    let view = match crop {
+                        Some(crop) => crop,
+                        None => Rect { x: 0, y: 0, width: dims.0, height: dims.1 },
+                    };
+                    let rect = Rect::from_crop(step_crop, (view.width, view.height))?;
+                    crop = Some(Rect { x: view.x + rect.x, y: view.y + rect.y, ..rect });
+                },
+            }
+        }
+
+        Ok(Transform {
+            op: op.to_op(),
+            crop: crop.map(|rect| TransformCrop {
+                x: rect.x,
+                y: rect.y,
+                width: Some(rect.width),
+                height: Some(rect.height),
+            }),
+            edge: self.edge,
+            gray: self.gray,
+            progressive: self.progressive,
+            markers: self.markers.clone(),
+            strip: self.strip,
+        })
+    }
+}
+
+impl Transformer {
+    /// Apply the transform `pipeline` to the JPEG image in `jpeg_data` in a single pass.
+    ///
+    /// See [`TransformPipeline`] for details.
+    pub fn transform_pipeline(&mut self, pipeline: &TransformPipeline, jpeg_data: &[u8]) -> Result<OwnedBuf> {
+        let transform = pipeline.to_transform(jpeg_data)?;
+        self.transform_to_owned(&transform, jpeg_data)
+    }
+}
+
+/// Element of the dihedral group D4 (the symmetries of a rectangle), represented as an optional
+/// transposition followed by optional horizontal and vertical flips.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct D4 {
+    transpose: bool,
+    hflip: bool,
+    vflip: bool,
+}
+
+impl D4 {
+    fn from_op(op: TransformOp) -> D4 {
+        let (transpose, hflip, vflip) = match op {
+            TransformOp::None => (false, false, false),
+            TransformOp::Hflip => (false, true, false),
+            TransformOp::Vflip => (false, false, true),
+            TransformOp::Rot180 => (false, true, true),
+            TransformOp::Transpose => (true, false, false),
+            TransformOp::Rot90 => (true, true, false),
+            TransformOp::Rot270 => (true, false, true),
+            TransformOp::Transverse => (true, true, true),
+        };
+        D4 { transpose, hflip, vflip }
+    }
+
+    fn to_op(self) -> TransformOp {
+        match (self.transpose, self.hflip, self.vflip) {
+            (false, false, false) => TransformOp::None,
+            (false, true, false) => TransformOp::Hflip,
+            (false, false, true) => TransformOp::Vflip,
+            (false, true, true) => TransformOp::Rot180,
+            (true, false, false) => TransformOp::Transpose,
+            (true, true, false) => TransformOp::Rot90,
+            (true, false, true) => TransformOp::Rot270,
+            (true, true, true) => TransformOp::Transverse,
+        }
+    }
+
+    /// Returns the operation that applies `self` and then `next`.
+    fn then(self, next: D4) -> D4 {
+        // transposition after a flip is equivalent to the other flip after the transposition
+        let (hflip, vflip) = if next.transpose { (self.vflip, self.hflip) } else { (self.hflip, self.vflip) };
+        D4 {
+            transpose: self.transpose ^ next.transpose,
+            hflip: hflip ^ next.hflip,
+            vflip: vflip ^ next.vflip,
+        }
+    }
+
+    fn map_dims(self, (width, height): (usize, usize)) -> (usize, usize) {
+        if self.transpose { (height, width) } else { (width, height) }
+    }
+
+    fn map_rect(self, rect: Rect, dims: (usize, usize)) -> Rect {
+        let mut rect = rect;
+        if self.transpose {
+            rect = Rect { x: rect.y, y: rect.x, width: rect.height, height: rect.width };
+        }
+        let (width, height) = self.map_dims(dims);
+        if self.hflip {
+            rect.x = width - rect.x - rect.width;
+        }
+        if self.vflip {
+            rect.y = height - rect.y - rect.height;
+        }
+        rect
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    fn from_crop(crop: TransformCrop, (width, height): (usize, usize)) -> Result<Rect> {
+        if crop.x >= width || crop.y >= height || crop.width == Some(0) || crop.height == Some(0) {
+            return Err(Error::InvalidCrop())
+        }
+        Ok(Rect {
+            x: crop.x,
+            y: crop.y,
+            width: crop.width.unwrap_or(width - crop.x).min(width - crop.x),
+            height: crop.height.unwrap_or(height - crop.y).min(height - crop.y),
+        })
+    }
+}
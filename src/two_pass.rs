@@ -0,0 +1,183 @@
+use std::fmt;
+use crate::{Compressor, Image, OutputBuf, Transformer};
+use crate::common::{Subsamp, Error, Result};
+use crate::quality::Quality;
+use crate::sharp_yuv::YuvPlanes;
+
+/// Number of magnitude categories of quantized DCT coefficients in 8-bit JPEG images.
+const CATEGORIES: usize = 12;
+
+/// First pass of a two-pass compression: the image converted into YUV and the statistics of
+/// trial compressions at several qualities.
+///
+/// [`Compressor::trial_encode()`] converts the image into YUV once and compresses it at every
+/// trial quality, recording the size of the output and the histogram of the quantized DCT
+/// coefficients. You can inspect the [trials][Self::trials] and choose the settings of the final
+/// compression, which [`compress()`][Self::compress] then runs from the cached YUV planes, so the
+/// color conversion (and the chrominance subsampling) is not repeated.
+///
+/// The final compression uses the quality that you pass and the coding settings of the
+/// compressor at that time (such as [progressive][Compressor::set_progressive] coding), so you
+/// may also change those between the passes. The subsampling and the color conversion are fixed
+/// in the first pass.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file and decompress it (the image has 384x256 pixels)
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let image = turbojpeg::decompress(&jpeg_data, turbojpeg::PixelFormat::RGB)?;
+///
+/// // the first pass compresses the image at three qualities
+/// let qualities = [50, 75, 90].map(turbojpeg::Quality::new_const);
+/// let mut compressor = turbojpeg::Compressor::new()?;
+/// compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2);
+/// let trial = compressor.trial_encode(image.as_deref(), &qualities)?;
+/// assert_eq!(trial.trials().len(), 3);
+/// assert!(trial.trials()[0].size < trial.trials()[2].size);
+/// // lower qualities quantize more coefficients to zero
+/// assert!(trial.trials()[0].histogram.zero_fraction() > trial.trials()[2].histogram.zero_fraction());
+///
+/// // the second pass uses the best quality that fits into the budget of 20 kB
+/// let quality = trial.best_quality_within(20_000).unwrap_or(turbojpeg::Quality::WORST);
+/// compressor.set_progressive(true);
+/// let output = trial.compress_to_vec(&mut compressor, quality)?;
+/// assert!(output.len() <= 20_000);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct TrialEncode {
+    planes: YuvPlanes,
+    width: usize,
+    height: usize,
+    subsamp: Subsamp,
+    trials: Vec<QualityTrial>,
+}
+
+/// Statistics of a trial compression at one quality (see [`TrialEncode`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityTrial {
+    /// The quality of the trial compression.
+    pub quality: Quality,
+    /// The size of the compressed JPEG image in bytes.
+    pub size: usize,
+    /// The histogram of the quantized DCT coefficients.
+    pub histogram: CoefficientHistogram,
+}
+
+/// Histogram of the magnitudes of quantized DCT coefficients.
+///
+/// The coefficients are counted by the magnitude category that JPEG uses for entropy coding:
+/// category 0 counts the zeros and category `k` counts the coefficients with
+/// `2^(k-1) <= |c| < 2^k`. The coefficients of the padding blocks on the right and bottom edges
+/// are counted as well.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoefficientHistogram {
+    /// Counts of the coefficients of the luminance (Y) component.
+    pub luma: [u64; CATEGORIES],
+    /// Counts of the coefficients of both chrominance (Cb and Cr) components.
+    pub chroma: [u64; CATEGORIES],
+}
+
+impl CoefficientHistogram {
+    /// Returns the total number of coefficients.
+    pub fn total(&self) -> u64 {
+        self.luma.iter().chain(&self.chroma).sum()
+    }
+
+    /// Returns the fraction of the coefficients that are zero, or 0 if there are no coefficients.
+    pub fn zero_fraction(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.
+        }
+        (self.luma[0] + self.chroma[0]) as f64 / total as f64
+    }
+
+    fn add_block(&mut self, component: usize, block: &[i16; 64]) {
+        let counts = if component == 0 { &mut self.luma } else { &mut self.chroma };
+        for &coef in block {
+            let category = (16 - coef.unsigned_abs().leading_zeros()) as usize;
+            counts[category.min(CATEGORIES - 1)] += 1;
+        }
+    }
+}
+
+impl TrialEncode {
+    /// Returns the statistics of the trial compressions, in the order of the trial qualities.
+    pub fn trials(&self) -> &[QualityTrial] {
+        &self.trials
+    }
+
+    /// Returns the chrominance subsampling that was chosen in the first pass.
+    pub fn subsamp(&self) -> Subsamp {
+        self.subsamp
+    }
+
+    /// Returns the best trial quality whose output has at most `max_size` bytes, or `None` if all
+    /// trials are larger.
+    pub fn best_quality_within(&self, max_size: usize) -> Option<Quality> {
+        self.trials.iter()
+            .filter(|trial| trial.size <= max_size)
+            .map(|trial| trial.quality)
+            .max()
+    }
+
+    /// Compresses the cached YUV planes with the given `quality` and the coding settings of
+    /// `compressor` into `output`.
+    pub fn compress(&self, compressor: &mut Compressor, quality: impl TryInto<Quality, Error: Into<Error>>, output: &mut OutputBuf) -> Result<()> {
+        let quality = quality.try_into().map_err(Into::into)?;
+        compressor.compress_planes(&self.planes, self.width, self.height, self.subsamp, quality, output)
+    }
+
+    /// Compresses the cached YUV planes with the given `quality` and the coding settings of
+    /// `compressor` into a `Vec<u8>`.
+    pub fn compress_to_vec(&self, compressor: &mut Compressor, quality: impl TryInto<Quality, Error: Into<Error>>) -> Result<Vec<u8>> {
+        let mut output = OutputBuf::new_owned();
+        self.compress(compressor, quality, &mut output)?;
+        Ok(output.to_vec())
+    }
+}
+
+impl fmt::Debug for TrialEncode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrialEncode")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("subsamp", &self.subsamp)
+            .field("trials", &self.trials)
+            .finish()
+    }
+}
+
+impl Compressor {
+    /// Runs the first pass of a two-pass compression: converts `image` into YUV and compresses it
+    /// at each of the `qualities`, recording the statistics of every trial.
+    ///
+    /// See [`TrialEncode`] for details.
+    pub fn trial_encode(&mut self, image: Image<&[u8]>, qualities: &[Quality]) -> Result<TrialEncode> {
+        let (planes, subsamp) = self.encode_planes(image)?;
+        let mut trial = TrialEncode {
+            planes,
+            width: image.width,
+            height: image.height,
+            subsamp,
+            trials: Vec::with_capacity(qualities.len()),
+        };
+
+        let mut transformer = Transformer::new()?;
+        let mut output = OutputBuf::new_owned();
+        let mut rewritten = OutputBuf::new_owned();
+        for &quality in qualities {
+            trial.compress(self, quality, &mut output)?;
+            let mut histogram = CoefficientHistogram::default();
+            transformer.filter_coefficients(&output, &mut rewritten, |component, _row, blocks| {
+                for block in blocks.iter() {
+                    histogram.add_block(component, block);
+                }
+            })?;
+            trial.trials.push(QualityTrial { quality, size: output.len(), histogram });
+        }
+        Ok(trial)
+    }
+}
@@ -0,0 +1,203 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use crate::Image;
+use crate::common::{PixelFormat, Error};
+
+/// Pixel format known at compile time, used as the type parameter of [`TypedImage`].
+///
+/// This trait is implemented by the marker types [`Rgb8`], [`Bgr8`], [`Rgbx8`], [`Bgrx8`],
+/// [`Xbgr8`], [`Xrgb8`], [`Gray8`], [`Rgba8`], [`Bgra8`], [`Abgr8`], [`Argb8`] and [`Cmyk8`],
+/// one for every [`PixelFormat`].
+pub trait PixelType: Copy + fmt::Debug + Send + Sync + 'static {
+    /// The pixel format described by this type.
+    const FORMAT: PixelFormat;
+}
+
+macro_rules! pixel_types {
+    ($($name:ident => $format:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("Marker type for [`PixelFormat::", stringify!($format), "`].")]
+            #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+            pub struct $name;
+
+            impl PixelType for $name {
+                const FORMAT: PixelFormat = PixelFormat::$format;
+            }
+        )*
+    };
+}
+
+pixel_types! {
+    Rgb8 => RGB,
+    Bgr8 => BGR,
+    Rgbx8 => RGBX,
+    Bgrx8 => BGRX,
+    Xbgr8 => XBGR,
+    Xrgb8 => XRGB,
+    Gray8 => GRAY,
+    Rgba8 => RGBA,
+    Bgra8 => BGRA,
+    Abgr8 => ABGR,
+    Argb8 => ARGB,
+    Cmyk8 => CMYK,
+}
+
+/// An image with pixels of type `T` in pixel format `P` that is known at compile time.
+///
+/// This is a stricter variant of [`Image`], whose pixel format is a runtime value. The pixel
+/// format is part of the type, so an RGBA buffer cannot be passed to code that expects an RGB
+/// image by mistake, and the size of the pixel data is checked against the pixel format when the
+/// image is created. Use [`as_image()`][Self::as_image] or
+/// [`as_image_mut()`][Self::as_image_mut] to pass the image to the methods of
+/// [`Compressor`][crate::Compressor] and [`Decompressor`][crate::Decompressor], and
+/// [`TypedImage::try_from()`] to check the pixel format of an [`Image`].
+///
+/// # Example
+///
+/// ```
+/// use turbojpeg::{Rgb8, Rgba8, TypedImage};
+///
+/// // the pixel format is part of the type
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+/// let mut decompressor = turbojpeg::Decompressor::new()?;
+/// let image: TypedImage<Rgb8, Vec<u8>> = decompressor.decompress_typed(&jpeg_data)?;
+/// assert_eq!(image.pitch, 384 * 3);
+///
+/// fn compress_rgba(image: &TypedImage<Rgba8, Vec<u8>>) -> turbojpeg::Result<turbojpeg::OwnedBuf> {
+///     turbojpeg::compress(image.as_image(), 90, turbojpeg::Subsamp::Sub2x2)
+/// }
+/// // compress_rgba(&image); // does not compile: expected `Rgba8`, found `Rgb8`
+///
+/// // dynamic images are checked when they are converted
+/// let dynamic = turbojpeg::Image::mandelbrot(64, 64, turbojpeg::PixelFormat::RGB);
+/// assert!(TypedImage::<Rgba8, _>::try_from(dynamic.clone()).is_err());
+/// let typed = TypedImage::<Rgb8, _>::try_from(dynamic)?;
+/// assert_eq!(typed.format(), turbojpeg::PixelFormat::RGB);
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct TypedImage<P, T> {
+    /// Pixel data of the image (typically `&[u8]`, `&mut [u8]` or `Vec<u8>`).
+    pub pixels: T,
+    /// Width of the image in pixels (number of columns).
+    pub width: usize,
+    /// Pitch (stride) defines the size of one image row in bytes.
+    pub pitch: usize,
+    /// Height of the image in pixels (number of rows).
+    pub height: usize,
+    format: PhantomData<P>,
+}
+
+impl<P: PixelType, T> TypedImage<P, T> {
+    /// Creates an image with rows of `width * P::FORMAT.size()` bytes without any padding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` are too short for an image of the given size.
+    pub fn new(pixels: T, width: usize, height: usize) -> TypedImage<P, T>
+        where T: Deref<Target = [u8]>
+    {
+        TypedImage::with_pitch(pixels, width, width * P::FORMAT.size(), height)
+    }
+
+    /// Creates an image with the given `pitch` (size of one row in bytes).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pitch is too small for the width, or if `pixels` are too short for an image
+    /// of the given size.
+    pub fn with_pitch(pixels: T, width: usize, pitch: usize, height: usize) -> TypedImage<P, T>
+        where T: Deref<Target = [u8]>
+    {
+        let image = TypedImage { pixels, width, pitch, height, format: PhantomData };
+        image.as_image().assert_valid(image.pixels.len());
+        image
+    }
+
+    /// Returns the pixel format of the image, which is always `P::FORMAT`.
+    pub fn format(&self) -> PixelFormat {
+        P::FORMAT
+    }
+
+    /// Converts from `&TypedImage<P, T>` to a dynamic `Image<&[u8]>`.
+    pub fn as_image(&self) -> Image<&[u8]> where T: Deref<Target = [u8]> {
+        Image {
+            pixels: self.pixels.deref(),
+            width: self.width,
+            pitch: self.pitch,
+            height: self.height,
+            format: P::FORMAT,
+        }
+    }
+
+    /// Converts from `&mut TypedImage<P, T>` to a dynamic `Image<&mut [u8]>`.
+    pub fn as_image_mut(&mut self) -> Image<&mut [u8]> where T: DerefMut<Target = [u8]> {
+        Image {
+            pixels: self.pixels.deref_mut(),
+            width: self.width,
+            pitch: self.pitch,
+            height: self.height,
+            format: P::FORMAT,
+        }
+    }
+
+    /// Converts the image into a dynamic [`Image`].
+    pub fn into_image(self) -> Image<T> {
+        Image {
+            pixels: self.pixels,
+            width: self.width,
+            pitch: self.pitch,
+            height: self.height,
+            format: P::FORMAT,
+        }
+    }
+}
+
+impl<P: PixelType, T> TryFrom<Image<T>> for TypedImage<P, T> where T: Deref<Target = [u8]> {
+    type Error = Error;
+
+    /// Checks that the image has pixel format `P::FORMAT`, otherwise returns
+    /// [`Error::PixelFormatMismatch`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image.pixels` are too short for the image.
+    fn try_from(image: Image<T>) -> Result<TypedImage<P, T>, Error> {
+        if image.format != P::FORMAT {
+            return Err(Error::PixelFormatMismatch(P::FORMAT, image.format))
+        }
+        Ok(TypedImage::with_pitch(image.pixels, image.width, image.pitch, image.height))
+    }
+}
+
+impl<P: PixelType, T> From<TypedImage<P, T>> for Image<T> {
+    fn from(image: TypedImage<P, T>) -> Image<T> {
+        image.into_image()
+    }
+}
+
+impl<P, T: Clone> Clone for TypedImage<P, T> {
+    fn clone(&self) -> Self {
+        TypedImage {
+            pixels: self.pixels.clone(),
+            width: self.width,
+            pitch: self.pitch,
+            height: self.height,
+            format: PhantomData,
+        }
+    }
+}
+
+impl<P: PixelType, T: fmt::Debug> fmt::Debug for TypedImage<P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedImage")
+            .field("pixels", &self.pixels)
+            .field("width", &self.width)
+            .field("pitch", &self.pitch)
+            .field("height", &self.height)
+            .field("format", &P::FORMAT)
+            .finish()
+    }
+}
@@ -0,0 +1,163 @@
+use crate::common::{Error, Result};
+use crate::marker::{self, EOI, SOF0, SOS};
+
+/// The JPEG coding process, determined by the frame header (SOF marker) of the image.
+///
+/// Old decoders (and some hardware decoders) only support baseline JPEG, so you may want to
+/// transcode images that use other processes (see [`Decompressor::read_header()`][crate::Decompressor::read_header]).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CodingProcess {
+    /// Baseline sequential DCT (SOF0), supported by all decoders.
+    #[doc(alias = "SOF0")]
+    Baseline,
+    /// Extended sequential DCT (SOF1 and SOF9), which allows 12-bit precision, more Huffman tables
+    /// and arithmetic coding.
+    #[doc(alias = "SOF1")]
+    #[doc(alias = "SOF9")]
+    ExtendedSequential,
+    /// Progressive DCT (SOF2 and SOF10), where the image is encoded in multiple scans that refine
+    /// the image.
+    #[doc(alias = "SOF2")]
+    #[doc(alias = "SOF10")]
+    Progressive,
+    /// Lossless (SOF3 and SOF11).
+    #[doc(alias = "SOF3")]
+    #[doc(alias = "SOF11")]
+    Lossless,
+    /// Hierarchical (differential) processes (SOF5-SOF7 and SOF13-SOF15), which are very rare and
+    /// not supported by TurboJPEG.
+    Hierarchical,
+    /// The coding process is not known, because the frame header could not be found (see
+    /// [`Decompressor::read_header()`][crate::Decompressor::read_header]).
+    Unknown,
+}
+
+impl CodingProcess {
+    pub(crate) fn from_sof(marker: u8) -> CodingProcess {
+        match marker & 0x07 {
+            0 => CodingProcess::Baseline,
+            1 => CodingProcess::ExtendedSequential,
+            2 => CodingProcess::Progressive,
+            3 => CodingProcess::Lossless,
+            _ => CodingProcess::Hierarchical,
+        }
+    }
+}
+
+/// Summary of the structure of JPEG data, returned by [`validate_jpeg()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct JpegSummary {
+    /// Width of the image in pixels, as declared in the frame header.
+    pub width: usize,
+    /// Height of the image in pixels, as declared in the frame header.
+    pub height: usize,
+    /// Coding process of the image (baseline, progressive, ...).
+    pub process: CodingProcess,
+    /// Sample precision in bits (typically 8).
+    pub precision: u8,
+    /// Number of color components (1 for grayscale, 3 for YCbCr or RGB, 4 for CMYK or YCCK).
+    pub components: usize,
+    /// Number of scans in the image.
+    pub scans: usize,
+    /// Length of the JPEG data, up to and including the EOI marker. Any data after this offset is
+    /// not part of the image.
+    pub len: usize,
+}
+
+/// Validates the structure of JPEG data without decompressing the image.
+///
+/// This function walks through all markers in the data and checks that the segment lengths are
+/// consistent, that there is exactly one frame header followed by at least one scan, that the
+/// scans refer only to the components declared in the frame, and that the data ends with the EOI
+/// marker. It does not decode the entropy-coded image data, so it is much cheaper than a full
+/// decompression, but it cannot detect corrupted image data.
+///
+/// Returns [`Error::MalformedJpeg`] if the data is not structurally valid.
+///
+/// # Example
+///
+/// ```
+/// // read JPEG data from file
+/// let jpeg_data = std::fs::read("examples/parrots.jpg")?;
+///
+/// let summary = turbojpeg::validate_jpeg(&jpeg_data)?;
+/// assert_eq!((summary.width, summary.height), (384, 256));
+/// assert_eq!(summary.process, turbojpeg::CodingProcess::Progressive);
+/// assert_eq!(summary.components, 3);
+/// assert_eq!(summary.scans, 10);
+///
+/// // truncated data is rejected
+/// assert!(turbojpeg::validate_jpeg(&jpeg_data[..1000]).is_err());
+///
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn validate_jpeg(jpeg_data: &[u8]) -> Result<JpegSummary> {
+    let mut frame = None;
+    let mut component_ids = [0u8; 4];
+    let mut scans = 0;
+
+    let mut segments = marker::segments(jpeg_data);
+    for segment in &mut segments {
+        let segment = segment.map_err(Error::MalformedJpeg)?;
+        if marker::is_sof(segment.marker) {
+            if frame.is_some() {
+                return Err(Error::MalformedJpeg("multiple frame headers"));
+            }
+            let data = segment.data;
+            if data.len() < 6 {
+                return Err(Error::MalformedJpeg("truncated frame header"));
+            }
+            let precision = data[0];
+            let height = u16::from_be_bytes([data[1], data[2]]) as usize;
+            let width = u16::from_be_bytes([data[3], data[4]]) as usize;
+            let components = data[5] as usize;
+            if width == 0 {
+                return Err(Error::MalformedJpeg("zero image width"));
+            } else if components == 0 || components > 4 {
+                return Err(Error::MalformedJpeg("invalid number of components"));
+            } else if data.len() != 6 + 3 * components {
+                return Err(Error::MalformedJpeg("invalid frame header length"));
+            }
+            for (i, id) in component_ids.iter_mut().take(components).enumerate() {
+                *id = data[6 + 3 * i];
+            }
+            let process = CodingProcess::from_sof(segment.marker);
+            frame = Some((process, precision, width, height, components));
+        } else if segment.marker == SOS {
+            let components = match frame {
+                Some((_, _, _, _, components)) => components,
+                None => return Err(Error::MalformedJpeg("scan before frame header")),
+            };
+            let data = segment.data;
+            if data.is_empty() {
+                return Err(Error::MalformedJpeg("truncated scan header"));
+            }
+            let scan_components = data[0] as usize;
+            if scan_components == 0 || scan_components > 4 {
+                return Err(Error::MalformedJpeg("invalid number of scan components"));
+            } else if data.len() != 4 + 2 * scan_components {
+                return Err(Error::MalformedJpeg("invalid scan header length"));
+            }
+            for i in 0..scan_components {
+                if !component_ids[..components].contains(&data[1 + 2 * i]) {
+                    return Err(Error::MalformedJpeg("scan refers to unknown component"));
+                }
+            }
+            scans += 1;
+        } else if segment.marker == EOI {
+            break;
+        } else if segment.marker < SOF0 && segment.marker != marker::TEM {
+            return Err(Error::MalformedJpeg("reserved marker"));
+        }
+    }
+
+    let (process, precision, width, height, components) = match frame {
+        Some(frame) => frame,
+        None => return Err(Error::MalformedJpeg("missing frame header")),
+    };
+    if scans == 0 {
+        return Err(Error::MalformedJpeg("missing scan"));
+    }
+
+    Ok(JpegSummary { width, height, process, precision, components, scans, len: segments.pos() })
+}
@@ -0,0 +1,216 @@
+//! Differential tests against the command-line tools of libjpeg-turbo (`djpeg`, `cjpeg` and
+//! `jpegtran`).
+//!
+//! The tools are built from the same library, so their outputs must match the outputs of this
+//! crate; a mismatch points to a mistake in the wrapper (such as a mix-up of the pitch, the pixel
+//! format or the transform options), not in libjpeg-turbo. The images are compared with a
+//! tolerance of 1, so that the tests also pass when the tools come from a slightly different
+//! version of libjpeg-turbo than the linked library.
+//!
+//! These tests need the `differential` feature. The tools are looked up in the directory in
+//! `TURBOJPEG_TOOLS_DIR` or in `PATH`; if they are not found, the tests are skipped with a
+//! message.
+#![cfg(feature = "differential")]
+
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use turbojpeg::testing::assert_images_similar;
+use turbojpeg::{Compressor, Decompressor, FrameSkip, FrameStatus, Image, MjpegPlayer, PixelFormat, ScalingFactor, Subsamp, Transform, TransformOp};
+
+const TOLERANCE: u8 = 1;
+
+fn parrots() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/parrots.jpg")).unwrap()
+}
+
+/// Finds a tool of libjpeg-turbo, or returns `None` (and prints a message) if it is missing.
+fn tool(name: &str) -> Option<PathBuf> {
+    let dirs = match std::env::var_os("TURBOJPEG_TOOLS_DIR") {
+        Some(dir) => vec![PathBuf::from(dir)],
+        None => std::env::var_os("PATH").map(|path| std::env::split_paths(&path).collect()).unwrap_or_default(),
+    };
+    let found = dirs.into_iter()
+        .map(|dir| dir.join(name).with_extension(std::env::consts::EXE_EXTENSION))
+        .find(|path| path.is_file());
+    if found.is_none() {
+        eprintln!("skipping the test, because `{}` was not found", name);
+    }
+    found
+}
+
+/// Runs the tool with `input` on its standard input and returns its standard output.
+fn run(tool: &Path, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "{} {:?} failed with {}", tool.display(), args, output.status);
+    output.stdout
+}
+
+/// Decompresses into an image with padded rows, so that mistakes in the pitch are detected.
+fn decompress_padded(jpeg_data: &[u8], format: PixelFormat, scaling: ScalingFactor) -> Image<Vec<u8>> {
+    let mut decompressor = Decompressor::new().unwrap();
+    let header = decompressor.read_header(jpeg_data).unwrap();
+    let (width, height) = (scaling.scale(header.width), scaling.scale(header.height));
+    let pitch = width * format.size() + 13;
+    let mut image = Image { pixels: vec![0; pitch * height], width, pitch, height, format };
+    decompressor.decompress(jpeg_data, image.as_deref_mut()).unwrap();
+    image
+}
+
+#[test]
+fn decompress_matches_djpeg() {
+    let djpeg = match tool("djpeg") { Some(djpeg) => djpeg, None => return };
+    let jpeg_data = parrots();
+    let rgb = turbojpeg::decode_pnm(&run(&djpeg, &["-ppm"], &jpeg_data)).unwrap();
+    let gray = turbojpeg::decode_pnm(&run(&djpeg, &["-grayscale", "-pnm"], &jpeg_data)).unwrap();
+    for format in PixelFormat::ALL {
+        if format == PixelFormat::CMYK {
+            continue
+        }
+        let image = decompress_padded(&jpeg_data, format, ScalingFactor::ONE);
+        let expected = if format == PixelFormat::GRAY { &gray } else { &rgb };
+        assert_images_similar(expected.as_deref(), image.as_deref(), TOLERANCE);
+    }
+}
+
+#[test]
+fn decompress_scaled_matches_djpeg() {
+    let djpeg = match tool("djpeg") { Some(djpeg) => djpeg, None => return };
+    let jpeg_data = parrots();
+    for factor in [ScalingFactor::ONE_HALF, ScalingFactor::new(3, 8), ScalingFactor::new(2, 1)] {
+        let scale = format!("{}/{}", factor.num(), factor.denom());
+        let expected = turbojpeg::decode_pnm(&run(&djpeg, &["-scale", &scale, "-ppm"], &jpeg_data)).unwrap();
+        let image = decompress_padded(&jpeg_data, PixelFormat::BGRX, factor);
+        assert_images_similar(expected.as_deref(), image.as_deref(), TOLERANCE);
+    }
+}
+
+#[test]
+fn compress_matches_cjpeg() {
+    let cjpeg = match tool("cjpeg") { Some(cjpeg) => cjpeg, None => return };
+    let image = Image::mandelbrot(123, 77, PixelFormat::RGB);
+    let ppm = turbojpeg::encode_pnm(image.as_deref());
+    let bgra = to_padded_bgra(image.as_deref());
+    // `-sample` sets the sampling factors of the luminance
+    let cases = [("1x1", Subsamp::None), ("2x1", Subsamp::Sub2x1), ("2x2", Subsamp::Sub2x2), ("1x2", Subsamp::Sub1x2)];
+    for (sample, subsamp) in cases {
+        let expected = run(&cjpeg, &["-quality", "85", "-sample", sample], &ppm);
+
+        // compress from a different pixel format with padded rows
+        let mut compressor = Compressor::new().unwrap();
+        compressor.set_quality(85).unwrap();
+        compressor.set_subsamp(subsamp);
+        let jpeg_data = compressor.compress_to_vec(bgra.as_deref()).unwrap();
+
+        assert_eq!(turbojpeg::read_header(&expected).unwrap().subsamp, subsamp);
+        assert_eq!(turbojpeg::read_header(&jpeg_data).unwrap().subsamp, subsamp);
+        let expected = turbojpeg::decompress(&expected, PixelFormat::RGB).unwrap();
+        let actual = turbojpeg::decompress(&jpeg_data, PixelFormat::RGB).unwrap();
+        assert_images_similar(expected.as_deref(), actual.as_deref(), TOLERANCE);
+    }
+}
+
+/// Converts an RGB image into a BGRA image with padded rows.
+fn to_padded_bgra(rgb: Image<&[u8]>) -> Image<Vec<u8>> {
+    let pitch = 4 * rgb.width + 13;
+    let mut pixels = vec![0; pitch * rgb.height];
+    for y in 0..rgb.height {
+        for x in 0..rgb.width {
+            let src = &rgb.pixels[y * rgb.pitch + 3 * x..][..3];
+            pixels[y * pitch + 4 * x..][..4].copy_from_slice(&[src[2], src[1], src[0], 255]);
+        }
+    }
+    Image { pixels, width: rgb.width, pitch, height: rgb.height, format: PixelFormat::BGRA }
+}
+
+#[test]
+fn transform_matches_jpegtran() {
+    let jpegtran = match tool("jpegtran") { Some(jpegtran) => jpegtran, None => return };
+    let jpeg_data = parrots();
+    let cases: [(&[&str], TransformOp); 5] = [
+        (&["-rotate", "90"], TransformOp::Rot90),
+        (&["-rotate", "180"], TransformOp::Rot180),
+        (&["-flip", "horizontal"], TransformOp::Hflip),
+        (&["-transpose"], TransformOp::Transpose),
+        (&["-transverse"], TransformOp::Transverse),
+    ];
+    for (args, op) in cases {
+        let expected = run(&jpegtran, args, &jpeg_data);
+        let transform = Transform { op, ..Transform::default() };
+        let actual = turbojpeg::transform(&transform, &jpeg_data).unwrap();
+
+        let expected = turbojpeg::decompress(&expected, PixelFormat::RGB).unwrap();
+        let actual = turbojpeg::decompress(&actual, PixelFormat::RGB).unwrap();
+        assert_images_similar(expected.as_deref(), actual.as_deref(), TOLERANCE);
+    }
+}
+
+#[test]
+fn mjpeg_player_skips_near_identical_frames() {
+    let cjpeg = match tool("cjpeg") { Some(cjpeg) => cjpeg, None => return };
+    let image = Image::mandelbrot(256, 256, PixelFormat::RGB);
+    let mut changed = image.clone();
+    for y in 100..108 {
+        changed.pixels[y * changed.pitch..][..24].fill(255);
+    }
+    // restart markers after every MCU row
+    let args = ["-quality", "85", "-restart", "1"];
+    let frame_1 = run(&cjpeg, &args, &turbojpeg::encode_pnm(image.as_deref()));
+    let frame_2 = run(&cjpeg, &args, &turbojpeg::encode_pnm(changed.as_deref()));
+
+    let mut player = MjpegPlayer::new(PixelFormat::RGB).unwrap();
+    player.set_frame_skip(FrameSkip::NearIdentical { max_changed: 0.1 }).unwrap();
+    assert_eq!(player.decode_frame(&frame_1).unwrap(), FrameStatus::Decoded);
+    assert_eq!(player.decode_frame(&frame_2).unwrap(), FrameStatus::Skipped);
+    assert_eq!(player.decode_frame(&frame_2).unwrap(), FrameStatus::Skipped);
+    let decoded = turbojpeg::decompress(&frame_1, PixelFormat::RGB).unwrap();
+    assert_eq!(player.image().pixels, decoded.pixels);
+
+    // identical frames are skipped, changed frames are decoded
+    player.set_frame_skip(FrameSkip::Identical).unwrap();
+    assert_eq!(player.decode_frame(&frame_1).unwrap(), FrameStatus::Skipped);
+    assert_eq!(player.decode_frame(&frame_2).unwrap(), FrameStatus::Decoded);
+    assert_eq!(player.skipped_frames(), 3);
+}
+
+#[cfg(feature = "rtp")]
+#[test]
+fn rtp_jpeg_round_trip_with_restart_markers() {
+    use turbojpeg::{RtpJpegDepacketizer, RtpJpegPacketizer};
+
+    let cjpeg = match tool("cjpeg") { Some(cjpeg) => cjpeg, None => return };
+    let image = Image::mandelbrot(320, 240, PixelFormat::RGB);
+    let jpeg_data = run(&cjpeg, &["-quality", "85", "-sample", "2x1", "-restart", "1"], &turbojpeg::encode_pnm(image.as_deref()));
+    let packets = RtpJpegPacketizer::new(1000).packetize(&jpeg_data).unwrap();
+    assert!(packets.len() > 2);
+
+    // the second frame loses a packet and is dropped
+    let mut depacketizer = RtpJpegDepacketizer::new();
+    let mut received = Vec::new();
+    for (frame, skip) in [(0, None), (1, Some(1)), (2, None)] {
+        for (index, packet) in packets.iter().enumerate() {
+            if skip == Some(index) {
+                continue
+            }
+            if let Some(jpeg) = depacketizer.push(&packet.payload, packet.marker).unwrap() {
+                received.push((frame, jpeg));
+            }
+        }
+    }
+    assert_eq!(received.iter().map(|(frame, _)| *frame).collect::<Vec<_>>(), [0, 2]);
+    assert_eq!(depacketizer.dropped_frames(), 1);
+
+    let expected = turbojpeg::decompress(&jpeg_data, PixelFormat::RGB).unwrap();
+    let decoded = turbojpeg::decompress(&received[0].1, PixelFormat::RGB).unwrap();
+    assert_eq!(decoded.pixels, expected.pixels);
+}
@@ -0,0 +1,46 @@
+//! Pools of TurboJPEG instances in a forked child process (see `turbojpeg::HandlePool`).
+#![cfg(unix)]
+
+use turbojpeg::{Compressor, HandlePool, Image, PixelFormat};
+
+/// Runs `child` in a forked process and returns its exit status.
+fn in_child(child: impl FnOnce() -> bool) -> libc::c_int {
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork failed"),
+        0 => {
+            let ok = std::panic::catch_unwind(std::panic::AssertUnwindSafe(child)).unwrap_or(false);
+            unsafe { libc::_exit(if ok { 0 } else { 1 }) }
+        },
+        pid => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            status
+        },
+    }
+}
+
+#[test]
+fn child_does_not_reuse_inherited_handles() {
+    let pool = HandlePool::<Compressor>::new(4);
+    let image = Image::mandelbrot(32, 32, PixelFormat::RGB);
+    let taken = pool.get().unwrap();
+    drop(pool.get().unwrap());
+    assert_eq!(pool.idle_count(), 1);
+
+    let status = in_child(|| {
+        // the idle instance came from the parent, so it is dropped
+        if pool.idle_count() != 0 {
+            return false
+        }
+        // the instance taken before the fork is not returned into the pool
+        drop(taken);
+        if pool.idle_count() != 0 {
+            return false
+        }
+        let mut compressor = pool.get().unwrap();
+        let ok = compressor.compress_to_vec(image.as_deref()).is_ok();
+        drop(compressor);
+        ok && pool.idle_count() == 1
+    });
+    assert_eq!(status, 0);
+}
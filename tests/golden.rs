@@ -0,0 +1,104 @@
+//! Golden vectors for compression, decompression and lossless transforms.
+//!
+//! The expected hashes were computed with libjpeg-turbo 2.1 on x86_64. The accurate DCT, the
+//! color conversions and the entropy coding in libjpeg-turbo are bit-exact across the scalar,
+//! SSE2/AVX2 and Neon code paths, so these hashes must be the same on every architecture (we
+//! only use the accurate DCT here, because the fast DCT is not exact). If a test fails on a
+//! particular architecture, the SIMD code for that architecture is broken; if a test fails
+//! after upgrading libjpeg-turbo, check the release notes of libjpeg-turbo before updating the
+//! hashes.
+//!
+//! Because the output does not depend on SIMD, these tests would pass even if libjpeg-turbo
+//! silently fell back to the (much slower) scalar code, so `simd_is_active` checks that the SIMD
+//! extensions are actually used.
+//!
+//! With the `testing` feature, `snapshot_decompress_scaled` also compares the output with a
+//! snapshot in `tests/snapshots` with a tolerance, which points to the pixels that changed when a
+//! hash does not match. Run the tests with `TURBOJPEG_UPDATE_SNAPSHOTS=1` to update the snapshot.
+
+use turbojpeg::{Compressor, Decompressor, DctMethod, Image, PixelFormat, Subsamp};
+
+/// 64-bit FNV-1a hash, which is good enough to detect changes in the output.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn parrots() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/parrots.jpg")).unwrap()
+}
+
+fn decompress_hash(jpeg_data: &[u8], format: PixelFormat, dct_method: DctMethod) -> u64 {
+    let mut decompressor = Decompressor::new().unwrap();
+    decompressor.set_dct_method(dct_method);
+    let image = decompressor.decompress_to_buf(jpeg_data, format).unwrap();
+    fnv1a(&image.pixels)
+}
+
+#[test]
+fn golden_compress() {
+    let image = Image::mandelbrot(123, 77, PixelFormat::RGB);
+    let cases = [
+        (Subsamp::None, 95, 0x816a276391ecb61d),
+        (Subsamp::Sub2x1, 85, 0xf775a8a5c9e465eb),
+        (Subsamp::Sub2x2, 75, 0xa67ab25dbeb7aca8),
+        (Subsamp::Sub1x2, 60, 0xc46b134c36ac6f8f),
+        (Subsamp::Sub4x1, 50, 0x46cdd4bdcb32feb3),
+        (Subsamp::Gray, 90, 0x970beb1852a9de49),
+    ];
+    for (subsamp, quality, expected) in cases {
+        let mut compressor = Compressor::new().unwrap();
+        compressor.set_subsamp(subsamp);
+        compressor.set_quality(quality).unwrap();
+        let jpeg_data = compressor.compress_to_vec(image.as_deref()).unwrap();
+        assert_eq!(fnv1a(&jpeg_data), expected, "compression with {:?} and quality {}", subsamp, quality);
+    }
+}
+
+#[test]
+fn golden_decompress() {
+    let jpeg_data = parrots();
+    let cases = [
+        (PixelFormat::RGB, 0x40cfc095fafc4195),
+        (PixelFormat::BGRA, 0xb9db141555bb580d),
+        (PixelFormat::GRAY, 0xf4ab31897183ce22),
+    ];
+    for (format, expected) in cases {
+        let hash = decompress_hash(&jpeg_data, format, DctMethod::Accurate);
+        assert_eq!(hash, expected, "decompression into {:?}", format);
+    }
+}
+
+#[test]
+fn golden_transform() {
+    let jpeg_data = parrots();
+    let transform = turbojpeg::Transform {
+        op: turbojpeg::TransformOp::Rot90,
+        crop: Some(turbojpeg::TransformCrop { x: 16, y: 32, width: Some(128), height: Some(96) }),
+        .. Default::default()
+    };
+    let output = turbojpeg::transform(&transform, &jpeg_data).unwrap();
+    assert_eq!(fnv1a(&output), 0xf190e003b7649daf);
+}
+
+#[test]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64"))]
+fn simd_is_active() {
+    if std::env::var_os("TURBOJPEG_ALLOW_NO_SIMD").is_some() {
+        return
+    }
+    match turbojpeg::raw::simd_available() {
+        Some(available) => assert!(available,
+            "libjpeg-turbo does not use SIMD extensions (was it built without NASM, or is \
+            JSIMD_FORCENONE set?); set TURBOJPEG_ALLOW_NO_SIMD=1 to skip this check"),
+        None => println!("cannot check SIMD extensions, libturbojpeg is linked dynamically"),
+    }
+}
+
+#[test]
+#[cfg(feature = "testing")]
+fn snapshot_decompress_scaled() {
+    let mut decompressor = Decompressor::new().unwrap();
+    let image = decompressor.decompress_scaled(&parrots(), PixelFormat::RGB, turbojpeg::ScalingFactor::ONE_QUARTER).unwrap();
+    let snapshots = turbojpeg::testing::Snapshots::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots"));
+    snapshots.assert_image("parrots_quarter", image.as_deref());
+}
@@ -0,0 +1,34 @@
+//! Reading headers of JPEG images that the marker parser of this crate rejects, but TurboJPEG
+//! accepts.
+
+use turbojpeg::{CodingProcess, PixelFormat};
+
+fn parrots() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/parrots.jpg")).unwrap()
+}
+
+/// Returns the test image with a comment segment with an invalid length after the SOI marker,
+/// which libjpeg skips without a warning.
+fn with_bad_comment() -> Vec<u8> {
+    let data = parrots();
+    let mut result = data[..2].to_vec();
+    result.extend_from_slice(&[0xff, 0xfe, 0x00, 0x01]);
+    result.extend_from_slice(&data[2..]);
+    result
+}
+
+#[test]
+fn header_without_frame_info() {
+    let header = turbojpeg::read_header(&with_bad_comment()).unwrap();
+    assert_eq!((header.width, header.height), (384, 256));
+    assert_eq!(header.process, CodingProcess::Unknown);
+    assert!(!header.multi_scan);
+    assert_eq!(header.components, 3);
+}
+
+#[test]
+fn decompress_without_frame_info() {
+    let image = turbojpeg::decompress(&with_bad_comment(), PixelFormat::RGB).unwrap();
+    let expected = turbojpeg::decompress(&parrots(), PixelFormat::RGB).unwrap();
+    assert_eq!(image.pixels, expected.pixels);
+}
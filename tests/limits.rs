@@ -0,0 +1,52 @@
+//! Tests of `DecompressLimits` on images with many scans.
+//!
+//! The test images repeat the last scan of a progressive image, which libjpeg accepts with a
+//! warning about the bogus progression, so that only the scan limits stop the decompression.
+
+use turbojpeg::{DecompressLimits, Decompressor, Error, PixelFormat};
+
+fn parrots() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/parrots.jpg")).unwrap()
+}
+
+/// Returns the test image with its last scan repeated until it has `scans` scans.
+fn with_scans(scans: usize) -> Vec<u8> {
+    let data = parrots();
+    let last_sos = data.windows(2).rposition(|pair| pair == [0xff, 0xda]).unwrap();
+    let eoi = data.len() - 2;
+    let mut result = data[..eoi].to_vec();
+    // the test image has 10 scans
+    for _ in 10..scans {
+        result.extend_from_slice(&data[last_sos..eoi]);
+    }
+    result.extend_from_slice(&data[eoi..]);
+    result
+}
+
+/// Decompresses the image and returns the error.
+fn decompress_error(jpeg_data: &[u8], max_scan_count: usize) -> Option<Error> {
+    let mut decompressor = Decompressor::new().unwrap();
+    decompressor.set_limits(DecompressLimits { max_scan_count: Some(max_scan_count), ..DecompressLimits::default() });
+    decompressor.decompress_to_buf(jpeg_data, PixelFormat::RGB).err()
+}
+
+#[test]
+fn max_scan_count_counts_scans() {
+    let jpeg_data = with_scans(50);
+    assert!(matches!(decompress_error(&jpeg_data, 40), Some(Error::TooManyScans(40))));
+}
+
+#[test]
+fn library_scan_limit_is_too_many_scans() {
+    // the scan count is within `max_scan_count`, but TurboJPEG stops after 500 scans
+    let jpeg_data = with_scans(600);
+    assert!(matches!(decompress_error(&jpeg_data, 1000), Some(Error::TooManyScans(500))));
+}
+
+#[test]
+fn other_errors_are_not_too_many_scans() {
+    let mut jpeg_data = with_scans(20);
+    jpeg_data.truncate(1000);
+    jpeg_data[2] = 0;
+    assert!(matches!(decompress_error(&jpeg_data, 1000), Some(Error::TurboJpegError(_))));
+}
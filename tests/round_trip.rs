@@ -0,0 +1,14 @@
+//! Round trips over randomly generated geometries, pixel formats and compression settings (see
+//! `turbojpeg::testing::RoundTripCase`).
+//!
+//! Set `TURBOJPEG_ROUND_TRIPS` to the number of cases to check more of the configuration space
+//! (the default is 200).
+#![cfg(feature = "testing")]
+
+#[test]
+fn random_round_trips() {
+    let count = std::env::var("TURBOJPEG_ROUND_TRIPS").ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(200);
+    turbojpeg::testing::check_round_trips(0x7572_626f_6a70_6567, count);
+}
@@ -0,0 +1,33 @@
+//! Compressing with a `turbojpeg::SharedPreset`, which must not change the own settings of the
+//! compressor.
+
+use turbojpeg::{Compressor, CompressorPreset, Image, PixelFormat, Quality, SharedPreset, Subsamp};
+
+fn subsamp_of(compressor: &mut Compressor, image: &Image<Vec<u8>>) -> Subsamp {
+    let jpeg_data = compressor.compress_to_vec(image.as_deref()).unwrap();
+    turbojpeg::read_header(&jpeg_data).unwrap().subsamp
+}
+
+#[test]
+fn clearing_shared_preset_restores_own_settings() {
+    let image = Image::mandelbrot(64, 64, PixelFormat::RGB);
+    let mut compressor = Compressor::new().unwrap();
+    compressor.set_quality(60).unwrap();
+    compressor.set_subsamp(Subsamp::Gray);
+
+    let shared = SharedPreset::new(CompressorPreset {
+        quality: Quality::new(90).unwrap(),
+        subsamp: Subsamp::Sub2x2,
+        progressive: true,
+        ..CompressorPreset::WEB
+    });
+    compressor.set_shared_preset(Some(shared));
+    assert_eq!(subsamp_of(&mut compressor, &image), Subsamp::Sub2x2);
+    assert_eq!(compressor.quality().get(), 60);
+
+    compressor.set_shared_preset(None);
+    assert_eq!(subsamp_of(&mut compressor, &image), Subsamp::Gray);
+    assert_eq!(compressor.quality().get(), 60);
+    let jpeg_data = compressor.compress_to_vec(image.as_deref()).unwrap();
+    assert!(!turbojpeg::read_header(&jpeg_data).unwrap().multi_scan);
+}
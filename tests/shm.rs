@@ -0,0 +1,41 @@
+//! Opening `turbojpeg::ShmRing` files that were not created by `ShmRing::create()`.
+#![cfg(unix)]
+
+use std::io::ErrorKind;
+use turbojpeg::{Error, ShmRing};
+
+/// Writes a ring header with the given number and length of slots, opens it and reads a frame.
+/// Returns the error of opening the ring.
+fn open_header(name: &str, slots: u64, slot_len: u64) -> Option<Error> {
+    let path = std::env::temp_dir().join(format!("turbojpeg-test-{}-{}", name, std::process::id()));
+    let mut data = Vec::new();
+    data.extend_from_slice(b"TJRING01");
+    data.extend_from_slice(&slots.to_le_bytes());
+    data.extend_from_slice(&slot_len.to_le_bytes());
+    // the write sequence is ahead of the read sequence, so the consumer reads a slot
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.resize(64 + 1024, 0);
+    std::fs::write(&path, &data).unwrap();
+
+    let res = ShmRing::open(&path).map(|mut ring| ring.pop());
+    std::fs::remove_file(&path).unwrap();
+    res.err()
+}
+
+#[test]
+fn open_valid_header() {
+    assert!(open_header("valid", 4, 64).is_none());
+}
+
+#[test]
+fn open_rejects_zero_slots() {
+    let err = open_header("zero-slots", 0, 64);
+    assert!(matches!(err, Some(Error::Io(err)) if err.kind() == ErrorKind::InvalidData));
+}
+
+#[test]
+fn open_rejects_zero_slot_len() {
+    let err = open_header("zero-slot-len", 4, 0);
+    assert!(matches!(err, Some(Error::Io(err)) if err.kind() == ErrorKind::InvalidData));
+}
@@ -0,0 +1,58 @@
+//! Tests of perfect lossless transforms (`EdgeHandling::Perfect`).
+
+use turbojpeg::{EdgeHandling, Error, Image, PixelFormat, Subsamp, Transform, TransformOp};
+
+/// Returns a JPEG image of the given size with 4:2:0 subsampling (MCUs of 16x16 pixels).
+fn jpeg(width: usize, height: usize) -> Vec<u8> {
+    let image = Image::mandelbrot(width, height, PixelFormat::RGB);
+    turbojpeg::compress(image.as_deref(), 90, Subsamp::Sub2x2).unwrap().to_vec()
+}
+
+fn perfect(op: TransformOp, gray: bool, jpeg_data: &[u8]) -> bool {
+    let transform = Transform { op, edge: EdgeHandling::Perfect, gray, ..Transform::default() };
+    match turbojpeg::transform(&transform, jpeg_data) {
+        Ok(_) => true,
+        Err(Error::ImperfectTransform(err_op)) => {
+            assert_eq!(err_op, op);
+            false
+        },
+        Err(err) => panic!("unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn perfect_transforms_with_partial_columns() {
+    // the right edge has a partial MCU, the bottom edge does not
+    let jpeg_data = jpeg(200, 256);
+    let expected = [
+        (TransformOp::None, true),
+        (TransformOp::Hflip, false),
+        (TransformOp::Vflip, true),
+        (TransformOp::Transpose, true),
+        (TransformOp::Transverse, false),
+        (TransformOp::Rot90, true),
+        (TransformOp::Rot180, false),
+        (TransformOp::Rot270, false),
+    ];
+    for (op, is_perfect) in expected {
+        assert_eq!(perfect(op, false, &jpeg_data), is_perfect, "{:?}", op);
+    }
+}
+
+#[test]
+fn perfect_transforms_with_partial_rows() {
+    // the bottom edge has a partial MCU, the right edge does not
+    let jpeg_data = jpeg(256, 200);
+    assert!(perfect(TransformOp::Hflip, false, &jpeg_data));
+    assert!(perfect(TransformOp::Rot270, false, &jpeg_data));
+    assert!(!perfect(TransformOp::Vflip, false, &jpeg_data));
+    assert!(!perfect(TransformOp::Rot90, false, &jpeg_data));
+}
+
+#[test]
+fn perfect_transforms_to_grayscale() {
+    // grayscale output has MCUs of 8x8 pixels, so a width of 200 is not partial
+    let jpeg_data = jpeg(200, 200);
+    assert!(!perfect(TransformOp::Rot180, false, &jpeg_data));
+    assert!(perfect(TransformOp::Rot180, true, &jpeg_data));
+}
@@ -0,0 +1,79 @@
+//! Tests of the composition of lossless transforms in `TransformPipeline`.
+//!
+//! The composed transform is checked against the same steps applied one after another to the
+//! decompressed image. The crop offsets are multiples of the MCU size of the test image (8x8
+//! pixels, because it is not subsampled), so the lossless transforms are exact up to the
+//! rounding of the inverse DCT.
+
+use turbojpeg::{Image, PixelFormat, TransformCrop, TransformOp, TransformPipeline};
+
+fn parrots() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/parrots.jpg")).unwrap()
+}
+
+fn crop(x: usize, y: usize, width: usize, height: usize) -> TransformCrop {
+    TransformCrop { x, y, width: Some(width), height: Some(height) }
+}
+
+/// Crops a grayscale image.
+fn crop_image(image: &Image<Vec<u8>>, x: usize, y: usize, width: usize, height: usize) -> Image<Vec<u8>> {
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in y..y + height {
+        pixels.extend_from_slice(&image.pixels[row * image.pitch + x..][..width]);
+    }
+    Image { pixels, width, pitch: width, height, format: PixelFormat::GRAY }
+}
+
+/// Rotates a grayscale image by 90 degrees clockwise.
+fn rotate_image(image: &Image<Vec<u8>>) -> Image<Vec<u8>> {
+    let (width, height) = (image.height, image.width);
+    let mut pixels = vec![0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            pixels[y * width + x] = image.pixels[(image.height - 1 - x) * image.pitch + y];
+        }
+    }
+    Image { pixels, width, pitch: width, height, format: PixelFormat::GRAY }
+}
+
+#[test]
+fn nested_crops_are_relative() {
+    let pipeline = TransformPipeline::new()
+        .crop(crop(128, 0, 128, 256))
+        .crop(crop(0, 0, 64, 256));
+    let transform = pipeline.to_transform_for_size(384, 256).unwrap();
+    assert_eq!(transform.crop, Some(crop(128, 0, 64, 256)));
+
+    // a crop outside of the previously cropped image is rejected
+    let pipeline = TransformPipeline::new()
+        .crop(crop(128, 0, 128, 256))
+        .crop(crop(160, 0, 64, 256));
+    assert!(matches!(pipeline.to_transform_for_size(384, 256), Err(turbojpeg::Error::InvalidCrop())));
+}
+
+#[test]
+fn crop_rotate_crop() {
+    let jpeg_data = parrots();
+    let pipeline = TransformPipeline::new()
+        .crop(crop(128, 16, 128, 224))
+        .op(TransformOp::Rot90)
+        .crop(crop(64, 32, 96, 64))
+        .gray();
+
+    // the second crop selects columns 64..160 and rows 32..96 of the rotated 224x128 image
+    let transform = pipeline.to_transform(&jpeg_data).unwrap();
+    assert_eq!(transform.op, TransformOp::Rot90);
+    assert_eq!(transform.crop, Some(crop(80, 160, 96, 64)));
+
+    let mut transformer = turbojpeg::Transformer::new().unwrap();
+    let output_data = transformer.transform_pipeline(&pipeline, &jpeg_data).unwrap();
+    let output = turbojpeg::decompress(&output_data, PixelFormat::GRAY).unwrap();
+
+    let gray = turbojpeg::decompress(&jpeg_data, PixelFormat::GRAY).unwrap();
+    let expected = crop_image(&rotate_image(&crop_image(&gray, 128, 16, 128, 224)), 64, 32, 96, 64);
+    assert_eq!((output.width, output.height), (expected.width, expected.height));
+    let max_diff = output.pixels.iter().zip(&expected.pixels)
+        .map(|(&a, &b)| a.abs_diff(b))
+        .max().unwrap();
+    assert!(max_diff <= 2, "transformed image differs by {}", max_diff);
+}
@@ -0,0 +1,97 @@
+/* C API for the safe TurboJPEG wrapper from the `turbojpeg` Rust crate.
+ *
+ * All functions that can fail return 0 on success and -1 on failure; use
+ * tjrs_last_error() to get the error message. Pixel formats, subsampling
+ * options and transform operations use the values of the TJPF_*, TJSAMP_* and
+ * TJXOP_* constants from turbojpeg.h. */
+#ifndef TURBOJPEG_CAPI_H
+#define TURBOJPEG_CAPI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct tjrs_decompressor tjrs_decompressor;
+
+/* Limits on decompressed images, zero means no limit. */
+typedef struct tjrs_limits {
+  size_t max_pixels;
+  size_t max_output_bytes;
+  size_t max_scan_count;
+  size_t max_memory;
+} tjrs_limits;
+
+typedef struct tjrs_header {
+  size_t width;
+  size_t height;
+  int subsamp;     /* TJSAMP_* */
+  int colorspace;  /* TJCS_* */
+  int multi_scan;  /* 1 for multi-scan (such as progressive) images */
+} tjrs_header;
+
+typedef struct tjrs_image {
+  uint8_t *pixels;
+  size_t width;
+  size_t pitch;
+  size_t height;
+  int format;  /* TJPF_* */
+} tjrs_image;
+
+/* Crop region, zero width or height means "up to the edge". */
+typedef struct tjrs_crop {
+  size_t x;
+  size_t y;
+  size_t width;
+  size_t height;
+} tjrs_crop;
+
+#define TJRS_TRANSFORM_GRAY 1
+#define TJRS_TRANSFORM_PROGRESSIVE 2
+#define TJRS_TRANSFORM_PERFECT 4
+#define TJRS_TRANSFORM_TRIM 8
+#define TJRS_TRANSFORM_STRIP_METADATA 16
+
+/* Error message of the last failed call in this thread. */
+const char *tjrs_last_error(void);
+
+/* Frees buffers returned by tjrs_compress(), tjrs_transform() and
+ * tjrs_thumbnail(). */
+void tjrs_free(uint8_t *data, size_t len);
+
+tjrs_decompressor *tjrs_decompressor_new(void);
+void tjrs_decompressor_free(tjrs_decompressor *decompressor);
+int tjrs_decompressor_set_limits(tjrs_decompressor *decompressor,
+                                 const tjrs_limits *limits);
+
+int tjrs_read_header(const uint8_t *jpeg_data, size_t jpeg_len,
+                     tjrs_header *header);
+
+/* The image must be freed with tjrs_image_free(). */
+int tjrs_decompress(tjrs_decompressor *decompressor, const uint8_t *jpeg_data,
+                    size_t jpeg_len, int format, tjrs_image *image);
+void tjrs_image_free(tjrs_image *image);
+
+/* The output must be freed with tjrs_free(). */
+int tjrs_compress(const tjrs_image *image, int quality, int subsamp,
+                  uint8_t **out, size_t *out_len);
+
+/* `crop` may be NULL, `flags` is a bitwise OR of TJRS_TRANSFORM_*. The output
+ * must be freed with tjrs_free(). */
+int tjrs_transform(const uint8_t *jpeg_data, size_t jpeg_len, int op,
+                   const tjrs_crop *crop, int flags, uint8_t **out,
+                   size_t *out_len);
+
+/* Resizes the image to fit into max_width x max_height (without upscaling) and
+ * compresses it with `quality`. The output must be freed with tjrs_free(). */
+int tjrs_thumbnail(const uint8_t *jpeg_data, size_t jpeg_len, size_t max_width,
+                   size_t max_height, int quality, uint8_t **out,
+                   size_t *out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
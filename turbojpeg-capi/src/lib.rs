@@ -0,0 +1,440 @@
+//! C API for the [`turbojpeg`] crate.
+//!
+//! This crate exposes the safe wrapper from the `turbojpeg` crate (with its input validation and
+//! [decompression limits][turbojpeg::DecompressLimits]) through a small, stable C ABI, so that
+//! applications written in other languages can use it. The C declarations are in
+//! `include/turbojpeg_capi.h`.
+//!
+//! All functions that can fail return 0 on success and -1 on failure; the error message can then
+//! be obtained with [`tjrs_last_error()`]. Panics are caught and reported as errors, so they never
+//! unwind into the C code. Pixel formats, subsampling options and transform operations use the
+//! same integer values as the `TJPF_*`, `TJSAMP_*` and `TJXOP_*` constants of TurboJPEG.
+#![allow(non_camel_case_types)]
+
+use std::cell::RefCell;
+use std::ffi::{CString, c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use turbojpeg::{raw, Image, PixelFormat, Subsamp, TransformOp};
+
+/// Opaque handle to a [`turbojpeg::Decompressor`].
+pub struct tjrs_decompressor {
+    inner: turbojpeg::Decompressor,
+}
+
+/// Limits on decompressed images (see [`turbojpeg::DecompressLimits`]). Zero means no limit.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct tjrs_limits {
+    /// Maximal number of pixels (width times height).
+    pub max_pixels: usize,
+    /// Maximal size in bytes of the decompressed image.
+    pub max_output_bytes: usize,
+    /// Maximal number of scans.
+    pub max_scan_count: usize,
+    /// Maximal estimated memory used by TurboJPEG.
+    pub max_memory: usize,
+}
+
+/// Header of a JPEG image.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct tjrs_header {
+    /// Width of the image in pixels.
+    pub width: usize,
+    /// Height of the image in pixels.
+    pub height: usize,
+    /// Chrominance subsampling (`TJSAMP_*`).
+    pub subsamp: c_int,
+    /// Colorspace (`TJCS_*`).
+    pub colorspace: c_int,
+    /// 1 if the image has multiple scans (such as progressive images), 0 otherwise.
+    pub multi_scan: c_int,
+}
+
+/// Uncompressed image. Images returned by [`tjrs_decompress()`] must be freed with
+/// [`tjrs_image_free()`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct tjrs_image {
+    /// Pixel data; the pixel in column `x` and row `y` starts at offset `y*pitch + x*pixel_size`.
+    pub pixels: *mut u8,
+    /// Width of the image in pixels.
+    pub width: usize,
+    /// Size of one row in bytes.
+    pub pitch: usize,
+    /// Height of the image in pixels.
+    pub height: usize,
+    /// Pixel format (`TJPF_*`).
+    pub format: c_int,
+}
+
+/// Crop region for [`tjrs_transform()`]. Zero width or height means "up to the edge".
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct tjrs_crop {
+    /// Left edge of the region (must be a multiple of the MCU width).
+    pub x: usize,
+    /// Top edge of the region (must be a multiple of the MCU height).
+    pub y: usize,
+    /// Width of the region.
+    pub width: usize,
+    /// Height of the region.
+    pub height: usize,
+}
+
+/// Flag for [`tjrs_transform()`]: convert the image to grayscale.
+pub const TJRS_TRANSFORM_GRAY: c_int = 1;
+/// Flag for [`tjrs_transform()`]: use progressive entropy coding.
+pub const TJRS_TRANSFORM_PROGRESSIVE: c_int = 2;
+/// Flag for [`tjrs_transform()`]: fail if the transform is not perfect.
+pub const TJRS_TRANSFORM_PERFECT: c_int = 4;
+/// Flag for [`tjrs_transform()`]: discard partial MCU blocks that cannot be transformed.
+pub const TJRS_TRANSFORM_TRIM: c_int = 8;
+/// Flag for [`tjrs_transform()`]: remove all metadata (EXIF, ICC, XMP, comments).
+pub const TJRS_TRANSFORM_STRIP_METADATA: c_int = 16;
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = msg);
+}
+
+/// Runs `f`, converting errors and panics into -1 and storing the error message.
+fn guard<F: FnOnce() -> Result<(), String>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => 0,
+        Ok(Err(msg)) => { set_last_error(msg); -1 },
+        Err(payload) => {
+            let msg = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".into());
+            set_last_error(format!("panic: {}", msg));
+            -1
+        },
+    }
+}
+
+fn pixel_format(format: c_int) -> Result<PixelFormat, String> {
+    Ok(match format {
+        raw::TJPF_TJPF_RGB => PixelFormat::RGB,
+        raw::TJPF_TJPF_BGR => PixelFormat::BGR,
+        raw::TJPF_TJPF_RGBX => PixelFormat::RGBX,
+        raw::TJPF_TJPF_BGRX => PixelFormat::BGRX,
+        raw::TJPF_TJPF_XBGR => PixelFormat::XBGR,
+        raw::TJPF_TJPF_XRGB => PixelFormat::XRGB,
+        raw::TJPF_TJPF_GRAY => PixelFormat::GRAY,
+        raw::TJPF_TJPF_RGBA => PixelFormat::RGBA,
+        raw::TJPF_TJPF_BGRA => PixelFormat::BGRA,
+        raw::TJPF_TJPF_ABGR => PixelFormat::ABGR,
+        raw::TJPF_TJPF_ARGB => PixelFormat::ARGB,
+        raw::TJPF_TJPF_CMYK => PixelFormat::CMYK,
+        _ => return Err(format!("unknown pixel format {}", format)),
+    })
+}
+
+fn subsamp(subsamp: c_int) -> Result<Subsamp, String> {
+    Ok(match subsamp as u32 {
+        raw::TJSAMP_TJSAMP_444 => Subsamp::None,
+        raw::TJSAMP_TJSAMP_422 => Subsamp::Sub2x1,
+        raw::TJSAMP_TJSAMP_420 => Subsamp::Sub2x2,
+        raw::TJSAMP_TJSAMP_GRAY => Subsamp::Gray,
+        raw::TJSAMP_TJSAMP_440 => Subsamp::Sub1x2,
+        raw::TJSAMP_TJSAMP_411 => Subsamp::Sub4x1,
+        _ => return Err(format!("unknown subsampling {}", subsamp)),
+    })
+}
+
+fn transform_op(op: c_int) -> Result<TransformOp, String> {
+    Ok(match op as u32 {
+        raw::TJXOP_TJXOP_NONE => TransformOp::None,
+        raw::TJXOP_TJXOP_HFLIP => TransformOp::Hflip,
+        raw::TJXOP_TJXOP_VFLIP => TransformOp::Vflip,
+        raw::TJXOP_TJXOP_TRANSPOSE => TransformOp::Transpose,
+        raw::TJXOP_TJXOP_TRANSVERSE => TransformOp::Transverse,
+        raw::TJXOP_TJXOP_ROT90 => TransformOp::Rot90,
+        raw::TJXOP_TJXOP_ROT180 => TransformOp::Rot180,
+        raw::TJXOP_TJXOP_ROT270 => TransformOp::Rot270,
+        _ => return Err(format!("unknown transform operation {}", op)),
+    })
+}
+
+unsafe fn input<'a>(data: *const u8, len: usize) -> Result<&'a [u8], String> {
+    if data.is_null() {
+        return if len == 0 { Ok(&[]) } else { Err("input data is null".into()) }
+    }
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+/// Moves `data` to the output pointers, which must be freed with [`tjrs_free()`].
+unsafe fn output(data: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) -> Result<(), String> {
+    if out.is_null() || out_len.is_null() {
+        return Err("output pointer is null".into())
+    }
+    let data = data.into_boxed_slice();
+    *out_len = data.len();
+    *out = Box::into_raw(data) as *mut u8;
+    Ok(())
+}
+
+/// Returns the error message of the last function that failed in the current thread.
+///
+/// The string is valid until the next call into this library from the same thread.
+#[no_mangle]
+pub extern "C" fn tjrs_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
+}
+
+/// Frees a buffer returned by [`tjrs_compress()`], [`tjrs_transform()`] or [`tjrs_thumbnail()`].
+///
+/// # Safety
+///
+/// `data` and `len` must have been returned by this library (or `data` must be null).
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Creates a new decompressor. Returns null on failure.
+#[no_mangle]
+pub extern "C" fn tjrs_decompressor_new() -> *mut tjrs_decompressor {
+    let mut decompressor = ptr::null_mut();
+    guard(|| {
+        let inner = turbojpeg::Decompressor::new().map_err(|err| err.to_string())?;
+        decompressor = Box::into_raw(Box::new(tjrs_decompressor { inner }));
+        Ok(())
+    });
+    decompressor
+}
+
+/// Destroys a decompressor.
+///
+/// # Safety
+///
+/// `decompressor` must have been returned by [`tjrs_decompressor_new()`] (or be null), and it
+/// must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_decompressor_free(decompressor: *mut tjrs_decompressor) {
+    if !decompressor.is_null() {
+        drop(Box::from_raw(decompressor));
+    }
+}
+
+/// Sets the limits on images accepted by the decompressor.
+///
+/// # Safety
+///
+/// `decompressor` and `limits` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_decompressor_set_limits(
+    decompressor: *mut tjrs_decompressor,
+    limits: *const tjrs_limits,
+) -> c_int {
+    guard(|| {
+        let decompressor = decompressor.as_mut().ok_or("decompressor is null")?;
+        let limits = limits.as_ref().ok_or("limits is null")?;
+        let limit = |value: usize| if value == 0 { None } else { Some(value) };
+        decompressor.inner.set_limits(turbojpeg::DecompressLimits {
+            max_pixels: limit(limits.max_pixels),
+            max_output_bytes: limit(limits.max_output_bytes),
+            max_scan_count: limit(limits.max_scan_count),
+            max_memory: limit(limits.max_memory),
+        });
+        Ok(())
+    })
+}
+
+/// Reads the header of a JPEG image.
+///
+/// # Safety
+///
+/// `jpeg_data` must point to `jpeg_len` readable bytes and `header` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_read_header(
+    jpeg_data: *const u8,
+    jpeg_len: usize,
+    header: *mut tjrs_header,
+) -> c_int {
+    guard(|| {
+        let jpeg_data = input(jpeg_data, jpeg_len)?;
+        let header = header.as_mut().ok_or("header is null")?;
+        let res = turbojpeg::read_header(jpeg_data).map_err(|err| err.to_string())?;
+        *header = tjrs_header {
+            width: res.width,
+            height: res.height,
+            subsamp: res.subsamp as c_int,
+            colorspace: res.colorspace as c_int,
+            multi_scan: res.multi_scan as c_int,
+        };
+        Ok(())
+    })
+}
+
+/// Decompresses a JPEG image into a new image with pixel format `format` (`TJPF_*`).
+///
+/// The image must be freed with [`tjrs_image_free()`].
+///
+/// # Safety
+///
+/// `decompressor` and `image` must be valid pointers and `jpeg_data` must point to `jpeg_len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_decompress(
+    decompressor: *mut tjrs_decompressor,
+    jpeg_data: *const u8,
+    jpeg_len: usize,
+    format: c_int,
+    image: *mut tjrs_image,
+) -> c_int {
+    guard(|| {
+        let decompressor = decompressor.as_mut().ok_or("decompressor is null")?;
+        let jpeg_data = input(jpeg_data, jpeg_len)?;
+        let image = image.as_mut().ok_or("image is null")?;
+        let pixel_format = pixel_format(format)?;
+
+        let header = decompressor.inner.read_header(jpeg_data).map_err(|err| err.to_string())?;
+        let pitch = header.width * pixel_format.size();
+        let mut output = Image {
+            pixels: vec![0; pitch * header.height],
+            width: header.width,
+            pitch,
+            height: header.height,
+            format: pixel_format,
+        };
+        decompressor.inner.decompress(jpeg_data, output.as_deref_mut())
+            .map_err(|err| err.to_string())?;
+
+        let pixels = Box::into_raw(output.pixels.into_boxed_slice());
+        *image = tjrs_image { pixels: pixels as *mut u8, width: output.width, pitch, height: output.height, format };
+        Ok(())
+    })
+}
+
+/// Frees the pixels of an image returned by [`tjrs_decompress()`].
+///
+/// # Safety
+///
+/// `image` must have been filled by [`tjrs_decompress()`] (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_image_free(image: *mut tjrs_image) {
+    if let Some(image) = image.as_mut() {
+        tjrs_free(image.pixels, image.pitch * image.height);
+        image.pixels = ptr::null_mut();
+    }
+}
+
+/// Compresses an image into JPEG with the given `quality` (1 to 100) and `subsamp` (`TJSAMP_*`).
+///
+/// The output must be freed with [`tjrs_free()`].
+///
+/// # Safety
+///
+/// `image` must point to a valid image, and `out` and `out_len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_compress(
+    image: *const tjrs_image,
+    quality: c_int,
+    subsamp_: c_int,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let image = image.as_ref().ok_or("image is null")?;
+        let format = pixel_format(image.format)?;
+        if image.pitch < image.width * format.size() {
+            return Err("pitch is too small".into())
+        }
+        let len = if image.height == 0 { 0 } else { image.pitch * (image.height - 1) + image.width * format.size() };
+        let pixels = input(image.pixels, len)?;
+
+        let mut compressor = turbojpeg::Compressor::new().map_err(|err| err.to_string())?;
+        compressor.set_quality(quality).map_err(|err| err.to_string())?;
+        compressor.set_subsamp(subsamp(subsamp_)?);
+        let image = Image { pixels, width: image.width, pitch: image.pitch, height: image.height, format };
+        let data = compressor.compress_to_vec(image).map_err(|err| err.to_string())?;
+        output(data, out, out_len)
+    })
+}
+
+/// Losslessly transforms a JPEG image with operation `op` (`TJXOP_*`), optional `crop` (can be
+/// null) and `flags` (bitwise OR of `TJRS_TRANSFORM_*`).
+///
+/// The output must be freed with [`tjrs_free()`].
+///
+/// # Safety
+///
+/// `jpeg_data` must point to `jpeg_len` readable bytes, `crop` must be null or a valid pointer,
+/// and `out` and `out_len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_transform(
+    jpeg_data: *const u8,
+    jpeg_len: usize,
+    op: c_int,
+    crop: *const tjrs_crop,
+    flags: c_int,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let jpeg_data = input(jpeg_data, jpeg_len)?;
+        let edge = if flags & TJRS_TRANSFORM_PERFECT != 0 {
+            turbojpeg::EdgeHandling::Perfect
+        } else if flags & TJRS_TRANSFORM_TRIM != 0 {
+            turbojpeg::EdgeHandling::Trim
+        } else {
+            turbojpeg::EdgeHandling::Keep
+        };
+        let transform = turbojpeg::Transform {
+            op: transform_op(op)?,
+            crop: crop.as_ref().map(|crop| turbojpeg::TransformCrop {
+                x: crop.x,
+                y: crop.y,
+                width: if crop.width == 0 { None } else { Some(crop.width) },
+                height: if crop.height == 0 { None } else { Some(crop.height) },
+            }),
+            edge,
+            gray: flags & TJRS_TRANSFORM_GRAY != 0,
+            progressive: flags & TJRS_TRANSFORM_PROGRESSIVE != 0,
+            strip: if flags & TJRS_TRANSFORM_STRIP_METADATA != 0 {
+                turbojpeg::StripMetadata::all()
+            } else {
+                turbojpeg::StripMetadata::default()
+            },
+            .. turbojpeg::Transform::default()
+        };
+        let data = turbojpeg::transform(&transform, jpeg_data).map_err(|err| err.to_string())?;
+        output(data.to_vec(), out, out_len)
+    })
+}
+
+/// Resizes a JPEG image to fit into `max_width` x `max_height` (keeping the aspect ratio and never
+/// upscaling) and compresses it with `quality` (1 to 100). The metadata are copied.
+///
+/// The output must be freed with [`tjrs_free()`].
+///
+/// # Safety
+///
+/// `jpeg_data` must point to `jpeg_len` readable bytes, and `out` and `out_len` must be valid
+/// pointers.
+#[no_mangle]
+pub unsafe extern "C" fn tjrs_thumbnail(
+    jpeg_data: *const u8,
+    jpeg_len: usize,
+    max_width: usize,
+    max_height: usize,
+    quality: c_int,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let jpeg_data = input(jpeg_data, jpeg_len)?;
+        let data = turbojpeg::resize_jpeg(jpeg_data, (max_width, max_height), quality)
+            .map_err(|err| err.to_string())?;
+        output(data, out, out_len)
+    })
+}
@@ -0,0 +1,102 @@
+package org.turbojpeg;
+
+import java.nio.ByteBuffer;
+
+/**
+ * JPEG compression, decompression and lossless transforms with the turbojpeg Rust crate.
+ *
+ * <p>The methods with {@code byte[]} arguments copy the data between Java and native memory. The
+ * methods with {@link ByteBuffer} arguments require direct buffers (such as the planes of an
+ * Android camera image) and read and write them in place, between the position and the limit of
+ * each buffer. The buffers must not be accessed by other threads during the call.
+ *
+ * <p>All methods are thread-safe. Errors are thrown as {@link TurboJpegException}.
+ */
+public final class TurboJpeg {
+    static {
+        System.loadLibrary("turbojpeg_jni");
+    }
+
+    private TurboJpeg() {}
+
+    // pixel formats (TJPF_*)
+    public static final int PF_RGB = 0;
+    public static final int PF_BGR = 1;
+    public static final int PF_RGBX = 2;
+    public static final int PF_BGRX = 3;
+    public static final int PF_XBGR = 4;
+    public static final int PF_XRGB = 5;
+    public static final int PF_GRAY = 6;
+    public static final int PF_RGBA = 7;
+    public static final int PF_BGRA = 8;
+    public static final int PF_ABGR = 9;
+    public static final int PF_ARGB = 10;
+    public static final int PF_CMYK = 11;
+
+    // chrominance subsampling (TJSAMP_*)
+    public static final int SAMP_444 = 0;
+    public static final int SAMP_422 = 1;
+    public static final int SAMP_420 = 2;
+    public static final int SAMP_GRAY = 3;
+    public static final int SAMP_440 = 4;
+    public static final int SAMP_411 = 5;
+
+    // lossless transform operations (TJXOP_*)
+    public static final int XOP_NONE = 0;
+    public static final int XOP_HFLIP = 1;
+    public static final int XOP_VFLIP = 2;
+    public static final int XOP_TRANSPOSE = 3;
+    public static final int XOP_TRANSVERSE = 4;
+    public static final int XOP_ROT90 = 5;
+    public static final int XOP_ROT180 = 6;
+    public static final int XOP_ROT270 = 7;
+
+    /** Returns {@code {width, height, subsamp, colorspace}} of a JPEG image. */
+    public static native int[] readHeader(byte[] jpeg);
+
+    /** Decompresses a JPEG image into pixels in {@code format}, without padding between rows. */
+    public static native byte[] decompress(byte[] jpeg, int format);
+
+    /**
+     * Decompresses the JPEG image between the position and the limit of {@code jpeg} into
+     * {@code output} (starting at its position), without copying.
+     */
+    public static void decompress(ByteBuffer jpeg, ByteBuffer output, int width, int pitch, int height, int format) {
+        decompressDirect(jpeg, jpeg.position(), jpeg.remaining(),
+            output, output.position(), output.remaining(), width, pitch, height, format);
+    }
+
+    /** Compresses an image into JPEG with {@code quality} from 1 to 100. */
+    public static native byte[] compress(byte[] pixels, int width, int pitch, int height, int format, int quality, int subsamp);
+
+    /**
+     * Compresses the image at the position of {@code pixels} into {@code output} (starting at its
+     * position), without copying. Advances the position of {@code output} past the JPEG data and
+     * returns its size. Use {@link #compressedBufLen} to allocate an output that is large enough.
+     */
+    public static int compress(ByteBuffer pixels, int width, int pitch, int height, int format, int quality, int subsamp, ByteBuffer output) {
+        int len = compressDirect(pixels, pixels.position(), pixels.remaining(), width, pitch, height, format, quality, subsamp,
+            output, output.position(), output.remaining());
+        output.position(output.position() + len);
+        return len;
+    }
+
+    /** Returns the size of a buffer that is large enough for any compressed image of this size. */
+    public static native int compressedBufLen(int width, int height, int subsamp);
+
+    /** Losslessly transforms a JPEG image with operation {@code op} (one of {@code XOP_*}). */
+    public static native byte[] transform(byte[] jpeg, int op);
+
+    /**
+     * Resizes a JPEG image to fit into {@code maxWidth} x {@code maxHeight} (keeping the aspect
+     * ratio and never upscaling) and compresses it with {@code quality}. The metadata are copied.
+     */
+    public static native byte[] thumbnail(byte[] jpeg, int maxWidth, int maxHeight, int quality);
+
+    private static native void decompressDirect(ByteBuffer jpeg, int jpegOffset, int jpegLen,
+        ByteBuffer output, int outputOffset, int outputLen, int width, int pitch, int height, int format);
+
+    private static native int compressDirect(ByteBuffer pixels, int pixelsOffset, int pixelsLen,
+        int width, int pitch, int height, int format, int quality, int subsamp,
+        ByteBuffer output, int outputOffset, int outputLen);
+}
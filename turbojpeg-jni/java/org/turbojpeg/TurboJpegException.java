@@ -0,0 +1,8 @@
+package org.turbojpeg;
+
+/** Error reported by the native TurboJPEG library. */
+public class TurboJpegException extends RuntimeException {
+    public TurboJpegException(String message) {
+        super(message);
+    }
+}
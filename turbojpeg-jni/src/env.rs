@@ -0,0 +1,170 @@
+//! Minimal bindings to the JNI functions used by this crate.
+//!
+//! The functions are looked up in the function table of `JNIEnv` by their index, which is fixed
+//! by the JNI specification (see `struct JNINativeInterface_` in `jni.h`).
+#![allow(non_camel_case_types)]
+
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::ptr;
+
+pub type jint = i32;
+pub type jsize = jint;
+pub type jbyte = i8;
+pub type jboolean = u8;
+pub type jlong = i64;
+pub type jobject = *mut c_void;
+pub type jclass = jobject;
+pub type jbyteArray = jobject;
+pub type jintArray = jobject;
+
+/// The `JNIEnv` type of C: a pointer to the function table.
+pub type JNIEnv = *const *const c_void;
+
+const FIND_CLASS: usize = 6;
+const THROW_NEW: usize = 14;
+const DELETE_LOCAL_REF: usize = 23;
+const GET_ARRAY_LENGTH: usize = 171;
+const NEW_BYTE_ARRAY: usize = 176;
+const NEW_INT_ARRAY: usize = 179;
+const GET_BYTE_ARRAY_REGION: usize = 200;
+const SET_BYTE_ARRAY_REGION: usize = 208;
+const SET_INT_ARRAY_REGION: usize = 211;
+const EXCEPTION_CHECK: usize = 228;
+const GET_DIRECT_BUFFER_ADDRESS: usize = 230;
+const GET_DIRECT_BUFFER_CAPACITY: usize = 231;
+
+type FindClass = unsafe extern "system" fn(*mut JNIEnv, *const c_char) -> jclass;
+type ThrowNew = unsafe extern "system" fn(*mut JNIEnv, jclass, *const c_char) -> jint;
+type DeleteLocalRef = unsafe extern "system" fn(*mut JNIEnv, jobject);
+type GetArrayLength = unsafe extern "system" fn(*mut JNIEnv, jobject) -> jsize;
+type NewArray = unsafe extern "system" fn(*mut JNIEnv, jsize) -> jobject;
+type GetByteArrayRegion = unsafe extern "system" fn(*mut JNIEnv, jbyteArray, jsize, jsize, *mut jbyte);
+type SetByteArrayRegion = unsafe extern "system" fn(*mut JNIEnv, jbyteArray, jsize, jsize, *const jbyte);
+type SetIntArrayRegion = unsafe extern "system" fn(*mut JNIEnv, jintArray, jsize, jsize, *const jint);
+type ExceptionCheck = unsafe extern "system" fn(*mut JNIEnv) -> jboolean;
+type GetDirectBufferAddress = unsafe extern "system" fn(*mut JNIEnv, jobject) -> *mut c_void;
+type GetDirectBufferCapacity = unsafe extern "system" fn(*mut JNIEnv, jobject) -> jlong;
+
+/// JNI environment of the current native call.
+pub struct Env(*mut JNIEnv);
+
+impl Env {
+    /// Wraps the environment passed to a native method.
+    ///
+    /// # Safety
+    ///
+    /// `env` must be the environment passed by the JVM to the current native call.
+    pub unsafe fn new(env: *mut JNIEnv) -> Env {
+        Env(env)
+    }
+
+    /// Returns the function with `index` from the function table.
+    unsafe fn function<F: Copy>(&self, index: usize) -> F {
+        let table = *self.0;
+        std::mem::transmute_copy(&*table.add(index))
+    }
+
+    fn check_exception(&self) -> Result<(), String> {
+        let exception_check: ExceptionCheck = unsafe { self.function(EXCEPTION_CHECK) };
+        if unsafe { exception_check(self.0) } != 0 {
+            return Err("a Java exception is pending".into())
+        }
+        Ok(())
+    }
+
+    /// Throws an exception of class `class` (such as `c"java/lang/IllegalArgumentException"`)
+    /// with message `msg`, unless another exception is already pending.
+    pub fn throw(&self, class: &CStr, msg: &str) {
+        if self.check_exception().is_err() {
+            return
+        }
+        let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+        unsafe {
+            let find_class: FindClass = self.function(FIND_CLASS);
+            let class = find_class(self.0, class.as_ptr());
+            if class.is_null() {
+                // FindClass() has thrown NoClassDefFoundError
+                return
+            }
+            let throw_new: ThrowNew = self.function(THROW_NEW);
+            throw_new(self.0, class, msg.as_ptr());
+            let delete_local_ref: DeleteLocalRef = self.function(DELETE_LOCAL_REF);
+            delete_local_ref(self.0, class);
+        }
+    }
+
+    /// Copies the contents of a Java `byte[]`.
+    pub fn byte_array(&self, array: jbyteArray) -> Result<Vec<u8>, String> {
+        if array.is_null() {
+            return Err("array is null".into())
+        }
+        unsafe {
+            let get_array_length: GetArrayLength = self.function(GET_ARRAY_LENGTH);
+            let len = get_array_length(self.0, array);
+            let mut data = vec![0u8; len as usize];
+            let get_byte_array_region: GetByteArrayRegion = self.function(GET_BYTE_ARRAY_REGION);
+            get_byte_array_region(self.0, array, 0, len, data.as_mut_ptr() as *mut jbyte);
+            self.check_exception()?;
+            Ok(data)
+        }
+    }
+
+    /// Creates a new Java `byte[]` with a copy of `data`.
+    pub fn new_byte_array(&self, data: &[u8]) -> Result<jbyteArray, String> {
+        let len = jsize::try_from(data.len()).map_err(|_| "array is too large for Java")?;
+        unsafe {
+            let new_byte_array: NewArray = self.function(NEW_BYTE_ARRAY);
+            let array = new_byte_array(self.0, len);
+            self.check_exception()?;
+            let set_byte_array_region: SetByteArrayRegion = self.function(SET_BYTE_ARRAY_REGION);
+            set_byte_array_region(self.0, array, 0, len, data.as_ptr() as *const jbyte);
+            self.check_exception()?;
+            Ok(array)
+        }
+    }
+
+    /// Creates a new Java `int[]` with a copy of `data`.
+    pub fn new_int_array(&self, data: &[jint]) -> Result<jintArray, String> {
+        let len = jsize::try_from(data.len()).map_err(|_| "array is too large for Java")?;
+        unsafe {
+            let new_int_array: NewArray = self.function(NEW_INT_ARRAY);
+            let array = new_int_array(self.0, len);
+            self.check_exception()?;
+            let set_int_array_region: SetIntArrayRegion = self.function(SET_INT_ARRAY_REGION);
+            set_int_array_region(self.0, array, 0, len, data.as_ptr());
+            self.check_exception()?;
+            Ok(array)
+        }
+    }
+
+    /// Returns the memory of a direct `java.nio.ByteBuffer` as a pointer and its capacity.
+    ///
+    /// The memory is not managed by the garbage collector, so it can be accessed without copying
+    /// for the duration of the native call.
+    pub fn direct_buffer(&self, buffer: jobject) -> Result<(*mut u8, usize), String> {
+        if buffer.is_null() {
+            return Err("buffer is null".into())
+        }
+        unsafe {
+            let get_address: GetDirectBufferAddress = self.function(GET_DIRECT_BUFFER_ADDRESS);
+            let get_capacity: GetDirectBufferCapacity = self.function(GET_DIRECT_BUFFER_CAPACITY);
+            let address = get_address(self.0, buffer) as *mut u8;
+            let capacity = get_capacity(self.0, buffer);
+            if address.is_null() || capacity < 0 {
+                return Err("buffer is not a direct ByteBuffer".into())
+            }
+            Ok((address, capacity as usize))
+        }
+    }
+
+    /// Returns the part of a direct buffer from `offset` with `len` bytes.
+    pub fn direct_buffer_range(&self, buffer: jobject, offset: jint, len: jint) -> Result<(*mut u8, usize), String> {
+        let (address, capacity) = self.direct_buffer(buffer)?;
+        let (offset, len) = match (usize::try_from(offset), usize::try_from(len)) {
+            (Ok(offset), Ok(len)) if offset.checked_add(len).is_some_and(|end| end <= capacity) => (offset, len),
+            _ => return Err(format!("range {}+{} is out of the buffer with capacity {}", offset, len, capacity)),
+        };
+        let address = if len == 0 { ptr::null_mut() } else { unsafe { address.add(offset) } };
+        Ok((address, len))
+    }
+}
@@ -0,0 +1,329 @@
+//! JNI bindings for the [`turbojpeg`] crate.
+//!
+//! This crate implements the native methods of the Java class `org.turbojpeg.TurboJpeg` (in
+//! `java/org/turbojpeg/TurboJpeg.java`), so that Java and Kotlin applications (such as Android
+//! camera apps) can use the safe wrapper from the `turbojpeg` crate without writing their own JNI
+//! code. The methods accept `byte[]` arrays, which are copied, and direct `java.nio.ByteBuffer`s,
+//! which are read and written in place without copying.
+//!
+//! Errors and panics are reported as `org.turbojpeg.TurboJpegException`. Pixel formats,
+//! subsampling options and transform operations use the same integer values as the `TJPF_*`,
+//! `TJSAMP_*` and `TJXOP_*` constants of TurboJPEG (the constants are also defined in the Java
+//! class). The compressors and decompressors are reused across calls from
+//! [`HandlePool`]s, so the calls are cheap even for small images.
+#![allow(non_snake_case)]
+
+mod env;
+
+use std::ffi::CStr;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::OnceLock;
+use turbojpeg::{raw, CompressOverrides, Compressor, Decompressor, HandlePool, Image, PixelFormat, Subsamp, TransformOp};
+use crate::env::{Env, JNIEnv, jbyteArray, jclass, jint, jintArray, jobject};
+
+const EXCEPTION_CLASS: &CStr = c"org/turbojpeg/TurboJpegException";
+
+/// Maximal number of idle compressors and decompressors kept between calls.
+const MAX_IDLE: usize = 8;
+
+fn compressors() -> &'static HandlePool<Compressor> {
+    static POOL: OnceLock<HandlePool<Compressor>> = OnceLock::new();
+    POOL.get_or_init(|| HandlePool::new(MAX_IDLE))
+}
+
+fn decompressors() -> &'static HandlePool<Decompressor> {
+    static POOL: OnceLock<HandlePool<Decompressor>> = OnceLock::new();
+    POOL.get_or_init(|| HandlePool::new(MAX_IDLE))
+}
+
+/// Runs `f`, converting errors and panics into a Java exception and returning `failed`.
+fn guard<T, F>(env: *mut JNIEnv, failed: T, f: F) -> T
+    where F: FnOnce(&Env) -> Result<T, String>
+{
+    let env = unsafe { Env::new(env) };
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&env))) {
+        Ok(Ok(value)) => value,
+        Ok(Err(msg)) => { env.throw(EXCEPTION_CLASS, &msg); failed },
+        Err(payload) => {
+            let msg = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".into());
+            env.throw(EXCEPTION_CLASS, &format!("panic: {}", msg));
+            failed
+        },
+    }
+}
+
+fn pixel_format(format: jint) -> Result<PixelFormat, String> {
+    Ok(match format {
+        raw::TJPF_TJPF_RGB => PixelFormat::RGB,
+        raw::TJPF_TJPF_BGR => PixelFormat::BGR,
+        raw::TJPF_TJPF_RGBX => PixelFormat::RGBX,
+        raw::TJPF_TJPF_BGRX => PixelFormat::BGRX,
+        raw::TJPF_TJPF_XBGR => PixelFormat::XBGR,
+        raw::TJPF_TJPF_XRGB => PixelFormat::XRGB,
+        raw::TJPF_TJPF_GRAY => PixelFormat::GRAY,
+        raw::TJPF_TJPF_RGBA => PixelFormat::RGBA,
+        raw::TJPF_TJPF_BGRA => PixelFormat::BGRA,
+        raw::TJPF_TJPF_ABGR => PixelFormat::ABGR,
+        raw::TJPF_TJPF_ARGB => PixelFormat::ARGB,
+        raw::TJPF_TJPF_CMYK => PixelFormat::CMYK,
+        _ => return Err(format!("unknown pixel format {}", format)),
+    })
+}
+
+fn subsamp(subsamp: jint) -> Result<Subsamp, String> {
+    Ok(match subsamp as u32 {
+        raw::TJSAMP_TJSAMP_444 => Subsamp::None,
+        raw::TJSAMP_TJSAMP_422 => Subsamp::Sub2x1,
+        raw::TJSAMP_TJSAMP_420 => Subsamp::Sub2x2,
+        raw::TJSAMP_TJSAMP_GRAY => Subsamp::Gray,
+        raw::TJSAMP_TJSAMP_440 => Subsamp::Sub1x2,
+        raw::TJSAMP_TJSAMP_411 => Subsamp::Sub4x1,
+        _ => return Err(format!("unknown subsampling {}", subsamp)),
+    })
+}
+
+fn transform_op(op: jint) -> Result<TransformOp, String> {
+    Ok(match op as u32 {
+        raw::TJXOP_TJXOP_NONE => TransformOp::None,
+        raw::TJXOP_TJXOP_HFLIP => TransformOp::Hflip,
+        raw::TJXOP_TJXOP_VFLIP => TransformOp::Vflip,
+        raw::TJXOP_TJXOP_TRANSPOSE => TransformOp::Transpose,
+        raw::TJXOP_TJXOP_TRANSVERSE => TransformOp::Transverse,
+        raw::TJXOP_TJXOP_ROT90 => TransformOp::Rot90,
+        raw::TJXOP_TJXOP_ROT180 => TransformOp::Rot180,
+        raw::TJXOP_TJXOP_ROT270 => TransformOp::Rot270,
+        _ => return Err(format!("unknown transform operation {}", op)),
+    })
+}
+
+fn dimension(value: jint, name: &str) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| format!("{} must not be negative", name))
+}
+
+/// Converts the geometry passed from Java into an image with pixels at `pixels`.
+fn image<T>(pixels: T, width: jint, pitch: jint, height: jint, format: jint) -> Result<Image<T>, String> {
+    let format = pixel_format(format)?;
+    let width = dimension(width, "width")?;
+    let pitch = dimension(pitch, "pitch")?;
+    let height = dimension(height, "height")?;
+    if pitch < width * format.size() {
+        return Err(format!("pitch {} is too small for width {} and {:?}", pitch, width, format))
+    }
+    Ok(Image { pixels, width, pitch, height, format })
+}
+
+/// Returns the number of bytes needed for an image with the given geometry.
+fn image_len<T>(image: &Image<T>) -> usize {
+    if image.height == 0 { 0 } else { image.pitch * (image.height - 1) + image.width * image.format.size() }
+}
+
+fn overrides(quality: jint, subsamp_: jint) -> Result<CompressOverrides, String> {
+    Ok(CompressOverrides {
+        quality: Some(turbojpeg::Quality::new(quality).map_err(|err| err.to_string())?),
+        subsamp: Some(subsamp(subsamp_)?),
+        ..CompressOverrides::default()
+    })
+}
+
+fn to_jint(value: usize, name: &str) -> Result<jint, String> {
+    jint::try_from(value).map_err(|_| format!("{} {} is too large for Java", name, value))
+}
+
+/// `static native int[] readHeader(byte[] jpeg)`: returns `{width, height, subsamp, colorspace}`.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_readHeader(
+    env: *mut JNIEnv,
+    _class: jclass,
+    jpeg: jbyteArray,
+) -> jintArray {
+    guard(env, ptr::null_mut(), |env| {
+        let jpeg_data = env.byte_array(jpeg)?;
+        let header = decompressors().get().map_err(|err| err.to_string())?
+            .read_header(&jpeg_data).map_err(|err| err.to_string())?;
+        env.new_int_array(&[
+            to_jint(header.width, "width")?,
+            to_jint(header.height, "height")?,
+            header.subsamp as jint,
+            header.colorspace as jint,
+        ])
+    })
+}
+
+/// `static native byte[] decompress(byte[] jpeg, int format)`: returns the pixels without row
+/// padding.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_decompress(
+    env: *mut JNIEnv,
+    _class: jclass,
+    jpeg: jbyteArray,
+    format: jint,
+) -> jbyteArray {
+    guard(env, ptr::null_mut(), |env| {
+        let jpeg_data = env.byte_array(jpeg)?;
+        let format = pixel_format(format)?;
+        let mut decompressor = decompressors().get().map_err(|err| err.to_string())?;
+        let image = decompressor.decompress_to_buf(&jpeg_data, format).map_err(|err| err.to_string())?;
+        env.new_byte_array(&image.pixels)
+    })
+}
+
+/// `static native void decompressDirect(ByteBuffer jpeg, int jpegOffset, int jpegLen, ByteBuffer
+/// output, int outputOffset, int outputLen, int width, int pitch, int height, int format)`:
+/// decompresses from and into direct buffers without copying.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_decompressDirect(
+    env: *mut JNIEnv,
+    _class: jclass,
+    jpeg: jobject,
+    jpeg_offset: jint,
+    jpeg_len: jint,
+    output: jobject,
+    output_offset: jint,
+    output_len: jint,
+    width: jint,
+    pitch: jint,
+    height: jint,
+    format: jint,
+) {
+    guard(env, (), |env| {
+        let (jpeg_ptr, jpeg_len) = env.direct_buffer_range(jpeg, jpeg_offset, jpeg_len)?;
+        let (output_ptr, output_len) = env.direct_buffer_range(output, output_offset, output_len)?;
+        let image = image(output_ptr, width, pitch, height, format)?;
+        if output_len < image_len(&image) {
+            return Err(format!("output buffer of {} bytes is too small for the image", output_len))
+        }
+        if jpeg_ptr < output_ptr.wrapping_add(output_len) && output_ptr < jpeg_ptr.wrapping_add(jpeg_len) {
+            return Err("the JPEG data and the output overlap".into())
+        }
+        let mut decompressor = decompressors().get().map_err(|err| err.to_string())?;
+        // the direct buffers are initialized by Java and are not moved by the garbage collector;
+        // the caller must not access them from other threads during the call
+        unsafe { decompressor.decompress_raw(jpeg_ptr, jpeg_len, image, output_len) }
+            .map_err(|err| err.to_string())
+    })
+}
+
+/// `static native byte[] compress(byte[] pixels, int width, int pitch, int height, int format,
+/// int quality, int subsamp)`.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_compress(
+    env: *mut JNIEnv,
+    _class: jclass,
+    pixels: jbyteArray,
+    width: jint,
+    pitch: jint,
+    height: jint,
+    format: jint,
+    quality: jint,
+    subsamp_: jint,
+) -> jbyteArray {
+    guard(env, ptr::null_mut(), |env| {
+        let pixels = env.byte_array(pixels)?;
+        let image = image(&pixels[..], width, pitch, height, format)?;
+        if pixels.len() < image_len(&image) {
+            return Err(format!("pixels array of {} bytes is too small for the image", pixels.len()))
+        }
+        let overrides = overrides(quality, subsamp_)?;
+        let mut compressor = compressors().get().map_err(|err| err.to_string())?;
+        let jpeg_data = compressor.with_overrides(&overrides, |compressor| compressor.compress_to_owned(image))
+            .map_err(|err| err.to_string())?;
+        env.new_byte_array(&jpeg_data)
+    })
+}
+
+/// `static native int compressDirect(ByteBuffer pixels, int pixelsOffset, int pixelsLen, int
+/// width, int pitch, int height, int format, int quality, int subsamp, ByteBuffer output, int
+/// outputOffset, int outputLen)`: compresses from and into direct buffers without copying and
+/// returns the size of the JPEG data.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_compressDirect(
+    env: *mut JNIEnv,
+    _class: jclass,
+    pixels: jobject,
+    pixels_offset: jint,
+    pixels_len: jint,
+    width: jint,
+    pitch: jint,
+    height: jint,
+    format: jint,
+    quality: jint,
+    subsamp_: jint,
+    output: jobject,
+    output_offset: jint,
+    output_len: jint,
+) -> jint {
+    guard(env, 0, |env| {
+        let (pixels_ptr, pixels_len) = env.direct_buffer_range(pixels, pixels_offset, pixels_len)?;
+        let (output_ptr, output_len) = env.direct_buffer_range(output, output_offset, output_len)?;
+        let image = image(pixels_ptr as *const u8, width, pitch, height, format)?;
+        if pixels_len < image_len(&image) {
+            return Err(format!("pixels buffer of {} bytes is too small for the image", pixels_len))
+        }
+        if pixels_ptr < output_ptr.wrapping_add(output_len) && output_ptr < pixels_ptr.wrapping_add(pixels_len) {
+            return Err("the pixels and the output overlap".into())
+        }
+        let overrides = overrides(quality, subsamp_)?;
+        let mut compressor = compressors().get().map_err(|err| err.to_string())?;
+        // the direct buffers are initialized by Java and are not moved by the garbage collector;
+        // the caller must not access them from other threads during the call
+        let len = compressor.with_overrides(&overrides, |compressor| unsafe {
+            compressor.compress_raw(image, pixels_len, output_ptr, output_len)
+        }).map_err(|err| err.to_string())?;
+        to_jint(len, "JPEG size")
+    })
+}
+
+/// `static native int compressedBufLen(int width, int height, int subsamp)`: returns the size of
+/// an output buffer that is large enough for any image compressed with these parameters.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_compressedBufLen(
+    env: *mut JNIEnv,
+    _class: jclass,
+    width: jint,
+    height: jint,
+    subsamp_: jint,
+) -> jint {
+    guard(env, 0, |_| {
+        let len = turbojpeg::compressed_buf_len(dimension(width, "width")?, dimension(height, "height")?, subsamp(subsamp_)?)
+            .map_err(|err| err.to_string())?;
+        to_jint(len, "buffer size")
+    })
+}
+
+/// `static native byte[] transform(byte[] jpeg, int op)`: losslessly transforms the image.
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_transform(
+    env: *mut JNIEnv,
+    _class: jclass,
+    jpeg: jbyteArray,
+    op: jint,
+) -> jbyteArray {
+    guard(env, ptr::null_mut(), |env| {
+        let jpeg_data = env.byte_array(jpeg)?;
+        let transform = turbojpeg::Transform { op: transform_op(op)?, ..turbojpeg::Transform::default() };
+        let output = turbojpeg::transform(&transform, &jpeg_data).map_err(|err| err.to_string())?;
+        env.new_byte_array(&output)
+    })
+}
+
+/// `static native byte[] thumbnail(byte[] jpeg, int maxWidth, int maxHeight, int quality)`:
+/// resizes the image to fit into `maxWidth` x `maxHeight` (without upscaling).
+#[no_mangle]
+pub extern "system" fn Java_org_turbojpeg_TurboJpeg_thumbnail(
+    env: *mut JNIEnv,
+    _class: jclass,
+    jpeg: jbyteArray,
+    max_width: jint,
+    max_height: jint,
+    quality: jint,
+) -> jbyteArray {
+    guard(env, ptr::null_mut(), |env| {
+        let jpeg_data = env.byte_array(jpeg)?;
+        let target = (dimension(max_width, "maxWidth")?, dimension(max_height, "maxHeight")?);
+        let output = turbojpeg::resize_jpeg(&jpeg_data, target, quality).map_err(|err| err.to_string())?;
+        env.new_byte_array(&output)
+    })
+}
@@ -0,0 +1,221 @@
+//! Python bindings for the [`turbojpeg`] crate.
+//!
+//! The Python module `turbojpeg` provides `read_header()`, `decompress()`, `compress()` and
+//! `transform()`. Images are exchanged as NumPy arrays of shape `(height, width, channels)` (or
+//! `(height, width)` for grayscale images) with dtype `uint8`:
+//!
+//! - `decompress()` decodes the JPEG directly into the memory of a new NumPy array, without any
+//!   intermediate buffer.
+//! - `compress()` reads the pixels directly from the NumPy array when its rows are contiguous
+//!   (the array may still have padding between rows, such as a slice of a larger array), and
+//!   copies the array only if the pixels are not laid out in rows.
+//!
+//! The GIL is released while TurboJPEG is running, so images can be processed in parallel from
+//! multiple Python threads.
+use numpy::{PyArray3, PyArrayMethods as _, PyReadonlyArrayDyn};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use turbojpeg::{Image, PixelFormat, Subsamp, TransformOp};
+
+create_exception!(turbojpeg, TurboJpegError, PyException, "Error reported by TurboJPEG.");
+
+fn to_py_err(err: turbojpeg::Error) -> PyErr {
+    TurboJpegError::new_err(err.to_string())
+}
+
+fn pixel_format(name: &str) -> PyResult<PixelFormat> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "rgb" => PixelFormat::RGB,
+        "bgr" => PixelFormat::BGR,
+        "rgbx" => PixelFormat::RGBX,
+        "bgrx" => PixelFormat::BGRX,
+        "xbgr" => PixelFormat::XBGR,
+        "xrgb" => PixelFormat::XRGB,
+        "gray" => PixelFormat::GRAY,
+        "rgba" => PixelFormat::RGBA,
+        "bgra" => PixelFormat::BGRA,
+        "abgr" => PixelFormat::ABGR,
+        "argb" => PixelFormat::ARGB,
+        "cmyk" => PixelFormat::CMYK,
+        _ => return Err(PyValueError::new_err(format!("unknown pixel format {:?}", name))),
+    })
+}
+
+fn subsamp(name: &str) -> PyResult<Subsamp> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "444" => Subsamp::None,
+        "422" => Subsamp::Sub2x1,
+        "420" => Subsamp::Sub2x2,
+        "440" => Subsamp::Sub1x2,
+        "411" => Subsamp::Sub4x1,
+        "gray" => Subsamp::Gray,
+        _ => return Err(PyValueError::new_err(format!("unknown subsampling {:?}", name))),
+    })
+}
+
+fn subsamp_name(subsamp: Subsamp) -> &'static str {
+    match subsamp {
+        Subsamp::None => "444",
+        Subsamp::Sub2x1 => "422",
+        Subsamp::Sub2x2 => "420",
+        Subsamp::Sub1x2 => "440",
+        Subsamp::Sub4x1 => "411",
+        Subsamp::Gray => "gray",
+    }
+}
+
+fn transform_op(name: &str) -> PyResult<TransformOp> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "none" => TransformOp::None,
+        "hflip" => TransformOp::Hflip,
+        "vflip" => TransformOp::Vflip,
+        "transpose" => TransformOp::Transpose,
+        "transverse" => TransformOp::Transverse,
+        "rot90" => TransformOp::Rot90,
+        "rot180" => TransformOp::Rot180,
+        "rot270" => TransformOp::Rot270,
+        _ => return Err(PyValueError::new_err(format!("unknown transform operation {:?}", name))),
+    })
+}
+
+/// Reads the header of a JPEG image and returns a dict with keys `width`, `height`, `subsamp`,
+/// `colorspace` and `multi_scan`.
+#[pyfunction]
+fn read_header<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+    let header = turbojpeg::read_header(data).map_err(to_py_err)?;
+    let dict = PyDict::new_bound(py);
+    dict.set_item("width", header.width)?;
+    dict.set_item("height", header.height)?;
+    dict.set_item("subsamp", subsamp_name(header.subsamp))?;
+    dict.set_item("colorspace", format!("{:?}", header.colorspace).to_ascii_lowercase())?;
+    dict.set_item("multi_scan", header.multi_scan)?;
+    Ok(dict)
+}
+
+/// Decompresses a JPEG image into a NumPy array of shape `(height, width, channels)`.
+///
+/// `format` is the pixel format of the array (such as `"rgb"`, `"bgra"` or `"gray"`).
+#[pyfunction]
+#[pyo3(signature = (data, format = "rgb"))]
+fn decompress<'py>(py: Python<'py>, data: &[u8], format: &str) -> PyResult<Bound<'py, PyArray3<u8>>> {
+    let format = pixel_format(format)?;
+    let mut decompressor = turbojpeg::Decompressor::new().map_err(to_py_err)?;
+    let header = decompressor.read_header(data).map_err(to_py_err)?;
+    let array = PyArray3::<u8>::zeros_bound(py, [header.height, header.width, format.size()], false);
+    {
+        let mut array = array.readwrite();
+        let image = Image {
+            pixels: array.as_slice_mut()?,
+            width: header.width,
+            pitch: header.width * format.size(),
+            height: header.height,
+            format,
+        };
+        py.allow_threads(|| decompressor.decompress(data, image)).map_err(to_py_err)?;
+    }
+    Ok(array)
+}
+
+/// Compresses a NumPy array of shape `(height, width, channels)` or `(height, width)` into JPEG.
+///
+/// If `format` is not given, it is determined from the number of channels (`"gray"`, `"rgb"` or
+/// `"rgba"`).
+#[pyfunction]
+#[pyo3(signature = (array, quality = 90, subsamp = "420", format = None))]
+fn compress<'py>(
+    py: Python<'py>,
+    array: PyReadonlyArrayDyn<'py, u8>,
+    quality: i32,
+    subsamp: &str,
+    format: Option<&str>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let view = array.as_array();
+    let (height, width, channels) = match *view.shape() {
+        [height, width] => (height, width, 1),
+        [height, width, channels] => (height, width, channels),
+        _ => return Err(PyValueError::new_err("array must have 2 or 3 dimensions")),
+    };
+    let format = match format {
+        Some(format) => pixel_format(format)?,
+        None => match channels {
+            1 => PixelFormat::GRAY,
+            3 => PixelFormat::RGB,
+            4 => PixelFormat::RGBA,
+            _ => return Err(PyValueError::new_err("cannot determine the pixel format from the array shape")),
+        },
+    };
+    if format.size() != channels {
+        return Err(PyValueError::new_err(format!(
+            "pixel format {:?} needs {} channels, but the array has {}", format, format.size(), channels)))
+    }
+    let subsamp = if format == PixelFormat::GRAY { Subsamp::Gray } else { self::subsamp(subsamp)? };
+
+    // use the memory of the array directly if the pixels in every row are contiguous
+    let strides = view.strides();
+    let row_contiguous = width <= 1 || strides[1] == channels as isize;
+    let channel_contiguous = strides.len() < 3 || channels <= 1 || strides[2] == 1;
+    let data = if row_contiguous && channel_contiguous && strides[0] >= (width * channels) as isize && height > 0 {
+        let pitch = strides[0] as usize;
+        let len = pitch * (height - 1) + width * channels;
+        let pixels = unsafe { std::slice::from_raw_parts(view.as_ptr(), len) };
+        let image = Image { pixels, width, pitch, height, format };
+        py.allow_threads(|| compress_image(image, quality, subsamp))?
+    } else {
+        let owned = view.as_standard_layout();
+        let pixels = owned.as_slice().expect("standard layout is contiguous");
+        let image = Image { pixels, width, pitch: width * channels, height, format };
+        py.allow_threads(|| compress_image(image, quality, subsamp))?
+    };
+    Ok(PyBytes::new_bound(py, &data))
+}
+
+fn compress_image(image: Image<&[u8]>, quality: i32, subsamp: Subsamp) -> PyResult<Vec<u8>> {
+    let mut compressor = turbojpeg::Compressor::new().map_err(to_py_err)?;
+    compressor.set_quality(quality).map_err(to_py_err)?;
+    compressor.set_subsamp(subsamp);
+    compressor.compress_to_vec(image).map_err(to_py_err)
+}
+
+/// Losslessly transforms a JPEG image.
+///
+/// `op` is one of `"none"`, `"hflip"`, `"vflip"`, `"transpose"`, `"transverse"`, `"rot90"`,
+/// `"rot180"` and `"rot270"`, and `crop` is an optional tuple `(x, y, width, height)`.
+#[pyfunction]
+#[pyo3(signature = (data, op = "none", crop = None, gray = false, progressive = false, strip_metadata = false))]
+fn transform<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    op: &str,
+    crop: Option<(usize, usize, usize, usize)>,
+    gray: bool,
+    progressive: bool,
+    strip_metadata: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let transform = turbojpeg::Transform {
+        op: transform_op(op)?,
+        crop: crop.map(|(x, y, width, height)| turbojpeg::TransformCrop {
+            x, y, width: Some(width), height: Some(height),
+        }),
+        gray,
+        progressive,
+        strip: if strip_metadata { turbojpeg::StripMetadata::all() } else { turbojpeg::StripMetadata::default() },
+        .. turbojpeg::Transform::default()
+    };
+    let output = py.allow_threads(|| {
+        turbojpeg::transform(&transform, data).map(|output| output.to_vec())
+    }).map_err(to_py_err)?;
+    Ok(PyBytes::new_bound(py, &output))
+}
+
+#[pymodule]
+#[pyo3(name = "turbojpeg")]
+fn turbojpeg_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("TurboJpegError", m.py().get_type_bound::<TurboJpegError>())?;
+    m.add_function(wrap_pyfunction!(read_header, m)?)?;
+    m.add_function(wrap_pyfunction!(decompress, m)?)?;
+    m.add_function(wrap_pyfunction!(compress, m)?)?;
+    m.add_function(wrap_pyfunction!(transform, m)?)?;
+    Ok(())
+}
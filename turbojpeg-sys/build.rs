@@ -8,6 +8,7 @@ use std::process::Command;
 
 fn main() -> Result<()> {
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(turbojpeg_static)");
     let link_kind = get_link_kind()?;
     let library = build_or_find_library(link_kind)?;
     generate_or_copy_bindings(&library)?;
@@ -84,7 +85,7 @@ fn find_pkg_config(link_kind: LinkKind) -> Result<Library> {
     let mut cfg = pkg_config::Config::new();
     cfg.atleast_version("2.0");
     match link_kind {
-        LinkKind::Static => { cfg.statik(true); },
+        LinkKind::Static => { cfg.statik(true); emit_static_cfg(); },
         LinkKind::Dynamic => { cfg.statik(false); },
         LinkKind::Default => {},
     }
@@ -125,6 +126,9 @@ fn find_explicit(link_kind: LinkKind) -> Result<Library> {
         LinkKind::Static | LinkKind::Default => "static",
         LinkKind::Dynamic => "dylib",
     });
+    if !matches!(link_kind, LinkKind::Dynamic) {
+        emit_static_cfg();
+    }
 
     Ok(Library {
         include_paths: include_dir.into_iter().collect(),
@@ -177,6 +181,9 @@ fn build_vendor(link_kind: LinkKind) -> Result<Library> {
     } else {
         ""
     });
+    if !matches!(link_kind, LinkKind::Dynamic) {
+        emit_static_cfg();
+    }
 
     Ok(Library {
         include_paths: vec![include_path],
@@ -184,6 +191,12 @@ fn build_vendor(link_kind: LinkKind) -> Result<Library> {
     })
 }
 
+/// The internal symbols of libjpeg-turbo (such as `jsimd_can_*`) are only available when we link
+/// statically.
+fn emit_static_cfg() {
+    println!("cargo:rustc-cfg=turbojpeg_static");
+}
+
 fn check_nasm() {
     if !Command::new("nasm").arg("-v").status().map(|s| s.success()).unwrap_or(false) {
         println!("cargo:warning=NASM does not seem to be installed, so turbojpeg will be compiled without \
@@ -5,6 +5,30 @@
 #![allow(deref_nullptr)]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+/// Returns whether libjpeg-turbo uses SIMD extensions on this CPU.
+///
+/// TurboJPEG 2 has no API to query this, so we call the internal `jsimd_can_*()` functions of
+/// libjpeg-turbo, which are only available when the library is linked statically. Returns `None`
+/// if the library is linked dynamically.
+///
+/// This returns `false` if libjpeg-turbo was built without SIMD extensions (for example, because
+/// NASM was not installed), if the CPU does not support them, or if they were disabled with the
+/// `JSIMD_FORCENONE` environment variable.
+pub fn simd_available() -> Option<bool> {
+    #[cfg(turbojpeg_static)]
+    {
+        extern "C" {
+            fn jsimd_can_rgb_ycc() -> libc::c_int;
+            fn jsimd_can_idct_islow() -> libc::c_int;
+        }
+        unsafe { Some(jsimd_can_rgb_ycc() != 0 || jsimd_can_idct_islow() != 0) }
+    }
+    #[cfg(not(turbojpeg_static))]
+    {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
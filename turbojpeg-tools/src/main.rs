@@ -0,0 +1,262 @@
+use std::fs;
+use anyhow::{Result, Context as _, bail};
+use clap::{clap_app, ArgMatches};
+
+use turbojpeg::{
+    Colorspace, Compressor, Decompressor, EdgeHandling, MarkerCopy, PixelFormat,
+    ScalingFactor, StripMetadata, Subsamp, TransformCrop, TransformOp, TransformPipeline,
+    Transformer,
+};
+
+fn main() -> Result<()> {
+    let args = clap_app!(turbojpeg =>
+        (about: "Encode, decode, transform and inspect JPEG images with TurboJPEG")
+        (@setting SubcommandRequiredElseHelp)
+        (@subcommand encode =>
+            (about: "Compress a BMP or PPM/PGM image into JPEG")
+            (@arg INPUT: <input> "Input image file (BMP or PPM/PGM)")
+            (@arg OUTPUT: <output> "Output JPEG file")
+            (@arg QUALITY: -q --quality [quality]
+                "Compression quality from 1 (worst) to 100 (best) (default 90)")
+            (@arg SUBSAMP: -s --subsamp [subsamp]
+                "Chrominance subsampling: 444, 422, 420, 440, 411 or gray (default 420)")
+            (@arg PROGRESSIVE: --progressive
+                "Use progressive entropy coding")
+        )
+        (@subcommand decode =>
+            (about: "Decompress a JPEG image into BMP or PPM/PGM")
+            (@arg INPUT: <input> "Input JPEG file")
+            (@arg OUTPUT: <output>
+                "Output image file (the format is BMP if the extension is .bmp, PPM/PGM otherwise)")
+            (@arg SCALE: --scale [factor]
+                "Scale the image by factor M/N (such as 1/2 or 3/8) during decompression")
+        )
+        (@subcommand transform =>
+            (about: "Losslessly transform a JPEG image")
+            (@arg INPUT: <input> "Input JPEG file")
+            (@arg OUTPUT: <output> "Output JPEG file")
+            (@arg CROP: --crop [region]
+                "Crop the input image to region WxH+X+Y before the other transforms")
+            (@arg FLIP: --flip [direction]
+                "Performs a flip ('horizontal' or 'vertical')")
+            (@arg ROTATE: --rotate [angle]
+                "Rotates the image (angle is 90, 180 or 270)")
+            (@arg TRANSPOSE: --transpose
+                "Transpose image (flip along upper left to lower right axis)")
+            (@arg TRANSVERSE: --transverse
+                "Transverse transpose image (flip along upper right to lower left axis)")
+            (@arg PERFECT: --perfect
+                "Return an error if the transformation is not perfecly lossless")
+            (@arg TRIM: --trim
+                "Discard partial blocks that cannot be transformed")
+            (@arg PROGRESSIVE: --progressive
+                "Use progressive entropy coding")
+            (@arg GRAYSCALE: --grayscale
+                "Convert the image into grayscale")
+            (@arg STRIP: --strip [kinds]
+                "Remove metadata: 'all' or a comma-separated list of 'exif', 'icc', 'xmp' and 'comments'")
+            (@arg COPY_NONE: --("copy-none")
+                "Do not copy any extra markers (such as EXIF data)")
+        )
+        (@subcommand inspect =>
+            (about: "Print information about a JPEG image")
+            (@arg INPUT: <input> "Input JPEG file")
+        )
+    ).get_matches();
+
+    match args.subcommand() {
+        ("encode", Some(args)) => encode(args),
+        ("decode", Some(args)) => decode(args),
+        ("transform", Some(args)) => transform(args),
+        ("inspect", Some(args)) => inspect(args),
+        _ => unreachable!(),
+    }
+}
+
+fn encode(args: &ArgMatches) -> Result<()> {
+    let quality = match args.value_of("QUALITY") {
+        Some(quality) => quality.parse().context("invalid value of --quality")?,
+        None => 90,
+    };
+    let subsamp = match args.value_of("SUBSAMP").unwrap_or("420") {
+        "444" => Subsamp::None,
+        "422" => Subsamp::Sub2x1,
+        "420" => Subsamp::Sub2x2,
+        "440" => Subsamp::Sub1x2,
+        "411" => Subsamp::Sub4x1,
+        "gray" => Subsamp::Gray,
+        _ => bail!("unknown value of --subsamp"),
+    };
+
+    let image = turbojpeg::load_image(args.value_of("INPUT").unwrap())
+        .context("could not read input image")?;
+    let mut compressor = Compressor::new()
+        .context("could not create compressor")?;
+    compressor.set_quality(quality)?;
+    compressor.set_subsamp(if image.format == PixelFormat::GRAY { Subsamp::Gray } else { subsamp });
+    compressor.set_progressive(args.is_present("PROGRESSIVE"));
+    let jpeg_data = compressor.compress_to_vec(image.as_deref())
+        .context("could not compress the image")?;
+
+    fs::write(args.value_of("OUTPUT").unwrap(), &jpeg_data)
+        .context("could not write output image")?;
+    Ok(())
+}
+
+fn decode(args: &ArgMatches) -> Result<()> {
+    let factor = match args.value_of("SCALE") {
+        Some(factor) => parse_scale(factor).context("invalid value of --scale")?,
+        None => ScalingFactor::ONE,
+    };
+
+    let jpeg_data = fs::read(args.value_of("INPUT").unwrap())
+        .context("could not read input image")?;
+    let mut decompressor = Decompressor::new()
+        .context("could not create decompressor")?;
+    let header = decompressor.read_header(&jpeg_data)
+        .context("could not read JPEG header")?;
+    let format = match header.colorspace {
+        Colorspace::Gray => PixelFormat::GRAY,
+        _ => PixelFormat::RGB,
+    };
+    let image = decompressor.decompress_scaled(&jpeg_data, format, factor)
+        .context("could not decompress JPEG data")?;
+
+    turbojpeg::save_image(args.value_of("OUTPUT").unwrap(), image.as_deref())
+        .context("could not write output image")?;
+    Ok(())
+}
+
+fn transform(args: &ArgMatches) -> Result<()> {
+    let mut pipeline = TransformPipeline::new();
+    if let Some(region) = args.value_of("CROP") {
+        pipeline = pipeline.crop(parse_crop(region).context("invalid value of --crop")?);
+    }
+
+    if let Some(direction) = args.value_of("FLIP") {
+        pipeline = pipeline.op(match direction {
+            "horizontal" => TransformOp::Hflip,
+            "vertical" => TransformOp::Vflip,
+            _ => bail!("unknown value of --flip"),
+        });
+    }
+    if let Some(angle) = args.value_of("ROTATE") {
+        pipeline = pipeline.op(match angle {
+            "90" => TransformOp::Rot90,
+            "180" => TransformOp::Rot180,
+            "270" => TransformOp::Rot270,
+            _ => bail!("unknown value of --rotate"),
+        });
+    }
+    if args.is_present("TRANSPOSE") {
+        pipeline = pipeline.op(TransformOp::Transpose);
+    }
+    if args.is_present("TRANSVERSE") {
+        pipeline = pipeline.op(TransformOp::Transverse);
+    }
+
+    if args.is_present("PERFECT") {
+        pipeline = pipeline.edge(EdgeHandling::Perfect);
+    } else if args.is_present("TRIM") {
+        pipeline = pipeline.edge(EdgeHandling::Trim);
+    }
+    if args.is_present("PROGRESSIVE") {
+        pipeline = pipeline.progressive();
+    }
+    if args.is_present("GRAYSCALE") {
+        pipeline = pipeline.gray();
+    }
+    if let Some(kinds) = args.value_of("STRIP") {
+        pipeline = pipeline.strip(parse_strip(kinds)?);
+    }
+    if args.is_present("COPY_NONE") {
+        pipeline = pipeline.markers(MarkerCopy::None);
+    }
+
+    let jpeg_data = fs::read(args.value_of("INPUT").unwrap())
+        .context("could not read input image")?;
+    let mut transformer = Transformer::new()
+        .context("could not create transformer")?;
+    let transformed_data = transformer.transform_pipeline(&pipeline, &jpeg_data)
+        .context("could not transform JPEG data")?;
+    fs::write(args.value_of("OUTPUT").unwrap(), &transformed_data)
+        .context("could not write output image")?;
+    Ok(())
+}
+
+fn inspect(args: &ArgMatches) -> Result<()> {
+    let jpeg_data = fs::read(args.value_of("INPUT").unwrap())
+        .context("could not read input image")?;
+    let summary = turbojpeg::validate_jpeg(&jpeg_data)
+        .context("JPEG data is not valid")?;
+    let header = turbojpeg::read_header(&jpeg_data)
+        .context("could not read JPEG header")?;
+
+    println!("size:        {}x{}", header.width, header.height);
+    println!("process:     {:?}", summary.process);
+    println!("precision:   {} bits", summary.precision);
+    println!("colorspace:  {:?}", header.colorspace);
+    println!("subsampling: {:?}", header.subsamp);
+    println!("components:  {}", summary.components);
+    println!("scans:       {}", summary.scans);
+    println!("length:      {} bytes", jpeg_data.len());
+    if summary.len < jpeg_data.len() {
+        println!("trailing:    {} bytes after EOI", jpeg_data.len() - summary.len);
+    }
+
+    // measure the size of every kind of metadata by stripping it
+    let kinds = [
+        ("exif", StripMetadata { exif: true, .. StripMetadata::default() }),
+        ("icc", StripMetadata { icc: true, .. StripMetadata::default() }),
+        ("xmp", StripMetadata { xmp: true, .. StripMetadata::default() }),
+        ("comments", StripMetadata { comments: true, .. StripMetadata::default() }),
+    ];
+    for (name, strip) in kinds {
+        let stripped_len = turbojpeg::strip_metadata(&jpeg_data, &strip)
+            .context("could not parse JPEG metadata")?
+            .len();
+        if stripped_len < jpeg_data.len() {
+            println!("{:<12} {} bytes", format!("{}:", name), jpeg_data.len() - stripped_len);
+        }
+    }
+    Ok(())
+}
+
+fn parse_scale(factor: &str) -> Result<ScalingFactor> {
+    let (num, denom) = factor.split_once('/').unwrap_or((factor, "1"));
+    let num: usize = num.parse()?;
+    let denom: usize = denom.parse()?;
+    if num == 0 || denom == 0 {
+        bail!("scaling factor must not be zero")
+    }
+    Ok(ScalingFactor::new(num, denom))
+}
+
+fn parse_crop(region: &str) -> Result<TransformCrop> {
+    let (size, offset) = region.split_once('+').unwrap_or((region, "0+0"));
+    let (width, height) = size.split_once('x').context("expected WxH+X+Y")?;
+    let (x, y) = offset.split_once('+').context("expected WxH+X+Y")?;
+    Ok(TransformCrop {
+        x: x.parse()?,
+        y: y.parse()?,
+        width: Some(width.parse()?),
+        height: Some(height.parse()?),
+    })
+}
+
+fn parse_strip(kinds: &str) -> Result<StripMetadata> {
+    if kinds == "all" {
+        return Ok(StripMetadata::all())
+    }
+    let mut strip = StripMetadata::default();
+    for kind in kinds.split(',') {
+        match kind.trim() {
+            "exif" => strip.exif = true,
+            "icc" => strip.icc = true,
+            "xmp" => strip.xmp = true,
+            "comments" => strip.comments = true,
+            _ => bail!("unknown kind of metadata {:?} in --strip", kind),
+        }
+    }
+    Ok(strip)
+}